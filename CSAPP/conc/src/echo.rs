@@ -1,4 +1,5 @@
 use std::thread;
+use std::sync::Arc;
 use std::net::{TcpListener, TcpStream};
 use std::io::{self, BufRead, Result, Write};
 use std::env;
@@ -7,32 +8,42 @@ pub fn echo_server() -> Result<()> {
     let mut args = env::args();
     let bin = args.next().expect("bin path missing");
     let port: u16 = args.next()
-        .expect(&format!("Usage: {} PORT", bin))
+        .unwrap_or_else(|| panic!("Usage: {} PORT", bin))
         .parse()
         .expect("Invalid port number");
 
     let listener = TcpListener::bind(("localhost", port))?;
+    thread_per_connection(listener, |stream| {
+        if let Err(e) = echo(&stream) {
+            eprintln!("{}", e);
+        }
+    })
+}
+
+// serve each connection by a newly spawned peer thread
+pub fn thread_per_connection<F>(listener: TcpListener, handler: F) -> Result<()>
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
     for conn in listener.incoming() {
         let stream = conn?;
+        let handler = handler.clone();
         // by move semantics, stream is automatically copied to the context of the newly spawned thread
         // thereby avoided the possible data racing in the C version
-        thread::spawn(move || {
-            if let Err(e) = echo(&stream) {
-                eprintln!("{}", e);
-            }
-        });
+        thread::spawn(move || handler(stream));
     }
     Ok(())
 }
 
 fn echo(stream: &TcpStream) -> Result<()> {
-    const NEWLINE: [u8; 1] = ['\n' as u8];
+    const NEWLINE: [u8; 1] = [b'\n'];
     let reader = io::BufReader::new(stream);
     let mut writer = io::BufWriter::new(stream);
 
     for line in reader.lines() {
-        writer.write((line?).as_bytes())?;
-        writer.write(&NEWLINE)?;
+        writer.write_all((line?).as_bytes())?;
+        writer.write_all(&NEWLINE)?;
         writer.flush()?;
     }
     // threads in Rust are detached by default if the main thread didn't call the join method
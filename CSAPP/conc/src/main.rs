@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+extern crate http_message;
 extern crate rand;
 extern crate scoped_threadpool;
 
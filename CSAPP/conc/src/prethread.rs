@@ -1,7 +1,7 @@
 use std::thread;
 use std::process;
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{self, Receiver};
+use std::sync::mpsc;
 use std::net::{TcpListener, TcpStream};
 use std::io::{self, BufRead, Write};
 use std::env;
@@ -13,21 +13,39 @@ pub fn echo_server() -> Result<(), io::Error> {
     let mut args = env::args();
     let bin = args.next().expect("bin path missing");
     let port: u16 = args.next()
-        .expect(&format!("Usage: {} PORT", bin))
+        .unwrap_or_else(|| panic!("Usage: {} PORT", bin))
         .parse()
         .expect("Invalid port number");
 
-    let (tx, rx) = mpsc::sync_channel(SBUFSIZE);
+    let listener: TcpListener = TcpListener::bind(("localhost", port))?;
+    prethreaded(listener, NTHREADS, echo_thread)
+}
+
+// serve connections by a fixed number of worker threads created in advance
+// the main thread inserts connected sockets into a bounded buffer, workers remove them
+// a sync_channel of SBUFSIZE slots plays the role of the sbuf package in the book
+pub fn prethreaded<F>(listener: TcpListener, nthreads: usize, handler: F) -> Result<(), io::Error>
+where
+    F: Fn(TcpStream) + Send + Sync + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel::<TcpStream>(SBUFSIZE);
     let arc_rx = Arc::new(Mutex::new(rx));
-    for _ in 0..NTHREADS {
+    let handler = Arc::new(handler);
+    for _ in 0..nthreads {
         let rx_clone = arc_rx.clone();
-        thread::spawn(|| {
-            echo_thread(rx_clone);
+        let handler = handler.clone();
+        thread::spawn(move || loop {
+            // the lock is released as soon as a connection is removed from the buffer
+            // otherwise workers would serve clients one at a time
+            let stream = rx_clone
+                .lock()
+                .expect("Arc error")
+                .recv()
+                .expect("Receive error");
+            handler(stream);
         });
     }
 
-    let listener: TcpListener = TcpListener::bind(("localhost", port))?;
-
     for conn in listener.incoming() {
         let stream = conn?;
         if let Err(e) = tx.send(stream) {
@@ -39,32 +57,27 @@ pub fn echo_server() -> Result<(), io::Error> {
     Ok(())
 }
 
-fn echo_thread(arc_rx: Arc<Mutex<Receiver<TcpStream>>>) {
+fn echo_thread(mut stream: TcpStream) {
     let id = thread::current().id();
     let greeting = format!("Worker thread {:?} at service\n", id);
-    loop {
-        let rx = arc_rx.lock().expect("Arc error");
-        let mut stream = rx.recv().expect("Receive error");
-        if let Err(e) = stream.write(greeting.as_bytes()) {
-            eprintln!("{}", e);
-            process::exit(1);
-        }
-        drop(rx);
-        if let Err(e) = echo(&stream) {
-            eprintln!("{}", e);
-            process::exit(1);
-        }
+    if let Err(e) = stream.write_all(greeting.as_bytes()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    if let Err(e) = echo(&stream) {
+        eprintln!("{}", e);
+        process::exit(1);
     }
 }
 
 fn echo(stream: &TcpStream) -> Result<(), io::Error> {
-    const NEWLINE: [u8; 1] = ['\n' as u8];
+    const NEWLINE: [u8; 1] = [b'\n'];
     let reader = io::BufReader::new(stream);
     let mut writer = io::BufWriter::new(stream);
 
     for line in reader.lines() {
-        writer.write((line?).as_bytes())?;
-        writer.write(&NEWLINE)?;
+        writer.write_all((line?).as_bytes())?;
+        writer.write_all(&NEWLINE)?;
         writer.flush()?;
     }
     Ok(())
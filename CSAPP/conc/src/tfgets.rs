@@ -4,7 +4,7 @@ use std::error::Error;
 use std::thread;
 use std::time::Duration;
 
-pub fn tfgets() -> Result<String, Box<Error>> {
+pub fn tfgets() -> Result<String, Box<dyn Error>> {
     let (tx, rx) = mpsc::channel();
     let timeout = Duration::from_secs(5);
 
@@ -1,109 +1,143 @@
-extern crate httparse;
-
+use echo::thread_per_connection;
+use http_message::{Headers, Method, Request, Response, Status};
+use prethread::prethreaded;
 use std::thread;
-use std::process::{self, Command};
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{self, Receiver};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::net::{TcpListener, TcpStream};
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::error::Error;
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 const NTHREADS: usize = 4;
-const SBUFSIZE: usize = 16;
+// CGI programs running longer are killed
+const CGI_TIMEOUT: Duration = Duration::from_secs(5);
+const CGI_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// how concurrent connections are served
+#[derive(Clone, Copy)]
+pub enum Mode {
+    // a fixed pool of NTHREADS worker threads, as in section 12.5.5 of the book
+    Prethreaded,
+    // a new peer thread for each connection, as in section 12.3.8 of the book
+    ThreadPerConnection,
+}
 
 pub fn tiny_server() -> Result<(), io::Error> {
     let mut args = env::args();
     let bin = args.next().expect("bin path missing");
     let port: u16 = args.next()
-        .expect(&format!("Usage: {} PORT", bin))
+        .unwrap_or_else(|| panic!("Usage: {} PORT [prethread|thread]", bin))
         .parse()
         .expect("Invalid port number");
-
-    let (tx, rx) = mpsc::sync_channel(SBUFSIZE);
-    let arc_rx = Arc::new(Mutex::new(rx));
-    for _ in 0..NTHREADS {
-        let rx_clone = arc_rx.clone();
-        thread::spawn(|| {
-            if let Err(e) = tiny_thread(rx_clone) {
-                eprintln!("{}", e);
-            }
-        });
-    }
+    let mode = match args.next().as_deref() {
+        None | Some("prethread") => Mode::Prethreaded,
+        Some("thread") => Mode::ThreadPerConnection,
+        Some(mode) => panic!("Unknown mode {}, expected prethread or thread", mode),
+    };
 
     let listener: TcpListener = TcpListener::bind(("localhost", port))?;
+    serve(listener, mode, PathBuf::from("."))
+}
 
-    for conn in listener.incoming() {
-        let stream = conn?;
-        if let Err(e) = tx.send(stream) {
-            eprintln!("Send error: {}", e);
-            process::exit(1);
-        }
+// serve the files under root, request targets are resolved against it
+pub fn serve(listener: TcpListener, mode: Mode, root: PathBuf) -> Result<(), io::Error> {
+    let handler = move |stream| tiny_thread(stream, &root);
+    match mode {
+        Mode::Prethreaded => prethreaded(listener, NTHREADS, handler),
+        Mode::ThreadPerConnection => thread_per_connection(listener, handler),
     }
-
-    Ok(())
 }
 
-fn tiny_thread(arc_rx: Arc<Mutex<Receiver<TcpStream>>>) -> Result<(), Box<Error>> {
-    let id = thread::current().id();
-    loop {
-        let rx = arc_rx.lock().expect("Arc error");
-        let stream = rx.recv().expect("Receive error");
-        println!("Thread {:?} at service", id);
-        doit(&stream)?;
-        drop(rx);
+fn tiny_thread(stream: TcpStream, root: &Path) {
+    println!("Thread {:?} at service", thread::current().id());
+    // a failed request must not bring down the worker thread
+    if let Err(e) = doit(&stream, root) {
+        eprintln!("{}", e);
     }
 }
 
-fn doit(stream: &TcpStream) -> Result<(), Box<Error>> {
+fn doit(stream: &TcpStream, root: &Path) -> Result<(), Box<dyn Error>> {
     let mut reader = BufReader::new(stream);
     let mut writer = BufWriter::new(stream);
 
-    let (method, uri) = parse_request(&mut reader)?;
+    let request = match Request::from_reader(&mut reader) {
+        Ok(request) => request,
+        Err(e) => {
+            if let Some(status) = e.status() {
+                let _ = client_error(
+                    &mut writer,
+                    &e.to_string(),
+                    status,
+                    "Tiny couldn't parse the request",
+                );
+            }
+            return Err(e.into());
+        }
+    };
+    let method = request.method();
+    let uri = request.target();
+    let (filename, args) = parse_uri(uri);
+    println!("{}, {}", filename, args);
+    let dynamic = uri.starts_with("/cgi-bin/");
 
-    if method != "GET" {
+    // only CGI programs take POST requests
+    if method != Method::GET && !(dynamic && method == Method::POST) {
         return client_error(
             &mut writer,
-            &method,
-            "501",
-            "Not implemented",
+            method.as_str(),
+            Status::NotImplemented,
             "Tiny does not implement this method",
         ).map_err(|e| e.into());
     }
 
-    let (filename, args) = parse_uri(&uri);
-    println!("{}, {}", filename, args);
-    if let Err(e) = fs::metadata(&filename) {
+    // ../ could reach, and run, any program on the machine
+    if filename.split('/').any(|component| component == "..") {
+        return client_error(
+            &mut writer,
+            &filename,
+            Status::Forbidden,
+            "Tiny only serves files under its directory",
+        ).map_err(|e| e.into());
+    }
+
+    let path = root.join(&filename);
+    if let Err(e) = fs::metadata(&path) {
         let _ = client_error(
             &mut writer,
             &filename,
-            "404",
-            "Not found",
+            Status::NotFound,
             "Tiny couldn't find this file",
         );
         return Err(e.into());
     }
     // dynamic
-    if uri.starts_with("/cgi-bin") {
-        if let Err(e) = serve_dynamic(&mut writer, &filename, &args) {
-            let _ = client_error(
-                &mut writer,
-                &filename,
-                "403",
-                "Forbidden",
-                "Tiny couldn't run the CGI program",
-            );
-            return Err(e.into());
-        }
+    if dynamic {
+        let body = match request.read_body(&mut reader) {
+            Ok(body) => body,
+            Err(e) => {
+                if let Some(status) = e.status() {
+                    let _ = client_error(
+                        &mut writer,
+                        &filename,
+                        status,
+                        "Tiny couldn't read the request body",
+                    );
+                }
+                return Err(e.into());
+            }
+        };
+        serve_dynamic(&mut writer, &filename, &path, method, &args, &body)?;
     // static
     } else {
-        if let Err(e) = serve_static(&mut writer, &filename) {
+        if let Err(e) = serve_static(&mut writer, &path) {
             let _ = client_error(
                 &mut writer,
                 &filename,
-                "403",
-                "Forbidden",
+                Status::Forbidden,
                 "Tiny couldn't read the file",
             );
             return Err(e.into());
@@ -114,7 +148,7 @@ fn doit(stream: &TcpStream) -> Result<(), Box<Error>> {
 
 fn invalid_input<E>(e: E) -> io::Error
 where
-    E: Into<Box<Error + Send + Sync>>,
+    E: Into<Box<dyn Error + Send + Sync>>,
 {
     io::Error::new(io::ErrorKind::InvalidInput, e)
 }
@@ -122,8 +156,7 @@ where
 fn client_error(
     stream: &mut BufWriter<&TcpStream>,
     cause: &str,
-    errnum: &str,
-    shortmsg: &str,
+    status: Status,
     longmsg: &str,
 ) -> Result<(), io::Error> {
     let mut body = vec![];
@@ -140,38 +173,23 @@ fn client_error(
                 </p>
             </body>
         </html>",
-        errnum, shortmsg, longmsg, cause
+        status.code(), status.reason(), longmsg, cause
     )?;
 
-    write!(
-        stream,
-        "HTTP/1.0 {} {}\r\n\
-         Content-type: text/html\r\n\
-         Content-length: {}\r\n\r\n",
-        errnum,
-        shortmsg,
-        body.len()
-    )?;
+    http_1_0(status)
+        .attach_header("Content-type", "text/html")
+        .attach_header("Content-length", &body.len().to_string())
+        .write_to(&mut *stream)?;
     stream.write_all(&body)?;
     stream.flush()?;
     Err(invalid_input(longmsg))
 }
 
-fn parse_request(stream: &mut BufReader<&TcpStream>) -> Result<(String, String), io::Error> {
-    let mut buf: Vec<u8> = vec![];
-    let mut line = String::new();
-    while line != "\r\n" {
-        buf.extend_from_slice(line.as_bytes());
-        line.clear();
-        stream.read_line(&mut line)?;
-    }
-    let mut headers = [httparse::EMPTY_HEADER; 16];
-    let mut req = httparse::Request::new(&mut headers);
-    req.parse(&buf).map_err(|e| invalid_input(e))?;
-    let method = req.method.ok_or(invalid_input("method missing"))?;
-    let uri = req.path.ok_or(invalid_input("uri missing"))?;
-
-    Ok((String::from(method), String::from(uri)))
+// Tiny speaks HTTP/1.0 only
+fn http_1_0(status: Status) -> Response {
+    let mut response = Response::new(status);
+    response.set_minor_version(0);
+    response
 }
 
 fn parse_uri(uri: &str) -> (String, String) {
@@ -204,45 +222,383 @@ fn get_filetype(filename: &str) -> String {
     String::from(filetype)
 }
 
-fn serve_static(stream: &mut BufWriter<&TcpStream>, filename: &str) -> Result<(), io::Error> {
-    let meta = fs::metadata(filename)?;
-    let filetype = get_filetype(filename);
-    write!(
-        stream,
-        "HTTP/1.0 200 OK\r\n\
-         Server: Tiny Web Server\r\n\
-         Content-length: {}\r\n\
-         Content-type: {}\r\n\r\n",
-        meta.len(),
-        filetype
-    )?;
+fn serve_static(stream: &mut BufWriter<&TcpStream>, path: &Path) -> Result<(), io::Error> {
+    let meta = fs::metadata(path)?;
+    let filetype = get_filetype(&path.to_string_lossy());
+    http_1_0(Status::OK)
+        .attach_header("Server", "Tiny Web Server")
+        .attach_header("Content-length", &meta.len().to_string())
+        .attach_header("Content-type", &filetype)
+        .write_to(&mut *stream)?;
 
-    let mut file = fs::File::open(filename)?;
+    let mut file = fs::File::open(path)?;
     io::copy(&mut file, stream)?;
     drop(file);
 
     Ok(())
 }
 
+// run the CGI program and relay its response, error responses are sent by this function
 fn serve_dynamic(
     stream: &mut BufWriter<&TcpStream>,
     filename: &str,
+    path: &Path,
+    method: Method,
     args: &str,
+    body: &[u8],
 ) -> Result<(), io::Error> {
+    let cgi = match run_cgi(filename, path, method, args, body) {
+        Ok(cgi) => cgi,
+        Err(_) => {
+            return client_error(
+                stream,
+                filename,
+                Status::Forbidden,
+                "Tiny couldn't run the CGI program",
+            );
+        }
+    };
+
+    let (status, stdout) = match cgi {
+        Some(output) => output,
+        None => {
+            return client_error(
+                stream,
+                filename,
+                Status::GatewayTimeout,
+                "The CGI program took too long and was killed",
+            );
+        }
+    };
+    if !status.success() {
+        return client_error(
+            stream,
+            filename,
+            Status::InternalServerError,
+            "The CGI program failed",
+        );
+    }
+
+    let (headers, content) = match parse_cgi_output(&stdout) {
+        Some(output) => output,
+        None => {
+            return client_error(
+                stream,
+                filename,
+                Status::InternalServerError,
+                "The CGI program sent a malformed response",
+            );
+        }
+    };
+
+    // the status defaults to 200, or 302 when the program redirects the client
+    let status_line = match headers.get("Status") {
+        Some(status) => status,
+        None if headers.get("Location").is_some() => "302 Found",
+        None => "200 OK",
+    };
     write!(
         stream,
-        "HTTP/1.0 200 OK\r\n\
-         Server: Tiny Web Server\r\n\r\n",
+        "HTTP/1.0 {}\r\n\
+         Server: Tiny Web Server\r\n",
+        status_line
     )?;
+    for (name, value) in &headers {
+        if !name.eq_ignore_ascii_case("Status") && !name.eq_ignore_ascii_case("Content-length") {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+    }
+    write!(stream, "Content-length: {}\r\n\r\n", content.len())?;
+    stream.write_all(content)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+// fork and exec the CGI program with the request in its environment and the body on its stdin
+// returns None if the program was killed for running too long, or kept its output open past the deadline
+fn run_cgi(
+    filename: &str,
+    path: &Path,
+    method: Method,
+    args: &str,
+    body: &[u8],
+) -> Result<Option<(ExitStatus, Vec<u8>)>, io::Error> {
+    // variables are set on the child only, env::set_var would race between threads
+    let mut command = Command::new(path);
+    command
+        .env("GATEWAY_INTERFACE", "CGI/1.1")
+        .env("SERVER_PROTOCOL", "HTTP/1.0")
+        .env("SERVER_SOFTWARE", "Tiny Web Server")
+        .env("REQUEST_METHOD", method.as_str())
+        .env("QUERY_STRING", args)
+        .env("SCRIPT_NAME", &filename[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    if method == Method::POST {
+        command.env("CONTENT_LENGTH", body.len().to_string());
+    }
+    let mut child = command.spawn()?;
+
+    // the pipes are fed and drained by their own threads
+    // a program writing a large response before reading its input would otherwise deadlock
+    let mut stdin = child.stdin.take().expect("stdin piped");
+    let body = body.to_vec();
+    thread::spawn(move || {
+        // the program may exit without reading its input, closing the pipe early
+        let _ = stdin.write_all(&body);
+    });
+    let mut stdout = child.stdout.take().expect("stdout piped");
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = vec![];
+        let _ = tx.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+
+    let deadline = Instant::now() + CGI_TIMEOUT;
+    let status = match wait_until(&mut child, deadline)? {
+        Some(status) => status,
+        None => {
+            child.kill()?;
+            // reap the zombie
+            child.wait()?;
+            return Ok(None);
+        }
+    };
+    // a grandchild left running in the background may hold stdout open after the program exited
+    // the reader thread is abandoned to it once the deadline passes
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    match rx.recv_timeout(timeout) {
+        Ok(output) => Ok(Some((status, output?))),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => panic!("CGI reader thread panicked"),
+    }
+}
+
+// std offers no waitpid with a timeout, the child is polled instead
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>, io::Error> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(CGI_POLL_INTERVAL);
+    }
+}
+
+// split the output of a CGI program into header fields and content, defined by:
+// https://tools.ietf.org/html/rfc3875#section-6
+fn parse_cgi_output(output: &[u8]) -> Option<(Headers, &[u8])> {
+    let mut headers = Headers::new();
+    let mut rest = output;
+    loop {
+        let newline = rest.iter().position(|&b| b == b'\n')?;
+        let line = &rest[..newline];
+        rest = &rest[newline + 1..];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+        let line = String::from_utf8_lossy(line);
+        let colon = line.find(':')?;
+        headers.append(line[..colon].trim(), line[colon + 1..].trim());
+    }
 
-    env::set_var("QUERY_STRING", args);
-    let output = Command::new(filename).arg(args).output()?;
-    if output.status.success() {
-        stream.write_all(&output.stdout)?;
+    // at least one header field is required
+    if headers.is_empty() {
+        None
     } else {
-        let err = String::from_utf8(output.stderr).expect("stderr parsing error");
-        return Err(invalid_input(err));
+        Some((headers, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, Weak};
+
+    #[test]
+    fn cgi_output_lf() {
+        let (headers, content) = parse_cgi_output(b"Content-type: text/plain\n\nhello\n").unwrap();
+        assert_eq!(headers.get("Content-type"), Some("text/plain"));
+        assert_eq!(content, b"hello\n");
     }
 
-    Ok(())
+    #[test]
+    fn cgi_output_crlf() {
+        let output = b"Content-type: text/html\r\nX-Answer:  42 \r\n\r\n<p>\r\n";
+        let (headers, content) = parse_cgi_output(output).unwrap();
+        assert_eq!(headers.get("Content-type"), Some("text/html"));
+        assert_eq!(headers.get("X-Answer"), Some("42"));
+        assert_eq!(content, b"<p>\r\n");
+    }
+
+    #[test]
+    fn cgi_output_malformed() {
+        // no blank line after the header fields
+        assert!(parse_cgi_output(b"Content-type: text/plain\nhello").is_none());
+        assert!(parse_cgi_output(b"Content-type: text/plain").is_none());
+        // no header fields at all
+        assert!(parse_cgi_output(b"\nhello").is_none());
+        assert!(parse_cgi_output(b"").is_none());
+        // not a header field
+        assert!(parse_cgi_output(b"hello\n\n").is_none());
+    }
+
+    #[test]
+    fn cgi_output_status_location() {
+        let (headers, content) = parse_cgi_output(b"Status: 404 Not Found\n\n").unwrap();
+        assert_eq!(headers.get("Status"), Some("404 Not Found"));
+        assert!(content.is_empty());
+
+        let (headers, _) = parse_cgi_output(b"Location: http://example.com/\r\n\r\n").unwrap();
+        assert_eq!(headers.get("Location"), Some("http://example.com/"));
+        assert_eq!(headers.get("Status"), None);
+    }
+
+    const SCRIPTS: &[(&str, &str)] = &[
+        (
+            "env",
+            "printf 'Content-type: text/plain\\n\\n'\n\
+             echo \"$REQUEST_METHOD|$QUERY_STRING|$CONTENT_LENGTH\"\n",
+        ),
+        (
+            "cat",
+            "printf 'Content-type: text/plain\\n\\n'\n\
+             head -c \"$CONTENT_LENGTH\"\n",
+        ),
+        ("redirect", "printf 'Location: /home.html\\n\\n'\n"),
+        ("fail", "printf 'Content-type: text/plain\\n\\n'\nexit 1\n"),
+        ("sleep", "exec sleep 30\n"),
+        ("background", "printf 'Content-type: text/plain\\n\\n'\nsleep 30 &\n"),
+    ];
+
+    // a temporary document root holding the CGI scripts, removed once dropped
+    struct Root(PathBuf);
+
+    impl Root {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let n = NEXT.fetch_add(1, Ordering::Relaxed);
+            let root = env::temp_dir().join(format!("tiny-{}-{}", process::id(), n));
+            let bin = root.join("cgi-bin");
+            fs::create_dir_all(&bin).unwrap();
+            for (name, script) in SCRIPTS {
+                let path = bin.join(name);
+                fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            Root(root)
+        }
+    }
+
+    impl Drop for Root {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // the root is shared by the tests running at the same time
+    // a script written while another test forks a CGI program could leak its open file into the
+    // child, failing exec with ETXTBSY, so scripts are only written when no test holds a root
+    fn root() -> Arc<Root> {
+        static ROOT: Mutex<Weak<Root>> = Mutex::new(Weak::new());
+        let mut shared = ROOT.lock().unwrap();
+        shared.upgrade().unwrap_or_else(|| {
+            let root = Arc::new(Root::new());
+            *shared = Arc::downgrade(&root);
+            root
+        })
+    }
+
+    // the root must be kept alive until the test is over
+    fn spawn_server(mode: Mode) -> (u16, Arc<Root>) {
+        let root = root();
+        let listener = TcpListener::bind(("localhost", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let path = root.0.clone();
+        thread::spawn(move || serve(listener, mode, path));
+        (port, root)
+    }
+
+    fn request(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(("localhost", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn assert_status(response: &str, status: &str) {
+        assert!(
+            response.starts_with(&format!("HTTP/1.0 {}", status)),
+            "expected {}, got {:?}",
+            status,
+            response
+        );
+    }
+
+    fn body(response: &str) -> &str {
+        let idx = response.find("\r\n\r\n").expect("end of header missing");
+        &response[idx + 4..]
+    }
+
+    #[test]
+    fn cgi_environment() {
+        for &mode in &[Mode::Prethreaded, Mode::ThreadPerConnection] {
+            let (port, _root) = spawn_server(mode);
+            let response = request(port, "GET /cgi-bin/env?a=1&b=2 HTTP/1.0\r\n\r\n");
+            assert_status(&response, "200");
+            assert_eq!(body(&response), "GET|a=1&b=2|\n");
+
+            let response = request(
+                port,
+                "POST /cgi-bin/env HTTP/1.0\r\nContent-Length: 5\r\n\r\nhello",
+            );
+            assert_status(&response, "200");
+            assert_eq!(body(&response), "POST||5\n");
+        }
+    }
+
+    #[test]
+    fn cgi_stdin() {
+        let (port, _root) = spawn_server(Mode::ThreadPerConnection);
+        let response = request(
+            port,
+            "POST /cgi-bin/cat HTTP/1.0\r\nContent-Length: 11\r\n\r\nhello world",
+        );
+        assert_status(&response, "200");
+        assert!(response.contains("Content-length: 11\r\n"));
+        assert_eq!(body(&response), "hello world");
+    }
+
+    #[test]
+    fn cgi_redirect() {
+        let (port, _root) = spawn_server(Mode::Prethreaded);
+        let response = request(port, "GET /cgi-bin/redirect HTTP/1.0\r\n\r\n");
+        assert_status(&response, "302");
+        assert!(response.contains("Location: /home.html\r\n"));
+    }
+
+    #[test]
+    fn cgi_failure() {
+        let (port, _root) = spawn_server(Mode::Prethreaded);
+        let response = request(port, "GET /cgi-bin/fail HTTP/1.0\r\n\r\n");
+        assert_status(&response, "500");
+    }
+
+    #[test]
+    fn cgi_timeout() {
+        let (port, _root) = spawn_server(Mode::ThreadPerConnection);
+        let killed = thread::spawn(move || request(port, "GET /cgi-bin/sleep HTTP/1.0\r\n\r\n"));
+        let background = request(port, "GET /cgi-bin/background HTTP/1.0\r\n\r\n");
+        assert_status(&killed.join().unwrap(), "504");
+        assert_status(&background, "504");
+    }
 }
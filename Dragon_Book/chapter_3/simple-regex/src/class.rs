@@ -0,0 +1,170 @@
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// A set of chars kept as sorted, disjoint and non-adjacent inclusive ranges.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+}
+
+fn succ(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => std::char::from_u32(c as u32 + 1),
+    }
+}
+
+fn pred(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        _ => std::char::from_u32(c as u32 - 1),
+    }
+}
+
+impl CharClass {
+    pub fn new<I>(ranges: I) -> Self
+    where
+        I: IntoIterator<Item = (char, char)>,
+    {
+        let mut sorted: Vec<(char, char)> =
+            ranges.into_iter().filter(|(lo, hi)| lo <= hi).collect();
+        sorted.sort();
+
+        let mut ranges: Vec<(char, char)> = vec![];
+        for (lo, hi) in sorted {
+            match ranges.last_mut() {
+                Some((_, last)) if succ(*last).is_none_or(|next| lo <= next) => {
+                    *last = std::cmp::max(*last, hi);
+                }
+                _ => ranges.push((lo, hi)),
+            }
+        }
+
+        CharClass { ranges }
+    }
+
+    pub fn empty() -> Self {
+        CharClass { ranges: vec![] }
+    }
+
+    pub fn single(c: char) -> Self {
+        CharClass {
+            ranges: vec![(c, c)],
+        }
+    }
+
+    /// Any Unicode scalar value but the line feed, i.e. `.`
+    pub fn any() -> Self {
+        CharClass::single('\n').negate()
+    }
+
+    /// `\d`
+    pub fn digit() -> Self {
+        CharClass::new(vec![('0', '9')])
+    }
+
+    /// `\w`
+    pub fn word() -> Self {
+        CharClass::new(vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')])
+    }
+
+    /// `\s`
+    pub fn space() -> Self {
+        CharClass::new(vec![('\t', '\r'), (' ', ' ')])
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|(lo, hi)| {
+                if *hi < c {
+                    std::cmp::Ordering::Less
+                } else if *lo > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    /// All chars not in the class.
+    pub fn negate(&self) -> Self {
+        let mut ranges = vec![];
+        let mut next = Some('\0');
+
+        for (lo, hi) in &self.ranges {
+            if let (Some(from), Some(to)) = (next, pred(*lo)) {
+                if from <= to {
+                    ranges.push((from, to));
+                }
+            }
+            next = succ(*hi);
+        }
+        if let Some(from) = next {
+            ranges.push((from, char::MAX));
+        }
+
+        CharClass { ranges }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        CharClass::new(self.ranges.iter().chain(&other.ranges).cloned())
+    }
+}
+
+impl Display for CharClass {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self.ranges.as_slice() {
+            [(lo, hi)] if lo == hi => write!(f, "{}", lo.escape_debug()),
+            ranges => {
+                write!(f, "[")?;
+                for (lo, hi) in ranges {
+                    if lo == hi {
+                        write!(f, "{}", lo.escape_debug())?;
+                    } else {
+                        write!(f, "{}-{}", lo.escape_debug(), hi.escape_debug())?;
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Debug for CharClass {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        Display::fmt(self, f)
+    }
+}
+
+#[test]
+fn char_class_test() {
+    let class = CharClass::new(vec![('a', 'f'), ('0', '9'), ('c', 'k'), ('l', 'l')]);
+    assert_eq!(class.ranges(), &[('0', '9'), ('a', 'l')]);
+    assert!(class.contains('5'));
+    assert!(class.contains('l'));
+    assert!(!class.contains('m'));
+
+    let negated = class.negate();
+    assert!(!negated.contains('a'));
+    assert!(negated.contains('é'));
+    assert!(negated.contains(char::MAX));
+    assert_eq!(negated.negate(), class);
+
+    let any = CharClass::any();
+    assert!(any.contains('\u{1F600}'));
+    assert!(!any.contains('\n'));
+    assert_eq!(
+        any.union(&CharClass::single('\n')).negate(),
+        CharClass::empty()
+    );
+}
@@ -1,4 +1,5 @@
-use crate::parser::Regex;
+use crate::class::CharClass;
+use crate::parser::{ErrorKind, ParseError, Regex};
 use disjoint_sets::UnionFind;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Formatter};
@@ -21,7 +22,13 @@ impl DFA {
         }
     }
 
-    pub fn from_regex(regex: &Regex, alphabet: &str) -> Self {
+    /// Anchors are rejected, the position construction has no way to express them. A regex built
+    /// by hand has no positions, so the error points at 0.
+    pub fn from_regex(regex: &Regex, alphabet: &str) -> Result<Self, ParseError> {
+        if regex.is_anchored() {
+            return Err(ParseError::new(0, ErrorKind::AnchorsUnsupported));
+        }
+
         let mut env = HashMap::new();
         let pos_tree = PosTree::from_regex(regex, &mut env, 0).wrap_end(&mut env);
         let mut follow_pos = HashMap::new();
@@ -46,8 +53,8 @@ impl DFA {
                 let next = sorted(
                     &states
                         .iter()
-                        .filter(|state| env[state] == symbol)
-                        .flat_map(|state| &follow_pos[&state])
+                        .filter(|state| env[state].contains(symbol))
+                        .flat_map(|state| &follow_pos[state])
                         .cloned()
                         .collect(),
                 );
@@ -68,18 +75,18 @@ impl DFA {
             }
         }
 
-        dfa
+        Ok(dfa)
     }
 
-    pub fn parse(regex: &str, alphabet: &str) -> Self {
-        DFA::from_regex(&Regex::parse(regex), alphabet)
+    pub fn parse(regex: &str, alphabet: &str) -> Result<Self, ParseError> {
+        DFA::from_regex(&Regex::parse_unanchored(regex)?, alphabet)
     }
 
     pub fn size(&self) -> usize {
         self.map.len()
     }
 
-    pub fn init(&self) -> DFAState {
+    pub fn init(&self) -> DFAState<'_> {
         DFAState::new(self)
     }
 
@@ -127,7 +134,7 @@ impl DFA {
         state.accepted()
     }
 
-    fn state_trace(&self, string: &str) -> Vec<(usize, DFAState)> {
+    fn state_trace(&self, string: &str) -> Vec<(usize, DFAState<'_>)> {
         let mut stack = vec![(0, self.init())];
 
         for (i, c) in string.char_indices() {
@@ -283,7 +290,7 @@ impl Debug for DFA {
             if !labels.is_empty() {
                 write!(f, "    {}: ", from)?;
                 f.debug_set().entries(labels).finish()?;
-                writeln!(f, "")?;
+                writeln!(f)?;
             }
             for (symbol, to) in trans {
                 writeln!(f, "    δ({}, {}) = {}", from, symbol, to)?;
@@ -344,7 +351,7 @@ impl<'a> DFAState<'a> {
 enum SynNode {
     End,
     Empty,
-    Literal(State),
+    Literal,
     Kleene(Box<PosTree>),
     Union(Box<PosTree>, Box<PosTree>),
     Concat(Box<PosTree>, Box<PosTree>),
@@ -359,7 +366,7 @@ struct PosTree {
 }
 
 impl PosTree {
-    fn from_regex(regex: &Regex, env: &mut HashMap<State, char>, state: State) -> Self {
+    fn from_regex(regex: &Regex, env: &mut HashMap<State, CharClass>, state: State) -> Self {
         match regex {
             Regex::Empty => PosTree {
                 size: 0,
@@ -368,17 +375,9 @@ impl PosTree {
                 last_pos: HashSet::new(),
                 node: SynNode::Empty,
             },
-            Regex::Literal(symbol) => {
-                env.insert(state, *symbol);
-
-                PosTree {
-                    size: 1,
-                    nullable: false,
-                    first_pos: [state].iter().cloned().collect(),
-                    last_pos: [state].iter().cloned().collect(),
-                    node: SynNode::Literal(state),
-                }
-            }
+            Regex::Literal(symbol) => PosTree::position(CharClass::single(*symbol), env, state),
+            Regex::Class(class) => PosTree::position(class.clone(), env, state),
+            Regex::Start | Regex::End => unreachable!("rejected by DFA::from_regex"),
             Regex::Kleene(inner) => {
                 let inner = PosTree::from_regex(inner, env, state);
                 PosTree {
@@ -410,6 +409,18 @@ impl PosTree {
         }
     }
 
+    fn position(class: CharClass, env: &mut HashMap<State, CharClass>, state: State) -> Self {
+        env.insert(state, class);
+
+        PosTree {
+            size: 1,
+            nullable: false,
+            first_pos: [state].iter().cloned().collect(),
+            last_pos: [state].iter().cloned().collect(),
+            node: SynNode::Literal,
+        }
+    }
+
     fn concat(self, rhs: Self) -> Self {
         let lhs = self;
         PosTree {
@@ -433,7 +444,7 @@ impl PosTree {
         self.size - 1
     }
 
-    fn wrap_end(self, env: &mut HashMap<State, char>) -> Self {
+    fn wrap_end(self, env: &mut HashMap<State, CharClass>) -> Self {
         let end = PosTree {
             size: 1,
             nullable: false,
@@ -442,7 +453,8 @@ impl PosTree {
             node: SynNode::End,
         };
 
-        env.insert(self.size, '#');
+        // the end marker # matches no char
+        env.insert(self.size, CharClass::empty());
 
         self.concat(end)
     }
@@ -453,10 +465,7 @@ impl PosTree {
                 inner.populate_follow_pos(follow_pos);
 
                 for i in &self.last_pos {
-                    follow_pos
-                        .entry(*i)
-                        .or_insert_with(HashSet::new)
-                        .extend(&self.first_pos);
+                    follow_pos.entry(*i).or_default().extend(&self.first_pos);
                 }
             }
             SynNode::Concat(lhs, rhs) => {
@@ -464,10 +473,7 @@ impl PosTree {
                 rhs.populate_follow_pos(follow_pos);
 
                 for i in &lhs.last_pos {
-                    follow_pos
-                        .entry(*i)
-                        .or_insert_with(HashSet::new)
-                        .extend(&rhs.first_pos);
+                    follow_pos.entry(*i).or_default().extend(&rhs.first_pos);
                 }
             }
             SynNode::Union(lhs, rhs) => {
@@ -487,14 +493,14 @@ fn sorted(set: &HashSet<State>) -> Vec<State> {
 
 #[test]
 fn dfa_construction_test() {
-    let dfa = DFA::parse("(a|b)*abb", "ab");
+    let dfa = DFA::parse("(a|b)*abb", "ab").unwrap();
 
     assert_eq!(dfa.map.len(), 4);
 }
 
 #[test]
 fn dfa_accept_test() {
-    let mut dfa = DFA::parse("(a|b)*abb(a|b)*", "ab");
+    let mut dfa = DFA::parse("(a|b)*abb(a|b)*", "ab").unwrap();
 
     assert!(dfa.accept("abb"));
     assert!(dfa.accept("aabbabbabab"));
@@ -510,7 +516,7 @@ fn dfa_accept_test() {
     assert!(!dfa.accept(""));
     assert!(!dfa.accept("ababababab"));
 
-    dfa = DFA::parse("(a|b)*a(a|b)(a|b)", "ab");
+    dfa = DFA::parse("(a|b)*a(a|b)(a|b)", "ab").unwrap();
 
     assert!(dfa.accept("abaab"));
     assert!(dfa.accept("abababb"));
@@ -527,7 +533,7 @@ fn dfa_accept_test() {
 
 #[test]
 fn dfa_capture_test() {
-    let mut dfa = DFA::parse("(a|b)*abb(a|b)*", "ab");
+    let mut dfa = DFA::parse("(a|b)*abb(a|b)*", "ab").unwrap();
 
     assert_eq!(dfa.capture("ababbababccccc"), Some("ababbabab"));
     assert_eq!(dfa.capture("ababab"), None);
@@ -542,10 +548,22 @@ fn dfa_capture_test() {
 fn lookahead_test() {
     use crate::nfa::NFA;
 
-    let (lhs, rhs) = NFA::parse_lookahead("(a|ab)/ba");
-    let (lexeme, lookahead) = (lhs.to_dfa("ab").1, rhs.to_dfa("ab").1);
+    let (lhs, rhs) = NFA::parse_lookahead("(a|ab)/ba").unwrap();
+    let (lexeme, lookahead) = (lhs.to_dfa("ab").unwrap().1, rhs.to_dfa("ab").unwrap().1);
 
     assert_eq!(lexeme.capture_lookahead(&lookahead, "aba"), Some("a"));
     assert_eq!(lexeme.capture_lookahead(&lookahead, "abba"), Some("ab"));
     assert_eq!(lexeme.capture_lookahead(&lookahead, "baba"), None);
 }
+
+#[test]
+fn dfa_anchors_test() {
+    use crate::nfa::NFA;
+
+    assert_eq!(
+        DFA::parse("^a", "a").unwrap_err(),
+        ParseError::new(0, ErrorKind::AnchorsUnsupported)
+    );
+    assert!(DFA::parse("a|b$", "ab").is_err());
+    assert!(NFA::parse("a$").unwrap().to_dfa("a").is_err());
+}
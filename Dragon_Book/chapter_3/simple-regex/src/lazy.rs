@@ -0,0 +1,416 @@
+//! A DFA built from the NFA by subset construction on demand, one transition at a time as the
+//! input asks for it, so no alphabet has to be enumerated up front. The cache of DFA states is
+//! bounded: when it's full, it's thrown away and rebuilt from the states still in use.
+//!
+//! To search in a single scan, a DFA state is a list of NFA state sets ordered by where their
+//! match started, earliest first, and while no match is found a fresh start is added after every
+//! char. An NFA state is kept only in the earliest set it appears in, the earliest start wins
+//! the leftmost match anyway, so the lists are finite and cached like plain subsets.
+
+use crate::dfa::State;
+use crate::nfa::{NFAState, NFA};
+use crate::parser::ParseError;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+struct Transition {
+    to: usize,
+    /// for each set of the target, the set of the source it came from, or None if it started
+    /// right after the char
+    origins: Vec<Option<usize>>,
+}
+
+struct CachedState {
+    groups: Vec<NFAState>,
+    /// whether new matches may still start
+    searching: bool,
+    /// earliest set accepting if the input goes on
+    accept_within: Option<usize>,
+    /// earliest set accepting if the input ends here
+    accept_at_end: Option<usize>,
+    /// None for the dead state
+    next: HashMap<char, Option<Transition>>,
+}
+
+type Key = (Vec<(Vec<State>, bool)>, bool);
+
+#[derive(Default)]
+struct Cache {
+    states: Vec<CachedState>,
+    index: HashMap<Key, usize>,
+    /// initial states by (at the start of input, searching)
+    starts: [Option<usize>; 4],
+    flushes: usize,
+}
+
+impl Cache {
+    fn clear(&mut self) {
+        self.states.clear();
+        self.index.clear();
+        self.starts = [None; 4];
+        self.flushes += 1;
+    }
+}
+
+pub struct LazyDFA {
+    nfa: NFA,
+    capacity: usize,
+    cache: RefCell<Cache>,
+}
+
+impl LazyDFA {
+    pub fn new(nfa: NFA) -> Self {
+        LazyDFA::with_capacity(nfa, DEFAULT_CAPACITY)
+    }
+
+    /// At most `capacity` DFA states are kept at any time.
+    pub fn with_capacity(nfa: NFA, capacity: usize) -> Self {
+        LazyDFA {
+            nfa,
+            capacity: std::cmp::max(capacity, 1),
+            cache: RefCell::new(Cache::default()),
+        }
+    }
+
+    pub fn parse(regex: &str) -> Result<Self, ParseError> {
+        NFA::parse(regex).map(LazyDFA::new)
+    }
+
+    /// Number of DFA states currently built.
+    pub fn cached_states(&self) -> usize {
+        self.cache.borrow().states.len()
+    }
+
+    /// Number of times the cache was full and thrown away.
+    pub fn flushes(&self) -> usize {
+        self.cache.borrow().flushes
+    }
+
+    fn insert(&self, cache: &mut Cache, groups: Vec<NFAState>, searching: bool) -> usize {
+        let key = (
+            groups
+                .iter()
+                .map(|group| (group.sorted(), group.at_start()))
+                .collect(),
+            searching,
+        );
+        if let Some(id) = cache.index.get(&key) {
+            return *id;
+        }
+
+        if cache.states.len() >= self.capacity {
+            cache.clear();
+        }
+
+        let id = cache.states.len();
+        cache.states.push(CachedState {
+            accept_within: groups.iter().position(|group| group.matched(false)),
+            accept_at_end: groups.iter().position(|group| group.matched(true)),
+            groups,
+            searching,
+            next: HashMap::new(),
+        });
+        cache.index.insert(key, id);
+        id
+    }
+
+    fn start(&self, cache: &mut Cache, at_start: bool, searching: bool) -> usize {
+        let slot = at_start as usize * 2 + searching as usize;
+        if let Some(id) = cache.starts[slot] {
+            return id;
+        }
+
+        let id = self.insert(cache, vec![self.nfa.init_at(at_start)], searching);
+        cache.starts[slot] = Some(id);
+        id
+    }
+
+    /// Moves on `c` and the start of each set along with it, `after` is the position following
+    /// `c`. The id of `from` is no longer valid after the call, the cache may have been cleared.
+    fn step(
+        &self,
+        cache: &mut Cache,
+        from: usize,
+        c: char,
+        after: usize,
+        starts: &mut Vec<usize>,
+    ) -> Option<usize> {
+        if !cache.states[from].next.contains_key(&c) {
+            let transition = self.transition(cache, from, c);
+            if let Some(Transition { to, origins }) = transition {
+                shift(starts, &origins, after);
+                return Some(to);
+            }
+            return None;
+        }
+
+        let Transition { to, origins } = cache.states[from].next[&c].as_ref()?;
+        shift(starts, origins, after);
+        Some(*to)
+    }
+
+    fn transition(&self, cache: &mut Cache, from: usize, c: char) -> Option<Transition> {
+        let state = &cache.states[from];
+        let searching = state.searching;
+        let mut seen = HashSet::new();
+        let mut groups = vec![];
+        let mut origins = vec![];
+
+        for (i, group) in state.groups.iter().enumerate() {
+            let next = group.next(c).without(&mut seen);
+            if !next.is_empty() {
+                groups.push(next);
+                origins.push(Some(i));
+            }
+        }
+        if searching {
+            let fresh = self.nfa.init_at(false).without(&mut seen);
+            if !fresh.is_empty() {
+                groups.push(fresh);
+                origins.push(None);
+            }
+        }
+
+        if groups.is_empty() {
+            cache.states[from].next.insert(c, None);
+            return None;
+        }
+
+        let flushes = cache.flushes;
+        let to = self.insert(cache, groups, searching);
+        if cache.flushes == flushes {
+            let cached = Transition {
+                to,
+                origins: origins.clone(),
+            };
+            cache.states[from].next.insert(c, Some(cached));
+        }
+        Some(Transition { to, origins })
+    }
+
+    /// Keeps the first `len` sets and stops new matches from starting.
+    fn prune(&self, cache: &mut Cache, id: usize, len: usize) -> usize {
+        let state = &cache.states[id];
+        if !state.searching && state.groups.len() <= len {
+            return id;
+        }
+
+        let groups = state.groups[..len].to_vec();
+        self.insert(cache, groups, false)
+    }
+
+    /// The leftmost-longest match starting at byte `start`, or at any later position if
+    /// `searching`, found in one scan of the text.
+    fn search<'t>(&self, text: &'t str, start: usize, searching: bool) -> Option<Match<'t>> {
+        let mut cache = self.cache.borrow_mut();
+        let mut state = self.start(&mut cache, start == 0, searching);
+        let mut starts = vec![start];
+        let mut found = None;
+        let mut pos = start;
+        let mut chars = text[start..].chars();
+
+        loop {
+            let accepting = if pos == text.len() {
+                cache.states[state].accept_at_end
+            } else {
+                cache.states[state].accept_within
+            };
+
+            // a later start only matters while nothing earlier has matched
+            if let Some(group) = accepting {
+                found = Some(Match::new(text, starts[group], pos));
+                state = self.prune(&mut cache, state, group + 1);
+                starts.truncate(group + 1);
+            }
+
+            let c = match chars.next() {
+                Some(c) => c,
+                None => break,
+            };
+            pos += c.len_utf8();
+
+            match self.step(&mut cache, state, c, pos, &mut starts) {
+                Some(next) => state = next,
+                None => break,
+            }
+        }
+
+        found
+    }
+
+    pub fn accept(&self, text: &str) -> bool {
+        self.search(text, 0, false)
+            .is_some_and(|m| m.end() == text.len())
+    }
+
+    /// The leftmost-longest match starting at or after byte `start`.
+    pub fn find_at<'t>(&self, text: &'t str, start: usize) -> Option<Match<'t>> {
+        self.search(text, start, true)
+    }
+
+    pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        self.find_at(text, 0)
+    }
+
+    /// Successive non-overlapping matches, an empty match right after the previous match is
+    /// skipped.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        Matches {
+            dfa: self,
+            text,
+            pos: Some(0),
+            last_end: None,
+        }
+    }
+}
+
+fn shift(starts: &mut Vec<usize>, origins: &[Option<usize>], after: usize) {
+    *starts = origins
+        .iter()
+        .map(|origin| origin.map_or(after, |i| starts[i]))
+        .collect();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn new(text: &'t str, start: usize, end: usize) -> Self {
+        Match { text, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.range()]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+pub struct Matches<'r, 't> {
+    dfa: &'r LazyDFA,
+    text: &'t str,
+    /// None when the whole text has been searched
+    pos: Option<usize>,
+    last_end: Option<usize>,
+}
+
+impl<'r, 't> Matches<'r, 't> {
+    fn after(&self, pos: usize) -> Option<usize> {
+        self.text[pos..].chars().next().map(|c| pos + c.len_utf8())
+    }
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            let m = self.dfa.find_at(self.text, self.pos?)?;
+
+            if m.is_empty() {
+                self.pos = self.after(m.end());
+                if self.last_end == Some(m.end()) {
+                    continue;
+                }
+            } else {
+                self.pos = Some(m.end());
+            }
+
+            self.last_end = Some(m.end());
+            return Some(m);
+        }
+    }
+}
+
+#[test]
+fn lazy_accept_test() {
+    let dfa = LazyDFA::parse("(a|b)*abb(a|b)*").unwrap();
+
+    assert!(dfa.accept("abb"));
+    assert!(dfa.accept("aabbabbabab"));
+    assert!(!dfa.accept("bba"));
+    assert!(!dfa.accept(""));
+    assert!(!dfa.accept("ababababab"));
+
+    let dfa = LazyDFA::parse("[^\\s]+@[\\w.]+\\.(com|org)").unwrap();
+    assert!(dfa.accept("ünïcødé@example.org"));
+    assert!(!dfa.accept("a b@example.org"));
+    assert!(!dfa.accept("ab@example.net"));
+}
+
+#[test]
+fn find_test() {
+    let dfa = LazyDFA::parse("\\d+(\\.\\d+)?").unwrap();
+    let m = dfa.find("pi ≈ 3.14159, e ≈ 2.7").unwrap();
+    assert_eq!(m.as_str(), "3.14159");
+    assert_eq!(m.range(), 7..14);
+
+    let found: Vec<&str> = dfa
+        .find_iter("pi ≈ 3.14159, e ≈ 2.7, 1.")
+        .map(|m| m.as_str())
+        .collect();
+    assert_eq!(found, vec!["3.14159", "2.7", "1"]);
+
+    let anchored = LazyDFA::parse("^a+|b$").unwrap();
+    let found: Vec<_> = anchored.find_iter("aabaab").map(|m| m.range()).collect();
+    assert_eq!(found, vec![0..2, 5..6]);
+
+    let empty = LazyDFA::parse("a*").unwrap();
+    let found: Vec<_> = empty.find_iter("baaé").map(|m| m.range()).collect();
+    assert_eq!(found, vec![0..0, 1..3, 5..5]);
+
+    assert_eq!(LazyDFA::parse("x").unwrap().find("abc"), None);
+}
+
+#[test]
+fn cache_bound_test() {
+    // (a|b)*a(a|b)^n needs 2^(n+1) states in a DFA
+    let nfa = NFA::parse("(a|b)*a(a|b){5}").unwrap();
+    let dfa = LazyDFA::with_capacity(nfa, 8);
+    let text = "abbabaababbbaabaaabbbabab".repeat(4);
+
+    let expected = text.len() >= 6 && text.as_bytes()[text.len() - 6] == b'a';
+    assert_eq!(dfa.accept(&text), expected);
+    assert!(dfa.accept("aaaaaa"));
+    assert!(!dfa.accept("abbbbbb"));
+    assert!(dfa.cached_states() <= 8);
+    assert!(dfa.flushes() > 0);
+}
+
+#[test]
+fn linear_search_test() {
+    // searching anchored at every position would take some 10^10 steps here
+    let dfa = LazyDFA::parse("a*b").unwrap();
+    let text = "a".repeat(100_000);
+    assert_eq!(dfa.find(&text), None);
+    assert_eq!(dfa.find_iter(&text).count(), 0);
+
+    let text = text + "b";
+    assert_eq!(dfa.find(&text).map(|m| m.range()), Some(0..100_001));
+
+    // the earliest start wins even when a later one accepts first
+    let dfa = LazyDFA::parse("abcd|bc").unwrap();
+    assert_eq!(dfa.find("xabcd").map(|m| m.range()), Some(1..5));
+    assert_eq!(dfa.find("xabce").map(|m| m.range()), Some(2..4));
+}
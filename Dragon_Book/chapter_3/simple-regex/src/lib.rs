@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate pest_derive;
 
+pub mod class;
 pub mod dfa;
+pub mod lazy;
 pub mod nfa;
 pub mod parser;
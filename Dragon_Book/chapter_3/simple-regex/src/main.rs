@@ -12,10 +12,26 @@ fn main() {
 fn exercise_3_7_3() {
     println!("Exercise 3.7.3:");
 
-    println!("{:?}", NFA::parse("(a|b)*").to_dfa("ab").1);
-    println!("{:?}", NFA::parse("(a*|b*)*").to_dfa("ab").1);
-    println!("{:?}", NFA::parse("((ε|a)b*)*").to_dfa("ab").1);
-    println!("{:?}", NFA::parse("(a|b)*abb(a|b)*").to_dfa("ab").1);
+    println!(
+        "{:?}",
+        NFA::parse("(a|b)*").unwrap().to_dfa("ab").unwrap().1
+    );
+    println!(
+        "{:?}",
+        NFA::parse("(a*|b*)*").unwrap().to_dfa("ab").unwrap().1
+    );
+    println!(
+        "{:?}",
+        NFA::parse("((ε|a)b*)*").unwrap().to_dfa("ab").unwrap().1
+    );
+    println!(
+        "{:?}",
+        NFA::parse("(a|b)*abb(a|b)*")
+            .unwrap()
+            .to_dfa("ab")
+            .unwrap()
+            .1
+    );
 }
 
 const DIGIT: &str = "0|1";
@@ -23,9 +39,9 @@ const DIGIT: &str = "0|1";
 fn exercise_3_8_1() {
     println!("Exercise 3.8.1:");
 
-    let nfa = NFA::multi_parse(&[("if", "IF"), ("(i|f)(i|f)*", "ID")]);
+    let nfa = NFA::multi_parse(&[("if", "IF"), ("(i|f)(i|f)*", "ID")]).unwrap();
     println!("{:?}", nfa);
-    println!("{:?}", nfa.to_dfa("abif").1);
+    println!("{:?}", nfa.to_dfa("abif").unwrap().1);
 }
 
 fn exercise_3_8_2() {
@@ -36,28 +52,39 @@ fn exercise_3_8_2() {
         ("while", "WHILE"),
         ("when", "WHEN"),
         (&format!("{}({}|{})*", LETTER, LETTER, DIGIT), "ID"),
-    ]);
+    ])
+    .unwrap();
 
     println!("{:?}", nfa);
-    println!("{:?}", nfa.to_dfa("whilen01").1);
+    println!("{:?}", nfa.to_dfa("whilen01").unwrap().1);
 }
 
 fn exercise_3_9_2() {
     println!("Exercise 3.9.2:");
 
-    println!("{:?}", DFA::parse("(a|b)*", "ab"));
-    println!("{:?}", DFA::parse("(a*|b*)*", "ab"));
-    println!("{:?}", DFA::parse("((ε|a)b*)*", "ab"));
-    println!("{:?}", DFA::parse("(a|b)*abb(a|b)*", "ab"));
+    println!("{:?}", DFA::parse("(a|b)*", "ab").unwrap());
+    println!("{:?}", DFA::parse("(a*|b*)*", "ab").unwrap());
+    println!("{:?}", DFA::parse("((ε|a)b*)*", "ab").unwrap());
+    println!("{:?}", DFA::parse("(a|b)*abb(a|b)*", "ab").unwrap());
 }
 
 fn exercise_3_9_4() {
     println!("Exercise 3.9.4:");
 
-    println!("{:?}", DFA::parse("(a|b)*a(a|b)", "ab").minimize("ab"));
-    println!("{:?}", DFA::parse("(a|b)*a(a|b)(a|b)", "ab").minimize("ab"));
     println!(
         "{:?}",
-        DFA::parse("(a|b)*a(a|b)(a|b)(a|b)", "ab").minimize("ab")
+        DFA::parse("(a|b)*a(a|b)", "ab").unwrap().minimize("ab")
+    );
+    println!(
+        "{:?}",
+        DFA::parse("(a|b)*a(a|b)(a|b)", "ab")
+            .unwrap()
+            .minimize("ab")
+    );
+    println!(
+        "{:?}",
+        DFA::parse("(a|b)*a(a|b)(a|b)(a|b)", "ab")
+            .unwrap()
+            .minimize("ab")
     );
 }
@@ -1,22 +1,50 @@
+use crate::class::CharClass;
 use crate::dfa::{State, DFA, START};
-use crate::parser::Regex;
+use crate::parser::{ErrorKind, ParseError, Regex};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::fmt::{self, Debug, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::rc::Rc;
 
+#[derive(Clone, PartialEq)]
+enum Symbol {
+    /// ε
+    Empty,
+    Class(CharClass),
+    /// `^`, crossed without input at the start of input
+    Start,
+    /// `$`, crossed without input at the end of input
+    End,
+}
+
+impl Symbol {
+    fn is_anchor(&self) -> bool {
+        matches!(self, Symbol::Start | Symbol::End)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Symbol::Empty => write!(f, "ε"),
+            Symbol::Class(class) => write!(f, "{}", class),
+            Symbol::Start => write!(f, "^"),
+            Symbol::End => write!(f, "$"),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 struct Node {
     state: State,
-    symbol: Option<char>,
+    symbol: Symbol,
     label: Option<String>,
     exits: Vec<SharedNode>,
 }
 
 impl Debug for Node {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        let c = if let Some(c) = self.symbol { c } else { 'ε' };
-        write!(f, "δ({}, {}) = ", self.state, c)?;
+        write!(f, "δ({}, {}) = ", self.state, self.symbol)?;
         f.debug_set()
             .entries(self.exits.iter().map(|exit| exit.borrow().state))
             .finish()
@@ -24,7 +52,7 @@ impl Debug for Node {
 }
 
 impl Node {
-    fn new(state: State, symbol: Option<char>) -> Self {
+    fn new(state: State, symbol: Symbol) -> Self {
         Node {
             state,
             symbol,
@@ -33,7 +61,7 @@ impl Node {
         }
     }
 
-    fn new_shared(state: State, symbol: Option<char>) -> SharedNode {
+    fn new_shared(state: State, symbol: Symbol) -> SharedNode {
         let node = Node::new(state, symbol);
         Rc::new(RefCell::new(node))
     }
@@ -56,9 +84,9 @@ pub struct NFA {
 }
 
 impl NFA {
-    fn new(state: State, symbol: Option<char>) -> Self {
+    fn new(state: State, symbol: Symbol) -> Self {
         let start = Node::new_shared(state, symbol);
-        let end = Node::new_shared(state + 1, None);
+        let end = Node::new_shared(state + 1, Symbol::Empty);
         start.borrow_mut().install_exit(&end);
 
         NFA {
@@ -71,13 +99,16 @@ impl NFA {
     pub fn from_regex(regex: &Regex, state: State) -> Self {
         use self::Regex::*;
         match regex {
-            Empty => NFA::new(state, None),
-            Literal(c) => NFA::new(state, Some(*c)),
+            Empty => NFA::new(state, Symbol::Empty),
+            Literal(c) => NFA::new(state, Symbol::Class(CharClass::single(*c))),
+            Class(class) => NFA::new(state, Symbol::Class(class.clone())),
+            Start => NFA::new(state, Symbol::Start),
+            End => NFA::new(state, Symbol::End),
             Union(l, r) => {
-                let start = Node::new_shared(state, None);
+                let start = Node::new_shared(state, Symbol::Empty);
                 let lhs = NFA::from_regex(l, state + 1);
                 let rhs = NFA::from_regex(r, state + lhs.size + 1);
-                let end = Node::new_shared(state + lhs.size + rhs.size + 1, None);
+                let end = Node::new_shared(state + lhs.size + rhs.size + 1, Symbol::Empty);
 
                 start.borrow_mut().install_exit(&lhs.start);
                 start.borrow_mut().install_exit(&rhs.start);
@@ -103,9 +134,9 @@ impl NFA {
                 }
             }
             Kleene(inner) => {
-                let start = Node::new_shared(state, None);
+                let start = Node::new_shared(state, Symbol::Empty);
                 let single = NFA::from_regex(inner, state + 1);
-                let end = Node::new_shared(state + single.size + 1, None);
+                let end = Node::new_shared(state + single.size + 1, Symbol::Empty);
 
                 start.borrow_mut().install_exit(&single.start);
                 start.borrow_mut().install_exit(&end);
@@ -121,51 +152,71 @@ impl NFA {
         }
     }
 
-    pub fn parse(regex: &str) -> Self {
-        NFA::from_regex(&Regex::parse(regex), START)
+    pub fn parse(regex: &str) -> Result<Self, ParseError> {
+        Regex::parse(regex).map(|regex| NFA::from_regex(&regex, START))
     }
 
     pub fn install_label(&mut self, label: &str) {
         self.end.borrow_mut().install_label(label);
     }
 
-    pub fn multi_parse(parts: &[(&str, &str)]) -> Self {
-        let start = Node::new_shared(START, None);
+    pub fn multi_parse(parts: &[(&str, &str)]) -> Result<Self, ParseError> {
+        let start = Node::new_shared(START, Symbol::Empty);
         let mut state = 1;
 
-        let branches: Vec<NFA> = parts
+        let branches = parts
             .iter()
             .map(|(regex, label)| {
-                let mut nfa = NFA::from_regex(&Regex::parse(regex), state);
+                let mut nfa = NFA::from_regex(&Regex::parse(regex)?, state);
                 state += nfa.size;
                 nfa.install_label(label);
-                nfa
+                Ok(nfa)
             })
-            .collect();
+            .collect::<Result<Vec<NFA>, ParseError>>()?;
 
-        let end = Node::new_shared(state, None);
+        let end = Node::new_shared(state, Symbol::Empty);
 
         for branch in branches.iter() {
             start.borrow_mut().install_exit(&branch.start);
             branch.end.borrow_mut().install_exit(&end);
         }
 
-        NFA {
+        Ok(NFA {
             start,
             end,
             size: state + 1,
-        }
+        })
     }
 
-    pub fn parse_lookahead(regex: &str) -> (NFA, NFA) {
+    pub fn parse_lookahead(regex: &str) -> Result<(NFA, NFA), ParseError> {
         let mut parts = regex.split('/');
         let lhs = parts.next().unwrap();
         let rhs = parts.next().unwrap();
-        (NFA::parse(lhs), NFA::parse(rhs))
+        Ok((NFA::parse(lhs)?, NFA::parse(rhs)?))
     }
 
     pub fn init(&self) -> NFAState {
-        NFAState::new(self)
+        self.init_at(true)
+    }
+
+    /// The initial state of a match starting at or after the start of input.
+    pub(crate) fn init_at(&self, at_start: bool) -> NFAState {
+        NFAState::new(self, at_start)
+    }
+
+    fn nodes(&self) -> Vec<SharedNode> {
+        let mut stack = vec![self.start.clone()];
+        let mut seen = HashSet::new();
+        let mut nodes = vec![];
+
+        while let Some(node) = stack.pop() {
+            if seen.insert(node.borrow().state) {
+                stack.extend(node.borrow().exits.iter().cloned());
+                nodes.push(node);
+            }
+        }
+
+        nodes
     }
 
     pub fn accept(&self, string: &str) -> bool {
@@ -176,7 +227,17 @@ impl NFA {
         state.accepted()
     }
 
-    pub fn to_dfa(&self, alphabet: &str) -> (HashMap<Vec<State>, State>, DFA) {
+    /// Anchors are rejected, a state of the DFA doesn't know whether it's at the start of input.
+    /// The NFA keeps no positions, so the error points at 0.
+    pub fn to_dfa(&self, alphabet: &str) -> Result<(HashMap<Vec<State>, State>, DFA), ParseError> {
+        if self
+            .nodes()
+            .iter()
+            .any(|node| node.borrow().symbol.is_anchor())
+        {
+            return Err(ParseError::new(0, ErrorKind::AnchorsUnsupported));
+        }
+
         let init = self.init();
         let mut set_to_state: HashMap<Vec<State>, State> = HashMap::new();
         set_to_state.insert(init.sorted(), START);
@@ -209,7 +270,7 @@ impl NFA {
             }
         }
 
-        (set_to_state, dfa)
+        Ok((set_to_state, dfa))
     }
 }
 
@@ -221,7 +282,7 @@ impl Debug for NFA {
         while let Some(node) = stack.pop() {
             let borrow = node.borrow();
             if let Some(ref label) = borrow.label {
-                println!("{}: {}", borrow.state, label);
+                writeln!(f, "{}: {}", borrow.state, label)?;
             }
             if !borrow.exits.is_empty() {
                 writeln!(f, "{:?}", borrow)?;
@@ -238,22 +299,25 @@ impl Debug for NFA {
     }
 }
 
+#[derive(Clone)]
 pub struct NFAState {
     states: Vec<SharedNode>,
     accept: State,
+    at_start: bool,
 }
 
 impl NFAState {
-    fn new(nfa: &NFA) -> Self {
+    fn new(nfa: &NFA, at_start: bool) -> Self {
         let state = NFAState {
             states: vec![nfa.start.clone()],
             accept: nfa.end.borrow().state,
+            at_start,
         };
 
-        state.extend_with_empty()
+        state.extend_with_empty(false)
     }
 
-    fn is_empty(&self) -> bool {
+    pub(crate) fn is_empty(&self) -> bool {
         self.states.is_empty()
     }
 
@@ -270,13 +334,45 @@ impl NFAState {
         states
     }
 
+    pub(crate) fn at_start(&self) -> bool {
+        self.at_start
+    }
+
+    /// The states not yet in `seen`, which are added to it.
+    pub(crate) fn without(self, seen: &mut HashSet<State>) -> Self {
+        let states = self
+            .states
+            .into_iter()
+            .filter(|node| seen.insert(node.borrow().state))
+            .collect();
+
+        NFAState {
+            states,
+            accept: self.accept,
+            at_start: self.at_start,
+        }
+    }
+
     pub fn next(&self, c: char) -> Self {
         let state = self.consume(c);
-        state.extend_with_empty()
+        state.extend_with_empty(false)
     }
 
+    /// Whether the input read so far is accepted when it ends here.
     pub fn accepted(&self) -> bool {
-        self.states
+        self.matched(true)
+    }
+
+    /// Whether the input read so far is accepted, `$` holds only if it ends here.
+    pub(crate) fn matched(&self, at_end: bool) -> bool {
+        let state = if at_end {
+            self.extend_with_empty(true)
+        } else {
+            self.clone()
+        };
+
+        state
+            .states
             .iter()
             .any(|node| node.borrow().state == self.accept)
     }
@@ -287,10 +383,9 @@ impl NFAState {
             .iter()
             .flat_map(|state| {
                 let borrow = state.borrow();
-                if borrow.symbol == Some(c) {
-                    borrow.exits.clone()
-                } else {
-                    vec![]
+                match &borrow.symbol {
+                    Symbol::Class(class) if class.contains(c) => borrow.exits.clone(),
+                    _ => vec![],
                 }
             })
             .collect();
@@ -298,19 +393,30 @@ impl NFAState {
         NFAState {
             states,
             accept: self.accept,
+            at_start: false,
         }
     }
 
-    fn extend_with_empty(&self) -> Self {
+    fn extend_with_empty(&self, at_end: bool) -> Self {
         let mut seen: HashSet<usize> = HashSet::new();
         let mut states: Vec<SharedNode> = vec![];
         let mut stack = self.states.clone();
 
         while let Some(node) = stack.pop() {
             let borrow = node.borrow();
-            seen.insert(borrow.state);
+            // a node may be pushed along two ε-paths before it's visited
+            if !seen.insert(borrow.state) {
+                continue;
+            }
+
+            let crossed = match &borrow.symbol {
+                Symbol::Empty => true,
+                Symbol::Class(_) => false,
+                Symbol::Start => self.at_start,
+                Symbol::End => at_end,
+            };
 
-            if borrow.symbol == None {
+            if crossed {
                 stack.extend(
                     borrow
                         .exits
@@ -327,20 +433,21 @@ impl NFAState {
         NFAState {
             states,
             accept: self.accept,
+            at_start: self.at_start,
         }
     }
 }
 
 #[test]
 fn nfa_construction_test() {
-    let nfa = NFA::parse("(a|b)*");
+    let nfa = NFA::parse("(a|b)*").unwrap();
     // print!("{:?}", nfa);
     assert_eq!(nfa.size, 8);
 }
 
 #[test]
 fn nfa_accept_test() {
-    let nfa = NFA::parse("(a|b)*abb(a|b)*");
+    let nfa = NFA::parse("(a|b)*abb(a|b)*").unwrap();
 
     assert!(nfa.accept("abb"));
     assert!(nfa.accept("aabbabbabab"));
@@ -348,7 +455,7 @@ fn nfa_accept_test() {
     assert!(!nfa.accept(""));
     assert!(!nfa.accept("ababababab"));
 
-    let mut dfa = nfa.to_dfa("ab").1;
+    let mut dfa = nfa.to_dfa("ab").unwrap().1;
 
     assert!(dfa.accept("abb"));
     assert!(dfa.accept("aabbabbabab"));
@@ -374,7 +481,8 @@ fn multi_parse_test() {
         ("while", "WHILE"),
         ("when", "WHEN"),
         (&format!("{}({}|{})*", LETTER, LETTER, DIGIT), "ID"),
-    ]);
+    ])
+    .unwrap();
 
     assert!(nfa.accept("while"));
     assert!(nfa.accept("when"));
@@ -382,7 +490,7 @@ fn multi_parse_test() {
     assert!(!nfa.accept("0101"));
     assert!(nfa.accept("w101"));
 
-    let mut dfa = nfa.to_dfa("whilen01").1;
+    let mut dfa = nfa.to_dfa("whilen01").unwrap().1;
 
     assert!(dfa.accept("while"));
     assert!(dfa.accept("when"));
@@ -398,3 +506,22 @@ fn multi_parse_test() {
     assert!(!dfa.accept("0101"));
     assert!(dfa.accept("w101"));
 }
+
+#[test]
+fn nfa_extended_test() {
+    let nfa = NFA::parse("^[a-c]+\\d{2,3}$|x.?").unwrap();
+
+    assert!(nfa.accept("ab12"));
+    assert!(nfa.accept("c123"));
+    assert!(!nfa.accept("c1234"));
+    assert!(!nfa.accept("d12"));
+    assert!(nfa.accept("x"));
+    assert!(nfa.accept("x😀"));
+    assert!(!nfa.accept("x\n"));
+
+    // an anchor in the middle of the regex can never be crossed
+    assert!(!NFA::parse("a^b").unwrap().accept("ab"));
+    assert!(!NFA::parse("a$b").unwrap().accept("ab"));
+    assert!(NFA::parse("(^a|b)*$").unwrap().accept("abb"));
+    assert!(!NFA::parse("(^a|b)*$").unwrap().accept("ba"));
+}
@@ -1,42 +1,148 @@
+use crate::class::CharClass;
+use pest::error::InputLocation;
 use pest::iterators::Pair;
 use pest::Parser;
+use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, PartialEq)]
+/// Counted repetitions are expanded into copies, so their bounds are capped.
+pub const MAX_REPEAT: u32 = 1000;
+/// Nested repetitions multiply, so the nodes all expansions of a regex create are capped too.
+pub const MAX_EXPANSION: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Regex {
     Empty,
     Literal(char),
+    Class(CharClass),
+    /// `^`, matches the empty string at the start of input
+    Start,
+    /// `$`, matches the empty string at the end of input
+    End,
     Kleene(Box<Regex>),
     Union(Box<Regex>, Box<Regex>),
     Concat(Box<Regex>, Box<Regex>),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Syntax,
+    /// `[z-a]`
+    InvalidRange(char, char),
+    /// `[\d-z]`
+    ClassInRange,
+    /// `a{3,2}`
+    InvalidRepetition(u32, u32),
+    RepetitionTooLarge,
+    UnknownEscape(char),
+    InvalidCodePoint(String),
+    /// `^` or `$` given to a construction that can't express them
+    AnchorsUnsupported,
+}
+
+/// An error with the byte offset in the regex where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub kind: ErrorKind,
+}
+
+impl ParseError {
+    pub(crate) fn new(pos: usize, kind: ErrorKind) -> Self {
+        ParseError { pos, kind }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        use ErrorKind::*;
+
+        write!(f, "at {}: ", self.pos)?;
+        match &self.kind {
+            Syntax => write!(f, "invalid regex expression"),
+            InvalidRange(lo, hi) => write!(f, "invalid range {}-{}", lo, hi),
+            ClassInRange => write!(f, "character class as range bound"),
+            InvalidRepetition(min, max) => write!(f, "invalid repetition {{{},{}}}", min, max),
+            RepetitionTooLarge => write!(
+                f,
+                "repetition larger than {} or expanding to more than {} nodes",
+                MAX_REPEAT, MAX_EXPANSION
+            ),
+            UnknownEscape(c) => write!(f, "unknown escape \\{}", c),
+            InvalidCodePoint(hex) => write!(f, "invalid code point {}", hex),
+            AnchorsUnsupported => write!(f, "anchors are only supported by NFA and LazyDFA"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+enum Escaped {
+    Char(char),
+    Class(CharClass),
+}
+
 impl Regex {
-    pub fn parse(input: &str) -> Self {
-        let pair = RegexParser::parse(Rule::union, input)
-            .expect("Error: Invalid regex expression")
-            .next()
-            .unwrap();
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        Regex::from_pair(syntax(input)?, &mut 0)
+    }
+
+    /// Same as `parse`, but `^` and `$` are errors.
+    pub fn parse_unanchored(input: &str) -> Result<Self, ParseError> {
+        let pair = syntax(input)?;
+        let anchor = pair
+            .clone()
+            .into_inner()
+            .flatten()
+            .find(|inner| inner.as_rule() == Rule::start || inner.as_rule() == Rule::end);
+
+        if let Some(anchor) = anchor {
+            let pos = anchor.as_span().start();
+            return Err(ParseError::new(pos, ErrorKind::AnchorsUnsupported));
+        }
 
-        Regex::from_pair(pair)
+        Regex::from_pair(pair, &mut 0)
+    }
+
+    pub fn is_anchored(&self) -> bool {
+        use Regex::*;
+        match self {
+            Start | End => true,
+            Empty | Literal(_) | Class(_) => false,
+            Kleene(inner) => inner.is_anchored(),
+            Union(lhs, rhs) | Concat(lhs, rhs) => lhs.is_anchored() || rhs.is_anchored(),
+        }
+    }
+
+    /// Number of nodes in the syntax tree.
+    pub fn size(&self) -> usize {
+        use Regex::*;
+        match self {
+            Empty | Literal(_) | Class(_) | Start | End => 1,
+            Kleene(inner) => inner.size() + 1,
+            Union(lhs, rhs) | Concat(lhs, rhs) => lhs.size() + rhs.size() + 1,
+        }
     }
 
-    fn from_pair(pair: Pair<Rule>) -> Self {
-        match pair.as_rule() {
+    /// `expanded` counts the nodes created by repetitions so far.
+    fn from_pair(pair: Pair<Rule>, expanded: &mut usize) -> Result<Self, ParseError> {
+        let regex = match pair.as_rule() {
             Rule::empty => Regex::Empty,
-            Rule::factor => {
-                let c = pair.as_str().chars().next().unwrap();
-                if let Some(inner) = pair.into_inner().next() {
-                    Regex::from_pair(inner)
-                } else {
-                    Regex::Literal(c)
-                }
-            }
+            Rule::any => Regex::Class(CharClass::any()),
+            Rule::start => Regex::Start,
+            Rule::end => Regex::End,
+            Rule::literal => Regex::Literal(pair.as_str().chars().next().unwrap()),
+            Rule::escape => match escape(pair)? {
+                Escaped::Char(c) => Regex::Literal(c),
+                Escaped::Class(class) => Regex::Class(class),
+            },
+            Rule::class => Regex::Class(class(pair)?),
+            Rule::factor => Regex::from_pair(pair.into_inner().next().unwrap(), expanded)?,
             Rule::kleene => {
                 let mut inners = pair.into_inner();
 
-                let factor = Regex::from_pair(inners.next().unwrap());
-                if inners.next().is_some() {
-                    Regex::Kleene(Box::new(factor))
+                let factor = Regex::from_pair(inners.next().unwrap(), expanded)?;
+                if let Some(quantifier) = inners.next() {
+                    factor.quantify(quantifier, expanded)?
                 } else {
                     factor
                 }
@@ -44,9 +150,9 @@ impl Regex {
             Rule::concat => {
                 let mut inners = pair.into_inner();
 
-                let kleene = Regex::from_pair(inners.next().unwrap());
+                let kleene = Regex::from_pair(inners.next().unwrap(), expanded)?;
                 if let Some(rest) = inners.next() {
-                    let concat = Regex::from_pair(rest);
+                    let concat = Regex::from_pair(rest, expanded)?;
                     Regex::Concat(Box::new(kleene), Box::new(concat))
                 } else {
                     kleene
@@ -55,16 +161,182 @@ impl Regex {
             Rule::union => {
                 let mut inners = pair.into_inner();
 
-                let concat = Regex::from_pair(inners.next().unwrap());
+                let concat = Regex::from_pair(inners.next().unwrap(), expanded)?;
                 if let Some(rest) = inners.next() {
-                    let union = Regex::from_pair(rest);
+                    let union = Regex::from_pair(rest, expanded)?;
                     Regex::Union(Box::new(concat), Box::new(union))
                 } else {
                     concat
                 }
             }
             _ => unreachable!(),
+        };
+
+        Ok(regex)
+    }
+
+    fn quantify(self, quantifier: Pair<Rule>, expanded: &mut usize) -> Result<Self, ParseError> {
+        let pos = quantifier.as_span().start();
+        let inner = quantifier.into_inner().next().unwrap();
+
+        let (min, max) = match inner.as_rule() {
+            Rule::star => return Ok(Regex::Kleene(Box::new(self))),
+            Rule::plus => (1, None),
+            Rule::question => (0, Some(1)),
+            Rule::counted => {
+                let mut inners = inner.into_inner();
+                let min = number(inners.next().unwrap())?;
+                let max = match (inners.next(), inners.next()) {
+                    (None, _) => Some(min),
+                    (Some(_), None) => None,
+                    (Some(_), Some(max)) => Some(number(max)?),
+                };
+                (min, max)
+            }
+            _ => unreachable!(),
+        };
+
+        if let Some(max) = max {
+            if max < min {
+                return Err(ParseError::new(pos, ErrorKind::InvalidRepetition(min, max)));
+            }
+        }
+
+        // every copy comes with a concatenation or union node
+        let copies = max.unwrap_or(min + 1) as usize;
+        *expanded = expanded.saturating_add(copies.saturating_mul(self.size() + 2));
+        if *expanded > MAX_EXPANSION {
+            return Err(ParseError::new(pos, ErrorKind::RepetitionTooLarge));
+        }
+
+        Ok(self.repeat(min, max))
+    }
+
+    /// `self{min,max}` desugared into concatenations, unions with ε and a Kleene closure,
+    /// e.g. a{2,4} = aa(a(a)?)? and a{2,} = aaa*.
+    fn repeat(self, min: u32, max: Option<u32>) -> Self {
+        let tail = match max {
+            None => Some(Regex::Kleene(Box::new(self.clone()))),
+            Some(max) => (min..max).fold(None, |rest, _| {
+                let body = concat(Some(self.clone()), rest).unwrap();
+                Some(Regex::Union(Box::new(body), Box::new(Regex::Empty)))
+            }),
+        };
+
+        let head = (0..min).fold(None, |head, _| concat(head, Some(self.clone())));
+        concat(head, tail).unwrap_or(Regex::Empty)
+    }
+}
+
+/// The top level union of a syntactically valid regex.
+fn syntax(input: &str) -> Result<Pair<'_, Rule>, ParseError> {
+    let pair = RegexParser::parse(Rule::regex, input)
+        .map_err(|err| {
+            let pos = match err.location {
+                InputLocation::Pos(pos) => pos,
+                InputLocation::Span((pos, _)) => pos,
+            };
+            ParseError::new(pos, ErrorKind::Syntax)
+        })?
+        .next()
+        .unwrap();
+
+    Ok(pair.into_inner().next().unwrap())
+}
+
+fn concat(lhs: Option<Regex>, rhs: Option<Regex>) -> Option<Regex> {
+    match (lhs, rhs) {
+        (Some(l), Some(r)) => Some(Regex::Concat(Box::new(l), Box::new(r))),
+        (l, r) => l.or(r),
+    }
+}
+
+fn number(pair: Pair<Rule>) -> Result<u32, ParseError> {
+    pair.as_str()
+        .parse::<u32>()
+        .ok()
+        .filter(|n| *n <= MAX_REPEAT)
+        .ok_or_else(|| ParseError::new(pair.as_span().start(), ErrorKind::RepetitionTooLarge))
+}
+
+fn escape(pair: Pair<Rule>) -> Result<Escaped, ParseError> {
+    let pos = pair.as_span().start();
+    let inner = pair.into_inner().next().unwrap();
+
+    if inner.as_rule() == Rule::unicode {
+        let hex = inner.into_inner().next().unwrap().as_str();
+        return u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .map(Escaped::Char)
+            .ok_or_else(|| ParseError::new(pos, ErrorKind::InvalidCodePoint(hex.to_owned())));
+    }
+
+    let escaped = match inner.as_str().chars().next().unwrap() {
+        'n' => Escaped::Char('\n'),
+        't' => Escaped::Char('\t'),
+        'r' => Escaped::Char('\r'),
+        'd' => Escaped::Class(CharClass::digit()),
+        'w' => Escaped::Class(CharClass::word()),
+        's' => Escaped::Class(CharClass::space()),
+        'D' => Escaped::Class(CharClass::digit().negate()),
+        'W' => Escaped::Class(CharClass::word().negate()),
+        'S' => Escaped::Class(CharClass::space().negate()),
+        c if c.is_ascii_punctuation() || c == 'ε' => Escaped::Char(c),
+        c => return Err(ParseError::new(pos, ErrorKind::UnknownEscape(c))),
+    };
+
+    Ok(escaped)
+}
+
+fn class_char(pair: Pair<Rule>) -> Result<Escaped, ParseError> {
+    let inner = pair.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::escape => escape(inner),
+        _ => Ok(Escaped::Char(inner.as_str().chars().next().unwrap())),
+    }
+}
+
+fn class(pair: Pair<Rule>) -> Result<CharClass, ParseError> {
+    let mut negated = false;
+    let mut class = CharClass::empty();
+
+    for inner in pair.into_inner() {
+        if inner.as_rule() == Rule::negated {
+            negated = true;
+            continue;
         }
+
+        let item = inner.into_inner().next().unwrap();
+        let pos = item.as_span().start();
+        let added = match item.as_rule() {
+            Rule::range => {
+                let mut bounds = item.into_inner();
+                match (
+                    class_char(bounds.next().unwrap())?,
+                    class_char(bounds.next().unwrap())?,
+                ) {
+                    (Escaped::Char(lo), Escaped::Char(hi)) if lo <= hi => {
+                        CharClass::new(vec![(lo, hi)])
+                    }
+                    (Escaped::Char(lo), Escaped::Char(hi)) => {
+                        return Err(ParseError::new(pos, ErrorKind::InvalidRange(lo, hi)))
+                    }
+                    _ => return Err(ParseError::new(pos, ErrorKind::ClassInRange)),
+                }
+            }
+            _ => match class_char(item)? {
+                Escaped::Char(c) => CharClass::single(c),
+                Escaped::Class(class) => class,
+            },
+        };
+        class = class.union(&added);
+    }
+
+    if negated {
+        Ok(class.negate())
+    } else {
+        Ok(class)
     }
 }
 
@@ -74,7 +346,7 @@ struct RegexParser;
 
 #[test]
 fn parse_test() {
-    let regex = Regex::parse("((ε|a)b*)*");
+    let regex = Regex::parse("((ε|a)b*)*").unwrap();
 
     assert_eq!(
         regex,
@@ -87,3 +359,82 @@ fn parse_test() {
         )))
     );
 }
+
+#[test]
+fn extended_parse_test() {
+    use Regex::*;
+    let lit = |c| Box::new(Literal(c));
+
+    assert_eq!(
+        Regex::parse("a+").unwrap(),
+        Concat(lit('a'), Box::new(Kleene(lit('a'))))
+    );
+    assert_eq!(
+        Regex::parse("a?").unwrap(),
+        Union(lit('a'), Box::new(Empty))
+    );
+    assert_eq!(
+        Regex::parse("a{1,3}").unwrap(),
+        Concat(
+            lit('a'),
+            Box::new(Union(
+                Box::new(Concat(lit('a'), Box::new(Union(lit('a'), Box::new(Empty))))),
+                Box::new(Empty)
+            ))
+        )
+    );
+    assert_eq!(Regex::parse("a{0}").unwrap(), Empty);
+    assert_eq!(
+        Regex::parse("^\\.$").unwrap(),
+        Concat(Box::new(Start), Box::new(Concat(lit('.'), Box::new(End))))
+    );
+    assert_eq!(
+        Regex::parse("[^a-c\\d_]").unwrap(),
+        Class(CharClass::new(vec![('a', 'c'), ('0', '9'), ('_', '_')]).negate())
+    );
+    assert_eq!(Regex::parse("\\u{1F600}").unwrap(), Literal('\u{1F600}'));
+    assert_eq!(Regex::parse("é").unwrap(), Literal('é'));
+}
+
+#[test]
+fn parse_error_test() {
+    use ErrorKind::*;
+    let error = |regex| Regex::parse(regex).unwrap_err();
+
+    assert_eq!(error("ab)"), ParseError::new(2, Syntax));
+    assert_eq!(error("(ab"), ParseError::new(3, Syntax));
+    assert_eq!(error("a**"), ParseError::new(2, Syntax));
+    assert_eq!(error("ab[z-a]"), ParseError::new(3, InvalidRange('z', 'a')));
+    assert_eq!(error("[\\d-z]"), ParseError::new(1, ClassInRange));
+    assert_eq!(
+        error("xa{3,2}"),
+        ParseError::new(2, InvalidRepetition(3, 2))
+    );
+    assert_eq!(error("a{1001}"), ParseError::new(2, RepetitionTooLarge));
+    assert_eq!(
+        error("((a{1000}){1000}){1000}"),
+        ParseError::new(10, RepetitionTooLarge)
+    );
+    assert_eq!(
+        error("(a{300}){300}"),
+        ParseError::new(8, RepetitionTooLarge)
+    );
+    assert_eq!(
+        Regex::parse(&"a{1000}".repeat(40)).unwrap_err(),
+        ParseError::new(232, RepetitionTooLarge)
+    );
+    assert!(Regex::parse("((a{10}){10}){10}").is_ok());
+    assert_eq!(error("a\\q"), ParseError::new(1, UnknownEscape('q')));
+    assert_eq!(
+        error("\\u{D800}"),
+        ParseError::new(0, InvalidCodePoint("D800".to_owned()))
+    );
+    assert_eq!(
+        error("a{3,2}").to_string(),
+        "at 1: invalid repetition {3,2}"
+    );
+    assert_eq!(
+        Regex::parse_unanchored("a(b|c$)"),
+        Err(ParseError::new(5, AnchorsUnsupported))
+    );
+}
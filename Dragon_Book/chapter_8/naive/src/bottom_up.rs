@@ -1,11 +1,18 @@
 //! An implementation of bottom-up register allocator introduced in Engnieering a Compiler by Cooper and Torczon
-//! imcomplete
-
-use crate::machine_code::{Addr, Binary, Code, Reg, Word};
-use crate::three_addr::{Program, RValue, Var, IR};
+//!
+//! Registers are allocated locally to basic blocks, values live out of a block are stored at its
+//! end. A pointer may reach any variable held in a register, every register is stored before an
+//! access through a pointer and forgotten after a write through one.
+
+use crate::builder::INT_SIZE;
+use crate::machine_code::{Addr, BinOp, Binary, Code, Cond, Idx, Reg, Word};
+use crate::three_addr::{Program, RValue, RelOp, Var, IR};
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 
+/// Pseudo variable held by registers computing array offsets.
+const SCRATCH: &str = "$scratch";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Next {
     Locked,
@@ -37,7 +44,6 @@ impl Ord for Next {
 
 #[derive(Debug)]
 struct Allocator {
-    size: usize,
     free: Vec<Option<Var>>,
     next: Vec<Next>,
     ensured: HashMap<Var, Reg>,
@@ -46,7 +52,6 @@ struct Allocator {
 impl Allocator {
     fn new(size: usize) -> Self {
         Allocator {
-            size,
             free: vec![None; size],
             next: vec![Next::Never; size],
             ensured: HashMap::new(),
@@ -74,6 +79,9 @@ impl Allocator {
     }
 
     fn allocate(&mut self, addr: &str, codes: &mut Vec<Code>) -> Reg {
+        // the value held for a redefined variable is dead
+        self.forget(addr);
+
         let r = if let Some(r) = self.free_reg() {
             r
         } else {
@@ -93,8 +101,24 @@ impl Allocator {
         r
     }
 
+    /// Drop the register holding a variable without storing it.
+    fn forget(&mut self, addr: &str) {
+        if let Some(r) = self.ensured.remove(addr) {
+            self.free[r] = None;
+            self.next[r] = Next::Never;
+        }
+    }
+
+    /// Drop all registers without storing them.
+    fn clear(&mut self) {
+        let size = self.free.len();
+        *self = Allocator::new(size);
+    }
+
     fn free(&mut self, r: Reg) {
-        self.free[r] = None;
+        if let Some(var) = self.free[r].take() {
+            self.ensured.remove(&var);
+        }
     }
 
     fn free_reg(&self) -> Option<Reg> {
@@ -113,12 +137,25 @@ impl Allocator {
             .expect("Error: Registers exhausted")
             .0
     }
+
+    fn held(&self) -> Vec<(Reg, Var)> {
+        self.free
+            .iter()
+            .enumerate()
+            .filter_map(|(r, v)| v.clone().map(|v| (r, v)))
+            .collect()
+    }
 }
 
+/// Next use information of a program split into basic blocks, with the variables live on exit of
+/// each block.
 #[derive(Debug, Default)]
 struct UseMap {
     uses: HashMap<Var, Vec<usize>>,
     on_exit: HashSet<Var>,
+    block_of: Vec<usize>,
+    ends: Vec<usize>,
+    live_out: Vec<HashSet<Var>>,
 }
 
 impl UseMap {
@@ -128,27 +165,40 @@ impl UseMap {
         for (i, ir) in program.lines.iter().map(|line| &line.ir).enumerate() {
             map.update(i, ir);
         }
+        map.split(program);
+        map.liveness(program);
         map
     }
 
     fn update(&mut self, i: usize, ir: &IR) {
         match ir {
-            IR::Op(_, lhs, _, rhs) => {
+            IR::ArrayAccess(_, _, idx) => {
+                self.push_rvalue(i, idx);
+            }
+            IR::ArrayAssign(_, idx, src) => {
+                self.push_rvalue(i, idx);
+                self.push_rvalue(i, src);
+            }
+            IR::RefAccess(_, src) => {
+                self.push_var(i, src);
+            }
+            IR::RefAssign(dst, src) => {
+                self.push_var(i, dst);
+                self.push_var(i, src);
+            }
+            IR::Op(_, lhs, _, rhs) | IR::If(lhs, _, rhs, _) => {
                 self.push_rvalue(i, lhs);
                 self.push_rvalue(i, rhs);
             }
             IR::Copy(_, src) => {
                 self.push_rvalue(i, src);
             }
-            _ => unimplemented!(),
+            IR::Goto(..) | IR::Noop => (),
         }
     }
 
     fn push_var(&mut self, i: usize, var: &str) {
-        self.uses
-            .entry(var.to_string())
-            .or_insert_with(Vec::new)
-            .push(i);
+        self.uses.entry(var.to_string()).or_default().push(i);
     }
 
     fn push_rvalue(&mut self, i: usize, rvalue: &RValue) {
@@ -157,10 +207,94 @@ impl UseMap {
         }
     }
 
+    /// Basic blocks start at labeled lines and after jumps.
+    fn split(&mut self, program: &Program) {
+        for (i, line) in program.lines.iter().enumerate() {
+            let leader = i > 0 && (!line.labels.is_empty() || jumps(&program.lines[i - 1].ir));
+            if leader {
+                self.ends.push(i - 1);
+            }
+            self.block_of.push(self.ends.len());
+        }
+        if !program.lines.is_empty() {
+            self.ends.push(program.lines.len() - 1);
+        }
+    }
+
+    /// Blocks following a block, None for the end of the program.
+    fn successors(&self, program: &Program, block: usize) -> Vec<Option<usize>> {
+        let target = |label: &str| {
+            program
+                .lines
+                .iter()
+                .position(|line| line.labels.iter().any(|l| l == label))
+                .map(|i| self.block_of[i])
+        };
+        let fallthrough = if block + 1 < self.ends.len() {
+            Some(block + 1)
+        } else {
+            None
+        };
+
+        match &program.lines[self.ends[block]].ir {
+            IR::Goto(label) => vec![target(label)],
+            IR::If(_, _, _, label) => vec![target(label), fallthrough],
+            _ => vec![fallthrough],
+        }
+    }
+
+    fn liveness(&mut self, program: &Program) {
+        let blocks = self.ends.len();
+        let mut used = vec![HashSet::new(); blocks];
+        let mut defined = vec![HashSet::new(); blocks];
+        for (i, ir) in program.lines.iter().map(|line| &line.ir).enumerate() {
+            let b = self.block_of[i];
+            for var in self.uses.iter().filter(|(_, uses)| uses.contains(&i)) {
+                if !defined[b].contains(var.0) {
+                    used[b].insert(var.0.clone());
+                }
+            }
+            if let Some(var) = def(ir) {
+                defined[b].insert(var.clone());
+            }
+        }
+
+        let successors: Vec<_> = (0..blocks).map(|b| self.successors(program, b)).collect();
+        let mut live_in: Vec<HashSet<Var>> = vec![HashSet::new(); blocks];
+        self.live_out = vec![HashSet::new(); blocks];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in (0..blocks).rev() {
+                let out: HashSet<Var> = successors[b]
+                    .iter()
+                    .flat_map(|succ| match succ {
+                        Some(succ) => live_in[*succ].clone(),
+                        None => self.on_exit.clone(),
+                    })
+                    .collect();
+                let mut inn: HashSet<Var> = out.difference(&defined[b]).cloned().collect();
+                inn.extend(used[b].iter().cloned());
+                if inn != live_in[b] || out != self.live_out[b] {
+                    changed = true;
+                    live_in[b] = inn;
+                    self.live_out[b] = out;
+                }
+            }
+        }
+    }
+
     fn next_use(&self, start: usize, var: &str) -> Next {
-        if let Some(i) = self.uses[var].iter().find(|i| **i > start) {
+        let block = self.block_of[start];
+        let end = self.ends[block];
+        let next = self
+            .uses
+            .get(var)
+            .and_then(|uses| uses.iter().find(|i| **i > start && **i <= end));
+
+        if let Some(i) = next {
             Next::Pos(*i)
-        } else if self.on_exit.contains(var) {
+        } else if self.live_out[block].contains(var) {
             Next::Exit
         } else {
             Next::Never
@@ -168,6 +302,20 @@ impl UseMap {
     }
 }
 
+fn jumps(ir: &IR) -> bool {
+    matches!(ir, IR::Goto(..) | IR::If(..))
+}
+
+/// The scalar variable defined by a line.
+fn def(ir: &IR) -> Option<&Var> {
+    match ir {
+        IR::ArrayAccess(dst, ..) | IR::RefAccess(dst, _) | IR::Op(dst, ..) | IR::Copy(dst, _) => {
+            Some(dst)
+        }
+        _ => None,
+    }
+}
+
 pub struct Builder {
     codes: Vec<Code>,
     use_map: UseMap,
@@ -227,10 +375,79 @@ impl Builder {
         self.codes.push(code);
     }
 
+    /// Address of an array element, the offset is held by a scratch register until released.
+    /// Returns the address and the index loaded.
+    fn access(&mut self, array: &str, idx: &RValue) -> (Addr, Word) {
+        let i = self.load_rvalue(idx);
+        let offset = self.new_reg(SCRATCH);
+        self.lock(offset);
+        let code = match i {
+            Word::Lit(i) => Code::Ld(offset, Addr::Imm(i * INT_SIZE)),
+            Word::Reg(..) => Code::Op(BinOp::Mul, offset, i, Word::Lit(INT_SIZE)),
+        };
+        self.codes.push(code);
+        (Addr::Indexed(Idx::Var(array.to_string()), offset), i)
+    }
+
+    fn release_scratch(&mut self) {
+        self.allocator.forget(SCRATCH);
+    }
+
+    /// Store every register, memory may be accessed through a pointer next.
+    fn spill(&mut self) {
+        for (r, var) in self.allocator.held() {
+            self.store(&var, Word::Reg(r));
+        }
+    }
+
+    /// Store registers live on exit of the block ending at line i, then forget all of them.
+    fn end_block(&mut self, i: usize) {
+        for (r, var) in self.allocator.held() {
+            if self.use_map.next_use(i, &var) != Next::Never {
+                self.store(&var, Word::Reg(r));
+            }
+        }
+        self.allocator.clear();
+    }
+
     pub fn gen(&mut self, i: usize, ir: &IR) {
         use self::IR::*;
 
         match ir {
+            ArrayAccess(dst, src, idx) => {
+                let (addr, w) = self.access(src, idx);
+                self.release_scratch();
+                self.cleanup_rvalue(i, w, idx);
+                let res = self.new_reg(dst);
+                self.codes.push(Code::Ld(res, addr));
+                self.update_next(i, res, dst);
+            }
+            ArrayAssign(dst, idx, src) => {
+                let (addr, w) = self.access(dst, idx);
+                let s = self.load_rvalue(src);
+                self.codes.push(Code::St(addr, s));
+                self.release_scratch();
+                self.cleanup_rvalue(i, w, idx);
+                self.cleanup_rvalue(i, s, src);
+            }
+            RefAccess(dst, src) => {
+                self.spill();
+                let r = self.load(src);
+                self.cleanup(i, r, src);
+                let res = self.new_reg(dst);
+                self.codes
+                    .push(Code::Ld(res, Addr::Indexed(Idx::Lit(0), r)));
+                self.update_next(i, res, dst);
+            }
+            RefAssign(dst, src) => {
+                let d = self.load(dst);
+                let s = self.load(src);
+                self.spill();
+                let addr = Addr::Indexed(Idx::Lit(0), d);
+                self.codes.push(Code::St(addr, Word::Reg(s)));
+                // the store may have changed any variable held in registers
+                self.allocator.clear();
+            }
             Op(dst, lhs, op, rhs) => {
                 let l = self.load_rvalue(lhs);
                 let r = self.load_rvalue(rhs);
@@ -246,8 +463,32 @@ impl Builder {
                 let w = self.load_rvalue(src);
                 self.store(dst, w);
                 self.cleanup_rvalue(i, w, src);
+                // the register held for dst is outdated by the store
+                self.allocator.forget(dst);
+            }
+            Goto(label) => {
+                self.end_block(i);
+                self.codes.push(Code::Br(label.to_string()));
             }
-            _ => unimplemented!(),
+            If(lhs, op, rhs, label) => {
+                let l = self.load_rvalue(lhs);
+                let r = self.load_rvalue(rhs);
+                self.end_block(i);
+
+                // all registers are free after the block ends, the operands are still there
+                let rel = match (l, r) {
+                    (Word::Reg(rel), _) | (_, Word::Reg(rel)) => rel,
+                    _ => 0,
+                };
+                let cmp = match op {
+                    RelOp::Gt => Code::Op(BinOp::Sub, rel, r, l),
+                    RelOp::Lt => Code::Op(BinOp::Sub, rel, l, r),
+                };
+                let cbr = Code::Cbr(Cond::Ltz, Addr::reg(rel), label.to_string());
+                self.codes.push(cmp);
+                self.codes.push(cbr);
+            }
+            Noop => (),
         }
     }
 
@@ -262,8 +503,14 @@ impl Builder {
     pub fn build(program: Program, on_exit: &[&str], regs: usize) -> Binary {
         let mut builder = Builder::new(&program, on_exit, regs);
 
-        for (i, ir) in program.lines.iter().map(|line| &line.ir).enumerate() {
-            builder.gen(i, ir);
+        for (i, line) in program.lines.iter().enumerate() {
+            if !line.labels.is_empty() && i > 0 {
+                builder.end_block(i - 1);
+            }
+            for label in &line.labels {
+                builder.codes.push(Code::Label(label.to_string()));
+            }
+            builder.gen(i, &line.ir);
         }
 
         builder.seal()
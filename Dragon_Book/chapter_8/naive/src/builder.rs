@@ -28,7 +28,10 @@ impl Builder {
             *r
         } else {
             let r = self.new_reg();
-            self.cache.insert(addr.clone(), r);
+            // values behind computed addresses change with stores to the variables they alias
+            if let Addr::LValue(..) | Addr::Imm(..) = addr {
+                self.cache.insert(addr.clone(), r);
+            }
             let code = Code::Ld(r, addr);
             self.codes.push(code);
             r
@@ -49,16 +52,23 @@ impl Builder {
     fn store(&mut self, addr: Addr, w: Word) {
         let code = Code::St(addr.clone(), w);
         self.codes.push(code);
-        if let Word::Reg(r) = w {
-            self.cache.insert(addr, r);
+        match (&addr, w) {
+            (Addr::LValue(..), Word::Reg(r)) => {
+                self.cache.insert(addr, r);
+            }
+            (Addr::LValue(..), Word::Lit(..)) => {
+                self.cache.remove(&addr);
+            }
+            // a store through a computed address may alias any variable
+            _ => self.cache.clear(),
         }
     }
 
     fn access(&mut self, src: &str, idx: &RValue) -> Addr {
         match idx {
             RValue::Lit(i) => {
-                let s = self.load_var(src);
-                Addr::Indexed(Idx::Lit(i * INT_SIZE), s)
+                let i = self.load(Addr::Imm(i * INT_SIZE));
+                Addr::Indexed(Idx::Var(src.to_owned()), i)
             }
             RValue::Var(v) => {
                 let r = self.load_var(v);
@@ -87,7 +97,7 @@ impl Builder {
             }
             RefAccess(dst, src) => {
                 let r = self.load_var(src);
-                let addr = Addr::Deref(0, r);
+                let addr = Addr::Indexed(Idx::Lit(0), r);
                 let d = Addr::LValue(dst.to_owned());
 
                 let s = self.load(addr);
@@ -95,7 +105,7 @@ impl Builder {
             }
             RefAssign(dst, src) => {
                 let r = self.load_var(dst);
-                let d = Addr::Deref(0, r);
+                let d = Addr::Indexed(Idx::Lit(0), r);
 
                 let s = self.load_var(src);
                 self.store(d, Word::Reg(s));
@@ -137,8 +147,14 @@ impl Builder {
         }
     }
 
+    /// Mark the position of a label, values kept in registers may not be valid from there.
+    pub fn label(&mut self, label: &str) {
+        self.codes.push(Code::Label(label.to_owned()));
+        self.cache.clear();
+    }
+
     pub fn seal(&mut self) -> Binary {
-        let codes = mem::replace(&mut self.codes, vec![]);
+        let codes = mem::take(&mut self.codes);
         Binary { codes }
     }
 }
@@ -0,0 +1,586 @@
+//! An emulator for the target machine of section 8.1 and a reference interpreter for three-address
+//! code sharing the same memory model, so the code generators can be checked against each other.
+//!
+//! Memory is byte addressed and holds one word every INT_SIZE bytes. Every variable of a program is
+//! given a fixed address by a `Layout`, an array occupies consecutive words starting from its address.
+
+use crate::builder::INT_SIZE;
+use crate::machine_code::{Addr, BinOp, Binary, Code, Cond, Idx, Reg, Word};
+use crate::three_addr::{Label, Program, RValue, RelOp, Var, IR};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+pub type Value = i64;
+
+/// Execution is aborted after this many instructions, most likely the program never terminates.
+const MAX_STEPS: usize = 1_000_000;
+/// The first address given to variables, keeps small integers from being valid pointers.
+const BASE_ADDR: usize = 0x100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    UnknownVariable(Var),
+    UndefinedLabel(Label),
+    UninitializedRegister(Reg),
+    InvalidAddress(Value),
+    InvalidStore(Addr),
+    DivisionByZero,
+    StepLimitExceeded,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::RuntimeError::*;
+
+        match self {
+            UnknownVariable(var) => write!(f, "variable {} has no address", var),
+            UndefinedLabel(label) => write!(f, "label {} is not defined", label),
+            UninitializedRegister(r) => write!(f, "R{} is read before written", r),
+            InvalidAddress(addr) => write!(f, "{} is not a valid word address", addr),
+            InvalidStore(addr) => write!(f, "cannot store to {:?}", addr),
+            DivisionByZero => write!(f, "division by zero"),
+            StepLimitExceeded => write!(f, "step limit of {} exceeded", MAX_STEPS),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Addresses of variables in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    addrs: HashMap<Var, usize>,
+    next: usize,
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Layout {
+            addrs: HashMap::new(),
+            next: BASE_ADDR,
+        }
+    }
+
+    /// Lay out every variable of the program in order of appearance, variables listed in `arrays`
+    /// are given the corresponding number of words, all others a single word.
+    pub fn of(program: &Program, arrays: &[(&str, usize)]) -> Self {
+        let mut layout = Self::new();
+        for (array, len) in arrays {
+            layout.array(array, *len);
+        }
+        for ir in program.lines.iter().map(|line| &line.ir) {
+            for var in variables(ir) {
+                layout.scalar(var);
+            }
+        }
+        layout
+    }
+
+    pub fn scalar(&mut self, var: &str) -> usize {
+        self.array(var, 1)
+    }
+
+    pub fn array(&mut self, var: &str, len: usize) -> usize {
+        if let Some(addr) = self.addrs.get(var) {
+            return *addr;
+        }
+        let addr = self.next;
+        self.addrs.insert(var.to_owned(), addr);
+        self.next += len.max(1) * INT_SIZE;
+        addr
+    }
+
+    pub fn addr(&self, var: &str) -> Result<usize> {
+        self.addrs
+            .get(var)
+            .cloned()
+            .ok_or_else(|| RuntimeError::UnknownVariable(var.to_owned()))
+    }
+}
+
+fn variables(ir: &IR) -> Vec<&str> {
+    use self::IR::*;
+
+    fn push_rvalue<'a>(vars: &mut Vec<&'a str>, rvalue: &'a RValue) {
+        if let RValue::Var(v) = rvalue {
+            vars.push(v.as_str());
+        }
+    }
+
+    let mut vars = vec![];
+
+    match ir {
+        ArrayAccess(dst, src, idx) => {
+            vars.push(dst.as_str());
+            vars.push(src.as_str());
+            push_rvalue(&mut vars, idx);
+        }
+        ArrayAssign(dst, idx, src) => {
+            vars.push(dst.as_str());
+            push_rvalue(&mut vars, idx);
+            push_rvalue(&mut vars, src);
+        }
+        RefAccess(dst, src) | RefAssign(dst, src) => {
+            vars.push(dst.as_str());
+            vars.push(src.as_str());
+        }
+        Op(dst, lhs, _, rhs) => {
+            vars.push(dst.as_str());
+            push_rvalue(&mut vars, lhs);
+            push_rvalue(&mut vars, rhs);
+        }
+        Copy(dst, src) => {
+            vars.push(dst.as_str());
+            push_rvalue(&mut vars, src);
+        }
+        If(lhs, _, rhs, _) => {
+            push_rvalue(&mut vars, lhs);
+            push_rvalue(&mut vars, rhs);
+        }
+        Goto(..) | Noop => (),
+    }
+
+    vars
+}
+
+/// Words of memory, never written words read as zero.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Memory {
+    words: HashMap<usize, Value>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check(addr: Value) -> Result<usize> {
+        if addr >= 0 && (addr as usize).is_multiple_of(INT_SIZE) {
+            Ok(addr as usize)
+        } else {
+            Err(RuntimeError::InvalidAddress(addr))
+        }
+    }
+
+    pub fn load(&self, addr: Value) -> Result<Value> {
+        let addr = Self::check(addr)?;
+        Ok(self.words.get(&addr).cloned().unwrap_or(0))
+    }
+
+    pub fn store(&mut self, addr: Value, value: Value) -> Result<()> {
+        let addr = Self::check(addr)?;
+        if value == 0 {
+            self.words.remove(&addr);
+        } else {
+            self.words.insert(addr, value);
+        }
+        Ok(())
+    }
+
+    /// Word at the address of a scalar variable.
+    pub fn var(&self, layout: &Layout, var: &str) -> Result<Value> {
+        self.load(layout.addr(var)? as Value)
+    }
+
+    pub fn set_var(&mut self, layout: &Layout, var: &str, value: Value) -> Result<()> {
+        self.store(layout.addr(var)? as Value, value)
+    }
+}
+
+fn apply(op: BinOp, lhs: Value, rhs: Value) -> Result<Value> {
+    match op {
+        BinOp::Add => Ok(lhs.wrapping_add(rhs)),
+        BinOp::Sub => Ok(lhs.wrapping_sub(rhs)),
+        BinOp::Mul => Ok(lhs.wrapping_mul(rhs)),
+        BinOp::Div if rhs == 0 => Err(RuntimeError::DivisionByZero),
+        BinOp::Div => Ok(lhs.wrapping_div(rhs)),
+    }
+}
+
+fn labels<'a, I>(labels: I) -> HashMap<&'a str, usize>
+where
+    I: Iterator<Item = (usize, &'a Label)>,
+{
+    let mut map = HashMap::new();
+    for (pos, label) in labels {
+        // the first definition wins, as in Program::find_label
+        map.entry(label.as_str()).or_insert(pos);
+    }
+    map
+}
+
+fn jump(labels: &HashMap<&str, usize>, label: &str) -> Result<usize> {
+    labels
+        .get(label)
+        .cloned()
+        .ok_or_else(|| RuntimeError::UndefinedLabel(label.to_owned()))
+}
+
+struct Machine<'a> {
+    layout: &'a Layout,
+    memory: Memory,
+    regs: HashMap<Reg, Value>,
+}
+
+impl<'a> Machine<'a> {
+    fn reg(&self, r: Reg) -> Result<Value> {
+        self.regs
+            .get(&r)
+            .cloned()
+            .ok_or(RuntimeError::UninitializedRegister(r))
+    }
+
+    fn word(&self, w: &Word) -> Result<Value> {
+        match w {
+            Word::Reg(r) => self.reg(*r),
+            Word::Lit(l) => Ok(*l as Value),
+        }
+    }
+
+    /// The memory location referred to by an address, None for immediates and registers.
+    fn location(&self, addr: &Addr) -> Result<Option<Value>> {
+        let location = match addr {
+            Addr::LValue(var) => self.layout.addr(var)? as Value,
+            Addr::Indexed(Idx::Lit(i), r) => *i as Value + self.reg(*r)?,
+            Addr::Indexed(Idx::Var(var), r) => self.layout.addr(var)? as Value + self.reg(*r)?,
+            Addr::Deref(i, r) => self.memory.load(*i as Value + self.reg(*r)?)?,
+            Addr::Imm(..) | Addr::Reg(..) => return Ok(None),
+        };
+        Ok(Some(location))
+    }
+
+    fn read(&self, addr: &Addr) -> Result<Value> {
+        match (addr, self.location(addr)?) {
+            (_, Some(location)) => self.memory.load(location),
+            (Addr::Imm(i), None) => Ok(*i as Value),
+            (Addr::Reg(r), None) => self.reg(*r),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: &Addr, value: Value) -> Result<()> {
+        match (addr, self.location(addr)?) {
+            (_, Some(location)) => self.memory.store(location, value),
+            (Addr::Reg(r), None) => {
+                self.regs.insert(*r, value);
+                Ok(())
+            }
+            _ => Err(RuntimeError::InvalidStore(addr.clone())),
+        }
+    }
+}
+
+/// Run a binary from the given memory to the end of its codes, returning the final memory.
+pub fn execute(binary: &Binary, layout: &Layout, memory: Memory) -> Result<Memory> {
+    let labels = labels(binary.codes.iter().enumerate().filter_map(|(i, code)| {
+        if let Code::Label(label) = code {
+            Some((i, label))
+        } else {
+            None
+        }
+    }));
+    let mut machine = Machine {
+        layout,
+        memory,
+        regs: HashMap::new(),
+    };
+
+    let mut pc = 0;
+    for _ in 0..MAX_STEPS {
+        let code = match binary.codes.get(pc) {
+            Some(code) => code,
+            None => return Ok(machine.memory),
+        };
+        pc += 1;
+
+        match code {
+            Code::Ld(r, addr) => {
+                let value = machine.read(addr)?;
+                machine.regs.insert(*r, value);
+            }
+            Code::St(addr, w) => {
+                let value = machine.word(w)?;
+                machine.write(addr, value)?;
+            }
+            Code::Op(op, dst, lhs, rhs) => {
+                let value = apply(*op, machine.word(lhs)?, machine.word(rhs)?)?;
+                machine.regs.insert(*dst, value);
+            }
+            Code::Br(label) => pc = jump(&labels, label)?,
+            Code::Cbr(Cond::Ltz, addr, label) => {
+                if machine.read(addr)? < 0 {
+                    pc = jump(&labels, label)?;
+                }
+            }
+            Code::Label(..) => (),
+        }
+    }
+
+    Err(RuntimeError::StepLimitExceeded)
+}
+
+/// Run a three-address program from the given memory to its last line, returning the final memory.
+pub fn interpret(program: &Program, layout: &Layout, mut memory: Memory) -> Result<Memory> {
+    let labels = labels(
+        program
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| line.labels.iter().map(move |label| (i, label))),
+    );
+    let var = |var: &str| layout.addr(var).map(|addr| addr as Value);
+    let element = |memory: &Memory, array: &str, idx: &RValue| -> Result<Value> {
+        let idx = rvalue(memory, layout, idx)?;
+        Ok(var(array)? + idx * INT_SIZE as Value)
+    };
+
+    let mut pc = 0;
+    for _ in 0..MAX_STEPS {
+        let ir = match program.lines.get(pc) {
+            Some(line) => &line.ir,
+            None => return Ok(memory),
+        };
+        pc += 1;
+
+        match ir {
+            IR::ArrayAccess(dst, src, idx) => {
+                let value = memory.load(element(&memory, src, idx)?)?;
+                memory.store(var(dst)?, value)?;
+            }
+            IR::ArrayAssign(dst, idx, src) => {
+                let value = rvalue(&memory, layout, src)?;
+                memory.store(element(&memory, dst, idx)?, value)?;
+            }
+            IR::RefAccess(dst, src) => {
+                let value = memory.load(memory.load(var(src)?)?)?;
+                memory.store(var(dst)?, value)?;
+            }
+            IR::RefAssign(dst, src) => {
+                let value = memory.load(var(src)?)?;
+                memory.store(memory.load(var(dst)?)?, value)?;
+            }
+            IR::Op(dst, lhs, op, rhs) => {
+                let lhs = rvalue(&memory, layout, lhs)?;
+                let rhs = rvalue(&memory, layout, rhs)?;
+                memory.store(var(dst)?, apply(*op, lhs, rhs)?)?;
+            }
+            IR::Copy(dst, src) => {
+                let value = rvalue(&memory, layout, src)?;
+                memory.store(var(dst)?, value)?;
+            }
+            IR::Goto(label) => pc = jump(&labels, label)?,
+            IR::If(lhs, op, rhs, label) => {
+                let lhs = rvalue(&memory, layout, lhs)?;
+                let rhs = rvalue(&memory, layout, rhs)?;
+                let taken = match op {
+                    RelOp::Gt => lhs > rhs,
+                    RelOp::Lt => lhs < rhs,
+                };
+                if taken {
+                    pc = jump(&labels, label)?;
+                }
+            }
+            IR::Noop => (),
+        }
+    }
+
+    Err(RuntimeError::StepLimitExceeded)
+}
+
+fn rvalue(memory: &Memory, layout: &Layout, rvalue: &RValue) -> Result<Value> {
+    match rvalue {
+        RValue::Lit(l) => Ok(*l as Value),
+        RValue::Var(v) => memory.var(layout, v),
+    }
+}
+
+#[cfg(test)]
+fn differential(source: &str, arrays: &[(&str, usize)], inputs: &[(&str, Value)]) -> Memory {
+    let program = Program::parse(source).unwrap();
+    let layout = Layout::of(&program, arrays);
+    let mut memory = Memory::new();
+    for (var, value) in inputs {
+        memory.set_var(&layout, var, *value).unwrap();
+    }
+
+    let expected = interpret(&program, &layout, memory.clone()).unwrap();
+    let actual = execute(&program.build(), &layout, memory.clone()).unwrap();
+    assert_eq!(actual, expected, "\n{:?}{:?}", program, program.build());
+
+    // every scalar is observed on exit of the program
+    let mut scalars: Vec<&str> = program
+        .lines
+        .iter()
+        .flat_map(|line| variables(&line.ir))
+        .filter(|var| arrays.iter().all(|(array, _)| array != var))
+        .collect();
+    scalars.sort();
+    scalars.dedup();
+    for regs in 3..6 {
+        let binary =
+            crate::bottom_up::Builder::build(Program::parse(source).unwrap(), &scalars, regs);
+        let actual = execute(&binary, &layout, memory.clone()).unwrap();
+        assert_eq!(actual, expected, "\n{:?}{:?}", program, binary);
+    }
+    expected
+}
+
+#[test]
+fn execute_test() {
+    let binary = Binary::parse(
+        "LD R0, #5
+LD R1, #0
+L0:
+ADD R1, R1, R0
+SUB R0, R0, #1
+SUB R2, #0, R0
+BLTZ R2, L0
+ST x, R1",
+    )
+    .unwrap();
+
+    let mut layout = Layout::new();
+    layout.scalar("x");
+    let memory = execute(&binary, &layout, Memory::new()).unwrap();
+    assert_eq!(memory.var(&layout, "x"), Ok(15));
+}
+
+#[test]
+fn runtime_error_test() {
+    let layout = Layout::new();
+    let binary = Binary::parse("ADD R0, R1, #1").unwrap();
+    assert_eq!(
+        execute(&binary, &layout, Memory::new()),
+        Err(RuntimeError::UninitializedRegister(1))
+    );
+
+    let binary = Binary::parse("L0: BR L0").unwrap();
+    assert_eq!(
+        execute(&binary, &layout, Memory::new()),
+        Err(RuntimeError::StepLimitExceeded)
+    );
+}
+
+#[test]
+fn straight_line_test() {
+    let memory = differential(
+        "x = b * c; y = a + x; z = y - 7; w = z / 2; x = 3; v = x;",
+        &[],
+        &[("a", 1), ("b", 2), ("c", 3)],
+    );
+    assert_eq!(memory.words.len(), 6);
+}
+
+#[test]
+fn loop_test() {
+    let source = "
+s = 0;
+i = k;
+L1: if i > n goto L2;
+s = s + i;
+i = i + 1;
+goto L1;
+L2: ;";
+
+    let program = Program::parse(source).unwrap();
+    let layout = Layout::of(&program, &[]);
+    let memory = differential(source, &[], &[("k", 1), ("n", 10)]);
+    assert_eq!(memory.var(&layout, "s"), Ok(55));
+}
+
+#[test]
+fn array_test() {
+    differential(
+        "
+x = a[i];
+y = b[j];
+a[i] = y;
+b[j] = x;
+c[2] = x;
+z = c[2];
+a[1] = z;",
+        &[("a", 4), ("b", 4), ("c", 4)],
+        &[("i", 2), ("j", 3)],
+    );
+}
+
+#[test]
+fn pointer_test() {
+    let source = "v = y; x = *p; *p = z; w = y; y = x; u = *p;";
+    let program = Program::parse(source).unwrap();
+    let layout = Layout::of(&program, &[]);
+    let y = layout.addr("y").unwrap() as Value;
+
+    let memory = differential(source, &[], &[("p", y), ("y", 1), ("z", 2)]);
+    assert_eq!(memory.var(&layout, "w"), Ok(2));
+    assert_eq!(memory.var(&layout, "u"), Ok(1));
+}
+
+#[test]
+fn bottom_up_test() {
+    use crate::bottom_up;
+
+    fn check(source: &str, inputs: &[&str], on_exit: &[&str], regs: std::ops::Range<usize>) {
+        let program = Program::parse(source).unwrap();
+        let layout = Layout::of(&program, &[]);
+        let mut memory = Memory::new();
+        for (i, var) in inputs.iter().enumerate() {
+            memory.set_var(&layout, var, 10 * i as Value + 1).unwrap();
+        }
+        let expected = interpret(&program, &layout, memory.clone()).unwrap();
+
+        for regs in regs {
+            let program = Program::parse(source).unwrap();
+            let binary = bottom_up::Builder::build(program, on_exit, regs);
+            let actual = execute(&binary, &layout, memory.clone()).unwrap();
+            // temporaries only live in registers
+            for var in on_exit {
+                assert_eq!(actual.var(&layout, var), expected.var(&layout, var));
+            }
+        }
+    }
+
+    let source = "
+t0 = b + c;
+t1 = a / t0;
+t2 = e + f;
+t3 = d * t2;
+t4 = t1 - t3;
+x = t4;
+y = x;
+    ";
+    check(source, &["a", "b", "c", "d", "e", "f"], &["x", "y"], 2..5);
+
+    // s and i are live around the loop, t is not
+    let source = "
+s = 0;
+i = k;
+L1: t = i * i;
+s = s + t;
+i = i + 1;
+if i < n goto L1;
+x = s;
+    ";
+    check(source, &["k", "n"], &["x"], 2..5);
+
+    // y is still in a register when read through p, then outdated by a write through p
+    let source = "y = a + b; x = *p; w = x + 1; *p = w; z = y;";
+    let program = Program::parse(source).unwrap();
+    let layout = Layout::of(&program, &[]);
+    let mut memory = Memory::new();
+    memory.set_var(&layout, "a", 1).unwrap();
+    memory.set_var(&layout, "b", 2).unwrap();
+    memory
+        .set_var(&layout, "p", layout.addr("y").unwrap() as Value)
+        .unwrap();
+    for regs in 2..5 {
+        let program = Program::parse(source).unwrap();
+        let binary = bottom_up::Builder::build(program, &["x", "z"], regs);
+        let actual = execute(&binary, &layout, memory.clone()).unwrap();
+        assert_eq!(actual.var(&layout, "x"), Ok(3));
+        assert_eq!(actual.var(&layout, "z"), Ok(4));
+    }
+}
@@ -3,5 +3,6 @@ extern crate lalrpop_util;
 
 pub mod bottom_up;
 pub mod builder;
+pub mod emulator;
 pub mod machine_code;
 pub mod three_addr;
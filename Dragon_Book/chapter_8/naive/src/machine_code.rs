@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::fmt::{self, Debug, Display, Formatter};
 
-lalrpop_mod!(pub code);
+lalrpop_mod!(#[allow(clippy::all, unused_parens)] pub code);
 
 type Var = String;
 pub type Reg = usize;
@@ -134,6 +134,7 @@ pub enum Code {
     Op(BinOp, Reg, Word, Word),
     Br(Label),
     Cbr(Cond, Addr, Label),
+    Label(Label),
 }
 
 impl Code {
@@ -144,6 +145,7 @@ impl Code {
             Ld(_, a) => 1 + a.cost(),
             St(a, _) => 1 + a.cost(),
             Cbr(_, a, _) => 1 + a.cost(),
+            Label(..) => 0,
             _ => 1,
         }
     }
@@ -170,6 +172,7 @@ impl Debug for Code {
             Op(op, dst, lhs, rhs) => write!(f, "{} R{}, {:?}, {:?}", op.tag(), dst, lhs, rhs),
             Br(l) => write!(f, "BR {}", l),
             Cbr(c, addr, l) => write!(f, "B{} {:?}, {}", c.tag(), addr, l),
+            Label(l) => write!(f, "{}:", l),
         }
     }
 }
@@ -179,7 +182,7 @@ pub struct Binary {
 }
 
 impl Binary {
-    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<Error + 'a>> {
+    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<dyn Error + 'a>> {
         code::BinParser::new().parse(s).map_err(Box::from)
     }
 
@@ -210,10 +213,7 @@ impl Display for Binary {
 #[allow(dead_code)]
 fn redundent_store(binary: Binary) -> Binary {
     fn immediate_store(last: &Option<(Reg, Addr)>, next: &Code) -> bool {
-        match (last, next) {
-            (Some((r0, a0)), Code::St(a1, r1)) if Word::Reg(*r0) == *r1 && a0 == a1 => true,
-            _ => false,
-        }
+        matches!((last, next), (Some((r0, a0)), Code::St(a1, r1)) if Word::Reg(*r0) == *r1 && a0 == a1)
     }
 
     let codes = binary
@@ -3,7 +3,7 @@ pub use crate::machine_code::{BinOp, Binary};
 use std::error::Error;
 use std::fmt::{self, Debug, Formatter};
 
-lalrpop_mod!(pub ir);
+lalrpop_mod!(#[allow(clippy::all, unused_parens)] pub ir);
 
 pub type Var = String;
 pub type Label = String;
@@ -60,7 +60,7 @@ pub enum IR {
 }
 
 impl IR {
-    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<Error + 'a>> {
+    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<dyn Error + 'a>> {
         ir::IRParser::new().parse(s).map_err(Box::from)
     }
 
@@ -143,7 +143,7 @@ impl Debug for Program {
 }
 
 impl Program {
-    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<Error + 'a>> {
+    pub fn parse<'a>(s: &'a str) -> Result<Self, Box<dyn Error + 'a>> {
         ir::ProgParser::new().parse(s).map_err(Box::from)
     }
 
@@ -161,8 +161,11 @@ impl Program {
     pub fn build(&self) -> Binary {
         let mut builder = Builder::new();
 
-        for ir in self.lines.iter().map(|line| &line.ir) {
-            builder.gen(ir);
+        for line in &self.lines {
+            for label in &line.labels {
+                builder.label(label);
+            }
+            builder.gen(&line.ir);
         }
 
         builder.seal()
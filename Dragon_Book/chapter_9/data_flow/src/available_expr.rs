@@ -68,6 +68,12 @@ pub struct AvailableExpressions<'a> {
     attrs: Vec<AvailableExpression<'a>>,
 }
 
+impl<'a> AvailableExpressions<'a> {
+    pub fn available_in(&self, block_id: BlockID) -> &HashSet<Expr<'a>> {
+        &self.attrs[block_id].in_set
+    }
+}
+
 impl<'a> std::fmt::Debug for AvailableExpressions<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         for (i, attr) in self.attrs.iter().enumerate() {
@@ -90,16 +96,15 @@ fn meet<'a>(
     program: &'a Program,
     attrs: &[AvailableExpression<'a>],
 ) -> HashSet<Expr<'a>> {
+    // an empty intersection must not be mistaken for no predecessor met yet
     program
         .predecessors(block_id)
         .map(|(p, _)| &attrs[p].out_set)
-        .fold(HashSet::new(), |set, out_p| {
-            if set.is_empty() {
-                out_p.clone()
-            } else {
-                &set & out_p
-            }
+        .fold(None, |set: Option<HashSet<Expr<'a>>>, out_p| match set {
+            Some(set) => Some(&set & out_p),
+            None => Some(out_p.clone()),
         })
+        .unwrap_or_default()
 }
 
 pub fn available_expressions(program: &Program) -> AvailableExpressions<'_> {
@@ -0,0 +1,353 @@
+//! Bit-vector problems: facts numbered from 0 and transfer functions of the form
+//! f(x) = gen ∪ (x - kill), declared by the gen and kill sets of single statements.
+
+use crate::framework::{Direction, Point, Problem, StmtCfg, Summarize};
+use crate::BlockID;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{BitAnd, BitOr, Sub};
+
+const WORD: usize = 64;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    size: usize,
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn empty(size: usize) -> Self {
+        BitSet {
+            size,
+            words: vec![0; size.div_ceil(WORD)],
+        }
+    }
+
+    pub fn full(size: usize) -> Self {
+        let mut set = BitSet {
+            size,
+            words: vec![!0; size.div_ceil(WORD)],
+        };
+        if let Some(last) = set.words.last_mut() {
+            if !size.is_multiple_of(WORD) {
+                *last = (1 << (size % WORD)) - 1;
+            }
+        }
+        set
+    }
+
+    /// Number of facts the set ranges over.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.size && self.words[i / WORD] & (1 << (i % WORD)) != 0
+    }
+
+    pub fn insert(&mut self, i: usize) -> bool {
+        assert!(i < self.size, "BitSet: {} out of {} facts", i, self.size);
+        let inserted = !self.contains(i);
+        self.words[i / WORD] |= 1 << (i % WORD);
+        inserted
+    }
+
+    pub fn remove(&mut self, i: usize) -> bool {
+        let removed = self.contains(i);
+        if removed {
+            self.words[i / WORD] &= !(1 << (i % WORD));
+        }
+        removed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.size).filter(move |i| self.contains(*i))
+    }
+
+    fn zip_with<F>(&self, other: &Self, f: F) -> Self
+    where
+        F: Fn(u64, u64) -> u64,
+    {
+        assert_eq!(self.size, other.size, "BitSet: sizes differ");
+        let words = self
+            .words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| f(*a, *b))
+            .collect();
+        BitSet {
+            size: self.size,
+            words,
+        }
+    }
+}
+
+impl Debug for BitSet {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl BitOr for &BitSet {
+    type Output = BitSet;
+
+    fn bitor(self, other: &BitSet) -> BitSet {
+        self.zip_with(other, |a, b| a | b)
+    }
+}
+
+impl BitAnd for &BitSet {
+    type Output = BitSet;
+
+    fn bitand(self, other: &BitSet) -> BitSet {
+        self.zip_with(other, |a, b| a & b)
+    }
+}
+
+impl Sub for &BitSet {
+    type Output = BitSet;
+
+    fn sub(self, other: &BitSet) -> BitSet {
+        self.zip_with(other, |a, b| a & !b)
+    }
+}
+
+/// f(x) = gen ∪ (x - kill), kept with gen and kill disjoint: each fact is either generated,
+/// killed or passed through, which makes meets and closures work fact by fact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitFunction {
+    gen: BitSet,
+    kill: BitSet,
+}
+
+impl BitFunction {
+    pub fn new(gen: BitSet, kill: BitSet) -> Self {
+        let kill = &kill - &gen;
+        BitFunction { gen, kill }
+    }
+
+    pub fn identity(size: usize) -> Self {
+        BitFunction {
+            gen: BitSet::empty(size),
+            kill: BitSet::empty(size),
+        }
+    }
+
+    pub fn gen(&self) -> &BitSet {
+        &self.gen
+    }
+
+    pub fn kill(&self) -> &BitSet {
+        &self.kill
+    }
+
+    pub fn apply(&self, value: &BitSet) -> BitSet {
+        &self.gen | &(value - &self.kill)
+    }
+
+    /// `self` followed by `next`
+    pub fn then(&self, next: &Self) -> Self {
+        let gen = &next.gen | &(&self.gen - &next.kill);
+        let kill = &next.kill | &(&self.kill - &next.gen);
+        BitFunction { gen, kill }
+    }
+
+    /// The meet of two functions is union if `may`, intersection otherwise.
+    pub fn meet(&self, other: &Self, may: bool) -> Self {
+        if may {
+            BitFunction {
+                gen: &self.gen | &other.gen,
+                kill: &self.kill & &other.kill,
+            }
+        } else {
+            BitFunction {
+                gen: &self.gen & &other.gen,
+                kill: &self.kill | &other.kill,
+            }
+        }
+    }
+
+    /// Every power of a gen-kill function but the identity is the function itself.
+    pub fn closure(&self, may: bool) -> Self {
+        let size = self.gen.size();
+        if may {
+            BitFunction::new(self.gen.clone(), BitSet::empty(size))
+        } else {
+            BitFunction::new(BitSet::empty(size), self.kill.clone())
+        }
+    }
+}
+
+/// A bit-vector problem declared by what single statements generate and kill.
+pub trait GenKill<G: StmtCfg + ?Sized> {
+    type Direction: Direction;
+    /// Facts hold if they hold along some path (union), rather than along all paths (intersection).
+    const MAY: bool;
+
+    /// Number of facts.
+    fn size(&self, cfg: &G) -> usize;
+    fn boundary(&self, cfg: &G) -> BitSet {
+        BitSet::empty(self.size(cfg))
+    }
+    fn gen_kill(&self, cfg: &G, at: Point, stmt: &G::Stmt, gen: &mut BitSet, kill: &mut BitSet);
+}
+
+/// A gen-kill problem with the functions of its statements composed block by block.
+pub struct Bits<P> {
+    problem: P,
+    size: usize,
+    functions: Vec<BitFunction>,
+}
+
+impl<P> Bits<P> {
+    pub fn new<G>(cfg: &G, problem: P) -> Self
+    where
+        G: StmtCfg + ?Sized,
+        P: GenKill<G>,
+    {
+        let size = problem.size(cfg);
+        let functions = (0..cfg.len())
+            .map(|block_id| {
+                let stmts = cfg.stmts(block_id);
+                let mut indices: Vec<usize> = (0..stmts.len()).collect();
+                if !P::Direction::FORWARD {
+                    indices.reverse();
+                }
+
+                indices
+                    .into_iter()
+                    .fold(BitFunction::identity(size), |f, index| {
+                        let mut gen = BitSet::empty(size);
+                        let mut kill = BitSet::empty(size);
+                        let at = Point { block_id, index };
+                        problem.gen_kill(cfg, at, &stmts[index], &mut gen, &mut kill);
+                        f.then(&BitFunction::new(gen, kill))
+                    })
+            })
+            .collect();
+
+        Bits {
+            problem,
+            size,
+            functions,
+        }
+    }
+
+    pub fn problem(&self) -> &P {
+        &self.problem
+    }
+
+    pub fn function(&self, block_id: BlockID) -> &BitFunction {
+        &self.functions[block_id]
+    }
+}
+
+impl<G, P> Problem<G> for Bits<P>
+where
+    G: StmtCfg + ?Sized,
+    P: GenKill<G>,
+{
+    type Value = BitSet;
+    type Direction = P::Direction;
+
+    fn top(&self, _: &G) -> BitSet {
+        if P::MAY {
+            BitSet::empty(self.size)
+        } else {
+            BitSet::full(self.size)
+        }
+    }
+
+    fn boundary(&self, cfg: &G) -> BitSet {
+        self.problem.boundary(cfg)
+    }
+
+    fn meet(&self, lhs: &BitSet, rhs: &BitSet) -> BitSet {
+        if P::MAY {
+            lhs | rhs
+        } else {
+            lhs & rhs
+        }
+    }
+
+    fn transfer(&self, _: &G, block_id: BlockID, value: &BitSet) -> BitSet {
+        self.functions[block_id].apply(value)
+    }
+}
+
+impl<G, P> Summarize<G> for Bits<P>
+where
+    G: StmtCfg + ?Sized,
+    P: GenKill<G>,
+{
+    type Function = BitFunction;
+
+    fn function(&self, _: &G, block_id: BlockID) -> BitFunction {
+        self.functions[block_id].clone()
+    }
+
+    fn identity(&self, _: &G) -> BitFunction {
+        BitFunction::identity(self.size)
+    }
+
+    fn compose(&self, first: &BitFunction, then: &BitFunction) -> BitFunction {
+        first.then(then)
+    }
+
+    fn meet_functions(&self, lhs: &BitFunction, rhs: &BitFunction) -> BitFunction {
+        lhs.meet(rhs, P::MAY)
+    }
+
+    fn closure(&self, f: &BitFunction) -> BitFunction {
+        f.closure(P::MAY)
+    }
+
+    fn apply(&self, f: &BitFunction, value: &BitSet) -> BitSet {
+        f.apply(value)
+    }
+}
+
+#[test]
+fn bit_set_test() {
+    let mut set = BitSet::empty(70);
+    assert!(set.insert(3));
+    assert!(set.insert(69));
+    assert!(!set.insert(3));
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 69]);
+
+    let full = BitSet::full(70);
+    assert_eq!(full.count(), 70);
+    assert_eq!((&full - &set).count(), 68);
+    assert_eq!(&full & &set, set);
+    assert!(set.remove(69));
+    assert!(!set.contains(69));
+}
+
+#[test]
+fn bit_function_test() {
+    let set = |size: usize, facts: &[usize]| {
+        let mut set = BitSet::empty(size);
+        for fact in facts {
+            set.insert(*fact);
+        }
+        set
+    };
+
+    // f generates 0 and kills 1, g generates 1 and kills 2
+    let f = BitFunction::new(set(4, &[0]), set(4, &[1]));
+    let g = BitFunction::new(set(4, &[1]), set(4, &[2]));
+    let x = set(4, &[1, 2, 3]);
+
+    assert_eq!(f.then(&g).apply(&x), g.apply(&f.apply(&x)));
+    assert_eq!(f.meet(&g, true).apply(&x), &f.apply(&x) | &g.apply(&x));
+    assert_eq!(f.meet(&g, false).apply(&x), &f.apply(&x) & &g.apply(&x));
+    assert_eq!(f.closure(true).apply(&x), &x | &f.apply(&x));
+    assert_eq!(f.closure(false).apply(&x), &x & &f.apply(&x));
+}
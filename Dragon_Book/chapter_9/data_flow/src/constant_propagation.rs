@@ -58,7 +58,7 @@ impl<'a> Constants<'a> {
         }
     }
 
-    fn update(&mut self, stmt: &'a Stmt) {
+    pub(crate) fn update(&mut self, stmt: &'a Stmt) {
         if let Some(def) = stmt.def() {
             *self.map.get_mut(def).unwrap() = self.eval_rhs(stmt);
         }
@@ -68,6 +68,14 @@ impl<'a> Constants<'a> {
     fn get(&self, var: &str) -> Value {
         self.map.get(var).cloned().unwrap_or(Value::Undef)
     }
+
+    /// The value of a variable if it is the same constant on every path.
+    pub(crate) fn constant(&self, var: &str) -> Option<Lit> {
+        match self.get(var) {
+            Value::Cst(lit) => Some(lit),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> SemiLattice<'a> for Constants<'a> {
@@ -121,7 +129,7 @@ impl<'a> Transfer<'a> for RefBlock<'a> {
     }
 }
 
-pub fn constant_propagation(program: &Program) -> Attrs<Constants<'_>, Forward, RefBlock<'_>, ()> {
+pub fn constant_propagation(program: &Program) -> Attrs<Constants<'_>> {
     DataFlow::<Constants<'_>, Forward, RefBlock<'_>>::run(program, &())
 }
 
@@ -161,9 +169,9 @@ fn meet_test() {
 #[test]
 fn constant_propagation_test() {
     let program = figure_9_27();
-    let attrs = constant_propagation(&program).attrs;
+    let attrs = constant_propagation(&program);
 
-    assert_eq!(attrs[3].out_value().unwrap().get("x"), Value::Nac);
-    assert_eq!(attrs[3].out_value().unwrap().get("y"), Value::Nac);
-    assert_eq!(attrs[3].out_value().unwrap().get("z"), Value::Nac);
+    assert_eq!(attrs.out_value(3).get("x"), Value::Nac);
+    assert_eq!(attrs.out_value(3).get("y"), Value::Nac);
+    assert_eq!(attrs.out_value(3).get("z"), Value::Nac);
 }
@@ -1,8 +1,7 @@
-use crate::framework::{DataFlow, Forward, SemiLattice, Transfer};
+use crate::framework::{Cfg, DataFlow, Forward, SemiLattice, Transfer};
 use crate::{BlockID, Program};
 use petgraph::algo::toposort;
 use petgraph::prelude::*;
-use petgraph::visit::DfsEvent;
 use std::collections::HashSet;
 
 #[derive(Clone, PartialEq)]
@@ -10,15 +9,15 @@ struct Dominator {
     ids: HashSet<BlockID>,
 }
 
-impl SemiLattice<'_> for Dominator {
-    fn top(program: &Program) -> Self {
-        let ids = program.block_range().collect();
+impl<'a, G: Cfg + ?Sized + 'a> SemiLattice<'a, G> for Dominator {
+    fn top(cfg: &'a G) -> Self {
+        let ids = (0..cfg.len()).collect();
         Dominator { ids }
     }
 
-    fn start(program: &Program) -> Self {
+    fn start(cfg: &'a G) -> Self {
         let mut ids = HashSet::new();
-        ids.insert(program.entry());
+        ids.insert(cfg.entry());
         Dominator { ids }
     }
 
@@ -33,11 +32,11 @@ struct DominatorT {
     block_id: BlockID,
 }
 
-impl Transfer<'_> for DominatorT {
+impl<'a, G: ?Sized + 'a> Transfer<'a, G> for DominatorT {
     type Target = Dominator;
     type Extra = ();
 
-    fn new(block_id: BlockID, _: &Program, _: &()) -> Self {
+    fn new(block_id: BlockID, _: &'a G, _: &()) -> Self {
         DominatorT { block_id }
     }
 
@@ -48,19 +47,63 @@ impl Transfer<'_> for DominatorT {
     }
 }
 
-pub struct Dominators<'a> {
+/// Blocks reachable from ENTRY.
+pub(crate) fn reachable<G: Cfg + ?Sized>(cfg: &G) -> HashSet<BlockID> {
+    let mut reachable = HashSet::new();
+    let mut stack = vec![cfg.entry()];
+    while let Some(block_id) = stack.pop() {
+        if reachable.insert(block_id) {
+            stack.extend(cfg.successors(block_id));
+        }
+    }
+    reachable
+}
+
+pub(crate) fn edges<G: Cfg + ?Sized>(cfg: &G) -> Vec<(BlockID, BlockID)> {
+    (0..cfg.len())
+        .flat_map(|from| cfg.successors(from).into_iter().map(move |to| (from, to)))
+        .collect()
+}
+
+/// `to` and the blocks reaching `from` without passing through `to`.
+pub(crate) fn natural_loop<G: Cfg + ?Sized>(
+    cfg: &G,
+    from: BlockID,
+    to: BlockID,
+) -> HashSet<BlockID> {
+    let mut body: HashSet<BlockID> = Some(to).into_iter().collect();
+    let mut stack = vec![from];
+    while let Some(block_id) = stack.pop() {
+        if body.insert(block_id) {
+            stack.extend(cfg.predecessors(block_id));
+        }
+    }
+    body
+}
+
+pub struct Dominators<'a, G: ?Sized = Program> {
     sets: Vec<HashSet<BlockID>>,
-    program: &'a Program,
+    // blocks unreachable from ENTRY are left with the top value, i.e. dominated by every block
+    reachable: HashSet<BlockID>,
+    cfg: &'a G,
 }
 
-impl<'a> Dominators<'a> {
-    pub fn new(program: &'a Program) -> Self {
-        let sets = DataFlow::<Dominator, Forward, DominatorT>::run(program, &())
+impl<'a, G: Cfg + ?Sized> Dominators<'a, G> {
+    pub fn new(cfg: &'a G) -> Self {
+        let sets = DataFlow::<Dominator, Forward, DominatorT>::run(cfg, &())
             .into_iter()
             .map(|(_, out_value)| out_value.ids)
             .collect();
 
-        Dominators { sets, program }
+        Dominators {
+            sets,
+            reachable: reachable(cfg),
+            cfg,
+        }
+    }
+
+    pub fn is_reachable(&self, id: BlockID) -> bool {
+        self.reachable.contains(&id)
     }
 
     pub fn rel(&self, from: BlockID, to: BlockID) -> bool {
@@ -68,8 +111,7 @@ impl<'a> Dominators<'a> {
     }
 
     pub fn dominated(&self, dom: BlockID) -> HashSet<BlockID> {
-        self.program
-            .block_range()
+        (0..self.cfg.len())
             .filter(|i| self.sets[*i].contains(&dom))
             .collect()
     }
@@ -109,13 +151,59 @@ impl<'a> Dominators<'a> {
         tree
     }
 
+    /// None for ENTRY and blocks unreachable from ENTRY
+    pub fn immediate(&self, id: BlockID) -> Option<BlockID> {
+        if !self.reachable.contains(&id) {
+            return None;
+        }
+
+        // strict dominators form a chain, the closest one is dominated by all others
+        self.sets[id]
+            .iter()
+            .filter(|dom| **dom != id)
+            .max_by_key(|dom| self.sets[**dom].len())
+            .cloned()
+    }
+
+    /// Dominance frontiers of all blocks, computed by walking up the dominator tree from the
+    /// predecessors of each join point as in Cooper, Harvey and Kennedy.
+    pub fn frontiers(&self) -> Vec<HashSet<BlockID>> {
+        let mut frontiers = vec![HashSet::new(); self.cfg.len()];
+
+        for &join in &self.reachable {
+            let preds: Vec<BlockID> = self
+                .cfg
+                .predecessors(join)
+                .into_iter()
+                .filter(|p| self.reachable.contains(p))
+                .collect();
+            if preds.len() < 2 {
+                continue;
+            }
+
+            let idom = self.immediate(join);
+            for pred in preds {
+                let mut runner = Some(pred);
+                while runner != idom {
+                    let r = runner.expect("Frontiers: ENTRY dominates every reachable block");
+                    frontiers[r].insert(join);
+                    runner = self.immediate(r);
+                }
+            }
+        }
+
+        frontiers
+    }
+
     pub fn is_reducible(&self) -> bool {
-        let graph: GraphMap<BlockID, (), Directed> = GraphMap::from_edges(
-            self.program
-                .edges()
+        let mut graph: GraphMap<BlockID, (), Directed> = GraphMap::from_edges(
+            edges(self.cfg)
                 .into_iter()
                 .filter(|(from, to)| !self.rel(*to, *from)),
         );
+        for id in 0..self.cfg.len() {
+            graph.add_node(id);
+        }
 
         toposort(&graph, None).is_ok()
     }
@@ -127,30 +215,24 @@ impl<'a> Dominators<'a> {
             from,
             to
         );
-        self.program.natural_loop(from, to)
+        natural_loop(self.cfg, from, to)
     }
 
+    /// Edges between reachable blocks whose head dominates their tail.
     pub fn back_edges(&self) -> Vec<(BlockID, BlockID)> {
-        let mut edges = vec![];
-
-        self.program.dfs_order(self.program.entry(), |event| {
-            if let DfsEvent::BackEdge(from, to) = event {
-                if self.rel(to, from) {
-                    edges.push((from, to));
-                }
-            }
-        });
-
-        edges
+        edges(self.cfg)
+            .into_iter()
+            .filter(|(from, to)| self.reachable.contains(from) && self.rel(*to, *from))
+            .collect()
     }
 }
 
-impl<'a> std::fmt::Debug for Dominators<'a> {
+impl<G: Cfg + ?Sized> std::fmt::Debug for Dominators<'_, G> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         for (i, doms) in self.sets.iter().enumerate() {
-            let name = if i == self.program.entry() {
+            let name = if i == self.cfg.entry() {
                 "ENTRY".into()
-            } else if Some(i) == self.program.exit() {
+            } else if Some(i) == self.cfg.exit() {
                 "EXIT".into()
             } else {
                 format!("B{}", i)
@@ -219,3 +301,28 @@ fn dominators_test() {
         vec![1, 3, 4, 7, 8, 10, 0].into_iter().collect()
     );
 }
+
+#[test]
+fn frontiers_test() {
+    let program = crate::figure_9_13();
+    let frontiers = Dominators::new(&program).frontiers();
+    assert_eq!(frontiers[1], HashSet::new());
+    assert_eq!(frontiers[2], vec![2].into_iter().collect());
+    assert_eq!(frontiers[3], vec![4].into_iter().collect());
+    assert_eq!(frontiers[4], vec![2].into_iter().collect());
+
+    // DF(n) contains b iff n dominates a predecessor of b but does not strictly dominate b
+    let program = figure_9_38();
+    let dominators = Dominators::new(&program);
+    let frontiers = dominators.frontiers();
+    for n in program.block_range() {
+        let expected: HashSet<BlockID> = program
+            .block_range()
+            .filter(|b| {
+                program.predecessors(*b).any(|(p, _)| dominators.rel(n, p))
+                    && !(n != *b && dominators.rel(n, *b))
+            })
+            .collect();
+        assert_eq!(frontiers[n], expected, "DF(B{})", n);
+    }
+}
@@ -1,331 +1,414 @@
-use crate::{BlockID, BlockType, Program};
+//! A data-flow framework generic over control flow graphs. Problems either implement
+//! [`Problem`] directly, split into a [`SemiLattice`] and per-block [`Transfer`] functions run by
+//! [`DataFlow`], or are declared statement by statement with [`StmtProblem`]. [`solve`] finds the
+//! maximum fixed point with a worklist, `region::solve` by region-based analysis.
+
+use crate::{BlockID, Program, Stmt};
+use std::collections::BTreeSet;
 use std::marker::PhantomData;
 
-pub trait Direction {}
+pub trait Direction {
+    const FORWARD: bool;
+}
 
 pub enum Forward {}
-impl Direction for Forward {}
-pub enum Backward {}
-impl Direction for Backward {}
-
-pub trait SemiLattice<'a>: PartialEq + Clone + 'a {
-    fn top(program: &'a Program) -> Self;
-    fn start(program: &'a Program) -> Self;
-    fn meet(&self, other: &Self) -> Self;
+impl Direction for Forward {
+    const FORWARD: bool = true;
 }
-
-pub trait Transfer<'a>: Clone + 'a {
-    type Target;
-    // some data flow analysis requires data from outside the program or computed from previous passes
-    // to initiate the transfer functions, e.g. the four-pass analysis of partial redundent expressions
-    type Extra: ?Sized;
-
-    fn new(block_id: BlockID, program: &'a Program, data: &Self::Extra) -> Self;
-    fn apply(&self, value: &Self::Target) -> Self::Target;
+pub enum Backward {}
+impl Direction for Backward {
+    const FORWARD: bool = false;
 }
 
-#[derive(Debug)]
-enum AttrType<V> {
-    Entry(V),
-    Basic(V, V),
-    Exit(V),
+/// A flow graph with blocks numbered from 0. ENTRY has no predecessors, EXIT if any has no
+/// successors.
+pub trait Cfg {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn entry(&self) -> BlockID;
+    fn exit(&self) -> Option<BlockID>;
+    fn successors(&self, block_id: BlockID) -> Vec<BlockID>;
+    fn predecessors(&self, block_id: BlockID) -> Vec<BlockID>;
 }
 
-pub struct Attr<V, D, T, E: ?Sized> {
-    block: AttrType<V>,
-    tranfer: T,
-    _direction: PhantomData<D>,
-    _extra: PhantomData<E>,
+/// A flow graph whose blocks are sequences of statements.
+pub trait StmtCfg: Cfg {
+    type Stmt;
+    fn stmts(&self, block_id: BlockID) -> &[Self::Stmt];
 }
 
-impl<V, D, T, E: ?Sized> Attr<V, D, T, E>
-where
-    V: Clone,
-{
-    fn new_block(block: AttrType<V>, tranfer: T) -> Self {
-        Attr {
-            block,
-            tranfer,
-            _direction: PhantomData,
-            _extra: PhantomData,
-        }
+impl Cfg for Program {
+    fn len(&self) -> usize {
+        Program::len(self)
     }
 
-    pub fn in_value(&self) -> Option<&V> {
-        match &self.block {
-            AttrType::Basic(v, _) => Some(v),
-            AttrType::Exit(v) => Some(v),
-            _ => None,
-        }
+    fn entry(&self) -> BlockID {
+        Program::entry(self)
     }
 
-    pub fn in_value_mut(&mut self) -> Option<&mut V> {
-        match &mut self.block {
-            AttrType::Basic(v, _) => Some(v),
-            AttrType::Exit(v) => Some(v),
-            _ => None,
-        }
+    fn exit(&self) -> Option<BlockID> {
+        Program::exit(self)
     }
 
-    pub fn out_value(&self) -> Option<&V> {
-        match &self.block {
-            AttrType::Entry(v) => Some(v),
-            AttrType::Basic(_, v) => Some(v),
-            _ => None,
-        }
+    fn successors(&self, block_id: BlockID) -> Vec<BlockID> {
+        Program::successors(self, block_id)
+            .map(|(s, _)| s)
+            .collect()
     }
 
-    pub fn out_value_mut(&mut self) -> Option<&mut V> {
-        match &mut self.block {
-            AttrType::Entry(v) => Some(v),
-            AttrType::Basic(_, v) => Some(v),
-            _ => None,
-        }
+    fn predecessors(&self, block_id: BlockID) -> Vec<BlockID> {
+        Program::predecessors(self, block_id)
+            .map(|(p, _)| p)
+            .collect()
     }
+}
 
-    pub fn into_pair(self) -> (V, V) {
-        use AttrType::*;
-        match self.block {
-            Entry(v) => (v.clone(), v),
-            Basic(in_v, out_v) => (in_v, out_v),
-            Exit(v) => (v.clone(), v),
-        }
+impl StmtCfg for Program {
+    type Stmt = Stmt;
+
+    fn stmts(&self, block_id: BlockID) -> &[Stmt] {
+        &self.blocks[block_id].stmts
     }
 }
 
-impl<'a, V, D, T, E: ?Sized> Attr<V, D, T, E>
-where
-    V: SemiLattice<'a>,
-    D: Direction,
-    T: Transfer<'a, Target = V, Extra = E>,
-{
-    fn new_basic(program: &'a Program, transfer: T) -> Self {
-        Attr::new_block(AttrType::Basic(V::top(program), V::top(program)), transfer)
-    }
+/// A data-flow problem on the blocks of a flow graph. The boundary value flows out of ENTRY
+/// (into EXIT for backward problems), neither ENTRY nor EXIT applies a transfer function.
+pub trait Problem<G: Cfg + ?Sized> {
+    type Value: Clone + PartialEq;
+    type Direction: Direction;
+
+    fn top(&self, cfg: &G) -> Self::Value;
+    fn boundary(&self, cfg: &G) -> Self::Value;
+    fn meet(&self, lhs: &Self::Value, rhs: &Self::Value) -> Self::Value;
+    fn transfer(&self, cfg: &G, block_id: BlockID, value: &Self::Value) -> Self::Value;
 }
 
-impl<'a, V, T, E: ?Sized> Attr<V, Forward, T, E>
-where
-    V: SemiLattice<'a>,
-    T: Transfer<'a, Target = V, Extra = E>,
-{
-    fn new_entry(program: &'a Program, transfer: T) -> Self {
-        Attr::new_block(AttrType::Entry(V::start(program)), transfer)
+/// Problems whose transfer functions have a representation closed under composition, meet and
+/// closure, so they can be summarized over regions.
+pub trait Summarize<G: Cfg + ?Sized>: Problem<G> {
+    type Function: Clone;
+
+    fn function(&self, cfg: &G, block_id: BlockID) -> Self::Function;
+    fn identity(&self, cfg: &G) -> Self::Function;
+    /// `first` followed by `then`
+    fn compose(&self, first: &Self::Function, then: &Self::Function) -> Self::Function;
+    fn meet_functions(&self, lhs: &Self::Function, rhs: &Self::Function) -> Self::Function;
+    /// Meet of the identity and all powers of `f`, i.e. any number of trips around a loop.
+    fn closure(&self, f: &Self::Function) -> Self::Function;
+    fn apply(&self, f: &Self::Function, value: &Self::Value) -> Self::Value;
+}
+
+/// IN and OUT values of every block.
+#[derive(Debug, Clone)]
+pub struct Attrs<V> {
+    pairs: Vec<(V, V)>,
+}
+
+impl<V> Attrs<V> {
+    pub(crate) fn new(pairs: Vec<(V, V)>) -> Self {
+        Attrs { pairs }
     }
 
-    fn new_exit(program: &'a Program, transfer: T) -> Self {
-        Attr::new_block(AttrType::Exit(V::top(program)), transfer)
+    pub fn in_value(&self, block_id: BlockID) -> &V {
+        &self.pairs[block_id].0
     }
 
-    fn new(block_id: BlockID, program: &'a Program, extra: &E) -> Self {
-        let block = program
-            .get_block(block_id)
-            .expect("Initialize Attr: Block inbound");
-        let transfer = T::new(block_id, program, extra);
+    pub fn out_value(&self, block_id: BlockID) -> &V {
+        &self.pairs[block_id].1
+    }
 
-        match block.btype {
-            BlockType::Entry => Self::new_entry(program, transfer),
-            BlockType::Basic => Self::new_basic(program, transfer),
-            BlockType::Exit => Self::new_exit(program, transfer),
-        }
+    pub fn into_pairs(self) -> Vec<(V, V)> {
+        self.pairs
     }
+}
 
-    fn update(&mut self) -> bool {
-        match &mut self.block {
-            AttrType::Basic(in_val, out_val) => {
-                let new_out = self.tranfer.apply(in_val);
-                let changed = out_val != &new_out;
-                *out_val = new_out;
-                changed
+impl<V> IntoIterator for Attrs<V> {
+    type Item = (V, V);
+    type IntoIter = std::vec::IntoIter<(V, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pairs.into_iter()
+    }
+}
+
+/// Blocks in reverse postorder from ENTRY followed by blocks unreachable from it, reversed for
+/// backward problems.
+fn order<G: Cfg + ?Sized>(cfg: &G, forward: bool) -> Vec<BlockID> {
+    let mut visited = vec![false; cfg.len()];
+    let mut postorder = vec![];
+    let mut stack = vec![(cfg.entry(), cfg.successors(cfg.entry()).into_iter())];
+    visited[cfg.entry()] = true;
+
+    while let Some((block_id, succs)) = stack.last_mut() {
+        let block_id = *block_id;
+        match succs.next() {
+            Some(s) if !visited[s] => {
+                visited[s] = true;
+                stack.push((s, cfg.successors(s).into_iter()));
+            }
+            Some(_) => (),
+            None => {
+                postorder.push(block_id);
+                stack.pop();
             }
-            _ => false,
         }
     }
+
+    let unreachable = (0..cfg.len()).filter(|i| !visited[*i]);
+    let mut order: Vec<_> = postorder.into_iter().rev().chain(unreachable).collect();
+    if !forward {
+        order.reverse();
+    }
+    order
 }
 
-#[allow(dead_code)]
-impl<'a, V, T, E: ?Sized> Attr<V, Backward, T, E>
+/// Maximum fixed point of the problem. Blocks are taken from a worklist in reverse postorder
+/// (postorder for backward problems) and only revisited when a value flowing into them changed.
+pub fn solve<G, P>(cfg: &G, problem: &P) -> Attrs<P::Value>
 where
-    V: SemiLattice<'a>,
-    T: Transfer<'a, Target = V, Extra = E>,
+    G: Cfg + ?Sized,
+    P: Problem<G>,
 {
-    fn new_entry(program: &'a Program, transfer: T) -> Self {
-        Attr::new_block(AttrType::Entry(V::top(program)), transfer)
+    let forward = P::Direction::FORWARD;
+    let (start, end) = if forward {
+        (Some(cfg.entry()), cfg.exit())
+    } else {
+        (cfg.exit(), Some(cfg.entry()))
+    };
+
+    // values before and after each block in the direction of the flow
+    let top = problem.top(cfg);
+    let mut before = vec![top.clone(); cfg.len()];
+    let mut after = vec![top; cfg.len()];
+    if let Some(start) = start {
+        before[start] = problem.boundary(cfg);
+        after[start] = before[start].clone();
     }
 
-    fn new_exit(program: &'a Program, transfer: T) -> Self {
-        Attr::new_block(AttrType::Exit(V::start(program)), transfer)
+    let order = order(cfg, forward);
+    let mut rank = vec![0; cfg.len()];
+    for (i, block_id) in order.iter().enumerate() {
+        rank[*block_id] = i;
     }
-
-    fn new(block_id: BlockID, program: &'a Program, extra: &E) -> Self {
-        let block = program
-            .get_block(block_id)
-            .expect("Initialize Attr: Block inbound");
-        let transfer = T::new(block_id, program, extra);
-
-        match block.btype {
-            BlockType::Entry => Self::new_entry(program, transfer),
-            BlockType::Basic => Self::new_basic(program, transfer),
-            BlockType::Exit => Self::new_exit(program, transfer),
+    let mut worklist: BTreeSet<usize> = (0..order.len())
+        .filter(|i| Some(order[*i]) != start)
+        .collect();
+
+    while let Some(i) = worklist.pop_first() {
+        let block_id = order[i];
+        let (sources, targets) = if forward {
+            (cfg.predecessors(block_id), cfg.successors(block_id))
+        } else {
+            (cfg.successors(block_id), cfg.predecessors(block_id))
+        };
+
+        // blocks without sources keep their value
+        let met = sources
+            .iter()
+            .map(|s| &after[*s])
+            .fold(None, |met, value| match met {
+                Some(met) => Some(problem.meet(&met, value)),
+                None => Some(value.clone()),
+            });
+        if let Some(met) = met {
+            before[block_id] = met;
         }
-    }
 
-    fn update(&mut self) -> bool {
-        match &mut self.block {
-            AttrType::Basic(in_val, out_val) => {
-                let new_in = self.tranfer.apply(out_val);
-                let changed = in_val != &new_in;
-                *in_val = new_in;
-                changed
-            }
-            _ => false,
+        let value = if Some(block_id) == end {
+            before[block_id].clone()
+        } else {
+            problem.transfer(cfg, block_id, &before[block_id])
+        };
+        if value != after[block_id] {
+            after[block_id] = value;
+            worklist.extend(
+                targets
+                    .into_iter()
+                    .filter(|t| Some(*t) != start)
+                    .map(|t| rank[t]),
+            );
         }
     }
+
+    let pairs = if forward {
+        before.into_iter().zip(after).collect()
+    } else {
+        after.into_iter().zip(before).collect()
+    };
+    Attrs::new(pairs)
 }
 
-pub struct Attrs<V, D, T, E: ?Sized> {
-    pub attrs: Vec<Attr<V, D, T, E>>,
+pub trait SemiLattice<'a, G: ?Sized + 'a = Program>: PartialEq + Clone + 'a {
+    fn top(program: &'a G) -> Self;
+    fn start(program: &'a G) -> Self;
+    fn meet(&self, other: &Self) -> Self;
 }
 
-impl<'a, V, D, T, E: ?Sized> Attrs<V, D, T, E>
+pub trait Transfer<'a, G: ?Sized + 'a = Program>: Clone + 'a {
+    type Target;
+    // some data flow analysis requires data from outside the program or computed from previous passes
+    // to initiate the transfer functions, e.g. the four-pass analysis of partial redundent expressions
+    type Extra: ?Sized;
+
+    fn new(block_id: BlockID, program: &'a G, data: &Self::Extra) -> Self;
+    fn apply(&self, value: &Self::Target) -> Self::Target;
+}
+
+/// A semilattice and the transfer functions of all blocks as a problem.
+struct Blocks<'a, G: ?Sized, V, D, T> {
+    top: V,
+    start: V,
+    transfers: Vec<T>,
+    _marker: PhantomData<(&'a G, D)>,
+}
+
+impl<'a, G, V, D, T> Problem<G> for Blocks<'a, G, V, D, T>
 where
-    V: SemiLattice<'a>,
+    G: Cfg + ?Sized,
+    V: SemiLattice<'a, G>,
+    D: Direction,
+    T: Transfer<'a, G, Target = V>,
 {
-    pub fn into_iter(self) -> impl Iterator<Item = (V, V)> {
-        self.attrs.into_iter().map(Attr::into_pair)
+    type Value = V;
+    type Direction = D;
+
+    fn top(&self, _: &G) -> V {
+        self.top.clone()
     }
 
-    pub fn into_pairs(self) -> Vec<(V, V)> {
-        self.into_iter().collect()
+    fn boundary(&self, _: &G) -> V {
+        self.start.clone()
+    }
+
+    fn meet(&self, lhs: &V, rhs: &V) -> V {
+        lhs.meet(rhs)
+    }
+
+    fn transfer(&self, _: &G, block_id: BlockID, value: &V) -> V {
+        self.transfers[block_id].apply(value)
     }
 }
 
 pub struct DataFlow<V, D, T, E: ?Sized = ()> {
-    attrs: Vec<Attr<V, D, T, E>>,
+    _marker: PhantomData<(V, D, T)>,
+    _extra: PhantomData<E>,
 }
 
-impl<'a, V, T, E: ?Sized> DataFlow<V, Forward, T, E>
+impl<V, D, T, E: ?Sized> DataFlow<V, D, T, E>
 where
-    V: SemiLattice<'a>,
-    T: Transfer<'a, Target = V, Extra = E>,
+    D: Direction,
 {
-    fn new(program: &'a Program, extra: &E) -> Self {
-        let attrs = program
-            .block_range()
-            .map(|block_id| Attr::<V, Forward, T, E>::new(block_id, program, extra))
-            .collect();
-
-        DataFlow { attrs }
-    }
-
-    // previous version inits the fold with the current in_value
-    // which may cause inconvenience for certain kind of data flow analysis (e.g. constant propagation)
-    // now this method only reuses in_value of the current block when the block have no predecessors / successors
-    fn propagate(&self, block_id: BlockID, program: &Program) -> V {
-        let meet = program
-            .predecessors(block_id)
-            .map(|(p, _)| {
-                self.attrs[p]
-                    .out_value()
-                    .expect("EXIT should not have successors")
-            })
-            .fold(None, |opt: Option<V>, p_val| {
-                if let Some(in_val) = opt {
-                    Some(in_val.meet(p_val))
-                } else {
-                    Some(p_val.clone())
-                }
-            });
-
-        meet.unwrap_or_else(|| {
-            self.attrs[block_id]
-                .in_value()
-                .expect("Propagate: Non-ENTRY block")
-                .clone()
-        })
+    pub fn run<'a, G>(program: &'a G, extra: &E) -> Attrs<V>
+    where
+        G: Cfg + ?Sized,
+        V: SemiLattice<'a, G>,
+        T: Transfer<'a, G, Target = V, Extra = E>,
+    {
+        let problem = Blocks::<G, V, D, T> {
+            top: V::top(program),
+            start: V::start(program),
+            transfers: (0..program.len())
+                .map(|block_id| T::new(block_id, program, extra))
+                .collect(),
+            _marker: PhantomData,
+        };
+
+        solve(program, &problem)
     }
+}
 
-    fn compute(mut self, program: &Program) -> Attrs<V, Forward, T, E> {
-        let mut updated = true;
+/// The `index`-th statement of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub block_id: BlockID,
+    pub index: usize,
+}
 
-        while updated {
-            updated = false;
-            for block_id in program.block_range().filter(|i| *i != program.entry()) {
-                *self.attrs[block_id]
-                    .in_value_mut()
-                    .expect("Forward compute: Non-ENTRY block") = self.propagate(block_id, program);
-                updated = self.attrs[block_id].update() || updated;
-            }
-        }
+/// A problem declared by the effect of single statements, a block applies its statements in the
+/// direction of the flow.
+pub trait StmtProblem<G: StmtCfg + ?Sized> {
+    type Value: Clone + PartialEq;
+    type Direction: Direction;
 
-        Attrs { attrs: self.attrs }
+    fn top(&self, cfg: &G) -> Self::Value;
+    fn boundary(&self, cfg: &G) -> Self::Value;
+    fn meet(&self, lhs: &Self::Value, rhs: &Self::Value) -> Self::Value;
+    fn apply(&self, cfg: &G, at: Point, stmt: &G::Stmt, value: &mut Self::Value);
+}
+
+pub struct Stmts<P>(pub P);
+
+impl<P> Stmts<P> {
+    /// Apply the statements of `block_id` before `index` in the direction of the flow, i.e. all
+    /// statements up to `index` for backward problems.
+    fn apply_until<G>(&self, cfg: &G, block_id: BlockID, index: usize, value: &mut P::Value)
+    where
+        G: StmtCfg + ?Sized,
+        P: StmtProblem<G>,
+    {
+        let stmts = cfg.stmts(block_id);
+        let indices: Box<dyn Iterator<Item = usize>> = if P::Direction::FORWARD {
+            Box::new(0..index)
+        } else {
+            Box::new((index..stmts.len()).rev())
+        };
+
+        for index in indices {
+            let at = Point { block_id, index };
+            self.0.apply(cfg, at, &stmts[index], value);
+        }
     }
 
-    pub fn run(program: &'a Program, extra: &E) -> Attrs<V, Forward, T, E> {
-        let data_flow = Self::new(program, extra);
-        data_flow.compute(program)
+    /// The value flowing into the statement at `at`, i.e. right before it for forward problems
+    /// and right after it for backward ones.
+    pub fn at<G>(&self, cfg: &G, attrs: &Attrs<P::Value>, at: Point) -> P::Value
+    where
+        G: StmtCfg + ?Sized,
+        P: StmtProblem<G>,
+    {
+        if P::Direction::FORWARD {
+            let mut value = attrs.in_value(at.block_id).clone();
+            self.apply_until(cfg, at.block_id, at.index, &mut value);
+            value
+        } else {
+            let mut value = attrs.out_value(at.block_id).clone();
+            self.apply_until(cfg, at.block_id, at.index + 1, &mut value);
+            value
+        }
     }
 }
 
-#[allow(dead_code)]
-impl<'a, V, T, E: ?Sized> DataFlow<V, Backward, T, E>
+impl<G, P> Problem<G> for Stmts<P>
 where
-    V: SemiLattice<'a>,
-    T: Transfer<'a, Target = V, Extra = E>,
+    G: StmtCfg + ?Sized,
+    P: StmtProblem<G>,
 {
-    fn new(program: &'a Program, extra: &E) -> Self {
-        let attrs = program
-            .block_range()
-            .map(|block_id| Attr::<V, Backward, T, E>::new(block_id, program, extra))
-            .collect();
-
-        DataFlow { attrs }
-    }
-
-    // see propagate of Forward version
-    fn propagate(&self, block_id: BlockID, program: &Program) -> V {
-        let meet = program
-            .successors(block_id)
-            .map(|(s, _)| {
-                self.attrs[s]
-                    .in_value()
-                    .expect("Propagate: ENTRY should not have predecessors")
-            })
-            .fold(None, |opt: Option<V>, s_val| {
-                if let Some(out_val) = opt {
-                    Some(out_val.meet(s_val))
-                } else {
-                    Some(s_val.clone())
-                }
-            });
+    type Value = P::Value;
+    type Direction = P::Direction;
 
-        meet.unwrap_or_else(|| {
-            self.attrs[block_id]
-                .out_value()
-                .expect("Propagate: Non-EXIT block")
-                .clone()
-        })
+    fn top(&self, cfg: &G) -> P::Value {
+        self.0.top(cfg)
     }
 
-    fn compute(mut self, program: &Program) -> Attrs<V, Backward, T, E> {
-        let mut updated = true;
-
-        while updated {
-            updated = false;
-            for block_id in program.block_range().filter(|i| Some(*i) != program.exit()) {
-                *self.attrs[block_id]
-                    .out_value_mut()
-                    .expect("Backward compute: Non-EXIT block") = self.propagate(block_id, program);
-                updated = self.attrs[block_id].update() || updated;
-            }
-        }
+    fn boundary(&self, cfg: &G) -> P::Value {
+        self.0.boundary(cfg)
+    }
 
-        Attrs { attrs: self.attrs }
+    fn meet(&self, lhs: &P::Value, rhs: &P::Value) -> P::Value {
+        self.0.meet(lhs, rhs)
     }
 
-    pub fn run(program: &'a Program, extra: &'a E) -> Attrs<V, Backward, T, E> {
-        let data_flow = Self::new(program, extra);
-        data_flow.compute(program)
+    fn transfer(&self, cfg: &G, block_id: BlockID, value: &P::Value) -> P::Value {
+        let mut value = value.clone();
+        let all = if P::Direction::FORWARD {
+            cfg.stmts(block_id).len()
+        } else {
+            0
+        };
+        self.apply_until(cfg, block_id, all, &mut value);
+        value
     }
 }
 
@@ -370,30 +453,135 @@ mod test {
         }
     }
 
-    fn reaching_definitions(program: &Program) -> Attrs<Defs, Forward, GenKill, ()> {
+    fn reaching_definitions(program: &Program) -> Attrs<Defs> {
         DataFlow::<Defs, Forward, GenKill, ()>::run(program, &())
     }
 
     #[test]
     fn reaching_definitions_test() {
         let program = crate::figure_9_13();
-        let attrs = reaching_definitions(&program).attrs;
+        let attrs = reaching_definitions(&program);
 
         assert_eq!(
-            attrs[1].out_value().unwrap().value,
+            attrs.out_value(1).value,
             vec![1, 2, 3].into_iter().collect()
         );
         assert_eq!(
-            attrs[2].out_value().unwrap().value,
+            attrs.out_value(2).value,
             vec![3, 4, 5, 6].into_iter().collect()
         );
         assert_eq!(
-            attrs[3].out_value().unwrap().value,
+            attrs.out_value(3).value,
             vec![4, 5, 6].into_iter().collect()
         );
         assert_eq!(
-            attrs[4].out_value().unwrap().value,
+            attrs.out_value(4).value,
             vec![3, 5, 6, 7].into_iter().collect()
         );
     }
+
+    /// A flow graph outside of `Program`, each statement defines a variable from others.
+    struct Graph {
+        edges: Vec<(BlockID, BlockID)>,
+        blocks: Vec<Vec<(char, Vec<char>)>>,
+    }
+
+    impl Cfg for Graph {
+        fn len(&self) -> usize {
+            self.blocks.len()
+        }
+
+        fn entry(&self) -> BlockID {
+            0
+        }
+
+        fn exit(&self) -> Option<BlockID> {
+            Some(self.blocks.len() - 1)
+        }
+
+        fn successors(&self, block_id: BlockID) -> Vec<BlockID> {
+            self.edges
+                .iter()
+                .filter(|(from, _)| *from == block_id)
+                .map(|(_, to)| *to)
+                .collect()
+        }
+
+        fn predecessors(&self, block_id: BlockID) -> Vec<BlockID> {
+            self.edges
+                .iter()
+                .filter(|(_, to)| *to == block_id)
+                .map(|(from, _)| *from)
+                .collect()
+        }
+    }
+
+    impl StmtCfg for Graph {
+        type Stmt = (char, Vec<char>);
+
+        fn stmts(&self, block_id: BlockID) -> &[Self::Stmt] {
+            &self.blocks[block_id]
+        }
+    }
+
+    struct Live;
+
+    impl StmtProblem<Graph> for Live {
+        type Value = HashSet<char>;
+        type Direction = Backward;
+
+        fn top(&self, _: &Graph) -> HashSet<char> {
+            HashSet::new()
+        }
+
+        fn boundary(&self, _: &Graph) -> HashSet<char> {
+            Some('r').into_iter().collect()
+        }
+
+        fn meet(&self, lhs: &HashSet<char>, rhs: &HashSet<char>) -> HashSet<char> {
+            lhs | rhs
+        }
+
+        fn apply(
+            &self,
+            _: &Graph,
+            _: Point,
+            (def, uses): &(char, Vec<char>),
+            live: &mut HashSet<char>,
+        ) {
+            live.remove(def);
+            live.extend(uses);
+        }
+    }
+
+    #[test]
+    fn stmt_problem_test() {
+        // ENTRY -> B1 -> B2 <-> B3, B2 -> EXIT
+        let graph = Graph {
+            edges: vec![(0, 1), (1, 2), (2, 3), (3, 2), (2, 4)],
+            blocks: vec![
+                vec![],
+                vec![('a', vec!['x']), ('r', vec![])],
+                vec![('b', vec!['a', 'r'])],
+                vec![('r', vec!['b']), ('a', vec!['a'])],
+                vec![],
+            ],
+        };
+
+        let live = Stmts(Live);
+        let attrs = solve(&graph, &live);
+        let set = |s: &str| s.chars().collect::<HashSet<char>>();
+
+        assert_eq!(attrs.in_value(1), &set("x"));
+        assert_eq!(attrs.in_value(2), &set("ar"));
+        assert_eq!(attrs.out_value(2), &set("abr"));
+        assert_eq!(attrs.in_value(3), &set("ab"));
+        assert_eq!(attrs.in_value(4), &set("r"));
+        // live right after a = x
+        let at = Point {
+            block_id: 1,
+            index: 0,
+        };
+        assert_eq!(live.at(&graph, &attrs, at), set("a"));
+    }
 }
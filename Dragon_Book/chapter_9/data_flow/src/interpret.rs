@@ -0,0 +1,168 @@
+//! A reference interpreter. Branch conditions are not part of the program, so control follows
+//! a path of blocks chosen by the caller.
+
+use crate::ssa::Phi;
+use crate::{BlockID, Lit, Program, RValue, Stmt, Var};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub type Env = HashMap<Var, Lit>;
+
+fn eval(env: &Env, rvalue: &RValue) -> Lit {
+    match rvalue {
+        RValue::Lit(lit) => *lit,
+        // variables never assigned read as zero
+        RValue::Var(var) => env.get(var).cloned().unwrap_or(0),
+    }
+}
+
+pub fn execute(stmt: &Stmt, env: &mut Env) {
+    match stmt {
+        Stmt::Op(dst, lhs, op, rhs) => {
+            let value = op.apply(eval(env, lhs), eval(env, rhs));
+            env.insert(dst.clone(), value);
+        }
+        Stmt::Copy(dst, src) => {
+            let value = eval(env, src);
+            env.insert(dst.clone(), value);
+        }
+    }
+}
+
+/// Blocks strictly between `from` and `to` along a shortest path through blocks numbered `added`
+/// or higher, i.e. blocks inserted by transformations into edges or split off from other blocks.
+fn connect(program: &Program, from: BlockID, to: BlockID, added: BlockID) -> Option<Vec<BlockID>> {
+    let mut parents: HashMap<BlockID, BlockID> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+
+    while let Some(n) = queue.pop_front() {
+        for (succ, _) in program.successors(n) {
+            if succ == to {
+                let mut between = vec![];
+                let mut m = n;
+                while m != from {
+                    between.push(m);
+                    m = parents[&m];
+                }
+                between.reverse();
+                return Some(between);
+            }
+            if succ >= added && visited.insert(succ) {
+                parents.insert(succ, n);
+                queue.push_back(succ);
+            }
+        }
+    }
+
+    None
+}
+
+/// The outcome of running a path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub env: Env,
+    /// Values computed by arithmetic statements in the order they were executed, independent of
+    /// how variables are named.
+    pub trace: Vec<Lit>,
+}
+
+/// Run the blocks along `path` starting from `env`, None if the path does not follow the edges
+/// of the program. Blocks numbered `added` or higher may be passed through between two blocks of
+/// the path, so a path of a program is still valid after it is transformed.\
+/// `phis` holds the phi functions of each block for a program in SSA form and may be empty
+/// otherwise. They are evaluated in parallel on entry to a block, with the arguments of the
+/// block executed just before it.
+pub fn run_path(
+    program: &Program,
+    phis: &[Vec<Phi>],
+    path: &[BlockID],
+    added: BlockID,
+    mut env: Env,
+) -> Option<Run> {
+    let mut blocks = vec![];
+    for (i, block_id) in path.iter().enumerate() {
+        if i > 0 {
+            blocks.extend(connect(program, path[i - 1], *block_id, added)?);
+        }
+        blocks.push(*block_id);
+    }
+
+    let mut trace = vec![];
+    let mut pred = None;
+    for block_id in blocks {
+        let phis = phis.get(block_id).map_or(&[][..], Vec::as_slice);
+        let values = phis
+            .iter()
+            .map(|phi| {
+                let (_, arg) = phi.args.iter().find(|(p, _)| Some(*p) == pred)?;
+                Some(eval(&env, arg))
+            })
+            .collect::<Option<Vec<Lit>>>()?;
+        for (phi, value) in phis.iter().zip(values) {
+            env.insert(phi.dst.clone(), value);
+        }
+
+        for stmt in program.get_block(block_id)?.stmts() {
+            execute(stmt, &mut env);
+            if let Stmt::Op(dst, ..) = stmt {
+                trace.push(env[dst]);
+            }
+        }
+        pred = Some(block_id);
+    }
+
+    Some(Run { env, trace })
+}
+
+/// Up to `limit` paths from ENTRY to a block without successors, visiting at most `max_len` blocks.
+/// Shorter paths come first.
+pub fn paths(program: &Program, max_len: usize, limit: usize) -> Vec<Vec<BlockID>> {
+    let mut complete = vec![];
+    let mut queue = VecDeque::new();
+    queue.push_back(vec![program.entry()]);
+
+    while let Some(path) = queue.pop_front() {
+        if complete.len() >= limit {
+            break;
+        }
+
+        let last = *path.last().unwrap();
+        let succs: Vec<BlockID> = program.successors(last).map(|(s, _)| s).collect();
+        if succs.is_empty() {
+            complete.push(path);
+        } else if path.len() < max_len {
+            for succ in succs {
+                let mut next = path.clone();
+                next.push(succ);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    complete
+}
+
+#[test]
+fn run_path_test() {
+    let program = crate::figure_9_13();
+    let paths = paths(&program, 8, 16);
+    assert_eq!(paths[0], vec![0, 1, 2, 4, 5]);
+    assert!(paths.iter().all(|path| path.len() <= 8));
+
+    let inputs: Env = vec![("m".to_string(), 5), ("n".to_string(), 9)]
+        .into_iter()
+        .collect();
+    let run = run_path(
+        &program,
+        &[],
+        &[0, 1, 2, 3, 4, 2, 4, 5],
+        program.len(),
+        inputs,
+    )
+    .unwrap();
+    assert_eq!(run.env["i"], 0);
+    assert_eq!(run.env["j"], 7);
+    assert_eq!(run.trace, vec![4, 5, 8, 1, 7]);
+    assert!(run_path(&program, &[], &[0, 2], program.len(), Env::new()).is_none());
+}
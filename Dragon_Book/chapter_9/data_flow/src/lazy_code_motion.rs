@@ -7,8 +7,7 @@ use std::collections::HashSet;
 type PairVec<T> = Vec<(T, T)>;
 pub type PairSlice<T> = [(T, T)];
 
-#[allow(dead_code)]
-fn cut_edges(mut program: Program) -> Program {
+pub(crate) fn cut_edges(mut program: Program) -> Program {
     for (from, to) in program.edges() {
         if program.predecessors(to).count() > 1 {
             program.insert_empty_between(from, to);
@@ -56,15 +55,17 @@ struct AnticipateT<'a> {
 
 impl<'a> AnticipateT<'a> {
     fn new(block: &'a Block) -> Self {
-        let mut gen = HashSet::new();
+        let mut gen: HashSet<Expr<'a>> = HashSet::new();
         let mut kill = HashSet::new();
 
+        // operands of a statement are read before its destination is written,
+        // so b + c is anticipated on entry to b = b + c
         for stmt in block.stmts().rev() {
-            gen.extend(stmt.as_expr());
             if let Some(def) = stmt.def() {
                 gen.retain(|expr| !expr.uses(def));
                 kill.insert(def);
             }
+            gen.extend(stmt.as_expr());
         }
 
         AnticipateT { gen, kill }
@@ -362,7 +363,7 @@ pub fn where_to_compute<'a>(
     assert_eq!(latests.len(), used.len());
 
     (0..latests.len())
-        .filter(|i| latests[*i].contains(&expr) && used[*i].1.exprs.contains(&expr))
+        .filter(|i| latests[*i].contains(expr) && used[*i].1.exprs.contains(expr))
         .collect()
 }
 
@@ -392,7 +393,7 @@ pub fn where_to_use<'a>(
 }
 
 #[cfg(test)]
-fn figure_9_33() -> Program {
+pub(crate) fn figure_9_33() -> Program {
     Program::with_entry_exit(
         vec![
             Block::empty(),
@@ -1,18 +1,21 @@
 pub mod available_expr;
+pub mod bit_vector;
 pub mod constant_propagation;
 pub mod dominator;
-pub(crate) mod framework;
+pub mod framework;
+pub mod interpret;
 pub mod lazy_code_motion;
 pub mod live_var;
+pub mod optimize;
 pub mod reaching_def;
 pub mod region;
+pub mod ssa;
 pub mod utils;
 
 use lazy_static::lazy_static;
 use petgraph::prelude::*;
-use petgraph::visit::{depth_first_search, Dfs, DfsEvent, VisitMap};
+use petgraph::visit::{depth_first_search, DfsEvent};
 use regex::Regex;
-use std::collections::HashSet;
 use std::fmt::{self, Debug, Formatter};
 
 lazy_static! {
@@ -26,7 +29,7 @@ type Lit = u32;
 pub type BlockID = usize;
 pub type StmtID = usize;
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum RValue {
     Var(Var),
     Lit(Lit),
@@ -89,12 +92,13 @@ impl BinOp {
         }
     }
 
+    // arithmetic wraps around, folding constants must agree with running the program
     fn apply(self, lhs: Lit, rhs: Lit) -> Lit {
         use BinOp::*;
         match self {
-            Add => lhs + rhs,
-            Sub => lhs - rhs,
-            Mul => lhs * rhs,
+            Add => lhs.wrapping_add(rhs),
+            Sub => lhs.wrapping_sub(rhs),
+            Mul => lhs.wrapping_mul(rhs),
         }
     }
 }
@@ -110,7 +114,7 @@ impl Debug for BinOp {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Stmt {
     Op(Var, RValue, BinOp, RValue),
     Copy(Var, RValue),
@@ -181,13 +185,14 @@ impl<'a> Debug for Expr<'a> {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum BlockType {
     Entry,
     Basic,
     Exit,
 }
 
+#[derive(Clone)]
 pub struct Block {
     start: usize,
     stmts: Vec<Stmt>,
@@ -266,7 +271,7 @@ impl Block {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Program {
     blocks: Vec<Block>,
     graph: GraphMap<usize, (), Directed>,
@@ -301,13 +306,11 @@ impl Program {
         0
     }
 
+    /// blocks inserted along edges are appended after EXIT
     pub fn exit(&self) -> Option<BlockID> {
-        let id = self.len() - 1;
-        if self.blocks[id].btype == BlockType::Exit {
-            Some(id)
-        } else {
-            None
-        }
+        self.blocks
+            .iter()
+            .rposition(|block| block.btype == BlockType::Exit)
     }
 
     pub fn blocks(&self) -> impl Iterator<Item = &Block> {
@@ -331,8 +334,12 @@ impl Program {
     }
 
     pub fn predecessors(&self, block_id: BlockID) -> impl Iterator<Item = (BlockID, &Block)> {
+        // petgraph keeps no incoming entry for self loops
+        let self_loop = Some(block_id).filter(|id| self.graph.contains_edge(*id, *id));
+
         self.graph
             .neighbors_directed(block_id, Direction::Incoming)
+            .chain(self_loop)
             .map(move |i| (i, &self.blocks[i]))
     }
 
@@ -368,6 +375,15 @@ impl Program {
         self.blocks().flat_map(Block::exprs)
     }
 
+    /// Number statements consecutively from 1 again after statements are added or removed.
+    pub(crate) fn renumber(&mut self) {
+        let mut start = 1;
+        for block in &mut self.blocks {
+            block.start = start;
+            start += block.len();
+        }
+    }
+
     pub fn dfs_order<F>(&self, start: BlockID, mut f: F) -> Vec<BlockID>
     where
         F: FnMut(DfsEvent<BlockID>),
@@ -386,23 +402,6 @@ impl Program {
         nodes
     }
 
-    pub(crate) fn natural_loop(&self, from: BlockID, to: BlockID) -> HashSet<BlockID> {
-        if from == to {
-            // for some reason, dfs in petgraph will run over the start node
-            // even when it is marked as visited
-            return Some(from).into_iter().collect();
-        }
-
-        let reverse: GraphMap<BlockID, (), Directed> =
-            GraphMap::from_edges(self.graph.all_edges().map(|(from, to, _)| (to, from)));
-
-        let mut dfs = Dfs::new(&reverse, from);
-        dfs.discovered.visit(to);
-        while let Some(..) = dfs.next(&reverse) {}
-
-        dfs.discovered
-    }
-
     pub fn reduce(&self) {
         let mut graph = self.graph().clone();
         while graph.node_count() > 1 {
@@ -484,23 +483,25 @@ d = c - a";
 
 #[test]
 fn natural_loop_test() {
+    use crate::dominator::natural_loop;
+
     let program = crate::dominator::figure_9_38();
 
     assert_eq!(
-        program.natural_loop(10, 7),
+        natural_loop(&program, 10, 7),
         vec![7, 8, 10].into_iter().collect()
     );
     assert_eq!(
-        program.natural_loop(7, 4),
+        natural_loop(&program, 7, 4),
         vec![4, 5, 6, 7, 8, 10].into_iter().collect()
     );
     assert_eq!(
-        program.natural_loop(4, 3),
+        natural_loop(&program, 4, 3),
         vec![3, 4, 5, 6, 7, 8, 10].into_iter().collect()
     );
     assert_eq!(
-        program.natural_loop(8, 3),
+        natural_loop(&program, 8, 3),
         vec![3, 4, 5, 6, 7, 8, 10].into_iter().collect()
     );
-    assert_eq!(program.natural_loop(9, 1), (1..=10).collect());
+    assert_eq!(natural_loop(&program, 9, 1), (1..=10).collect());
 }
@@ -78,6 +78,16 @@ pub struct LiveVariables<'a> {
     attrs: Vec<LiveVariable<'a>>,
 }
 
+impl<'a> LiveVariables<'a> {
+    pub fn live_in(&self, block_id: BlockID) -> &HashSet<&'a str> {
+        &self.attrs[block_id].in_set
+    }
+
+    pub fn live_out(&self, block_id: BlockID) -> &HashSet<&'a str> {
+        &self.attrs[block_id].out_set
+    }
+}
+
 impl<'a> Debug for LiveVariables<'a> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         for attr in &self.attrs {
@@ -99,6 +109,11 @@ fn meet<'a>(
 }
 
 pub fn live_variables(program: &Program) -> LiveVariables<'_> {
+    live_variables_on_exit(program, &[])
+}
+
+/// Live variables when `on_exit` are still used after blocks without successors, e.g. EXIT.
+pub fn live_variables_on_exit<'a>(program: &'a Program, on_exit: &[&'a str]) -> LiveVariables<'a> {
     let mut attrs: Vec<_> = (0..program.len())
         .map(|i| LiveVariable::new(i, program))
         .collect();
@@ -108,6 +123,9 @@ pub fn live_variables(program: &Program) -> LiveVariables<'_> {
         updated = false;
         for i in 0..program.len() {
             attrs[i].out_set = meet(i, program, &attrs);
+            if program.successors(i).next().is_none() {
+                attrs[i].out_set.extend(on_exit);
+            }
             updated = attrs[i].update() || updated;
         }
     }
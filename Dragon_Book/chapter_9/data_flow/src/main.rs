@@ -174,7 +174,7 @@ fn exercise_9_5_1() {
 fn exercise_9_5_2() {
     println!("Exercise 9.5.2:");
     let program = figure_9_10();
-    let stmts = vec![
+    let stmts = [
         Stmt::parse("z = a + b"),
         Stmt::parse("z = c - a"),
         Stmt::parse("z = b * d"),
@@ -0,0 +1,844 @@
+//! Transformations rewriting a program with the results of the data-flow analyses, run to a
+//! fixpoint by a pass manager that may check every change against the interpreter.
+
+use crate::available_expr::available_expressions;
+use crate::constant_propagation::constant_propagation;
+use crate::framework::{DataFlow, Forward, SemiLattice, Transfer};
+use crate::interpret::{paths, run_path, Env};
+use crate::lazy_code_motion::{
+    anticipates, availables, cut_edges, earliests, latests, postponables, used, where_to_compute,
+    where_to_use,
+};
+use crate::live_var::live_variables_on_exit;
+use crate::{BinOp, Block, BlockID, BlockType, Expr, Lit, Program, RValue, Stmt, Var};
+use std::collections::{HashMap, HashSet};
+
+/// The pass manager gives up after this many rounds without reaching a fixpoint.
+const MAX_ROUNDS: usize = 32;
+/// Paths and inputs the interpreter checks programs on.
+const MAX_PATH_LEN: usize = 24;
+const MAX_PATHS: usize = 64;
+const INPUTS: usize = 3;
+
+/// An expression apart from the statement it appears in.
+type Key = (RValue, BinOp, RValue);
+
+fn key(expr: &Expr<'_>) -> Key {
+    (expr.lhs.clone(), expr.op, expr.rhs.clone())
+}
+
+fn stmt_key(stmt: &Stmt) -> Option<Key> {
+    stmt.as_expr().map(|expr| key(&expr))
+}
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+    /// Rewrite the program, `outputs` are the variables observed after the program ends.
+    /// Returns whether the program changed.
+    fn run(&self, program: &mut Program, outputs: &[&str]) -> bool;
+}
+
+/// Names not used by any variable of the program yet.
+struct Fresh {
+    taken: HashSet<Var>,
+    next: usize,
+}
+
+impl Fresh {
+    fn new(program: &Program) -> Self {
+        let taken = program
+            .blocks()
+            .flat_map(Block::stmts)
+            .flat_map(|stmt| stmt.def().into_iter().chain(stmt.uses()))
+            .map(str::to_string)
+            .collect();
+        Fresh { taken, next: 0 }
+    }
+
+    fn var(&mut self) -> Var {
+        loop {
+            self.next += 1;
+            let var = format!("t{}", self.next);
+            if self.taken.insert(var.clone()) {
+                return var;
+            }
+        }
+    }
+}
+
+/// Removes assignments to variables not live afterwards.
+pub struct DeadCode;
+
+impl Pass for DeadCode {
+    fn name(&self) -> &'static str {
+        "dead code elimination"
+    }
+
+    fn run(&self, program: &mut Program, outputs: &[&str]) -> bool {
+        let live_outs: Vec<HashSet<Var>> = {
+            let lvs = live_variables_on_exit(program, outputs);
+            program
+                .block_range()
+                .map(|i| lvs.live_out(i).iter().map(|v| v.to_string()).collect())
+                .collect()
+        };
+
+        let mut changed = false;
+        for (block, mut live) in program.blocks.iter_mut().zip(live_outs) {
+            let mut dead = HashSet::new();
+            for (i, stmt) in block.stmts.iter().enumerate().rev() {
+                let def = stmt.def().expect("Statements define a variable");
+                if !live.contains(def) {
+                    dead.insert(i);
+                    continue;
+                }
+                live.remove(def);
+                live.extend(stmt.uses().into_iter().map(str::to_string));
+            }
+
+            changed |= !dead.is_empty();
+            let mut i = 0;
+            block.stmts.retain(|_| {
+                i += 1;
+                !dead.contains(&(i - 1))
+            });
+        }
+
+        program.renumber();
+        changed
+    }
+}
+
+/// Replaces variables holding the same constant on every path by the constant, and evaluates
+/// operations on constants.
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant folding"
+    }
+
+    fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+        let folded: Vec<Vec<Stmt>> = {
+            let pairs = constant_propagation(program).into_pairs();
+            program
+                .blocks()
+                .zip(pairs)
+                .map(|(block, (mut constants, _))| {
+                    block
+                        .stmts()
+                        .map(|stmt| {
+                            let subst = |rvalue: &RValue| match rvalue
+                                .var()
+                                .and_then(|v| constants.constant(v))
+                            {
+                                Some(lit) => RValue::Lit(lit),
+                                None => rvalue.clone(),
+                            };
+                            let folded = match stmt {
+                                Stmt::Op(dst, lhs, op, rhs) => match (subst(lhs), subst(rhs)) {
+                                    (RValue::Lit(l), RValue::Lit(r)) => {
+                                        Stmt::Copy(dst.clone(), RValue::Lit(op.apply(l, r)))
+                                    }
+                                    (l, r) => Stmt::Op(dst.clone(), l, *op, r),
+                                },
+                                Stmt::Copy(dst, src) => Stmt::Copy(dst.clone(), subst(src)),
+                            };
+                            constants.update(stmt);
+                            folded
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        replace_stmts(program, folded)
+    }
+}
+
+fn replace_stmts(program: &mut Program, stmts: Vec<Vec<Stmt>>) -> bool {
+    let mut changed = false;
+    for (block, stmts) in program.blocks.iter_mut().zip(stmts) {
+        if block.stmts != stmts {
+            block.stmts = stmts;
+            changed = true;
+        }
+    }
+    program.renumber();
+    changed
+}
+
+/// Copies x = y reaching a point along every path with neither x nor y assigned since.
+#[derive(Debug, Clone, PartialEq)]
+struct Copies {
+    pairs: HashSet<(Var, Var)>,
+}
+
+impl Copies {
+    fn source(&self, var: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(dst, _)| dst == var)
+            .map(|(_, src)| src.as_str())
+    }
+
+    fn update(&mut self, stmt: &Stmt) {
+        if let Some(def) = stmt.def() {
+            self.pairs.retain(|(dst, src)| dst != def && src != def);
+        }
+        if let Stmt::Copy(dst, RValue::Var(src)) = stmt {
+            if dst != src {
+                self.pairs.insert((dst.clone(), src.clone()));
+            }
+        }
+    }
+}
+
+impl SemiLattice<'_> for Copies {
+    fn top(program: &Program) -> Self {
+        let pairs = program
+            .blocks()
+            .flat_map(Block::stmts)
+            .filter_map(|stmt| match stmt {
+                Stmt::Copy(dst, RValue::Var(src)) if dst != src => Some((dst.clone(), src.clone())),
+                _ => None,
+            })
+            .collect();
+        Copies { pairs }
+    }
+
+    fn start(_: &Program) -> Self {
+        Copies {
+            pairs: HashSet::new(),
+        }
+    }
+
+    fn meet(&self, other: &Self) -> Self {
+        let pairs = &self.pairs & &other.pairs;
+        Copies { pairs }
+    }
+}
+
+#[derive(Clone)]
+struct CopiesT<'a> {
+    block: &'a Block,
+}
+
+impl<'a> Transfer<'a> for CopiesT<'a> {
+    type Target = Copies;
+    type Extra = ();
+
+    fn new(block_id: BlockID, program: &'a Program, _: &()) -> Self {
+        let block = program
+            .get_block(block_id)
+            .expect("CopiesT: Block in-bound");
+        CopiesT { block }
+    }
+
+    fn apply(&self, copies: &Self::Target) -> Self::Target {
+        self.block.stmts().fold(copies.clone(), |mut copies, stmt| {
+            copies.update(stmt);
+            copies
+        })
+    }
+}
+
+/// Replaces uses of x by y after a copy x = y, copies of a variable to itself are removed.
+pub struct CopyPropagation;
+
+impl Pass for CopyPropagation {
+    fn name(&self) -> &'static str {
+        "copy propagation"
+    }
+
+    fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+        let propagated: Vec<Vec<Stmt>> = {
+            let pairs = DataFlow::<Copies, Forward, CopiesT<'_>>::run(program, &()).into_pairs();
+            program
+                .blocks()
+                .zip(pairs)
+                .map(|(block, (mut copies, _))| {
+                    let mut stmts = vec![];
+                    for stmt in block.stmts() {
+                        let subst =
+                            |rvalue: &RValue| match rvalue.var().and_then(|v| copies.source(v)) {
+                                Some(src) => RValue::Var(src.to_string()),
+                                None => rvalue.clone(),
+                            };
+                        let propagated = match stmt {
+                            Stmt::Op(dst, lhs, op, rhs) => {
+                                Stmt::Op(dst.clone(), subst(lhs), *op, subst(rhs))
+                            }
+                            Stmt::Copy(dst, src) => Stmt::Copy(dst.clone(), subst(src)),
+                        };
+                        copies.update(stmt);
+
+                        match &propagated {
+                            Stmt::Copy(dst, RValue::Var(src)) if dst == src => (),
+                            _ => stmts.push(propagated),
+                        }
+                    }
+                    stmts
+                })
+                .collect()
+        };
+
+        replace_stmts(program, propagated)
+    }
+}
+
+/// Global common subexpression elimination: every computation of an expression available
+/// somewhere is saved to a temporary, which replaces the expression where it is available.
+pub struct CommonSubexpr;
+
+impl Pass for CommonSubexpr {
+    fn name(&self) -> &'static str {
+        "common subexpression elimination"
+    }
+
+    fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+        let redundant: Vec<HashSet<usize>> = {
+            let avs = available_expressions(program);
+            program
+                .blocks()
+                .enumerate()
+                .map(|(block_id, block)| {
+                    let mut available = avs.available_in(block_id).clone();
+                    let mut redundant = HashSet::new();
+                    for (i, stmt) in block.stmts().enumerate() {
+                        if let Some(expr) = stmt.as_expr() {
+                            if available.contains(&expr) {
+                                redundant.insert(i);
+                            }
+                            available.insert(expr);
+                        }
+                        if let Some(def) = stmt.def() {
+                            available.retain(|expr| !expr.uses(def));
+                        }
+                    }
+                    redundant
+                })
+                .collect()
+        };
+
+        let keys: HashSet<Key> = program
+            .blocks()
+            .zip(&redundant)
+            .flat_map(|(block, redundant)| {
+                redundant
+                    .iter()
+                    .filter_map(move |i| stmt_key(&block.stmts[*i]))
+            })
+            .collect();
+        if keys.is_empty() {
+            return false;
+        }
+
+        let mut fresh = Fresh::new(program);
+        let mut temps: HashMap<Key, Var> = HashMap::new();
+        for block in program.blocks() {
+            for key in block.stmts().filter_map(stmt_key) {
+                if keys.contains(&key) && !temps.contains_key(&key) {
+                    temps.insert(key, fresh.var());
+                }
+            }
+        }
+
+        for (block, redundant) in program.blocks.iter_mut().zip(redundant) {
+            let mut stmts = vec![];
+            for (i, stmt) in block.stmts.drain(..).enumerate() {
+                let temp = stmt_key(&stmt).and_then(|key| temps.get(&key));
+                match (stmt, temp) {
+                    (Stmt::Op(dst, ..), Some(temp)) if redundant.contains(&i) => {
+                        stmts.push(Stmt::Copy(dst, RValue::Var(temp.clone())));
+                    }
+                    (Stmt::Op(dst, lhs, op, rhs), Some(temp)) => {
+                        stmts.push(Stmt::Op(temp.clone(), lhs, op, rhs));
+                        stmts.push(Stmt::Copy(dst, RValue::Var(temp.clone())));
+                    }
+                    (stmt, _) => stmts.push(stmt),
+                }
+            }
+            block.stmts = stmts;
+        }
+
+        program.renumber();
+        true
+    }
+}
+
+/// Moves computations to the places chosen by the lazy-code-motion analyses of section 9.5.
+/// The analyses assume a statement per block and a block on every edge into a join point,
+/// the program is split accordingly when the placement changes anything.
+pub struct LazyCodeMotion;
+
+impl LazyCodeMotion {
+    fn split_blocks(program: &mut Program) {
+        for block_id in program.block_range() {
+            let rest: Vec<Stmt> = {
+                let stmts = &mut program.blocks[block_id].stmts;
+                if stmts.len() < 2 {
+                    continue;
+                }
+                stmts.drain(1..).collect()
+            };
+
+            let succs: Vec<BlockID> = program.successors(block_id).map(|(s, _)| s).collect();
+            for succ in &succs {
+                program.graph.remove_edge(block_id, *succ);
+            }
+
+            let mut last = block_id;
+            for stmt in rest {
+                let id = program.blocks.len();
+                program.blocks.push(Block {
+                    start: 0,
+                    stmts: vec![stmt],
+                    btype: BlockType::Basic,
+                });
+                program.graph.add_edge(last, id, ());
+                last = id;
+            }
+            for succ in succs {
+                // a block looping to itself now loops from its last part
+                program.graph.add_edge(last, succ, ());
+            }
+        }
+    }
+
+    /// For each expression, blocks computing it into a temporary and blocks using the temporary.
+    fn placements(program: &Program) -> Vec<(Key, HashSet<BlockID>, HashSet<BlockID>)> {
+        let anticipates = anticipates(program);
+        let availables = availables(program, &anticipates);
+        let earliests = earliests(&anticipates, &availables);
+        let postponables = postponables(program, &earliests);
+        let latests = latests(program, &earliests, &postponables);
+        let used = used(program, &latests);
+
+        let exprs: HashSet<Expr<'_>> = program.exprs().collect();
+        exprs
+            .iter()
+            .map(|expr| {
+                (
+                    key(expr),
+                    where_to_compute(expr, &latests, &used),
+                    where_to_use(expr, program, &latests, &used),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Pass for LazyCodeMotion {
+    fn name(&self) -> &'static str {
+        "lazy code motion"
+    }
+
+    fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+        if program.exit().is_none() {
+            return false;
+        }
+
+        let mut split = program.clone();
+        Self::split_blocks(&mut split);
+        let mut split = cut_edges(split);
+
+        let mut placements = Self::placements(&split);
+        // placements recomputing each use in its own block change nothing, and computations
+        // cannot be placed in ENTRY or EXIT
+        placements.retain(|(_, compute, use_temp)| {
+            !use_temp.is_empty()
+                && compute != use_temp
+                && compute
+                    .iter()
+                    .all(|b| split.blocks[*b].btype == BlockType::Basic)
+        });
+        if placements.is_empty() {
+            return false;
+        }
+
+        let mut fresh = Fresh::new(&split);
+        for ((lhs, op, rhs), compute, use_temp) in placements {
+            let temp = fresh.var();
+            let key = (lhs.clone(), op, rhs.clone());
+
+            for block_id in use_temp {
+                for stmt in &mut split.blocks[block_id].stmts {
+                    if stmt_key(stmt).as_ref() == Some(&key) {
+                        let dst = stmt.def().unwrap().to_string();
+                        *stmt = Stmt::Copy(dst, RValue::Var(temp.clone()));
+                    }
+                }
+            }
+            for block_id in compute {
+                let computation = Stmt::Op(temp.clone(), lhs.clone(), op, rhs.clone());
+                split.blocks[block_id].stmts.insert(0, computation);
+            }
+        }
+
+        split.renumber();
+        *program = split;
+        true
+    }
+}
+
+/// A pass changed the values of the outputs on some path.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub pass: &'static str,
+    pub path: Vec<BlockID>,
+    pub inputs: Env,
+    pub expected: Env,
+    pub actual: Env,
+}
+
+/// Why the pass manager stopped short of a fixpoint.
+#[derive(Debug)]
+pub enum PassError {
+    Mismatch(Mismatch),
+    /// Passes still changed the program after `MAX_ROUNDS` rounds, with the passes that changed it
+    /// in order.
+    NoFixpoint(Vec<&'static str>),
+}
+
+/// Outputs of the original program on a sample of paths and inputs.
+struct Oracle {
+    added: BlockID,
+    runs: Vec<(Vec<BlockID>, Env, Env)>,
+}
+
+impl Oracle {
+    fn new(program: &Program, outputs: &[&str]) -> Self {
+        let vars: Vec<&str> = {
+            let mut vars: Vec<&str> = program
+                .blocks()
+                .flat_map(Block::stmts)
+                .flat_map(|stmt| stmt.def().into_iter().chain(stmt.uses()))
+                .collect();
+            vars.sort();
+            vars.dedup();
+            vars
+        };
+
+        let mut runs = vec![];
+        for path in paths(program, MAX_PATH_LEN, MAX_PATHS) {
+            for seed in 0..INPUTS {
+                let inputs: Env = vars
+                    .iter()
+                    .enumerate()
+                    .map(|(i, var)| (var.to_string(), (i * 7 + seed * 13 + 1) as Lit))
+                    .collect();
+                let expected = Self::outputs(program, &path, program.len(), &inputs, outputs)
+                    .expect("Oracle: paths follow edges");
+                runs.push((path.clone(), inputs, expected));
+            }
+        }
+
+        Oracle {
+            added: program.len(),
+            runs,
+        }
+    }
+
+    fn outputs(
+        program: &Program,
+        path: &[BlockID],
+        added: BlockID,
+        inputs: &Env,
+        outputs: &[&str],
+    ) -> Option<Env> {
+        let env = run_path(program, &[], path, added, inputs.clone())?.env;
+        Some(
+            outputs
+                .iter()
+                .map(|var| (var.to_string(), env.get(*var).cloned().unwrap_or(0)))
+                .collect(),
+        )
+    }
+
+    fn check(
+        &self,
+        pass: &'static str,
+        program: &Program,
+        outputs: &[&str],
+    ) -> Result<(), Box<PassError>> {
+        for (path, inputs, expected) in &self.runs {
+            let actual =
+                Self::outputs(program, path, self.added, inputs, outputs).unwrap_or_default();
+            if &actual != expected {
+                return Err(Box::new(PassError::Mismatch(Mismatch {
+                    pass,
+                    path: path.clone(),
+                    inputs: inputs.clone(),
+                    expected: expected.clone(),
+                    actual,
+                })));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    outputs: Vec<Var>,
+    checked: bool,
+}
+
+impl PassManager {
+    pub fn new(outputs: &[&str]) -> Self {
+        PassManager {
+            passes: vec![],
+            outputs: outputs.iter().map(|var| var.to_string()).collect(),
+            checked: false,
+        }
+    }
+
+    /// All passes of this module, cheaper and more local ones first.
+    pub fn standard(outputs: &[&str]) -> Self {
+        let mut manager = Self::new(outputs);
+        manager
+            .add(ConstantFolding)
+            .add(CopyPropagation)
+            .add(CommonSubexpr)
+            .add(LazyCodeMotion)
+            .add(DeadCode);
+        manager
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Compare the outputs of the program with the original after every change.
+    pub fn set_checked(&mut self, checked: bool) -> &mut Self {
+        self.checked = checked;
+        self
+    }
+
+    /// Run the passes in order until none of them changes the program, returns the passes that
+    /// changed it in order. On a mismatch the program is left as the offending pass rewrote it, when
+    /// no fixpoint is reached within `MAX_ROUNDS` rounds the program is left as the last round
+    /// rewrote it.
+    pub fn run(&self, program: &mut Program) -> Result<Vec<&'static str>, Box<PassError>> {
+        let outputs: Vec<&str> = self.outputs.iter().map(String::as_str).collect();
+        let oracle = if self.checked {
+            Some(Oracle::new(program, &outputs))
+        } else {
+            None
+        };
+
+        let mut history = vec![];
+        for _ in 0..MAX_ROUNDS {
+            let mut changed = false;
+            for pass in &self.passes {
+                if pass.run(program, &outputs) {
+                    changed = true;
+                    history.push(pass.name());
+                    if let Some(oracle) = &oracle {
+                        oracle.check(pass.name(), program, &outputs)?;
+                    }
+                }
+            }
+            if !changed {
+                return Ok(history);
+            }
+        }
+
+        Err(Box::new(PassError::NoFixpoint(history)))
+    }
+}
+
+#[cfg(test)]
+fn computations(program: &Program, stmt: &str) -> usize {
+    let stmt = Stmt::parse(stmt);
+    let expr = stmt.as_expr().unwrap();
+    program.exprs().filter(|e| *e == expr).count()
+}
+
+#[test]
+fn dead_code_test() {
+    let mut program = Program::with_entry_exit(
+        vec![
+            Block::parse(1, "a = x + 1\nb = a * 2\nb = a + 3\nc = b + a"),
+            Block::parse(5, "d = c - 1\ne = d * 2"),
+        ],
+        &[(0, 1), (1, 2), (2, 3)],
+    );
+
+    assert!(DeadCode.run(&mut program, &["d"]));
+    assert_eq!(program.get_block(1).unwrap().len(), 3);
+    assert_eq!(program.get_block(2).unwrap().len(), 1);
+    assert_eq!(
+        program.get_block(2).unwrap().get(4),
+        Some(&Stmt::parse("d = c - 1"))
+    );
+    assert!(!DeadCode.run(&mut program, &["d"]));
+}
+
+#[test]
+fn constant_folding_test() {
+    let mut program = Program::with_entry_exit(
+        vec![
+            Block::parse(1, "x = 2\ny = x + 3"),
+            Block::parse(3, "z = y * w"),
+        ],
+        &[(0, 1), (1, 2), (2, 3)],
+    );
+
+    assert!(ConstantFolding.run(&mut program, &[]));
+    let stmts: Vec<_> = program.blocks().flat_map(Block::stmts).cloned().collect();
+    assert_eq!(
+        stmts,
+        vec![
+            Stmt::parse("x = 2"),
+            Stmt::parse("y = 5"),
+            Stmt::parse("z = 5 * w"),
+        ]
+    );
+    assert!(!ConstantFolding.run(&mut program, &[]));
+}
+
+#[test]
+fn copy_propagation_test() {
+    let mut program = Program::with_entry_exit(
+        vec![
+            Block::parse(1, "y = x\nz = y + 1\nx = z"),
+            Block::parse(4, "w = y + x"),
+        ],
+        &[(0, 1), (1, 2), (2, 3)],
+    );
+
+    assert!(CopyPropagation.run(&mut program, &[]));
+    let block = program.get_block(1).unwrap();
+    assert_eq!(block.get(2), Some(&Stmt::parse("z = x + 1")));
+    // y = x no longer holds after x = z
+    let block = program.get_block(2).unwrap();
+    assert_eq!(block.get(4), Some(&Stmt::parse("w = y + z")));
+}
+
+#[test]
+fn common_subexpr_test() {
+    let mut program = Program::with_entry_exit(
+        vec![
+            Block::parse(1, "a = b + c"),
+            Block::parse(2, "d = b + c"),
+            Block::parse(3, "e = b + c\nb = d - 1\nf = b + c"),
+        ],
+        &[(0, 1), (1, 2), (2, 3), (3, 4)],
+    );
+
+    assert_eq!(computations(&program, "x = b + c"), 4);
+    assert!(CommonSubexpr.run(&mut program, &[]));
+    assert_eq!(computations(&program, "x = b + c"), 2);
+    assert_eq!(
+        program.get_block(2).unwrap().get(3),
+        Some(&Stmt::parse("d = t1"))
+    );
+    assert!(!CommonSubexpr.run(&mut program, &[]));
+}
+
+#[test]
+fn lazy_code_motion_test() {
+    let mut program = crate::lazy_code_motion::figure_9_33();
+
+    assert_eq!(computations(&program, "x = b + c"), 3);
+    assert!(LazyCodeMotion.run(&mut program, &[]));
+    // b + c is computed once along any path, on the branch without it and before B4
+    assert_eq!(computations(&program, "x = b + c"), 2);
+    assert!(!LazyCodeMotion.run(&mut program, &[]));
+}
+
+#[test]
+fn pass_manager_test() {
+    let outputs = ["a", "d", "e"];
+    let mut program = crate::lazy_code_motion::figure_9_33();
+    let mut manager = PassManager::standard(&outputs);
+    manager.set_checked(true);
+
+    let history = manager.run(&mut program).unwrap();
+    assert!(history.contains(&"lazy code motion"));
+    // c is 2 on the path computing b + c before B4
+    assert_eq!(computations(&program, "x = b + c"), 1);
+    assert_eq!(computations(&program, "x = b + 2"), 1);
+    assert!(manager.run(&mut program).unwrap().is_empty());
+
+    let outputs = ["i", "j", "a"];
+    let mut program = crate::figure_9_13();
+    let mut manager = PassManager::standard(&outputs);
+    manager.set_checked(true);
+    manager.run(&mut program).unwrap();
+    assert!(manager.run(&mut program).unwrap().is_empty());
+}
+
+#[test]
+fn mismatch_test() {
+    struct Broken;
+
+    impl Pass for Broken {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+            let stmt = &mut program.blocks[1].stmts[1];
+            let changed = *stmt != Stmt::parse("j = 0");
+            *stmt = Stmt::parse("j = 0");
+            changed
+        }
+    }
+
+    let mut program = crate::figure_9_13();
+    let mut manager = PassManager::new(&["a"]);
+    manager.add(Broken);
+    // a does not depend on j
+    assert_eq!(
+        manager.set_checked(true).run(&mut program).unwrap(),
+        vec!["broken"]
+    );
+
+    let mut program = crate::figure_9_13();
+    let mut manager = PassManager::new(&["j"]);
+    let err = manager
+        .add(Broken)
+        .set_checked(true)
+        .run(&mut program)
+        .unwrap_err();
+    match *err {
+        PassError::Mismatch(mismatch) => {
+            assert_eq!(mismatch.pass, "broken");
+            assert_ne!(mismatch.expected, mismatch.actual);
+        }
+        err => panic!("expected a mismatch, got {:?}", err),
+    }
+}
+
+#[test]
+fn no_fixpoint_test() {
+    /// Swaps the operands of the first statement back and forth forever.
+    struct Flip;
+
+    impl Pass for Flip {
+        fn name(&self) -> &'static str {
+            "flip"
+        }
+
+        fn run(&self, program: &mut Program, _: &[&str]) -> bool {
+            let stmt = &mut program.blocks[1].stmts[0];
+            *stmt = if *stmt == Stmt::parse("i = m - 1") {
+                Stmt::parse("i = 1 - m")
+            } else {
+                Stmt::parse("i = m - 1")
+            };
+            true
+        }
+    }
+
+    let mut program = crate::figure_9_13();
+    let mut manager = PassManager::new(&[]);
+    match *manager.add(Flip).run(&mut program).unwrap_err() {
+        PassError::NoFixpoint(history) => assert_eq!(history, vec!["flip"; MAX_ROUNDS]),
+        err => panic!("expected no fixpoint, got {:?}", err),
+    }
+}
@@ -1,3 +1,5 @@
+use crate::bit_vector::{BitSet, GenKill as BitGenKill};
+use crate::framework::{Forward, Point};
 use crate::utils::sorted;
 use crate::{BlockID, Program, Stmt, StmtID};
 use std::collections::HashSet;
@@ -122,6 +124,37 @@ pub fn reaching_definitions(program: &Program) -> ReachingDefs {
     ReachingDefs { attrs }
 }
 
+/// Reaching definitions as a bit-vector problem, facts are statement ids.
+pub struct Definitions;
+
+impl BitGenKill<Program> for Definitions {
+    type Direction = Forward;
+    const MAY: bool = true;
+
+    fn size(&self, program: &Program) -> usize {
+        program
+            .stmts_indices()
+            .map(|(i, _)| i + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn gen_kill(
+        &self,
+        program: &Program,
+        at: Point,
+        _: &Stmt,
+        gen: &mut BitSet,
+        kill: &mut BitSet,
+    ) {
+        let i = program.blocks[at.block_id].start + at.index;
+        gen.insert(i);
+        for j in killed(program, i) {
+            kill.insert(j);
+        }
+    }
+}
+
 #[test]
 fn reaching_definitions_test() {
     let program = crate::figure_9_13();
@@ -137,3 +170,22 @@ fn reaching_definitions_test() {
     assert_eq!(rds.attrs[3].out_def, vec![4, 5, 6].into_iter().collect());
     assert_eq!(rds.attrs[4].out_def, vec![3, 5, 6, 7].into_iter().collect());
 }
+
+#[test]
+fn bit_vector_test() {
+    use crate::bit_vector::Bits;
+    use crate::framework::solve;
+
+    let program = crate::figure_9_13();
+    let rds = reaching_definitions(&program);
+    let bits = Bits::new(&program, Definitions);
+    let iterative = solve(&program, &bits);
+    let regional = crate::region::solve(&program, &bits).unwrap();
+
+    for i in program.block_range() {
+        let out_def: HashSet<StmtID> = iterative.out_value(i).iter().collect();
+        assert_eq!(out_def, rds.attrs[i].out_def);
+        assert_eq!(iterative.in_value(i), regional.in_value(i));
+        assert_eq!(iterative.out_value(i), regional.out_value(i));
+    }
+}
@@ -1,7 +1,9 @@
-use crate::dominator::Dominators;
-use crate::{BlockID, Program};
+use crate::dominator::{edges, Dominators};
+use crate::framework::{Attrs, Cfg, Direction, Summarize};
+use crate::BlockID;
+use petgraph::algo::toposort;
 use petgraph::prelude::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct Region {
@@ -39,10 +41,9 @@ impl std::fmt::Debug for Region {
 }
 
 impl Region {
-    fn new(program: &Program, head: BlockID, nodes: &HashSet<BlockID>) -> Self {
+    fn new<G: Cfg + ?Sized>(cfg: &G, head: BlockID, nodes: &HashSet<BlockID>) -> Self {
         let body: DiGraphMap<BlockID, ()> = DiGraphMap::from_edges(
-            program
-                .edges()
+            edges(cfg)
                 .into_iter()
                 .filter(|(from, to)| nodes.contains(from) && nodes.contains(to)),
         );
@@ -56,16 +57,26 @@ impl Region {
         Region { head, body }
     }
 
-    fn new_complete(program: &Program) -> Self {
+    fn new_complete<G: Cfg + ?Sized>(cfg: &G) -> Self {
+        let mut body = DiGraphMap::from_edges(edges(cfg));
+        for block_id in 0..cfg.len() {
+            body.add_node(block_id);
+        }
+
         Region {
-            head: program.entry(),
-            body: program.graph().clone(),
+            head: cfg.entry(),
+            body,
         }
     }
 
-    fn from_back_edge(program: &Program, from: BlockID, to: BlockID) -> (Self, Self) {
-        let nodes = program.natural_loop(from, to);
-        let loop_region = Region::new(program, to, &nodes);
+    fn from_back_edge<G: Cfg + ?Sized>(
+        dominators: &Dominators<G>,
+        cfg: &G,
+        from: BlockID,
+        to: BlockID,
+    ) -> (Self, Self) {
+        let nodes = dominators.natural_loop(from, to);
+        let loop_region = Region::new(cfg, to, &nodes);
         let mut body_region = loop_region.clone();
         body_region.body.remove_edge(from, to);
         (body_region, loop_region)
@@ -74,17 +85,24 @@ impl Region {
     fn node_count(&self) -> usize {
         self.body.node_count()
     }
+
+    /// A loop region keeps the back edges to its header, the body region of a loop does not.
+    fn is_loop(&self) -> bool {
+        self.body
+            .neighbors_directed(self.head, Incoming)
+            .next()
+            .is_some()
+    }
 }
 
-pub fn possible_nontrivial_regions(program: &Program) -> Vec<Region> {
-    let dominators = Dominators::new(program);
+pub fn possible_nontrivial_regions<G: Cfg + ?Sized>(cfg: &G) -> Vec<Region> {
+    let dominators = Dominators::new(cfg);
 
-    program
-        .block_range()
+    (0..cfg.len())
         .filter_map(|i| {
             let nodes = dominators.dominated(i);
             if nodes.len() > 1 {
-                Some(Region::new(program, i, &nodes))
+                Some(Region::new(cfg, i, &nodes))
             } else {
                 None
             }
@@ -92,34 +110,282 @@ pub fn possible_nontrivial_regions(program: &Program) -> Vec<Region> {
         .collect()
 }
 
-pub fn nested_regions(program: &Program) -> Vec<Region> {
+pub fn nested_regions<G: Cfg + ?Sized>(cfg: &G) -> Vec<Region> {
+    regions(&Dominators::new(cfg), cfg)
+}
+
+fn regions<G: Cfg + ?Sized>(dominators: &Dominators<G>, cfg: &G) -> Vec<Region> {
     let mut regions = vec![];
 
-    for block_id in program.block_range() {
+    for block_id in 0..cfg.len() {
         regions.push(Region::new_trivial(block_id));
     }
 
-    let dominators = Dominators::new(program);
-
     for (from, to) in dominators.back_edges() {
-        let (body_region, loop_region) = Region::from_back_edge(program, from, to);
+        let (body_region, loop_region) = Region::from_back_edge(dominators, cfg, from, to);
         regions.push(body_region);
         regions.push(loop_region);
     }
 
-    if regions
-        .iter()
-        .all(|region| region.node_count() < program.len())
-    {
-        regions.push(Region::new_complete(program));
+    if regions.iter().all(|region| region.node_count() < cfg.len()) {
+        regions.push(Region::new_complete(cfg));
     }
 
     regions
 }
 
+/// Why region-based analysis can't solve a problem, `framework::solve` can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionError {
+    /// The flow graph, reversed for backward problems, is not reducible. Reversed, a loop with
+    /// several exits has several entries.
+    Irreducible,
+    /// A backward problem on a flow graph without EXIT.
+    NoExit,
+}
+
+/// A flow graph in the direction of the flow of a problem, reversed for backward problems: from
+/// EXIT to ENTRY along the edges backwards.
+struct Flow<'a, G: ?Sized> {
+    cfg: &'a G,
+    forward: bool,
+}
+
+impl<G: Cfg + ?Sized> Cfg for Flow<'_, G> {
+    fn len(&self) -> usize {
+        self.cfg.len()
+    }
+
+    fn entry(&self) -> BlockID {
+        if self.forward {
+            self.cfg.entry()
+        } else {
+            self.cfg.exit().expect("Flow: backward without EXIT")
+        }
+    }
+
+    fn exit(&self) -> Option<BlockID> {
+        if self.forward {
+            self.cfg.exit()
+        } else {
+            Some(self.cfg.entry())
+        }
+    }
+
+    fn successors(&self, block_id: BlockID) -> Vec<BlockID> {
+        if self.forward {
+            self.cfg.successors(block_id)
+        } else {
+            self.cfg.predecessors(block_id)
+        }
+    }
+
+    fn predecessors(&self, block_id: BlockID) -> Vec<BlockID> {
+        if self.forward {
+            self.cfg.predecessors(block_id)
+        } else {
+            self.cfg.successors(block_id)
+        }
+    }
+}
+
+/// Transfer functions summarized over the loop nest of a reducible flow graph, section 9.7.
+/// Functions run in the direction of the flow, from the start of a block to its end for forward
+/// problems and the other way round for backward ones.
+struct Summaries<'a, G: ?Sized, P> {
+    cfg: &'a G,
+    flow: &'a Flow<'a, G>,
+    problem: &'a P,
+    // natural loops sharing a header are merged into one region
+    loops: HashMap<BlockID, HashSet<BlockID>>,
+}
+
+impl<'a, G: Cfg + ?Sized, P: Summarize<G>> Summaries<'a, G, P> {
+    fn new(
+        flow: &'a Flow<'a, G>,
+        problem: &'a P,
+        dominators: &Dominators<Flow<G>>,
+    ) -> Result<Self, RegionError> {
+        if !dominators.is_reducible() {
+            return Err(RegionError::Irreducible);
+        }
+
+        let mut loops: HashMap<BlockID, HashSet<BlockID>> = HashMap::new();
+        for region in regions(dominators, flow) {
+            if region.is_loop() {
+                loops
+                    .entry(region.head)
+                    .or_default()
+                    .extend(region.body.nodes().filter(|n| dominators.is_reachable(*n)));
+            }
+        }
+
+        Ok(Summaries {
+            cfg: flow.cfg,
+            flow,
+            problem,
+            loops,
+        })
+    }
+
+    fn block_function(&self, block_id: BlockID) -> P::Function {
+        if block_id == self.cfg.entry() || Some(block_id) == self.cfg.exit() {
+            self.problem.identity(self.cfg)
+        } else {
+            self.problem.function(self.cfg, block_id)
+        }
+    }
+
+    /// Functions from the entry of the region to the entry of each of its blocks. Loops nested
+    /// in the region are summarized first and collapsed into their headers, what remains is
+    /// acyclic apart from back edges to `header`.
+    fn summarize(
+        &self,
+        header: BlockID,
+        nodes: &HashSet<BlockID>,
+    ) -> Result<HashMap<BlockID, P::Function>, RegionError> {
+        let inner: Vec<(BlockID, &HashSet<BlockID>)> = self
+            .loops
+            .iter()
+            .filter(|(h, body)| **h != header && nodes.contains(h) && body.is_subset(nodes))
+            .map(|(h, body)| (*h, body))
+            .collect();
+
+        let mut unit: HashMap<BlockID, BlockID> = nodes.iter().map(|n| (*n, *n)).collect();
+        let mut nested = HashMap::new();
+        for (h, body) in &inner {
+            let outermost = inner
+                .iter()
+                .all(|(other, other_body)| other == h || !other_body.contains(h));
+            if outermost {
+                for n in body.iter() {
+                    unit.insert(*n, *h);
+                }
+                nested.insert(*h, self.summarize(*h, body)?);
+            }
+        }
+
+        let mut dag: DiGraphMap<BlockID, ()> = DiGraphMap::new();
+        for n in nodes {
+            dag.add_node(unit[n]);
+        }
+        for (from, to) in edges(self.flow) {
+            if nodes.contains(&from)
+                && nodes.contains(&to)
+                && to != header
+                && unit[&from] != unit[&to]
+            {
+                dag.add_edge(unit[&from], unit[&to], ());
+            }
+        }
+        let order = toposort(&dag, None).map_err(|_| RegionError::Irreducible)?;
+
+        let mut ins: HashMap<BlockID, P::Function> = HashMap::new();
+        let out = |ins: &HashMap<BlockID, P::Function>, n: BlockID| {
+            self.problem.compose(&ins[&n], &self.block_function(n))
+        };
+
+        for u in order {
+            let entry = if u == header {
+                self.problem.identity(self.cfg)
+            } else {
+                // edges into a loop all enter through its header, blocks not reached from the
+                // header of the region are only found in irreducible flow graphs
+                self.flow
+                    .predecessors(u)
+                    .into_iter()
+                    .filter(|p| nodes.contains(p) && unit[p] != u)
+                    .map(|p| out(&ins, p))
+                    .fold(None, |met: Option<P::Function>, f| match met {
+                        Some(met) => Some(self.problem.meet_functions(&met, &f)),
+                        None => Some(f),
+                    })
+                    .ok_or(RegionError::Irreducible)?
+            };
+
+            match nested.get(&u) {
+                Some(summary) => {
+                    for (n, f) in summary {
+                        ins.insert(*n, self.problem.compose(&entry, f));
+                    }
+                }
+                None => {
+                    ins.insert(u, entry);
+                }
+            }
+        }
+
+        let back = self
+            .flow
+            .predecessors(header)
+            .into_iter()
+            .filter(|p| nodes.contains(p))
+            .map(|p| out(&ins, p))
+            .fold(None, |met: Option<P::Function>, f| match met {
+                Some(met) => Some(self.problem.meet_functions(&met, &f)),
+                None => Some(f),
+            });
+        if let Some(back) = back {
+            let closure = self.problem.closure(&back);
+            for f in ins.values_mut() {
+                *f = self.problem.compose(&closure, f);
+            }
+        }
+
+        Ok(ins)
+    }
+}
+
+/// Solve a problem on a reducible flow graph by region-based analysis, an alternative to the
+/// iterative `framework::solve`. Backward problems are solved on the reversed flow graph, which
+/// has to be reducible too. Blocks unreachable from ENTRY (not reaching EXIT for backward
+/// problems) are left with the top value.
+pub fn solve<G, P>(cfg: &G, problem: &P) -> Result<Attrs<P::Value>, RegionError>
+where
+    G: Cfg + ?Sized,
+    P: Summarize<G>,
+{
+    let forward = P::Direction::FORWARD;
+    if !forward && cfg.exit().is_none() {
+        return Err(RegionError::NoExit);
+    }
+
+    let flow = Flow { cfg, forward };
+    let dominators = Dominators::new(&flow);
+    let summaries = Summaries::new(&flow, problem, &dominators)?;
+    let reachable = (0..cfg.len())
+        .filter(|n| dominators.is_reachable(*n))
+        .collect();
+    let ins = summaries.summarize(flow.entry(), &reachable)?;
+
+    let boundary = problem.boundary(cfg);
+    let top = problem.top(cfg);
+    let pairs = (0..cfg.len())
+        .map(|block_id| {
+            // values before and after the block in the direction of the flow
+            let before = match ins.get(&block_id) {
+                Some(f) => problem.apply(f, &boundary),
+                None => top.clone(),
+            };
+            let after = if block_id == cfg.entry() || Some(block_id) == cfg.exit() {
+                before.clone()
+            } else {
+                problem.transfer(cfg, block_id, &before)
+            };
+            if forward {
+                (before, after)
+            } else {
+                (after, before)
+            }
+        })
+        .collect();
+
+    Ok(Attrs::new(pairs))
+}
+
 #[cfg(test)]
-fn figure_9_48_flow_only() -> Program {
-    use crate::Block;
+fn figure_9_48_flow_only() -> crate::Program {
+    use crate::{Block, Program};
 
     Program::with_entry_exit(
         vec![Block::empty(), Block::empty(), Block::empty()],
@@ -146,3 +412,210 @@ fn regions_test() {
     assert!(regions.contains(&body_region));
     assert!(regions.contains(&Region::new_complete(&program)))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bit_vector::{BitSet, Bits, GenKill};
+    use crate::framework::{Backward, Forward, Point, StmtCfg};
+    use crate::reaching_def::Definitions;
+    use crate::{Block, Program, Stmt};
+
+    /// Variables assigned along every path, numbered by their first letter.
+    struct Assigned;
+
+    impl<G: StmtCfg<Stmt = Stmt>> GenKill<G> for Assigned {
+        type Direction = Forward;
+        const MAY: bool = false;
+
+        fn size(&self, _: &G) -> usize {
+            26
+        }
+
+        fn gen_kill(&self, _: &G, _: Point, stmt: &Stmt, gen: &mut BitSet, _: &mut BitSet) {
+            gen.insert(letter(stmt.def().unwrap()));
+        }
+    }
+
+    /// Live variables, numbered by their first letter.
+    struct Live;
+
+    impl<G: StmtCfg<Stmt = Stmt>> GenKill<G> for Live {
+        type Direction = Backward;
+        const MAY: bool = true;
+
+        fn size(&self, _: &G) -> usize {
+            26
+        }
+
+        fn gen_kill(&self, _: &G, _: Point, stmt: &Stmt, gen: &mut BitSet, kill: &mut BitSet) {
+            for var in stmt.uses() {
+                gen.insert(letter(var));
+            }
+            kill.insert(letter(stmt.def().unwrap()));
+        }
+    }
+
+    fn letter(var: &str) -> usize {
+        (var.as_bytes()[0] - b'a') as usize
+    }
+
+    /// A flow graph other than `Program`: its blocks numbered backwards, ENTRY last.
+    struct Renumbered<'a>(&'a Program);
+
+    impl Renumbered<'_> {
+        fn map(&self, block_id: BlockID) -> BlockID {
+            self.0.len() - 1 - block_id
+        }
+    }
+
+    impl Cfg for Renumbered<'_> {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn entry(&self) -> BlockID {
+            self.map(self.0.entry())
+        }
+
+        fn exit(&self) -> Option<BlockID> {
+            self.0.exit().map(|exit| self.map(exit))
+        }
+
+        fn successors(&self, block_id: BlockID) -> Vec<BlockID> {
+            <Program as Cfg>::successors(self.0, self.map(block_id))
+                .into_iter()
+                .map(|s| self.map(s))
+                .collect()
+        }
+
+        fn predecessors(&self, block_id: BlockID) -> Vec<BlockID> {
+            <Program as Cfg>::predecessors(self.0, self.map(block_id))
+                .into_iter()
+                .map(|p| self.map(p))
+                .collect()
+        }
+    }
+
+    impl StmtCfg for Renumbered<'_> {
+        type Stmt = Stmt;
+
+        fn stmts(&self, block_id: BlockID) -> &[Stmt] {
+            self.0.stmts(self.map(block_id))
+        }
+    }
+
+    fn letters(set: &BitSet) -> String {
+        set.iter().map(|i| (b'a' + i as u8) as char).collect()
+    }
+
+    // two nested loops B2-B5 and B3-B4, B6 entered from both
+    fn nested_loops() -> Program {
+        Program::with_entry_exit(
+            vec![
+                Block::parse(1, "a = 1\nb = 2"),
+                Block::parse(3, "c = a + b"),
+                Block::parse(4, "d = c * 2"),
+                Block::parse(5, "a = d - 1\ne = a"),
+                Block::parse(7, "b = e + 1"),
+                Block::parse(8, "f = a + b"),
+            ],
+            &[
+                (0, 1),
+                (1, 2),
+                (2, 3),
+                (3, 4),
+                (4, 3),
+                (4, 5),
+                (5, 2),
+                (5, 6),
+                (2, 6),
+                (6, 7),
+            ],
+        )
+    }
+
+    #[test]
+    fn region_solve_test() {
+        let program = nested_loops();
+
+        let bits = Bits::new(&program, Definitions);
+        let iterative = crate::framework::solve(&program, &bits);
+        let regional = solve(&program, &bits).unwrap();
+        assert_eq!(iterative.into_pairs(), regional.into_pairs());
+
+        let bits = Bits::new(&program, Assigned);
+        let iterative = crate::framework::solve(&program, &bits);
+        let regional = solve(&program, &bits).unwrap();
+        assert_eq!(letters(regional.in_value(6)), "abc");
+        assert_eq!(letters(regional.out_value(4)), "abcde");
+        assert_eq!(iterative.into_pairs(), regional.into_pairs());
+    }
+
+    #[test]
+    fn generic_region_solve_test() {
+        let program = nested_loops();
+        let renumbered = Renumbered(&program);
+
+        let expected = solve(&program, &Bits::new(&program, Assigned)).unwrap();
+        let regional = solve(&renumbered, &Bits::new(&renumbered, Assigned)).unwrap();
+        for block_id in program.block_range() {
+            let i = renumbered.map(block_id);
+            assert_eq!(expected.in_value(block_id), regional.in_value(i));
+            assert_eq!(expected.out_value(block_id), regional.out_value(i));
+        }
+
+        let bits = Bits::new(&renumbered, Assigned);
+        let iterative = crate::framework::solve(&renumbered, &bits);
+        let regional = solve(&renumbered, &bits).unwrap();
+        assert_eq!(iterative.into_pairs(), regional.into_pairs());
+    }
+
+    #[test]
+    fn backward_region_solve_test() {
+        // a single loop B2-B4 left from B4 only
+        let program = crate::figure_9_13();
+        let bits = Bits::new(&program, Live);
+        let iterative = crate::framework::solve(&program, &bits);
+        let regional = solve(&program, &bits).unwrap();
+        assert_eq!(letters(regional.in_value(1)), "mnu");
+        assert_eq!(letters(regional.in_value(2)), "iju");
+        assert_eq!(iterative.into_pairs(), regional.into_pairs());
+
+        let renumbered = Renumbered(&program);
+        let bits = Bits::new(&renumbered, Live);
+        let iterative = crate::framework::solve(&renumbered, &bits);
+        let regional = solve(&renumbered, &bits).unwrap();
+        assert_eq!(iterative.into_pairs(), regional.into_pairs());
+    }
+
+    #[test]
+    fn irreducible_test() {
+        // B2 and B3 both entered from B1
+        let program = Program::with_entry_exit(
+            vec![
+                Block::parse(1, "a = 1"),
+                Block::parse(2, "b = a"),
+                Block::parse(3, "c = b"),
+            ],
+            &[(0, 1), (1, 2), (1, 3), (2, 3), (3, 2), (3, 4)],
+        );
+        let bits = Bits::new(&program, Assigned);
+        assert_eq!(
+            solve(&program, &bits).unwrap_err(),
+            RegionError::Irreducible
+        );
+        // the iterative solver takes any flow graph
+        let iterative = crate::framework::solve(&program, &bits);
+        assert_eq!(letters(iterative.in_value(4)), "ac");
+
+        // reversed, the loop B2-B5 left from B2 and B5 is entered at both
+        let program = nested_loops();
+        let bits = Bits::new(&program, Live);
+        assert_eq!(
+            solve(&program, &bits).unwrap_err(),
+            RegionError::Irreducible
+        );
+        assert!(solve(&program, &Bits::new(&program, Assigned)).is_ok());
+    }
+}
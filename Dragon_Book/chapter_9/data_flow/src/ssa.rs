@@ -0,0 +1,553 @@
+//! Static single assignment form, constructed with dominance frontiers as in Cytron et al.
+//! and translated back by parallel copies on the incoming edges of phi functions.
+
+use crate::dominator::Dominators;
+use crate::live_var::live_variables;
+use crate::utils::sorted;
+use crate::{BlockID, Program, RValue, Stmt, Var};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// a phi function at every block in the iterated dominance frontier of a definition
+    Minimal,
+    /// as minimal, but only where the variable is live on entry to the block
+    Pruned,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Phi {
+    pub dst: Var,
+    /// one argument for each predecessor of the block
+    pub args: Vec<(BlockID, RValue)>,
+}
+
+impl Debug for Phi {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|(p, arg)| format!("B{}: {:?}", p, arg))
+            .collect();
+        write!(f, "{} = phi({})", self.dst, args.join(", "))
+    }
+}
+
+/// A program in SSA form, phi functions are kept apart from the statements of each block.
+/// Every definition of x is renamed to x.1, x.2 and so on, while x itself refers to the value
+/// x has on entry to the program.
+pub struct Ssa {
+    program: Program,
+    phis: Vec<Vec<Phi>>,
+}
+
+impl Ssa {
+    pub fn new(mut program: Program, placement: Placement) -> Self {
+        let origins = place_phis(&program, placement);
+        let mut phis: Vec<Vec<Phi>> = origins
+            .iter()
+            .map(|vars| {
+                vars.iter()
+                    .map(|var| Phi {
+                        dst: var.clone(),
+                        args: vec![],
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Renamer::new(&program).rename(&mut program, &mut phis, &origins);
+        Ssa { program, phis }
+    }
+
+    /// Wrap a program already in SSA form, e.g. one rewritten by an optimization on SSA.
+    pub fn with_phis(program: Program, phis: Vec<Vec<Phi>>) -> Self {
+        assert_eq!(
+            program.len(),
+            phis.len(),
+            "Error: Phi functions for each block required"
+        );
+        Ssa { program, phis }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn phis(&self, block_id: BlockID) -> &[Phi] {
+        &self.phis[block_id]
+    }
+
+    /// Replace phi functions by copies. Copies for a critical edge go to a new block along the
+    /// edge so they are not executed on other paths (the lost-copy problem), and copies of the
+    /// same edge are sequentialized as if executed in parallel (the swap problem).
+    pub fn destruct(self) -> Program {
+        let Ssa { mut program, phis } = self;
+        let mut copies: BTreeMap<BlockID, Vec<(Var, RValue)>> = BTreeMap::new();
+
+        for (block_id, block_phis) in phis.iter().enumerate() {
+            if block_phis.is_empty() {
+                continue;
+            }
+
+            let preds: Vec<BlockID> = program.predecessors(block_id).map(|(p, _)| p).collect();
+            for &pred in &preds {
+                let at = if preds.len() > 1 && program.successors(pred).count() > 1 {
+                    program.insert_empty_between(pred, block_id);
+                    program.len() - 1
+                } else {
+                    pred
+                };
+
+                let edge_copies = copies.entry(at).or_default();
+                for phi in block_phis {
+                    if let Some((_, arg)) = phi.args.iter().find(|(p, _)| *p == pred) {
+                        edge_copies.push((phi.dst.clone(), arg.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut temps = 0;
+        for (block_id, parallel) in copies {
+            let stmts = sequentialize(parallel, || {
+                temps += 1;
+                format!("tmp.{}", temps)
+            });
+            program.blocks[block_id].stmts.extend(stmts);
+        }
+
+        program.renumber();
+        program
+    }
+}
+
+impl Debug for Ssa {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        for (block_id, block) in self.program.blocks().enumerate() {
+            writeln!(f, "B{}:", block_id)?;
+            for phi in &self.phis[block_id] {
+                writeln!(f, "    {:?}", phi)?;
+            }
+            for stmt in block.stmts() {
+                writeln!(f, "    {:?}", stmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Variables that need a phi function at the start of each block.
+fn place_phis(program: &Program, placement: Placement) -> Vec<Vec<Var>> {
+    let frontiers = Dominators::new(program).frontiers();
+    let live = live_variables(program);
+
+    let mut defsites: BTreeMap<&str, HashSet<BlockID>> = BTreeMap::new();
+    for (block_id, block) in program.blocks().enumerate() {
+        for var in block.stmts().filter_map(Stmt::def) {
+            defsites.entry(var).or_default().insert(block_id);
+        }
+    }
+
+    let mut origins = vec![vec![]; program.len()];
+    for (var, sites) in defsites {
+        let mut has_phi = HashSet::new();
+        let mut visited = sites.clone();
+        let mut worklist = sorted(sites);
+
+        while let Some(n) = worklist.pop() {
+            for y in sorted(&frontiers[n]) {
+                if has_phi.contains(y) {
+                    continue;
+                }
+                if placement == Placement::Pruned && !live.live_in(*y).contains(var) {
+                    continue;
+                }
+
+                origins[*y].push(var.to_string());
+                has_phi.insert(*y);
+                if visited.insert(*y) {
+                    worklist.push(*y);
+                }
+            }
+        }
+    }
+
+    origins
+}
+
+struct Renamer {
+    children: Vec<Vec<BlockID>>,
+    successors: Vec<Vec<BlockID>>,
+    counters: HashMap<Var, usize>,
+    stacks: HashMap<Var, Vec<Var>>,
+}
+
+impl Renamer {
+    fn new(program: &Program) -> Self {
+        let dominators = Dominators::new(program);
+        let mut children = vec![vec![]; program.len()];
+        for block_id in program.block_range() {
+            if let Some(idom) = dominators.immediate(block_id) {
+                children[idom].push(block_id);
+            }
+        }
+
+        let successors = program
+            .block_range()
+            .map(|block_id| sorted(program.successors(block_id).map(|(s, _)| s)))
+            .collect();
+
+        Renamer {
+            children,
+            successors,
+            counters: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    fn current(&self, var: &str) -> Var {
+        self.stacks
+            .get(var)
+            .and_then(|stack| stack.last())
+            .cloned()
+            .unwrap_or_else(|| var.to_string())
+    }
+
+    fn define(&mut self, var: &str) -> Var {
+        let counter = self.counters.entry(var.to_string()).or_insert(0);
+        *counter += 1;
+        let name = format!("{}.{}", var, counter);
+        self.stacks
+            .entry(var.to_string())
+            .or_default()
+            .push(name.clone());
+        name
+    }
+
+    fn rename_rvalue(&self, rvalue: &mut RValue) {
+        if let RValue::Var(var) = rvalue {
+            *var = self.current(var);
+        }
+    }
+
+    fn rename(&mut self, program: &mut Program, phis: &mut [Vec<Phi>], origins: &[Vec<Var>]) {
+        // blocks unreachable from ENTRY are left untouched
+        self.rename_block(program.entry(), program, phis, origins);
+    }
+
+    fn rename_block(
+        &mut self,
+        block_id: BlockID,
+        program: &mut Program,
+        phis: &mut [Vec<Phi>],
+        origins: &[Vec<Var>],
+    ) {
+        let mut defined = vec![];
+
+        for (phi, var) in phis[block_id].iter_mut().zip(&origins[block_id]) {
+            phi.dst = self.define(var);
+            defined.push(var.clone());
+        }
+
+        for stmt in &mut program.blocks[block_id].stmts {
+            let dst = match stmt {
+                Stmt::Op(dst, lhs, _, rhs) => {
+                    self.rename_rvalue(lhs);
+                    self.rename_rvalue(rhs);
+                    dst
+                }
+                Stmt::Copy(dst, src) => {
+                    self.rename_rvalue(src);
+                    dst
+                }
+            };
+            defined.push(dst.clone());
+            *dst = self.define(dst);
+        }
+
+        for succ in self.successors[block_id].clone() {
+            for (phi, var) in phis[succ].iter_mut().zip(&origins[succ]) {
+                phi.args.push((block_id, RValue::Var(self.current(var))));
+            }
+        }
+
+        for child in self.children[block_id].clone() {
+            self.rename_block(child, program, phis, origins);
+        }
+
+        for var in defined {
+            self.stacks.get_mut(&var).and_then(Vec::pop);
+        }
+    }
+}
+
+/// Order a set of copies executed in parallel so that no source is overwritten before it is read,
+/// breaking cycles with fresh temporaries.
+fn sequentialize<F>(parallel: Vec<(Var, RValue)>, mut fresh: F) -> Vec<Stmt>
+where
+    F: FnMut() -> Var,
+{
+    let mut pending: Vec<(Var, RValue)> = parallel
+        .into_iter()
+        .filter(|(dst, src)| src.var() != Some(dst))
+        .collect();
+    let mut stmts = vec![];
+
+    while !pending.is_empty() {
+        let free = pending
+            .iter()
+            .position(|(dst, _)| pending.iter().all(|(_, src)| src.var() != Some(dst)));
+
+        match free {
+            Some(i) => {
+                let (dst, src) = pending.remove(i);
+                stmts.push(Stmt::Copy(dst, src));
+            }
+            None => {
+                // every remaining destination is still to be read, save one of them
+                let saved = pending[0].0.clone();
+                let temp = fresh();
+                stmts.push(Stmt::Copy(temp.clone(), RValue::Var(saved.clone())));
+                for (_, src) in &mut pending {
+                    if src.var() == Some(&saved) {
+                        *src = RValue::Var(temp.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    stmts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpret::{run_path, Env};
+    use crate::{BinOp, Block, BlockType, Lit};
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn block(start: usize, stmts: Vec<Stmt>) -> Block {
+        Block {
+            start,
+            stmts,
+            btype: BlockType::Basic,
+        }
+    }
+
+    fn phi(dst: &str, args: &[(BlockID, &str)]) -> Phi {
+        Phi {
+            dst: s(dst),
+            args: args.iter().map(|(p, v)| (*p, s(v).into())).collect(),
+        }
+    }
+
+    fn defined(ssa: &Ssa) -> Vec<&str> {
+        let mut defs: Vec<&str> = ssa
+            .program
+            .blocks()
+            .flat_map(Block::stmts)
+            .filter_map(Stmt::def)
+            .collect();
+        defs.extend(ssa.phis.iter().flatten().map(|phi| phi.dst.as_str()));
+        defs
+    }
+
+    fn phi_origins(ssa: &Ssa, block_id: BlockID) -> Vec<&str> {
+        sorted(
+            ssa.phis(block_id)
+                .iter()
+                .map(|phi| phi.dst.split('.').next().unwrap()),
+        )
+    }
+
+    #[test]
+    fn placement_test() {
+        let minimal = Ssa::new(crate::figure_9_13(), Placement::Minimal);
+        assert_eq!(phi_origins(&minimal, 2), vec!["a", "i", "j"]);
+        assert_eq!(phi_origins(&minimal, 4), vec!["a"]);
+
+        // a is never used, i is redefined in B4 before any use
+        let pruned = Ssa::new(crate::figure_9_13(), Placement::Pruned);
+        assert_eq!(phi_origins(&pruned, 2), vec!["i", "j"]);
+        assert!(pruned.phis(4).is_empty());
+    }
+
+    #[test]
+    fn rename_test() {
+        let ssa = Ssa::new(crate::figure_9_13(), Placement::Pruned);
+        let defs = defined(&ssa);
+        let unique: HashSet<&str> = defs.iter().cloned().collect();
+        assert_eq!(defs.len(), unique.len());
+
+        // B2: i.2 = phi(B1: i.1, B4: i.4), i.3 = i.2 + 1
+        let i = &ssa.phis(2)[0];
+        assert_eq!(i.dst, "i.2");
+        assert_eq!(i.args, vec![(1, s("i.1").into()), (4, s("i.4").into())]);
+        assert_eq!(
+            ssa.program.get_block(2).unwrap().stmts[0],
+            Stmt::Op(s("i.3"), s("i.2").into(), BinOp::Add, RValue::Lit(1))
+        );
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let path = [0, 1, 2, 3, 4, 2, 4, 2, 3, 4, 5];
+        let inputs: Env = [("m", 5), ("n", 9), ("u1", 1), ("u2", 2), ("u3", 3)]
+            .iter()
+            .map(|(var, lit)| (s(var), *lit))
+            .collect();
+
+        let program = crate::figure_9_13();
+        let added = program.len();
+        let expected = run_path(&program, &[], &path, added, inputs.clone())
+            .unwrap()
+            .trace;
+
+        for placement in &[Placement::Minimal, Placement::Pruned] {
+            let ssa = Ssa::new(crate::figure_9_13(), *placement);
+            let run = run_path(&ssa.program, &ssa.phis, &path, added, inputs.clone()).unwrap();
+            assert_eq!(run.trace, expected);
+            // blocks inserted into edges are executed when the edge is taken
+            let program = ssa.destruct();
+            let run = run_path(&program, &[], &path, added, inputs.clone()).unwrap();
+            assert_eq!(run.trace, expected);
+        }
+    }
+
+    // ENTRY -> B1 -> B2 <-> B2 -> B3 -> EXIT, where the copy x.3 = x.2 + 1 has been folded into the phi
+    fn lost_copy() -> Ssa {
+        let blocks = vec![
+            Block::entry(),
+            block(1, vec![Stmt::Copy(s("x.1"), RValue::Lit(1))]),
+            block(
+                2,
+                vec![Stmt::Op(
+                    s("x.3"),
+                    s("x.2").into(),
+                    BinOp::Add,
+                    RValue::Lit(1),
+                )],
+            ),
+            block(
+                3,
+                vec![Stmt::Op(
+                    s("y.1"),
+                    s("x.2").into(),
+                    BinOp::Mul,
+                    RValue::Lit(10),
+                )],
+            ),
+            Block::exit(),
+        ];
+        let program = Program::new(blocks, &[(0, 1), (1, 2), (2, 2), (2, 3), (3, 4)]);
+        let phis = vec![
+            vec![],
+            vec![],
+            vec![phi("x.2", &[(1, "x.1"), (2, "x.3")])],
+            vec![],
+            vec![],
+        ];
+        Ssa::with_phis(program, phis)
+    }
+
+    #[test]
+    fn lost_copy_test() {
+        let path = [0, 1, 2, 2, 2, 3, 4];
+        let ssa = lost_copy();
+        let added = ssa.program.len();
+        let expected = run_path(&ssa.program, &ssa.phis, &path, added, Env::new())
+            .unwrap()
+            .trace;
+        assert_eq!(expected, vec![2, 3, 4, 30]);
+
+        let program = ssa.destruct();
+        assert_eq!(program.exit(), Some(4));
+        let run = run_path(&program, &[], &path, added, Env::new()).unwrap();
+        assert_eq!(run.trace, expected);
+    }
+
+    // a.2 and b.2 are swapped on every iteration of the loop
+    fn swap() -> Ssa {
+        let blocks = vec![
+            Block::entry(),
+            block(
+                1,
+                vec![
+                    Stmt::Copy(s("a.1"), RValue::Lit(1)),
+                    Stmt::Copy(s("b.1"), RValue::Lit(2)),
+                ],
+            ),
+            block(
+                3,
+                vec![
+                    Stmt::Op(s("c.1"), s("a.2").into(), BinOp::Mul, RValue::Lit(10)),
+                    Stmt::Op(s("d.1"), s("c.1").into(), BinOp::Add, s("b.2").into()),
+                ],
+            ),
+            Block::exit(),
+        ];
+        let program = Program::new(blocks, &[(0, 1), (1, 2), (2, 2), (2, 3)]);
+        let phis = vec![
+            vec![],
+            vec![],
+            vec![
+                phi("a.2", &[(1, "a.1"), (2, "b.2")]),
+                phi("b.2", &[(1, "b.1"), (2, "a.2")]),
+            ],
+            vec![],
+        ];
+        Ssa::with_phis(program, phis)
+    }
+
+    #[test]
+    fn swap_test() {
+        let path = [0, 1, 2, 2, 2, 3];
+        let ssa = swap();
+        let added = ssa.program.len();
+        let expected = run_path(&ssa.program, &ssa.phis, &path, added, Env::new())
+            .unwrap()
+            .trace;
+        assert_eq!(expected, vec![10, 12, 20, 21, 10, 12]);
+
+        let program = ssa.destruct();
+        let run = run_path(&program, &[], &path, added, Env::new()).unwrap();
+        assert_eq!(run.trace, expected);
+    }
+
+    #[test]
+    fn sequentialize_test() {
+        let mut temps = 0;
+        let mut fresh = || {
+            temps += 1;
+            format!("t{}", temps)
+        };
+
+        // a <- b, b <- c, c <- a form a cycle, d <- a must read a before it is overwritten
+        let parallel = vec![
+            (s("a"), s("b").into()),
+            (s("b"), s("c").into()),
+            (s("c"), s("a").into()),
+            (s("d"), s("a").into()),
+            (s("e"), s("e").into()),
+        ];
+        let stmts = sequentialize(parallel, &mut fresh);
+        assert_eq!(stmts.len(), 5);
+
+        let mut env: HashMap<Var, Lit> = vec![(s("a"), 1), (s("b"), 2), (s("c"), 3)]
+            .into_iter()
+            .collect();
+        for stmt in &stmts {
+            if let Stmt::Copy(dst, RValue::Var(src)) = stmt {
+                let value = env[src];
+                env.insert(dst.clone(), value);
+            }
+        }
+        assert_eq!((env["a"], env["b"], env["c"], env["d"]), (2, 3, 1, 1));
+    }
+}
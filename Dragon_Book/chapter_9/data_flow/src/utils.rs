@@ -1,19 +1,19 @@
 use std::hash::Hash;
 
-pub fn sorted<'a, I, T: 'a>(set: I) -> Vec<T>
+pub fn sorted<'a, I, T>(set: I) -> Vec<T>
 where
     I: IntoIterator<Item = T>,
-    T: Ord + Hash + Clone,
+    T: Ord + Hash + Clone + 'a,
 {
     let mut sorted: Vec<_> = set.into_iter().collect();
     sorted.sort();
     sorted
 }
 
-pub fn filter_indices<'a, I: 'a, T, F: 'a>(iter: I, p: F) -> impl Iterator<Item = usize> + 'a
+pub fn filter_indices<'a, I, T, F>(iter: I, p: F) -> impl Iterator<Item = usize> + 'a
 where
-    I: IntoIterator<Item = T>,
-    F: Fn(T) -> bool,
+    I: IntoIterator<Item = T> + 'a,
+    F: Fn(T) -> bool + 'a,
 {
     iter.into_iter()
         .enumerate()
@@ -1,127 +1,141 @@
+mod page_table;
+mod tlb;
+
+use page_table::{Inverted, MultiLevel, PageTable};
+use replacement::{Replacement, FIFO};
 use std::{
-    collections::HashMap,
     fs::File,
     io::{self, Read, Seek, SeekFrom},
 };
+use tlb::Tlb;
 
 type PageNo = usize;
 type FrameNo = PageNo;
 pub type Addr = usize;
+/// Builds a replacement policy managing the given number of frames, or entries of a TLB set.
+pub type PolicyFactory<'a> = &'a dyn Fn(usize) -> Box<dyn Replacement>;
 
-const TLB_SIZE: usize = 16;
-const N_PAGES: usize = 256;
-const PAGE_SIZE: usize = 256;
-const N_FRAMES: usize = 128;
-const MEM_SIZE: usize = N_FRAMES * PAGE_SIZE;
-
-struct CacheEntry {
-    frame: PageNo,
-    last_accessed: usize,
+/// Organization of the page table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageTableKind {
+    /// A hierarchical page table with the given number of levels, 1 is a linear page table.
+    Levels(usize),
+    /// One entry per frame, looked up through a hash anchor table.
+    Inverted,
 }
 
-struct Cache {
-    size: usize,
-    entries: HashMap<PageNo, CacheEntry>,
+/// Geometry of the simulated machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Width of logical addresses, higher bits are ignored.
+    pub address_bits: u32,
+    /// Bytes per page, must be a power of 2.
+    pub page_size: usize,
+    pub tlb_entries: usize,
+    /// Entries per TLB set, equal to `tlb_entries` for a fully associative TLB.
+    pub tlb_ways: usize,
+    pub frames: usize,
+    pub page_table: PageTableKind,
 }
 
-impl Cache {
-    fn new(size: usize) -> Self {
+/// The machine from the textbook project: 16-bit addresses, 256 pages of 256 bytes, a fully
+/// associative TLB of 16 entries and 128 frames.
+impl Default for Config {
+    fn default() -> Self {
         Self {
-            size,
-            entries: HashMap::new(),
+            address_bits: 16,
+            page_size: 256,
+            tlb_entries: 16,
+            tlb_ways: 16,
+            frames: 128,
+            page_table: PageTableKind::Levels(1),
         }
     }
+}
 
-    fn get(&self, page: PageNo) -> Option<FrameNo> {
-        self.entries.get(&page).map(|entry| entry.frame)
+impl Config {
+    fn offset_bits(&self) -> u32 {
+        self.page_size.trailing_zeros()
     }
 
-    // LRU page replacement
-    fn replace_with(&mut self, page: PageNo, frame: FrameNo, curr_time: usize) {
-        if self.entries.len() >= self.size {
-            let replaced_page = *self
-                .entries
-                .iter()
-                .min_by_key(|(_, entry)| entry.last_accessed)
-                .expect("Cache::replace: cache size must be positive")
-                .0;
-            self.entries.remove(&replaced_page);
-        }
-
-        self.entries.insert(
-            page,
-            CacheEntry {
-                frame,
-                last_accessed: curr_time,
-            },
-        );
+    fn page_bits(&self) -> u32 {
+        self.address_bits - self.offset_bits()
     }
-}
 
-#[derive(Clone, Default)]
-struct PageTableEntry {
-    valid: bool,
-    frame: PageNo,
-    last_accessed: usize,
+    fn validate(&self) -> Result<(), &'static str> {
+        if !self.page_size.is_power_of_two() {
+            return Err("page size must be a power of 2");
+        }
+        if self.address_bits > 32 || self.address_bits <= self.offset_bits() {
+            return Err("address width must be wider than the page offset and at most 32 bits");
+        }
+        if self.tlb_ways == 0
+            || self.tlb_entries == 0
+            || !self.tlb_entries.is_multiple_of(self.tlb_ways)
+        {
+            return Err("TLB entries must be a positive multiple of its associativity");
+        }
+        if self.frames == 0 {
+            return Err("there must be at least one frame");
+        }
+        if let PageTableKind::Levels(levels) = self.page_table {
+            if levels == 0 || levels as u32 > self.page_bits() {
+                return Err("page table levels must be between 1 and the bits of page number");
+            }
+        }
+        Ok(())
+    }
 }
 
-struct PageTable {
-    entries: Vec<PageTableEntry>,
-    free_frames: Vec<FrameNo>,
+/// Counters accumulated over all accesses of a MMU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub accesses: usize,
+    pub tlb_hits: usize,
+    pub page_faults: usize,
+    /// Memory references made walking the page table on TLB misses.
+    pub walk_references: usize,
 }
 
-impl PageTable {
-    fn new(frames: usize) -> Self {
-        Self {
-            entries: vec![PageTableEntry::default(); N_PAGES],
-            free_frames: (0..frames).collect::<Vec<_>>(),
-        }
+impl Stats {
+    /// 0 before the first access.
+    pub fn tlb_hit_ratio(&self) -> f64 {
+        ratio(self.tlb_hits, self.accesses)
     }
 
-    fn get(&self, page: PageNo) -> Option<FrameNo> {
-        let entry = &self.entries[page as usize];
-        if entry.valid {
-            Some(entry.frame)
-        } else {
-            None
-        }
+    /// 0 before the first access.
+    pub fn page_fault_rate(&self) -> f64 {
+        ratio(self.page_faults, self.accesses)
     }
 
-    fn free_frame(&mut self) -> FrameNo {
-        if let Some(frame) = self.free_frames.pop() {
-            frame
-        } else {
-            self.replace()
-        }
-    }
-
-    fn replace(&mut self) -> FrameNo {
-        let replaced_entry = self
-            .entries
-            .iter_mut()
-            .filter(|entry| entry.valid)
-            .min_by_key(|entry| entry.last_accessed)
-            .expect("PageTable::replace: only called when all frames are assigned");
-
-        replaced_entry.valid = false;
-        replaced_entry.frame
+    /// Average memory references per page walk, 0 if the page table was never walked.
+    pub fn average_walk_cost(&self) -> f64 {
+        ratio(self.walk_references, self.accesses - self.tlb_hits)
     }
+}
 
-    fn update(&mut self, page: PageNo, frame: FrameNo, curr_time: usize) {
-        let entry = &mut self.entries[page as usize];
-        entry.valid = true;
-        entry.frame = frame;
-        entry.last_accessed = curr_time;
+// 0 / 0 would be NaN
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
     }
 }
 
 pub struct MMU {
+    config: Config,
     // tlb is a cache of page table
-    tlb: Cache,
+    tlb: Tlb,
     // main memory is a cache of backing store
-    page_table: PageTable,
-    main_memory: [u8; MEM_SIZE],
+    page_table: Box<dyn PageTable>,
+    frame_policy: Box<dyn Replacement>,
+    free_frames: Vec<FrameNo>,
+    main_memory: Vec<u8>,
     backing_store: File,
+    // pages referenced so far, followed by the rest of the trace if known in advance
+    trace: Vec<u32>,
+    stats: Stats,
 }
 
 pub enum MMUResponse {
@@ -132,56 +146,336 @@ pub enum MMUResponse {
 
 use MMUResponse::*;
 
+fn fifo(frames: usize) -> Box<dyn Replacement> {
+    Box::new(FIFO::new(frames))
+}
+
 impl MMU {
     pub fn new(backing_store: File) -> Self {
-        Self {
-            tlb: Cache::new(TLB_SIZE),
-            page_table: PageTable::new(N_FRAMES),
-            main_memory: [0; MEM_SIZE],
+        Self::with_config(Config::default(), backing_store)
+            .expect("MMU::new: the default configuration must be valid")
+    }
+
+    /// Both the TLB and main memory replace their oldest entries.
+    pub fn with_config(config: Config, backing_store: File) -> io::Result<Self> {
+        Self::with_policies(config, backing_store, &fifo, &fifo)
+    }
+
+    pub fn with_policies(
+        config: Config,
+        backing_store: File,
+        tlb_policy: PolicyFactory,
+        frame_policy: PolicyFactory,
+    ) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+
+        let page_table: Box<dyn PageTable> = match config.page_table {
+            PageTableKind::Levels(levels) => Box::new(MultiLevel::new(config.page_bits(), levels)),
+            PageTableKind::Inverted => Box::new(Inverted::new(config.frames)),
+        };
+
+        Ok(Self {
+            tlb: Tlb::new(config.tlb_entries, config.tlb_ways, tlb_policy),
+            page_table,
+            frame_policy: frame_policy(config.frames),
+            free_frames: (0..config.frames).collect(),
+            main_memory: vec![0; config.frames * config.page_size],
             backing_store,
-        }
+            trace: vec![],
+            stats: Stats::default(),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Entries allocated for the page table so far.
+    pub fn page_table_size(&self) -> usize {
+        self.page_table.size()
     }
 
     fn memory_access(&self, addr: Addr) -> u8 {
-        self.main_memory[addr as usize]
+        self.main_memory[addr]
+    }
+
+    /// Translate and read a single address. Policies looking into the future like OPT only see
+    /// this access, use `run` to give them the whole trace.
+    pub fn access(&mut self, addr: Addr) -> io::Result<MMUResponse> {
+        let (page, offset) = self.divide(addr);
+        self.trace.truncate(self.stats.accesses);
+        self.trace.push(page as u32);
+        self.step(offset)
     }
 
-    pub fn access(&mut self, addr: Addr, curr_time: usize) -> io::Result<MMUResponse> {
-        let (page, offset) = divide(addr);
+    /// Translate and read every address of a trace in order.
+    pub fn run(&mut self, addrs: &[Addr]) -> io::Result<Vec<MMUResponse>> {
+        self.trace.truncate(self.stats.accesses);
+        let offsets: Vec<usize> = addrs
+            .iter()
+            .map(|&addr| {
+                let (page, offset) = self.divide(addr);
+                self.trace.push(page as u32);
+                offset
+            })
+            .collect();
+
+        offsets
+            .into_iter()
+            .map(|offset| self.step(offset))
+            .collect()
+    }
 
-        if let Some(frame) = self.tlb.get(page) {
-            let physical_addr = combine(frame, offset);
-            Ok(TLBHit(self.memory_access(physical_addr)))
-        } else if let Some(frame) = self.page_table.get(page) {
-            let physical_addr = combine(frame, offset);
-            self.tlb.replace_with(page, frame, curr_time);
+    fn step(&mut self, offset: usize) -> io::Result<MMUResponse> {
+        let idx = self.stats.accesses;
+        let page = self.trace[idx] as PageNo;
+        self.stats.accesses += 1;
+
+        if let Some(frame) = self.tlb.get(&self.trace, idx) {
+            self.stats.tlb_hits += 1;
+            self.frame_policy.access(&self.trace, idx);
+            let physical_addr = self.combine(frame, offset);
+            return Ok(TLBHit(self.memory_access(physical_addr)));
+        }
+
+        let (entry, references) = self.page_table.walk(page);
+        self.stats.walk_references += references;
+
+        if let Some(frame) = entry {
+            self.frame_policy.access(&self.trace, idx);
+            let physical_addr = self.combine(frame, offset);
+            self.tlb.insert(&self.trace, idx, frame);
             Ok(PageTableHit(self.memory_access(physical_addr)))
         } else {
             // page fault
-            let frame = self.page_table.free_frame();
+            self.stats.page_faults += 1;
+            let frame = self.free_frame(idx);
             self.swap_in(page, frame)?;
-            self.page_table.update(page, frame, curr_time);
-            self.tlb.replace_with(page, frame, curr_time);
-            let physical_addr = combine(frame, offset);
+            self.page_table.map(page, frame);
+            self.tlb.insert(&self.trace, idx, frame);
+            let physical_addr = self.combine(frame, offset);
             Ok(PageFault(self.memory_access(physical_addr)))
         }
     }
 
+    fn free_frame(&mut self, idx: usize) -> FrameNo {
+        for victim in self.frame_policy.replace(&self.trace, idx) {
+            let victim = victim as PageNo;
+            let (frame, _) = self.page_table.walk(victim);
+            let frame = frame.expect("MMU::free_frame: evicted page must be mapped");
+            self.page_table.unmap(victim);
+            // the TLB must not keep translating the evicted page to its old frame
+            self.tlb.invalidate(victim);
+            self.free_frames.push(frame);
+        }
+
+        self.free_frames
+            .pop()
+            .expect("MMU::free_frame: replacement policy must not exceed the number of frames")
+    }
+
     fn swap_in(&mut self, page: PageNo, frame: FrameNo) -> io::Result<()> {
-        let frame_ptr = &mut self.main_memory[frame * PAGE_SIZE..(frame + 1) * PAGE_SIZE];
+        let page_size = self.config.page_size;
+        let frame_ptr = &mut self.main_memory[frame * page_size..(frame + 1) * page_size];
         self.backing_store
-            .seek(SeekFrom::Start((page * PAGE_SIZE) as u64))?;
+            .seek(SeekFrom::Start((page * page_size) as u64))?;
         self.backing_store.read_exact(frame_ptr)?;
         Ok(())
     }
-}
 
-fn divide(addr: Addr) -> (PageNo, usize) {
-    let page = ((addr & 0xff00) >> 8) as PageNo;
-    let offset = addr & 0x00ff;
-    (page, offset)
+    fn divide(&self, addr: Addr) -> (PageNo, usize) {
+        let addr = addr & ((1 << self.config.address_bits) - 1);
+        let page = addr >> self.config.offset_bits();
+        let offset = addr & (self.config.page_size - 1);
+        (page, offset)
+    }
+
+    fn combine(&self, frame: FrameNo, offset: usize) -> Addr {
+        (frame << self.config.offset_bits()) | offset
+    }
 }
 
-fn combine(frame: PageNo, offset: usize) -> Addr {
-    (frame << 8) | offset
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replacement::{LRU, OPT};
+    use std::{env, fs, io::Write, path::PathBuf, process};
+
+    // every byte of the backing store is the low byte of its page number
+    fn backing_store(name: &str, pages: usize, page_size: usize) -> File {
+        let path: PathBuf = env::temp_dir().join(format!("mmu-{}-{}.bin", name, process::id()));
+        let mut file = File::create(&path).unwrap();
+        for page in 0..pages {
+            file.write_all(&vec![page as u8; page_size]).unwrap();
+        }
+        let file = File::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        file
+    }
+
+    fn read(mmu: &mut MMU, addr: Addr) -> u8 {
+        match mmu.access(addr).unwrap() {
+            TLBHit(byte) | PageTableHit(byte) | PageFault(byte) => byte,
+        }
+    }
+
+    #[test]
+    fn default_geometry() {
+        let mut mmu = MMU::new(backing_store("default", 256, 256));
+
+        assert!(matches!(mmu.access(0x1234).unwrap(), PageFault(0x12)));
+        assert!(matches!(mmu.access(0x12ff).unwrap(), TLBHit(0x12)));
+        // bits above the address width are ignored
+        assert!(matches!(mmu.access(0xf1234).unwrap(), TLBHit(0x12)));
+
+        let stats = mmu.stats();
+        assert_eq!(
+            (stats.accesses, stats.tlb_hits, stats.page_faults),
+            (3, 2, 1)
+        );
+        assert_eq!(stats.walk_references, 1);
+        assert_eq!(stats.tlb_hit_ratio(), 2.0 / 3.0);
+        assert_eq!(stats.average_walk_cost(), 1.0);
+    }
+
+    #[test]
+    fn empty_stats() {
+        let stats = Stats::default();
+        assert_eq!(stats.tlb_hit_ratio(), 0.0);
+        assert_eq!(stats.page_fault_rate(), 0.0);
+        assert_eq!(stats.average_walk_cost(), 0.0);
+
+        // every access hit the TLB, no page walk to average
+        let stats = Stats {
+            accesses: 2,
+            tlb_hits: 2,
+            ..Stats::default()
+        };
+        assert_eq!(stats.tlb_hit_ratio(), 1.0);
+        assert_eq!(stats.average_walk_cost(), 0.0);
+    }
+
+    #[test]
+    fn walk_costs() {
+        let walk = |page_table| {
+            let config = Config {
+                address_bits: 12,
+                page_size: 16,
+                tlb_entries: 1,
+                tlb_ways: 1,
+                frames: 8,
+                page_table,
+            };
+            let mut mmu = MMU::with_config(config, backing_store("walk", 256, 16)).unwrap();
+            for &page in [0, 1, 0].iter() {
+                assert_eq!(read(&mut mmu, page << 4), page as u8);
+            }
+            (mmu.stats().walk_references, mmu.page_table_size())
+        };
+
+        // a fault in an empty directory stops at the root, a hit visits every level
+        assert_eq!(walk(PageTableKind::Levels(1)), (3, 256));
+        assert_eq!(walk(PageTableKind::Levels(2)), (1 + 2 + 2, 16 + 16));
+        assert_eq!(walk(PageTableKind::Levels(3)), (1 + 3 + 3, 8 + 8 + 4));
+        // the anchor, then one probe per chained entry
+        let (references, size) = walk(PageTableKind::Inverted);
+        assert!(references >= 4);
+        assert_eq!(size, 16);
+    }
+
+    #[test]
+    fn evicted_pages_leave_the_tlb() {
+        let config = Config {
+            address_bits: 8,
+            page_size: 16,
+            tlb_entries: 4,
+            tlb_ways: 4,
+            frames: 2,
+            page_table: PageTableKind::Levels(1),
+        };
+        let mut mmu = MMU::with_config(config, backing_store("evict", 16, 16)).unwrap();
+
+        for &page in [1, 2, 3].iter() {
+            assert_eq!(read(&mut mmu, page << 4), page as u8);
+        }
+        // page 1 was evicted for page 3 and must be swapped in again
+        assert!(matches!(mmu.access(1 << 4).unwrap(), PageFault(1)));
+        assert!(matches!(mmu.access(3 << 4).unwrap(), TLBHit(3)));
+        assert_eq!(mmu.stats().page_faults, 4);
+    }
+
+    #[test]
+    fn replacement_policies() {
+        let config = Config {
+            address_bits: 8,
+            page_size: 16,
+            tlb_entries: 2,
+            tlb_ways: 2,
+            frames: 3,
+            page_table: PageTableKind::Levels(1),
+        };
+        let lru = |frames| Box::new(LRU::new(frames)) as Box<dyn Replacement>;
+        let opt = |frames| Box::new(OPT::new(frames)) as Box<dyn Replacement>;
+
+        let run = |pages: &[usize], tlb_policy: PolicyFactory, frame_policy: PolicyFactory| {
+            let file = backing_store("policies", 16, 16);
+            let mut mmu =
+                MMU::with_policies(config.clone(), file, tlb_policy, frame_policy).unwrap();
+            let trace: Vec<Addr> = pages.iter().map(|page| page << 4).collect();
+            for (response, &page) in mmu.run(&trace).unwrap().into_iter().zip(pages) {
+                match response {
+                    TLBHit(byte) | PageTableHit(byte) | PageFault(byte) => {
+                        assert_eq!(byte as usize, page)
+                    }
+                }
+            }
+            mmu.stats()
+        };
+
+        // the classic page fault counts of Belady's trace on 3 frames
+        let belady = [1, 2, 3, 4, 1, 2, 5, 1, 2, 3, 4, 5];
+        assert_eq!(run(&belady, &fifo, &fifo).page_faults, 9);
+        assert_eq!(run(&belady, &fifo, &lru).page_faults, 10);
+        assert_eq!(run(&belady, &fifo, &opt).page_faults, 7);
+
+        // a FIFO TLB drops the translation of 1 for 3 although 1 is in use
+        let hot = [1, 2, 1, 3, 1];
+        assert_eq!(run(&hot, &fifo, &fifo).tlb_hits, 1);
+        assert_eq!(run(&hot, &lru, &fifo).tlb_hits, 2);
+    }
+
+    #[test]
+    fn invalid_configurations() {
+        let invalid = [
+            Config {
+                page_size: 100,
+                ..Config::default()
+            },
+            Config {
+                address_bits: 8,
+                ..Config::default()
+            },
+            Config {
+                tlb_ways: 3,
+                ..Config::default()
+            },
+            Config {
+                page_table: PageTableKind::Levels(9),
+                ..Config::default()
+            },
+        ];
+
+        for config in invalid.iter() {
+            let file = backing_store("invalid", 1, 1);
+            let err = MMU::with_config(config.clone(), file).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
 }
@@ -1,4 +1,5 @@
-use mmu::{Addr, MMUResponse, MMU};
+use mmu::{Addr, Config, MMUResponse, PageTableKind, PolicyFactory, MMU};
+use replacement::{Clock, Replacement, WorkingSet, CNT, FIFO, LRU, OPT};
 use std::{
     env,
     fs::{self, File},
@@ -7,13 +8,12 @@ use std::{
 use MMUResponse::*;
 
 const BACKING_STORE_PATH: &str = "BACKING_STORE.bin";
+const WORKING_SET_WINDOW: usize = 1000;
+const USAGE: &str = "Usage: EXEC [--address-bits N] [--page-size N] [--tlb N] [--ways N] \
+                     [--frames N] [--levels N | --inverted] INPUT_FILE";
 
 fn main() -> io::Result<()> {
-    let mut args = env::args();
-    args.next();
-    let input_file_path = args
-        .next()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Usage: EXEC INPUT_FILE"))?;
+    let (config, input_file_path) = parse_args(env::args().skip(1))?;
 
     let input = fs::read_to_string(input_file_path)?;
     let references = input
@@ -24,40 +24,92 @@ fn main() -> io::Result<()> {
             io::Error::new(io::ErrorKind::InvalidData, "Error: illegal address in data")
         })?;
 
-    translate(&references)
+    translate(config, &references)
 }
 
-fn translate(references: &[Addr]) -> io::Result<()> {
+fn parse_args(mut args: impl Iterator<Item = String>) -> io::Result<(Config, String)> {
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    let mut config = Config::default();
+    let mut ways = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--inverted" {
+            config.page_table = PageTableKind::Inverted;
+            continue;
+        }
+
+        if !arg.starts_with("--") {
+            // the TLB stays fully associative unless told otherwise
+            config.tlb_ways = ways.unwrap_or(config.tlb_entries);
+            return Ok((config, arg));
+        }
+
+        let value = args
+            .next()
+            .and_then(|value| value.parse::<usize>().ok())
+            .ok_or_else(usage)?;
+
+        match arg.as_str() {
+            "--address-bits" => config.address_bits = value as u32,
+            "--page-size" => config.page_size = value,
+            "--tlb" => config.tlb_entries = value,
+            "--ways" => ways = Some(value),
+            "--frames" => config.frames = value,
+            "--levels" => config.page_table = PageTableKind::Levels(value),
+            _ => return Err(usage()),
+        }
+    }
+
+    Err(usage())
+}
+
+fn translate(config: Config, references: &[Addr]) -> io::Result<()> {
     let backing_store = File::open(BACKING_STORE_PATH)?;
-    let mut mmu = MMU::new(backing_store);
-
-    let mut tlb_hit = 0;
-    let mut page_fault = 0;
-
-    for (i, &addr) in references.iter().enumerate() {
-        let byte = match mmu.access(addr, i)? {
-            TLBHit(byte) => {
-                tlb_hit += 1;
-                byte
-            }
-            PageTableHit(byte) => byte,
-            PageFault(byte) => {
-                page_fault += 1;
-                byte
-            }
+    let mut mmu = MMU::with_config(config.clone(), backing_store)?;
+
+    for (response, &addr) in mmu.run(references)?.into_iter().zip(references) {
+        let byte = match response {
+            TLBHit(byte) | PageTableHit(byte) | PageFault(byte) => byte,
         };
 
         println!("0x{:04X}: {}", addr, byte as i8);
     }
 
-    println!(
-        "TLB hit ratio: {}",
-        tlb_hit as f64 / references.len() as f64
-    );
-    println!(
-        "page fault rate: {}",
-        page_fault as f64 / references.len() as f64
-    );
+    let stats = mmu.stats();
+    println!("TLB hit ratio: {}", stats.tlb_hit_ratio());
+    println!("page fault rate: {}", stats.page_fault_rate());
+    println!("average page walk cost: {}", stats.average_walk_cost());
+    println!("page table entries: {}", mmu.page_table_size());
+
+    compare_policies(config, references)
+}
+
+/// Replay the trace with each replacement policy managing both the TLB and main memory.
+fn compare_policies(config: Config, references: &[Addr]) -> io::Result<()> {
+    let policies: [(&str, PolicyFactory); 6] = [
+        ("FIFO", &|frames| Box::new(FIFO::new(frames))),
+        ("LRU", &|frames| Box::new(LRU::new(frames))),
+        ("OPT", &|frames| Box::new(OPT::new(frames))),
+        ("CNT", &|frames| Box::new(CNT::new(frames))),
+        ("Clock", &|frames| Box::new(Clock::new(frames))),
+        ("WS", &|frames| {
+            Box::new(WorkingSet::new(frames, WORKING_SET_WINDOW)) as Box<dyn Replacement>
+        }),
+    ];
+
+    for (name, policy) in policies.iter() {
+        let backing_store = File::open(BACKING_STORE_PATH)?;
+        let mut mmu = MMU::with_policies(config.clone(), backing_store, *policy, *policy)?;
+        mmu.run(references)?;
+
+        let stats = mmu.stats();
+        println!(
+            "{}: TLB hit ratio {}, page fault rate {}",
+            name,
+            stats.tlb_hit_ratio(),
+            stats.page_fault_rate()
+        );
+    }
 
     Ok(())
 }
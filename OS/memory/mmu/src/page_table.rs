@@ -0,0 +1,202 @@
+use crate::{FrameNo, PageNo};
+
+/// Translation of page numbers to frame numbers, every lookup reports the number of memory
+/// references the hardware would make to walk the table.
+pub(crate) trait PageTable {
+    fn walk(&self, page: PageNo) -> (Option<FrameNo>, usize);
+
+    fn map(&mut self, page: PageNo, frame: FrameNo);
+
+    fn unmap(&mut self, page: PageNo);
+
+    /// Number of entries allocated for the table, a measure of its memory overhead.
+    fn size(&self) -> usize;
+}
+
+enum Table {
+    Directory(Vec<Option<Table>>),
+    Leaf(Vec<Option<FrameNo>>),
+}
+
+/// A hierarchical page table, the page number is split into one index per level from the most
+/// significant bits down. Tables of lower levels are only allocated once a page in their range
+/// is mapped. A single level is the plain linear page table.
+pub(crate) struct MultiLevel {
+    /// Index bits of each level, from the root down.
+    bits: Vec<u32>,
+    root: Table,
+    size: usize,
+}
+
+impl MultiLevel {
+    /// Split `page_bits` over `levels` as evenly as possible, upper levels take the remainder.
+    pub(crate) fn new(page_bits: u32, levels: usize) -> Self {
+        let levels = levels as u32;
+        let bits: Vec<u32> = (0..levels)
+            .map(|level| page_bits / levels + u32::from(level < page_bits % levels))
+            .collect();
+
+        let root = Self::table(&bits, 0);
+        Self {
+            size: 1 << bits[0],
+            bits,
+            root,
+        }
+    }
+
+    fn table(bits: &[u32], level: usize) -> Table {
+        let len = 1 << bits[level];
+        if level + 1 == bits.len() {
+            Table::Leaf(vec![None; len])
+        } else {
+            Table::Directory((0..len).map(|_| None).collect())
+        }
+    }
+
+    fn indices(&self, page: PageNo) -> Vec<usize> {
+        let mut shift: u32 = self.bits.iter().sum();
+        self.bits
+            .iter()
+            .map(|&bits| {
+                shift -= bits;
+                (page >> shift) & ((1 << bits) - 1)
+            })
+            .collect()
+    }
+}
+
+impl PageTable for MultiLevel {
+    fn walk(&self, page: PageNo) -> (Option<FrameNo>, usize) {
+        let mut table = &self.root;
+        let mut references = 0;
+
+        for index in self.indices(page) {
+            references += 1;
+            match table {
+                Table::Directory(entries) => match &entries[index] {
+                    Some(next) => table = next,
+                    None => return (None, references),
+                },
+                Table::Leaf(entries) => return (entries[index], references),
+            }
+        }
+
+        unreachable!("MultiLevel::walk: the last level must be a leaf")
+    }
+
+    fn map(&mut self, page: PageNo, frame: FrameNo) {
+        let indices = self.indices(page);
+        let mut table = &mut self.root;
+
+        for (level, &index) in indices.iter().enumerate() {
+            match table {
+                Table::Directory(entries) => {
+                    let next = &mut entries[index];
+                    if next.is_none() {
+                        self.size += 1 << self.bits[level + 1];
+                        *next = Some(Self::table(&self.bits, level + 1));
+                    }
+                    table = next.as_mut().unwrap();
+                }
+                Table::Leaf(entries) => {
+                    entries[index] = Some(frame);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn unmap(&mut self, page: PageNo) {
+        let indices = self.indices(page);
+        let mut table = &mut self.root;
+
+        for index in indices {
+            match table {
+                Table::Directory(entries) => match &mut entries[index] {
+                    Some(next) => table = next,
+                    None => return,
+                },
+                Table::Leaf(entries) => {
+                    entries[index] = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// An inverted page table with one entry per frame, found through a hash anchor table of the same
+/// size. Entries hashing to the same anchor are chained through the table.
+pub(crate) struct Inverted {
+    pages: Vec<Option<PageNo>>,
+    anchors: Vec<Option<FrameNo>>,
+    chain: Vec<Option<FrameNo>>,
+}
+
+impl Inverted {
+    pub(crate) fn new(frames: usize) -> Self {
+        Self {
+            pages: vec![None; frames],
+            anchors: vec![None; frames],
+            chain: vec![None; frames],
+        }
+    }
+
+    fn hash(&self, page: PageNo) -> usize {
+        // Fibonacci hashing spreads consecutive pages over the anchors
+        page.wrapping_mul(0x9E37_79B9_7F4A_7C15) % self.anchors.len()
+    }
+}
+
+impl PageTable for Inverted {
+    fn walk(&self, page: PageNo) -> (Option<FrameNo>, usize) {
+        // one reference to the anchor, one per entry of the chain visited
+        let mut references = 1;
+        let mut next = self.anchors[self.hash(page)];
+
+        while let Some(frame) = next {
+            references += 1;
+            if self.pages[frame] == Some(page) {
+                return (Some(frame), references);
+            }
+            next = self.chain[frame];
+        }
+
+        (None, references)
+    }
+
+    fn map(&mut self, page: PageNo, frame: FrameNo) {
+        let anchor = self.hash(page);
+        self.pages[frame] = Some(page);
+        self.chain[frame] = self.anchors[anchor];
+        self.anchors[anchor] = Some(frame);
+    }
+
+    fn unmap(&mut self, page: PageNo) {
+        let anchor = self.hash(page);
+        let mut prev: Option<FrameNo> = None;
+        let mut next = self.anchors[anchor];
+
+        while let Some(frame) = next {
+            if self.pages[frame] == Some(page) {
+                match prev {
+                    Some(prev) => self.chain[prev] = self.chain[frame],
+                    None => self.anchors[anchor] = self.chain[frame],
+                }
+                self.pages[frame] = None;
+                self.chain[frame] = None;
+                return;
+            }
+            prev = Some(frame);
+            next = self.chain[frame];
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.pages.len() + self.anchors.len()
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{FrameNo, PageNo, PolicyFactory};
+use replacement::Replacement;
+use std::collections::HashMap;
+
+struct Set {
+    policy: Box<dyn Replacement>,
+    entries: HashMap<PageNo, FrameNo>,
+}
+
+/// A set associative TLB, pages are mapped to sets by the low bits of the page number. One set
+/// makes a fully associative TLB, as many sets as entries a direct mapped one. Each set replaces
+/// its entries by its own instance of the replacement policy.
+pub(crate) struct Tlb {
+    sets: Vec<Set>,
+}
+
+impl Tlb {
+    pub(crate) fn new(entries: usize, ways: usize, policy: PolicyFactory) -> Self {
+        Self {
+            sets: (0..entries / ways)
+                .map(|_| Set {
+                    policy: policy(ways),
+                    entries: HashMap::with_capacity(ways),
+                })
+                .collect(),
+        }
+    }
+
+    fn set(&mut self, page: PageNo) -> &mut Set {
+        let n_sets = self.sets.len();
+        &mut self.sets[page % n_sets]
+    }
+
+    /// Translate the page referenced at `idx` of the trace.
+    pub(crate) fn get(&mut self, trace: &[u32], idx: usize) -> Option<FrameNo> {
+        let set = self.set(trace[idx] as PageNo);
+        let frame = set.entries.get(&(trace[idx] as PageNo)).copied();
+        if frame.is_some() {
+            set.policy.access(trace, idx);
+        }
+        frame
+    }
+
+    /// Cache the translation of the page referenced at `idx` of the trace.
+    pub(crate) fn insert(&mut self, trace: &[u32], idx: usize, frame: FrameNo) {
+        let page = trace[idx] as PageNo;
+        let set = self.set(page);
+        for victim in set.policy.replace(trace, idx) {
+            set.entries.remove(&(victim as PageNo));
+        }
+        set.entries.insert(page, frame);
+    }
+
+    /// Drop the translation of an evicted page.
+    pub(crate) fn invalidate(&mut self, page: PageNo) {
+        let set = self.set(page);
+        if set.entries.remove(&page).is_some() {
+            set.policy.evict(page as u32);
+        }
+    }
+}
@@ -3,7 +3,11 @@ use std::collections::{HashMap, VecDeque};
 pub trait Replacement {
     /// Return false if the page accessed is not in physical memory.
     fn access(&mut self, references: &[u32], idx: usize) -> bool;
-    fn replace(&mut self, references: &[u32], idx: usize);
+    /// Bring the page accessed into physical memory, return the pages evicted to make room.
+    fn replace(&mut self, references: &[u32], idx: usize) -> Vec<u32>;
+    /// Remove a page from physical memory without replacing it, e.g. a TLB entry invalidated
+    /// when the page it translates is evicted from main memory.
+    fn evict(&mut self, page: u32);
     /// Return true if a page fault happened.
     fn allocate(&mut self, references: &[u32], idx: usize) -> bool {
         assert!(idx < references.len());
@@ -35,12 +39,18 @@ impl Replacement for FIFO {
         self.pages.contains(&references[idx])
     }
 
-    fn replace(&mut self, references: &[u32], idx: usize) {
+    fn replace(&mut self, references: &[u32], idx: usize) -> Vec<u32> {
         let reference = references[idx];
+        let mut evicted = vec![];
         if self.pages.len() >= self.frames {
-            self.pages.pop_back();
+            evicted.extend(self.pages.pop_back());
         }
         self.pages.push_front(reference);
+        evicted
+    }
+
+    fn evict(&mut self, page: u32) {
+        self.pages.retain(|&p| p != page);
     }
 }
 
@@ -74,8 +84,9 @@ impl Replacement for LRU {
         }
     }
 
-    fn replace(&mut self, reference: &[u32], idx: usize) {
+    fn replace(&mut self, reference: &[u32], idx: usize) -> Vec<u32> {
         // break tie by FIFO, VedDeque::iter iterates the deque from front to back
+        let mut evicted = vec![];
         if self.pages.len() >= self.frames {
             let (replaced_idx, _) = self
                 .pages
@@ -83,7 +94,7 @@ impl Replacement for LRU {
                 .enumerate()
                 .min_by_key(|(_, entry)| entry.last_accessed)
                 .expect("LRU::replace: Frame number must be positive");
-            self.pages.remove(replaced_idx);
+            evicted.extend(self.pages.remove(replaced_idx).map(|entry| entry.page));
         }
 
         let page = reference[idx];
@@ -92,6 +103,11 @@ impl Replacement for LRU {
             page,
             last_accessed: idx,
         });
+        evicted
+    }
+
+    fn evict(&mut self, page: u32) {
+        self.pages.retain(|entry| entry.page != page);
     }
 }
 
@@ -114,8 +130,9 @@ impl Replacement for OPT {
         self.pages.contains(&references[idx])
     }
 
-    fn replace(&mut self, references: &[u32], idx: usize) {
+    fn replace(&mut self, references: &[u32], idx: usize) -> Vec<u32> {
         // break tie by FIFO
+        let mut evicted = vec![];
         if self.pages.len() >= self.frames {
             let (replaced_idx, _) = self
                 .pages
@@ -123,11 +140,16 @@ impl Replacement for OPT {
                 .enumerate()
                 .max_by_key(|(_, &page)| next_access(references, idx, page))
                 .expect("OPT::replace: frame number must be positive");
-            self.pages.remove(replaced_idx);
+            evicted.extend(self.pages.remove(replaced_idx));
         }
 
         let page = references[idx];
         self.pages.push_front(page);
+        evicted
+    }
+
+    fn evict(&mut self, page: u32) {
+        self.pages.retain(|&p| p != page);
     }
 }
 
@@ -179,7 +201,7 @@ impl Replacement for CNT {
         self.frames.iter().any(|frame| frame.loaded == Some(page))
     }
 
-    fn replace(&mut self, references: &[u32], page_idx: usize) {
+    fn replace(&mut self, references: &[u32], page_idx: usize) -> Vec<u32> {
         let page = references[page_idx];
 
         // disassociate this page from the frame recently stored it if any
@@ -196,8 +218,203 @@ impl Replacement for CNT {
             .min_by_key(|(_, frame)| frame.loaded)
             .expect("CNT::replace: frame number must be positive");
 
-        replaced_frame.loaded = Some(page);
+        let evicted = replaced_frame.loaded.replace(page);
         replaced_frame.counter += 1;
         self.assoc.insert(page, frame_idx);
+        evicted.into_iter().collect()
+    }
+
+    fn evict(&mut self, page: u32) {
+        for frame in self.frames.iter_mut() {
+            if frame.loaded == Some(page) {
+                frame.loaded = None;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClockEntry {
+    page: u32,
+    referenced: bool,
+}
+
+/// Second-chance replacement, frames are arranged in a circle swept by a hand. A page with its
+/// reference bit set is spared once, with the bit cleared.
+pub struct Clock {
+    frames: Vec<Option<ClockEntry>>,
+    hand: usize,
+}
+
+impl Clock {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: vec![None; frames],
+            hand: 0,
+        }
+    }
+}
+
+impl Replacement for Clock {
+    fn access(&mut self, references: &[u32], idx: usize) -> bool {
+        let page = references[idx];
+        if let Some(entry) = self
+            .frames
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.page == page)
+        {
+            entry.referenced = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn replace(&mut self, references: &[u32], idx: usize) -> Vec<u32> {
+        assert!(
+            !self.frames.is_empty(),
+            "Clock::replace: frame number must be positive"
+        );
+
+        let mut evicted = vec![];
+        // empty frames are taken before any page is replaced
+        let frame = if let Some(frame) = self.frames.iter().position(Option::is_none) {
+            frame
+        } else {
+            loop {
+                let frame = self.hand;
+                self.hand = (self.hand + 1) % self.frames.len();
+                let entry = self.frames[frame].as_mut().unwrap();
+                if entry.referenced {
+                    entry.referenced = false;
+                } else {
+                    evicted.push(entry.page);
+                    break frame;
+                }
+            }
+        };
+
+        self.frames[frame] = Some(ClockEntry {
+            page: references[idx],
+            referenced: true,
+        });
+        evicted
+    }
+
+    fn evict(&mut self, page: u32) {
+        for frame in self.frames.iter_mut() {
+            if frame.map(|entry| entry.page) == Some(page) {
+                *frame = None;
+            }
+        }
+    }
+}
+
+/// Working set replacement, pages not referenced in the last `window` references have left the
+/// working set and are released on the next page fault. When the working set alone fills all
+/// frames, the least recently used page is replaced.
+pub struct WorkingSet {
+    frames: usize,
+    window: usize,
+    pages: VecDeque<PageTableEntry>,
+}
+
+impl WorkingSet {
+    pub fn new(frames: usize, window: usize) -> Self {
+        Self {
+            frames,
+            window,
+            pages: VecDeque::with_capacity(frames),
+        }
+    }
+}
+
+impl Replacement for WorkingSet {
+    fn access(&mut self, references: &[u32], idx: usize) -> bool {
+        let page = references[idx];
+        if let Some(entry) = self.pages.iter_mut().find(|entry| entry.page == page) {
+            entry.last_accessed = idx;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn replace(&mut self, references: &[u32], idx: usize) -> Vec<u32> {
+        let window = self.window;
+        let mut evicted = vec![];
+        self.pages.retain(|entry| {
+            let in_working_set = entry.last_accessed + window > idx;
+            if !in_working_set {
+                evicted.push(entry.page);
+            }
+            in_working_set
+        });
+
+        if self.pages.len() >= self.frames {
+            let (replaced_idx, _) = self
+                .pages
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_accessed)
+                .expect("WorkingSet::replace: frame number must be positive");
+            evicted.extend(self.pages.remove(replaced_idx).map(|entry| entry.page));
+        }
+
+        self.pages.push_front(PageTableEntry {
+            page: references[idx],
+            last_accessed: idx,
+        });
+        evicted
+    }
+
+    fn evict(&mut self, page: u32) {
+        self.pages.retain(|entry| entry.page != page);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BELADY: &[u32] = &[1, 2, 3, 4, 1, 2, 5, 1, 2, 3, 4, 5];
+
+    fn page_faults<R: Replacement>(references: &[u32], replacement: &mut R) -> usize {
+        (0..references.len())
+            .filter(|&idx| replacement.allocate(references, idx))
+            .count()
+    }
+
+    #[test]
+    fn clock_gives_second_chance() {
+        assert_eq!(page_faults(BELADY, &mut Clock::new(3)), 9);
+
+        // 1 is referenced again before the hand comes back, 2 is replaced instead
+        let mut clock = Clock::new(2);
+        assert_eq!(page_faults(&[1, 2, 1], &mut clock), 2);
+        clock.frames.iter_mut().flatten().for_each(|entry| {
+            entry.referenced = entry.page == 1;
+        });
+        assert_eq!(clock.replace(&[3], 0), vec![2]);
+    }
+
+    #[test]
+    fn working_set_releases_stale_pages() {
+        let mut working_set = WorkingSet::new(5, 3);
+        assert_eq!(page_faults(BELADY, &mut working_set), 10);
+        assert!(working_set.pages.len() <= 3);
+    }
+
+    #[test]
+    fn replace_reports_evicted_pages() {
+        let mut fifo = FIFO::new(2);
+        assert!(fifo.replace(&[1, 2, 3], 0).is_empty());
+        assert!(fifo.replace(&[1, 2, 3], 1).is_empty());
+        assert_eq!(fifo.replace(&[1, 2, 3], 2), vec![1]);
+
+        fifo.evict(2);
+        assert!(!fifo.access(&[2], 0));
+        assert!(fifo.replace(&[4], 0).is_empty());
     }
 }
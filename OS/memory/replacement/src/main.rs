@@ -1,5 +1,7 @@
 use rand::{thread_rng, Rng};
-use replacement::{Replacement, CNT, FIFO, LRU, OPT};
+use replacement::{Clock, Replacement, WorkingSet, CNT, FIFO, LRU, OPT};
+
+const WORKING_SET_WINDOW: usize = 4;
 
 fn main() {
     problem_9_8();
@@ -29,6 +31,16 @@ fn problem_9_8() {
             "    OPT: {} page faults",
             count_page_faults(references, &mut opt)
         );
+        let mut clock = Clock::new(frames);
+        println!(
+            "    Clock: {} page faults",
+            count_page_faults(references, &mut clock)
+        );
+        let mut working_set = WorkingSet::new(frames, WORKING_SET_WINDOW);
+        println!(
+            "    WS: {} page faults",
+            count_page_faults(references, &mut working_set)
+        );
     }
 }
 
@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+
+use crate::transport::Transport;
+use crate::vector_clock::{Causality, VectorClock};
+use crate::ProcessId;
+
+/// A broadcast message stamped with the vector clock of its sender, whose entries count messages
+/// broadcast by each process that causally precede this one, this one included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CausalMessage<P> {
+    pub sender: ProcessId,
+    pub clock: VectorClock,
+    pub payload: P,
+}
+
+/// Delays received messages until all messages causally preceding them are delivered, as in the
+/// protocol of Birman, Schiper and Stephenson.
+#[derive(Debug)]
+pub struct CausalOrder<P> {
+    /// Messages delivered from each process, including the ones broadcast by this process.
+    delivered: VectorClock,
+    pending: Vec<CausalMessage<P>>,
+}
+
+impl<P> Default for CausalOrder<P> {
+    fn default() -> Self {
+        Self {
+            delivered: VectorClock::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<P> CausalOrder<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn delivered(&self) -> &VectorClock {
+        &self.delivered
+    }
+
+    /// The clock of a new message broadcast by process `id`.
+    pub fn stamp(&mut self, id: ProcessId) -> VectorClock {
+        self.delivered.increment(id);
+        self.delivered.clone()
+    }
+
+    /// A message is deliverable if it's the next one from its sender, and everything its sender
+    /// had delivered before broadcasting it has been delivered here as well.
+    fn deliverable(&self, msg: &CausalMessage<P>) -> bool {
+        let mut next = self.delivered.clone();
+        next.increment(msg.sender);
+
+        msg.clock.get(msg.sender) == next.get(msg.sender)
+            && matches!(
+                msg.clock.compare(&next),
+                Causality::Before | Causality::Equal
+            )
+    }
+
+    /// Buffer a received message, returns all messages that became deliverable in a causal order.
+    pub fn receive(&mut self, msg: CausalMessage<P>) -> Vec<CausalMessage<P>> {
+        self.pending.push(msg);
+
+        let mut delivered = Vec::new();
+        while let Some(i) = self.pending.iter().position(|msg| self.deliverable(msg)) {
+            let msg = self.pending.remove(i);
+            self.delivered.increment(msg.sender);
+            delivered.push(msg);
+        }
+
+        delivered
+    }
+}
+
+/// Broadcast to all processes over a FIFO transport, messages are delivered in an order
+/// consistent with causality: a message is never delivered before one its sender had delivered or
+/// broadcast earlier.
+pub struct CausalBroadcast<P, T> {
+    id: ProcessId,
+    peers: Vec<ProcessId>,
+    transport: T,
+    order: CausalOrder<P>,
+    ready: VecDeque<CausalMessage<P>>,
+}
+
+impl<P, T> CausalBroadcast<P, T>
+where
+    P: Clone,
+    T: Transport<CausalMessage<P>>,
+{
+    pub fn new(transport: T) -> Self {
+        Self {
+            id: transport.id(),
+            peers: transport.peers(),
+            transport,
+            order: CausalOrder::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    pub fn delivered(&self) -> &VectorClock {
+        self.order.delivered()
+    }
+
+    /// Send `payload` to all other processes, it's delivered after every message delivered here
+    /// so far.
+    pub fn broadcast(&mut self, payload: P) {
+        let msg = CausalMessage {
+            sender: self.id,
+            clock: self.order.stamp(self.id),
+            payload,
+        };
+
+        for &peer in &self.peers {
+            self.transport.send(peer, msg.clone());
+        }
+    }
+
+    /// The next message from other processes in causal order, None once the transport is closed.
+    pub async fn recv(&mut self) -> Option<CausalMessage<P>> {
+        loop {
+            if let Some(msg) = self.ready.pop_front() {
+                return Some(msg);
+            }
+
+            let msg = self.transport.recv().await?;
+            self.ready.extend(self.order.receive(msg));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hub::hub;
+    use crate::transport::{broadcast, delayed_channel};
+
+    use std::time::Duration;
+
+    const P: ProcessId = ProcessId(0);
+    const Q: ProcessId = ProcessId(1);
+    const ROUNDS: u32 = 20;
+
+    fn senders<T>(messages: &[CausalMessage<T>]) -> Vec<ProcessId> {
+        messages.iter().map(|msg| msg.sender).collect()
+    }
+
+    #[test]
+    fn delays_messages_with_missing_dependencies() {
+        let mut p = CausalOrder::<&str>::new();
+        let mut q = CausalOrder::new();
+        let mut r = CausalOrder::new();
+
+        let question = CausalMessage {
+            sender: P,
+            clock: p.stamp(P),
+            payload: "question",
+        };
+        assert_eq!(q.receive(question.clone()).len(), 1);
+        let answer = CausalMessage {
+            sender: Q,
+            clock: q.stamp(Q),
+            payload: "answer",
+        };
+
+        // the answer overtakes the question on its way to r
+        assert!(r.receive(answer).is_empty());
+        assert_eq!(senders(&r.receive(question)), vec![P, Q]);
+        assert_eq!(r.delivered(), q.delivered());
+    }
+
+    #[test]
+    fn concurrent_messages_delivered_on_arrival() {
+        let mut p = CausalOrder::<()>::new();
+        let mut q = CausalOrder::<()>::new();
+        let mut r = CausalOrder::new();
+
+        let from_p = CausalMessage {
+            sender: P,
+            clock: p.stamp(P),
+            payload: (),
+        };
+        let from_q = CausalMessage {
+            sender: Q,
+            clock: q.stamp(Q),
+            payload: (),
+        };
+        assert!(from_p.clock.concurrent(&from_q.clock));
+
+        assert_eq!(senders(&r.receive(from_q)), vec![Q]);
+        assert_eq!(senders(&r.receive(from_p)), vec![P]);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Chat {
+        Question(u32),
+        Answer(u32),
+    }
+
+    /// p asks questions, q answers each of them, r must never deliver an answer before its
+    /// question.
+    async fn questions_and_answers<T>(transports: Vec<T>)
+    where
+        T: Transport<CausalMessage<Chat>>,
+    {
+        let mut processes: Vec<_> = transports.into_iter().map(CausalBroadcast::new).collect();
+        let mut r = processes.pop().unwrap();
+        let mut q = processes.pop().unwrap();
+        let mut p = processes.pop().unwrap();
+
+        let asker = tokio::spawn(async move {
+            for i in 0..ROUNDS {
+                p.broadcast(Chat::Question(i));
+                // a burst would queue up behind the slowest message on each link
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // keep the transport open until everyone is done
+            p
+        });
+
+        let answerer = tokio::spawn(async move {
+            for _ in 0..ROUNDS {
+                if let Some(CausalMessage {
+                    payload: Chat::Question(i),
+                    ..
+                }) = q.recv().await
+                {
+                    q.broadcast(Chat::Answer(i));
+                }
+            }
+            q
+        });
+
+        let mut asked = 0;
+        for _ in 0..ROUNDS * 2 {
+            match r.recv().await.unwrap().payload {
+                Chat::Question(i) => {
+                    assert_eq!(i, asked);
+                    asked += 1;
+                }
+                Chat::Answer(i) => assert!(i < asked, "answer {} before its question", i),
+            }
+        }
+
+        let (_p, _q) = (asker.await.unwrap(), answerer.await.unwrap());
+        assert_eq!(r.delivered().get(P), ROUNDS as u64);
+        assert_eq!(r.delivered().get(Q), ROUNDS as u64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn answers_never_precede_questions() {
+        let (transports, _) = delayed_channel(3, Duration::from_millis(10));
+        questions_and_answers(transports).await;
+    }
+
+    #[tokio::test]
+    async fn over_broadcast_channel() {
+        let (transports, _) = broadcast(3, 256);
+        questions_and_answers(transports).await;
+    }
+
+    #[test]
+    fn over_message_hub() {
+        actix::System::new().block_on(async {
+            let (transports, counter) = hub(3);
+            questions_and_answers(transports).await;
+            // every message is sent to both other processes
+            assert_eq!(counter.get(), 2 * 2 * ROUNDS as u64);
+        });
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use actix::{Actor, Addr, Context, Handler, Message};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::transport::{Envelope, MessageCounter, Transport};
+use crate::ProcessId;
+
+impl<M: Send + 'static> Message for Envelope<M> {
+    type Result = ();
+}
+
+/// An actor routing messages between processes. Messages through the hub are delivered in the
+/// order they were sent.
+pub struct MessageHub<M> {
+    recipients: HashMap<ProcessId, UnboundedSender<M>>,
+}
+
+impl<M: Send + 'static> Actor for MessageHub<M> {
+    type Context = Context<Self>;
+}
+
+impl<M: Send + 'static> Handler<Envelope<M>> for MessageHub<M> {
+    type Result = ();
+
+    fn handle(&mut self, envelope: Envelope<M>, _ctx: &mut Self::Context) -> Self::Result {
+        // the recipient stopped, nobody is waiting for the message anymore
+        let _ = self.recipients[&envelope.to].send(envelope.msg);
+    }
+}
+
+/// A process connected to a [`MessageHub`].
+pub struct HubTransport<M: Send + 'static> {
+    id: ProcessId,
+    peers: Vec<ProcessId>,
+    hub: Addr<MessageHub<M>>,
+    receiver: UnboundedReceiver<M>,
+    counter: MessageCounter,
+}
+
+/// Connect `n` processes with ids from 0 to `n - 1` through a hub actor, which stops once all
+/// transports are dropped. Must be called within an actix system.
+pub fn hub<M: Send + 'static>(n: u32) -> (Vec<HubTransport<M>>, MessageCounter) {
+    let counter = MessageCounter::default();
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..n).map(|_| mpsc::unbounded_channel()).unzip();
+    let hub = MessageHub {
+        recipients: (0..n).map(ProcessId).zip(senders).collect(),
+    }
+    .start();
+
+    let transports = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(i, receiver)| {
+            let id = ProcessId(i as u32);
+            HubTransport {
+                id,
+                peers: (0..n).map(ProcessId).filter(|&peer| peer != id).collect(),
+                hub: hub.clone(),
+                receiver,
+                counter: counter.clone(),
+            }
+        })
+        .collect();
+
+    (transports, counter)
+}
+
+impl<M: Send + 'static> Transport<M> for HubTransport<M> {
+    fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    fn peers(&self) -> Vec<ProcessId> {
+        self.peers.clone()
+    }
+
+    fn send(&mut self, to: ProcessId, msg: M) {
+        self.counter.increment();
+        self.hub.do_send(Envelope { to, msg });
+    }
+
+    fn recv(&mut self) -> Pin<Box<dyn Future<Output = Option<M>> + Send + '_>> {
+        Box::pin(self.receiver.recv())
+    }
+}
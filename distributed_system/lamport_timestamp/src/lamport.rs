@@ -0,0 +1,98 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::mutex::{Outbox, Protocol};
+use crate::{Clock, Lamport, Message, MessageKind, ProcessId};
+
+/// Lamport's mutual exclusion algorithm. Every process keeps a queue of all requests ordered by
+/// timestamp, a process enters the critical section once its request is the earliest in its queue
+/// and it has heard from every other process after the request.
+#[derive(Debug)]
+pub struct LamportQueue {
+    clock: Clock,
+    peers: Vec<ProcessId>,
+    /// Pending requests of all processes including this one, the earliest (wrt. the total order
+    /// of lamport timestamps) comes first.
+    queue: BTreeSet<Lamport>,
+    /// The timestamp of the latest message received from each peer.
+    latest: HashMap<ProcessId, Lamport>,
+    request: Option<Lamport>,
+}
+
+impl LamportQueue {
+    pub fn new(id: ProcessId, peers: Vec<ProcessId>) -> Self {
+        Self {
+            clock: Clock::new(id),
+            peers,
+            queue: BTreeSet::new(),
+            latest: HashMap::new(),
+            request: None,
+        }
+    }
+
+    fn broadcast(&mut self, kind: MessageKind) -> (Lamport, Outbox) {
+        let timestamp = self.clock.tick();
+        let msg = Message { timestamp, kind };
+        let outbox = self.peers.iter().map(|&peer| (peer, msg)).collect();
+        (timestamp, outbox)
+    }
+}
+
+impl Protocol for LamportQueue {
+    fn request(&mut self) -> Outbox {
+        assert!(self.request.is_none(), "request already pending");
+
+        // Rule 1: put the request on its own queue and send it to all other processes
+        let (timestamp, outbox) = self.broadcast(MessageKind::Request);
+        self.queue.insert(timestamp);
+        self.request = Some(timestamp);
+        outbox
+    }
+
+    fn receive(&mut self, msg: Message) -> Outbox {
+        let from = msg.from();
+        self.clock.observe(msg.timestamp);
+        // channels are FIFO, timestamps from the same peer only increase
+        self.latest.insert(from, msg.timestamp);
+
+        match msg.kind {
+            MessageKind::Request => {
+                // Rule 2: put the request on the queue and acknowledge it
+                self.queue.insert(msg.timestamp);
+                let ack = Message {
+                    timestamp: self.clock.tick(),
+                    kind: MessageKind::Acknowledge,
+                };
+                vec![(from, ack)]
+            }
+            MessageKind::Release => {
+                // Rule 4: remove the released request from the queue
+                self.queue.retain(|request| request.id != from);
+                vec![]
+            }
+            MessageKind::Acknowledge => vec![],
+        }
+    }
+
+    fn granted(&self) -> bool {
+        // Rule 5: the request is the earliest and every other process has sent a later message
+        match self.request {
+            Some(request) => {
+                self.queue.iter().next() == Some(&request)
+                    && self
+                        .peers
+                        .iter()
+                        .all(|peer| self.latest.get(peer).is_some_and(|&t| t > request))
+            }
+            None => false,
+        }
+    }
+
+    fn release(&mut self) -> Outbox {
+        let request = self.request.take().expect("released without a request");
+
+        // Rule 3: remove the request from its own queue and tell all other processes
+        self.queue.remove(&request);
+        let (_, outbox) = self.broadcast(MessageKind::Release);
+        outbox
+    }
+}
@@ -0,0 +1,327 @@
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::lamport::LamportQueue;
+use crate::ricart_agrawala::RicartAgrawala;
+use crate::transport::Transport;
+use crate::{Message, ProcessId};
+
+/// Messages to send after a protocol step.
+pub type Outbox = Vec<(ProcessId, Message)>;
+
+/// A mutual exclusion protocol as a state machine of a single process, driven by
+/// [`DistributedMutex`].
+pub trait Protocol: Send + 'static {
+    /// Request the critical section, at most one request may be pending at a time.
+    fn request(&mut self) -> Outbox;
+
+    fn receive(&mut self, msg: Message) -> Outbox;
+
+    /// Whether the pending request has been granted.
+    fn granted(&self) -> bool;
+
+    /// Leave the critical section.
+    fn release(&mut self) -> Outbox;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutexError {
+    /// Another acquire through the same handle is pending, or the process holds the mutex already.
+    Busy,
+    /// Released without holding the mutex.
+    NotHeld,
+}
+
+type Reply = oneshot::Sender<Result<(), MutexError>>;
+
+enum Command {
+    Acquire(Reply),
+    Release(Reply),
+}
+
+/// A handle to a mutex shared by all processes connected by the transport. The protocol is run by
+/// a background task, which keeps answering requests of other processes after the handle is
+/// dropped, until the transport is closed as well.
+pub struct DistributedMutex {
+    id: ProcessId,
+    commands: UnboundedSender<Command>,
+}
+
+impl DistributedMutex {
+    /// Must be called within a tokio runtime.
+    pub fn new<P: Protocol, T: Transport>(protocol: P, transport: T) -> Self {
+        let id = transport.id();
+        let (commands, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(protocol, transport, receiver));
+
+        Self { id, commands }
+    }
+
+    /// A mutex running Lamport's algorithm.
+    pub fn lamport<T: Transport>(transport: T) -> Self {
+        let protocol = LamportQueue::new(transport.id(), transport.peers());
+        Self::new(protocol, transport)
+    }
+
+    /// A mutex running the Ricart-Agrawala algorithm.
+    pub fn ricart_agrawala<T: Transport>(transport: T) -> Self {
+        let protocol = RicartAgrawala::new(transport.id(), transport.peers());
+        Self::new(protocol, transport)
+    }
+
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    /// Resolves once the process entered the critical section. Dropping the future before it
+    /// resolves gives up the request: the critical section is left as soon as it is granted. A
+    /// later acquire still waiting for that grant takes the request over instead.
+    pub async fn acquire(&self) -> Result<(), MutexError> {
+        let mut pending = PendingAcquire {
+            commands: &self.commands,
+            receiver: self.command(Command::Acquire),
+            resolved: false,
+        };
+        let result = (&mut pending.receiver).await.expect("mutex task stopped");
+        pending.resolved = true;
+        result
+    }
+
+    /// Resolves once the process left the critical section.
+    pub async fn release(&self) -> Result<(), MutexError> {
+        self.command(Command::Release)
+            .await
+            .expect("mutex task stopped")
+    }
+
+    fn command(&self, command: fn(Reply) -> Command) -> oneshot::Receiver<Result<(), MutexError>> {
+        let (reply, receiver) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .expect("mutex task stopped");
+        receiver
+    }
+}
+
+/// An acquire whose future may be dropped between the grant and the caller seeing it.
+struct PendingAcquire<'a> {
+    commands: &'a UnboundedSender<Command>,
+    receiver: oneshot::Receiver<Result<(), MutexError>>,
+    resolved: bool,
+}
+
+impl Drop for PendingAcquire<'_> {
+    fn drop(&mut self) {
+        if self.resolved {
+            return;
+        }
+        // after closing no grant can be sent anymore, one already sent is handed back
+        self.receiver.close();
+        if let Ok(Ok(())) = self.receiver.try_recv() {
+            let (reply, _) = oneshot::channel();
+            let _ = self.commands.send(Command::Release(reply));
+        }
+    }
+}
+
+enum State {
+    Idle,
+    Waiting(Reply),
+    Held,
+}
+
+fn send_all<T: Transport>(transport: &mut T, outbox: Outbox) {
+    for (to, msg) in outbox {
+        transport.send(to, msg);
+    }
+}
+
+async fn run<P: Protocol, T: Transport>(
+    mut protocol: P,
+    mut transport: T,
+    mut commands: UnboundedReceiver<Command>,
+) {
+    let mut state = State::Idle;
+    let mut dropped = false;
+    let mut closed = false;
+
+    while !(dropped && closed) {
+        tokio::select! {
+            command = commands.recv(), if !dropped => match command {
+                Some(Command::Acquire(reply)) => match state {
+                    State::Idle => {
+                        send_all(&mut transport, protocol.request());
+                        state = State::Waiting(reply);
+                    }
+                    // the previous caller gave up waiting, its request is still pending
+                    State::Waiting(ref waiting) if waiting.is_closed() => {
+                        state = State::Waiting(reply);
+                    }
+                    State::Waiting(_) | State::Held => {
+                        let _ = reply.send(Err(MutexError::Busy));
+                    }
+                },
+                Some(Command::Release(reply)) => {
+                    let result = if let State::Held = state {
+                        send_all(&mut transport, protocol.release());
+                        state = State::Idle;
+                        Ok(())
+                    } else {
+                        Err(MutexError::NotHeld)
+                    };
+                    let _ = reply.send(result);
+                }
+                None => dropped = true,
+            },
+            msg = transport.recv(), if !closed => match msg {
+                Some(msg) => send_all(&mut transport, protocol.receive(msg)),
+                None => closed = true,
+            },
+        }
+
+        if matches!(state, State::Waiting(_)) && protocol.granted() {
+            if let State::Waiting(reply) = std::mem::replace(&mut state, State::Held) {
+                // the caller gave up waiting, other processes must not wait for a release forever
+                if reply.send(Ok(())).is_err() {
+                    send_all(&mut transport, protocol.release());
+                    state = State::Idle;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{channel, delayed_channel, ChannelTransport};
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rand::Rng;
+
+    const PROCESSES: u32 = 8;
+    const ROUNDS: u64 = 5;
+
+    /// Every process enters the critical section `ROUNDS` times, returns the number of messages
+    /// sent.
+    async fn exclusive_resource(mutex: fn(ChannelTransport) -> DistributedMutex) -> u64 {
+        let (transports, counter) = delayed_channel(PROCESSES, Duration::from_millis(5));
+        let occupied = Arc::new(AtomicBool::new(false));
+
+        let processes: Vec<_> = transports
+            .into_iter()
+            .map(|transport| {
+                let mutex = mutex(transport);
+                let occupied = occupied.clone();
+                tokio::spawn(async move {
+                    for _ in 0..ROUNDS {
+                        mutex.acquire().await.unwrap();
+                        assert!(!occupied.swap(true, Ordering::SeqCst), "mutex violated");
+                        let duration = rand::thread_rng().gen_range(0..5);
+                        tokio::time::sleep(Duration::from_millis(duration)).await;
+                        occupied.store(false, Ordering::SeqCst);
+                        mutex.release().await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for process in processes {
+            process.await.unwrap();
+        }
+
+        counter.get()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lamport_exclusive_resource() {
+        let messages = exclusive_resource(DistributedMutex::lamport).await;
+        let n = PROCESSES as u64;
+        assert_eq!(messages, 3 * (n - 1) * n * ROUNDS);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ricart_agrawala_exclusive_resource() {
+        let messages = exclusive_resource(DistributedMutex::ricart_agrawala).await;
+        let n = PROCESSES as u64;
+        assert_eq!(messages, 2 * (n - 1) * n * ROUNDS);
+    }
+
+    #[tokio::test]
+    async fn single_process() {
+        let (mut transports, _) = channel(1);
+        let mutex = DistributedMutex::ricart_agrawala(transports.pop().unwrap());
+        mutex.acquire().await.unwrap();
+        mutex.release().await.unwrap();
+        mutex.acquire().await.unwrap();
+    }
+
+    /// Fails the test instead of hanging if the mutex deadlocked.
+    async fn within<F: std::future::Future>(future: F) -> F::Output {
+        tokio::time::timeout(Duration::from_secs(5), future)
+            .await
+            .expect("deadlocked")
+    }
+
+    #[tokio::test]
+    async fn cancelled_acquire() {
+        let (mut transports, _) = channel(2);
+        let second = DistributedMutex::ricart_agrawala(transports.pop().unwrap());
+        let first = DistributedMutex::ricart_agrawala(transports.pop().unwrap());
+
+        first.acquire().await.unwrap();
+        // gives up while the first process is in the critical section
+        let timeout = Duration::from_millis(10);
+        assert!(tokio::time::timeout(timeout, second.acquire()).await.is_err());
+        first.release().await.unwrap();
+
+        // the abandoned request is granted then released right away
+        within(first.acquire()).await.unwrap();
+        first.release().await.unwrap();
+        within(second.acquire()).await.unwrap();
+        second.release().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retried_acquire() {
+        let (mut transports, _) = channel(2);
+        let second = DistributedMutex::lamport(transports.pop().unwrap());
+        let first = DistributedMutex::lamport(transports.pop().unwrap());
+
+        first.acquire().await.unwrap();
+        let timeout = Duration::from_millis(10);
+        assert!(tokio::time::timeout(timeout, second.acquire()).await.is_err());
+
+        // the retry takes over the pending request
+        let (retried, released) = tokio::join!(second.acquire(), first.release());
+        retried.unwrap();
+        released.unwrap();
+        second.release().await.unwrap();
+        within(first.acquire()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn double_acquire() {
+        let (mut transports, _) = channel(2);
+        let second = DistributedMutex::ricart_agrawala(transports.pop().unwrap());
+        let first = DistributedMutex::ricart_agrawala(transports.pop().unwrap());
+
+        // overlapping acquires through the same handle
+        let (a, b) = tokio::join!(first.acquire(), first.acquire());
+        assert_eq!(a, Ok(()));
+        assert_eq!(b, Err(MutexError::Busy));
+        assert_eq!(first.acquire().await, Err(MutexError::Busy));
+
+        first.release().await.unwrap();
+        assert_eq!(first.release().await, Err(MutexError::NotHeld));
+        assert_eq!(second.release().await, Err(MutexError::NotHeld));
+
+        // no stray release reached the other process
+        within(second.acquire()).await.unwrap();
+        second.release().await.unwrap();
+        within(first.acquire()).await.unwrap();
+    }
+}
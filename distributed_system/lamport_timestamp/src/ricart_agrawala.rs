@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use crate::mutex::{Outbox, Protocol};
+use crate::{Clock, Lamport, Message, MessageKind, ProcessId};
+
+/// The Ricart-Agrawala algorithm. A process acknowledges a request immediately unless its own
+/// pending request is earlier, in which case the acknowledgement is deferred until it leaves the
+/// critical section. Entering takes an acknowledgement from every other process, no release
+/// message is needed.
+#[derive(Debug)]
+pub struct RicartAgrawala {
+    clock: Clock,
+    peers: Vec<ProcessId>,
+    request: Option<Lamport>,
+    /// Peers yet to acknowledge the pending request.
+    awaiting: HashSet<ProcessId>,
+    /// Peers whose requests are acknowledged after leaving the critical section.
+    deferred: Vec<ProcessId>,
+}
+
+impl RicartAgrawala {
+    pub fn new(id: ProcessId, peers: Vec<ProcessId>) -> Self {
+        Self {
+            clock: Clock::new(id),
+            peers,
+            request: None,
+            awaiting: HashSet::new(),
+            deferred: Vec::new(),
+        }
+    }
+
+    fn acknowledge(&mut self, to: ProcessId) -> (ProcessId, Message) {
+        let ack = Message {
+            timestamp: self.clock.tick(),
+            kind: MessageKind::Acknowledge,
+        };
+        (to, ack)
+    }
+}
+
+impl Protocol for RicartAgrawala {
+    fn request(&mut self) -> Outbox {
+        assert!(self.request.is_none(), "request already pending");
+
+        let timestamp = self.clock.tick();
+        self.request = Some(timestamp);
+        self.awaiting = self.peers.iter().copied().collect();
+
+        let msg = Message {
+            timestamp,
+            kind: MessageKind::Request,
+        };
+        self.peers.iter().map(|&peer| (peer, msg)).collect()
+    }
+
+    fn receive(&mut self, msg: Message) -> Outbox {
+        let from = msg.from();
+        self.clock.observe(msg.timestamp);
+
+        match msg.kind {
+            MessageKind::Request => {
+                // requests arriving in the critical section are always later than the own one:
+                // the requester must have received the own request before acknowledging it
+                if self.request.is_some_and(|request| request < msg.timestamp) {
+                    self.deferred.push(from);
+                    vec![]
+                } else {
+                    vec![self.acknowledge(from)]
+                }
+            }
+            MessageKind::Acknowledge => {
+                self.awaiting.remove(&from);
+                vec![]
+            }
+            MessageKind::Release => vec![],
+        }
+    }
+
+    fn granted(&self) -> bool {
+        self.request.is_some() && self.awaiting.is_empty()
+    }
+
+    fn release(&mut self) -> Outbox {
+        assert!(self.granted(), "released without holding the mutex");
+
+        self.request = None;
+        let deferred = std::mem::take(&mut self.deferred);
+        deferred
+            .into_iter()
+            .map(|peer| self.acknowledge(peer))
+            .collect()
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+
+use crate::{Message, ProcessId};
+
+/// Point-to-point channels between a process and all other processes. Both mutex protocols and
+/// causal broadcast assume messages between each pair of processes are delivered reliably and in
+/// FIFO order.
+pub trait Transport<M = Message>: Send + 'static {
+    /// The process owning this end of the transport.
+    fn id(&self) -> ProcessId;
+
+    /// All other processes.
+    fn peers(&self) -> Vec<ProcessId>;
+
+    fn send(&mut self, to: ProcessId, msg: M);
+
+    /// Wait for the next message to this process, None once no more messages may arrive. Must be
+    /// cancel safe, the future may be dropped before it completes.
+    fn recv(&mut self) -> Pin<Box<dyn Future<Output = Option<M>> + Send + '_>>;
+}
+
+/// Counts messages sent through a group of transports.
+#[derive(Debug, Clone, Default)]
+pub struct MessageCounter(Arc<AtomicU64>);
+
+impl MessageCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn increment(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// An in-process transport over unbounded tokio channels.
+pub struct ChannelTransport<M = Message> {
+    id: ProcessId,
+    senders: HashMap<ProcessId, UnboundedSender<M>>,
+    receiver: UnboundedReceiver<M>,
+    counter: MessageCounter,
+}
+
+/// Connect `n` processes with ids from 0 to `n - 1`.
+pub fn channel<M>(n: u32) -> (Vec<ChannelTransport<M>>, MessageCounter) {
+    connect(n, |sender| sender)
+}
+
+/// Connect `n` processes with links delaying each message by a random duration up to `max_delay`,
+/// messages on the same link are still delivered in order. Must be called within a tokio runtime.
+pub fn delayed_channel<M: Send + 'static>(
+    n: u32,
+    max_delay: Duration,
+) -> (Vec<ChannelTransport<M>>, MessageCounter) {
+    connect(n, |sender| {
+        let (link, mut queue) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut last = Instant::now();
+            while let Some(msg) = queue.recv().await {
+                let delay = rand::thread_rng().gen_range(Duration::ZERO..=max_delay);
+                last = last.max(Instant::now() + delay);
+                tokio::time::sleep_until(last).await;
+                if sender.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+        link
+    })
+}
+
+fn connect<M, F>(n: u32, mut link: F) -> (Vec<ChannelTransport<M>>, MessageCounter)
+where
+    F: FnMut(UnboundedSender<M>) -> UnboundedSender<M>,
+{
+    let counter = MessageCounter::default();
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..n).map(|_| mpsc::unbounded_channel()).unzip();
+
+    let transports = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(i, receiver)| {
+            let id = ProcessId(i as u32);
+            let senders = (0..n)
+                .map(ProcessId)
+                .zip(senders.iter())
+                .filter(|(peer, _)| *peer != id)
+                .map(|(peer, sender)| (peer, link(sender.clone())))
+                .collect();
+
+            ChannelTransport {
+                id,
+                senders,
+                receiver,
+                counter: counter.clone(),
+            }
+        })
+        .collect();
+
+    (transports, counter)
+}
+
+impl<M: Send + 'static> Transport<M> for ChannelTransport<M> {
+    fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    fn peers(&self) -> Vec<ProcessId> {
+        let mut peers: Vec<_> = self.senders.keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    fn send(&mut self, to: ProcessId, msg: M) {
+        self.counter.increment();
+        // the peer stopped, nobody is waiting for its permission anymore
+        let _ = self.senders[&to].send(msg);
+    }
+
+    fn recv(&mut self) -> Pin<Box<dyn Future<Output = Option<M>> + Send + '_>> {
+        Box::pin(self.receiver.recv())
+    }
+}
+
+/// A message on its way to `to`.
+#[derive(Debug, Clone)]
+pub struct Envelope<M> {
+    pub to: ProcessId,
+    pub msg: M,
+}
+
+/// A transport over a single tokio broadcast channel: every message reaches every process, which
+/// drops the ones addressed to others. The channel holds at most `capacity` messages not yet seen
+/// by every process, a process falling further behind loses messages and panics.
+pub struct Broadcast<M> {
+    id: ProcessId,
+    peers: Vec<ProcessId>,
+    sender: broadcast::Sender<Envelope<M>>,
+    receiver: broadcast::Receiver<Envelope<M>>,
+    counter: MessageCounter,
+}
+
+/// Connect `n` processes with ids from 0 to `n - 1` by a broadcast channel of `capacity` slots.
+/// Each process holds a sender as well, its end is never closed while it's alive.
+pub fn broadcast<M: Clone>(n: u32, capacity: usize) -> (Vec<Broadcast<M>>, MessageCounter) {
+    let counter = MessageCounter::default();
+    let (sender, _) = broadcast::channel(capacity);
+
+    let transports = (0..n)
+        .map(ProcessId)
+        .map(|id| Broadcast {
+            id,
+            peers: (0..n).map(ProcessId).filter(|&peer| peer != id).collect(),
+            sender: sender.clone(),
+            receiver: sender.subscribe(),
+            counter: counter.clone(),
+        })
+        .collect();
+
+    (transports, counter)
+}
+
+impl<M: Clone + Send + 'static> Transport<M> for Broadcast<M> {
+    fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    fn peers(&self) -> Vec<ProcessId> {
+        self.peers.clone()
+    }
+
+    fn send(&mut self, to: ProcessId, msg: M) {
+        self.counter.increment();
+        // never fails, this process is subscribed to the channel
+        let _ = self.sender.send(Envelope { to, msg });
+    }
+
+    fn recv(&mut self) -> Pin<Box<dyn Future<Output = Option<M>> + Send + '_>> {
+        Box::pin(async move {
+            loop {
+                match self.receiver.recv().await {
+                    Ok(Envelope { to, msg }) if to == self.id => return Some(msg),
+                    Ok(_) => (),
+                    Err(RecvError::Lagged(n)) => panic!("{:?} lost {} messages", self.id, n),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::ProcessId;
+
+/// Causal relation between two events, or two versions of a replicated object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Before,
+    Equal,
+    After,
+    Concurrent,
+}
+
+/// A vector clock counting events of each process, unlike Lamport timestamps it orders two events
+/// if and only if one happened before the other. Processes never heard of count as zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VectorClock {
+    // zeros are never stored, so that equal clocks are structurally equal
+    counters: BTreeMap<ProcessId, u64>,
+}
+
+impl VectorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: ProcessId) -> u64 {
+        self.counters.get(&id).copied().unwrap_or(0)
+    }
+
+    /// Count a local event of process `id`, returns its new counter.
+    pub fn increment(&mut self, id: ProcessId) -> u64 {
+        let counter = self.counters.entry(id).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// Pointwise maximum of both clocks.
+    pub fn merge(&mut self, other: &Self) {
+        for (&id, &counter) in &other.counters {
+            let entry = self.counters.entry(id).or_insert(0);
+            *entry = (*entry).max(counter);
+        }
+    }
+
+    /// Receiving a message stamped with `other` by process `id`.
+    pub fn observe(&mut self, id: ProcessId, other: &Self) {
+        self.merge(other);
+        self.increment(id);
+    }
+
+    pub fn compare(&self, other: &Self) -> Causality {
+        let ids = self.counters.keys().chain(other.counters.keys());
+        let (mut less, mut greater) = (false, false);
+
+        for &id in ids {
+            match self.get(id).cmp(&other.get(id)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => (),
+            }
+        }
+
+        match (less, greater) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+
+    pub fn happens_before(&self, other: &Self) -> bool {
+        self.compare(other) == Causality::Before
+    }
+
+    pub fn concurrent(&self, other: &Self) -> bool {
+        self.compare(other) == Causality::Concurrent
+    }
+}
+
+/// The happens-before partial order, concurrent clocks are incomparable.
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.compare(other) {
+            Causality::Before => Some(Ordering::Less),
+            Causality::Equal => Some(Ordering::Equal),
+            Causality::After => Some(Ordering::Greater),
+            Causality::Concurrent => None,
+        }
+    }
+}
+
+/// A version vector tags a version of a replicated object with the number of updates applied at
+/// each replica. Unlike a vector clock it only moves on updates, not on every message, two
+/// versions conflict exactly when their vectors are concurrent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VersionVector(VectorClock);
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates of the object applied at `replica`.
+    pub fn get(&self, replica: ProcessId) -> u64 {
+        self.0.get(replica)
+    }
+
+    /// Record an update made at `replica`.
+    pub fn update(&mut self, replica: ProcessId) {
+        self.0.increment(replica);
+    }
+
+    /// The version after reconciling both versions, which descends from both.
+    pub fn merge(&mut self, other: &Self) {
+        self.0.merge(&other.0);
+    }
+
+    pub fn compare(&self, other: &Self) -> Causality {
+        self.0.compare(&other.0)
+    }
+
+    /// Whether this version has seen every update of `other`, `other` may then be discarded.
+    pub fn dominates(&self, other: &Self) -> bool {
+        matches!(self.compare(other), Causality::After | Causality::Equal)
+    }
+
+    /// Whether the versions were updated independently and must be reconciled.
+    pub fn conflicts(&self, other: &Self) -> bool {
+        self.compare(other) == Causality::Concurrent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: ProcessId = ProcessId(0);
+    const Q: ProcessId = ProcessId(1);
+    const R: ProcessId = ProcessId(2);
+
+    #[test]
+    fn happens_before() {
+        let mut p = VectorClock::new();
+        let mut q = VectorClock::new();
+        let mut r = VectorClock::new();
+
+        // p sends a message to q
+        p.increment(P);
+        let sent = p.clone();
+        q.observe(Q, &sent);
+        assert!(sent.happens_before(&q));
+        assert!(sent < q);
+
+        // r never talked to anybody
+        r.increment(R);
+        assert!(r.concurrent(&q));
+        assert_eq!(r.partial_cmp(&q), None);
+
+        // q forwards to r, transitively after p's event
+        r.observe(R, &q);
+        assert!(sent.happens_before(&r));
+        assert_eq!(r.compare(&r.clone()), Causality::Equal);
+    }
+
+    #[test]
+    fn missing_entries_are_zero() {
+        let mut a = VectorClock::new();
+        a.merge(&VectorClock::new());
+        assert_eq!(a, VectorClock::new());
+
+        a.increment(P);
+        let mut b = VectorClock::new();
+        b.increment(P);
+        b.merge(&VectorClock::new());
+        assert_eq!(a, b);
+        assert_eq!(a.get(Q), 0);
+    }
+
+    #[test]
+    fn version_vectors_detect_conflicts() {
+        let mut base = VersionVector::new();
+        base.update(P);
+
+        let mut left = base.clone();
+        left.update(P);
+        let mut right = base.clone();
+        right.update(Q);
+
+        assert!(left.dominates(&base));
+        assert!(!base.dominates(&left));
+        assert!(left.conflicts(&right));
+
+        let mut merged = left.clone();
+        merged.merge(&right);
+        assert!(merged.dominates(&left) && merged.dominates(&right));
+        assert_eq!((merged.get(P), merged.get(Q)), (2, 1));
+    }
+}
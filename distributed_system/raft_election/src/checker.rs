@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use actix::Message;
+
+use crate::network::Fault;
+use crate::NodeId;
+
+/// State transitions reported by Raft nodes to the hub, the only things the safety checker sees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The node moved to a new term.
+    Term { node: NodeId, term: u64 },
+    /// The node granted its vote in a term, candidates vote for themselves.
+    Vote {
+        node: NodeId,
+        term: u64,
+        candidate: NodeId,
+    },
+    /// The node became the leader of a term.
+    Leader { node: NodeId, term: u64 },
+    /// The hub changed the network topology.
+    Fault(Fault),
+}
+
+impl Event {
+    fn node(&self) -> Option<NodeId> {
+        match *self {
+            Event::Term { node, .. } | Event::Vote { node, .. } | Event::Leader { node, .. } => {
+                Some(node)
+            }
+            Event::Fault(..) => None,
+        }
+    }
+
+    fn term(&self) -> Option<u64> {
+        match *self {
+            Event::Term { term, .. } | Event::Vote { term, .. } | Event::Leader { term, .. } => {
+                Some(term)
+            }
+            Event::Fault(..) => None,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Event::Term { node, term } => write!(f, "node {} moved to term {}", node, term),
+            Event::Vote {
+                node,
+                term,
+                candidate,
+            } => write!(
+                f,
+                "node {} voted for node {} in term {}",
+                node, candidate, term
+            ),
+            Event::Leader { node, term } => {
+                write!(f, "node {} became the leader of term {}", node, term)
+            }
+            Event::Fault(fault) => write!(f, "network fault {:?}", fault),
+        }
+    }
+}
+
+/// An event reported to the hub.
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Observe(pub Event);
+
+/// Events in the order the hub observed them, stamped with the simulated time since the cluster
+/// started.
+pub type Trace = Vec<(Duration, Event)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Two nodes became the leader of the same term.
+    ElectionSafety {
+        term: u64,
+        leaders: (NodeId, NodeId),
+        at: usize,
+    },
+    /// A node moved to a term not greater than its current one.
+    TermMonotonicity {
+        node: NodeId,
+        from: u64,
+        to: u64,
+        at: usize,
+    },
+    /// A node voted for two candidates in the same term.
+    VoteUniqueness {
+        node: NodeId,
+        term: u64,
+        candidates: (NodeId, NodeId),
+        at: usize,
+    },
+}
+
+impl Violation {
+    /// Index of the event in the trace that violated the invariant.
+    pub fn at(&self) -> usize {
+        match *self {
+            Violation::ElectionSafety { at, .. }
+            | Violation::TermMonotonicity { at, .. }
+            | Violation::VoteUniqueness { at, .. } => at,
+        }
+    }
+
+    /// Whether an event may have contributed to the violation.
+    fn involves(&self, event: &Event) -> bool {
+        match *self {
+            Violation::ElectionSafety { term, .. } => event.term().is_none_or(|t| t == term),
+            Violation::TermMonotonicity { node, .. } => match event {
+                Event::Term { .. } => event.node() == Some(node),
+                Event::Fault(fault) => fault.node() == Some(node),
+                _ => false,
+            },
+            Violation::VoteUniqueness { node, term, .. } => match event {
+                Event::Fault(fault) => fault.node() == Some(node),
+                _ => event.node() == Some(node) && event.term() == Some(term),
+            },
+        }
+    }
+
+    /// Events up to the violation that are relevant to it, with their indices in the full trace.
+    pub fn minimal_trace<'a>(
+        &self,
+        trace: &'a [(Duration, Event)],
+    ) -> Vec<(usize, &'a (Duration, Event))> {
+        trace[..=self.at()]
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, event))| self.involves(event))
+            .collect()
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Violation::ElectionSafety {
+                term,
+                leaders: (a, b),
+                ..
+            } => write!(f, "nodes {} and {} both led term {}", a, b, term),
+            Violation::TermMonotonicity { node, from, to, .. } => write!(
+                f,
+                "node {} moved from term {} back to term {}",
+                node, from, to
+            ),
+            Violation::VoteUniqueness {
+                node,
+                term,
+                candidates: (a, b),
+                ..
+            } => write!(
+                f,
+                "node {} voted for both node {} and node {} in term {}",
+                node, a, b, term
+            ),
+        }
+    }
+}
+
+/// Check election safety (at most one leader per term), term monotonicity and vote uniqueness
+/// over a trace, returns the first violation found.
+pub fn check(trace: &[(Duration, Event)]) -> Result<(), Violation> {
+    let mut leaders: HashMap<u64, NodeId> = HashMap::new();
+    let mut terms: HashMap<NodeId, u64> = HashMap::new();
+    let mut votes: HashMap<(NodeId, u64), NodeId> = HashMap::new();
+
+    for (at, (_, event)) in trace.iter().enumerate() {
+        match *event {
+            Event::Term { node, term } => {
+                let current = terms.entry(node).or_insert(0);
+                if term <= *current {
+                    return Err(Violation::TermMonotonicity {
+                        node,
+                        from: *current,
+                        to: term,
+                        at,
+                    });
+                }
+                *current = term;
+            }
+            Event::Vote {
+                node,
+                term,
+                candidate,
+            } => {
+                let voted = *votes.entry((node, term)).or_insert(candidate);
+                if voted != candidate {
+                    return Err(Violation::VoteUniqueness {
+                        node,
+                        term,
+                        candidates: (voted, candidate),
+                        at,
+                    });
+                }
+            }
+            Event::Leader { node, term } => {
+                let leader = *leaders.entry(term).or_insert(node);
+                if leader != node {
+                    return Err(Violation::ElectionSafety {
+                        term,
+                        leaders: (leader, node),
+                        at,
+                    });
+                }
+            }
+            Event::Fault(..) => (),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(events: Vec<Event>) -> Trace {
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(i, event)| (Duration::from_millis(i as u64), event))
+            .collect()
+    }
+
+    #[test]
+    fn valid_election() {
+        let trace = trace(vec![
+            Event::Term { node: 0, term: 1 },
+            Event::Vote {
+                node: 0,
+                term: 1,
+                candidate: 0,
+            },
+            Event::Term { node: 1, term: 1 },
+            Event::Vote {
+                node: 1,
+                term: 1,
+                candidate: 0,
+            },
+            // duplicated RequestVote RPC
+            Event::Vote {
+                node: 1,
+                term: 1,
+                candidate: 0,
+            },
+            Event::Leader { node: 0, term: 1 },
+        ]);
+        assert_eq!(check(&trace), Ok(()));
+    }
+
+    #[test]
+    fn split_brain() {
+        let trace = trace(vec![
+            Event::Leader { node: 0, term: 1 },
+            Event::Leader { node: 2, term: 2 },
+            Event::Fault(Fault::Heal),
+            Event::Leader { node: 1, term: 1 },
+        ]);
+        let violation = check(&trace).unwrap_err();
+        assert_eq!(
+            violation,
+            Violation::ElectionSafety {
+                term: 1,
+                leaders: (0, 1),
+                at: 3
+            }
+        );
+
+        let minimal: Vec<usize> = violation
+            .minimal_trace(&trace)
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(minimal, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn double_vote_and_term_regression() {
+        let votes = trace(vec![
+            Event::Vote {
+                node: 0,
+                term: 1,
+                candidate: 1,
+            },
+            Event::Vote {
+                node: 0,
+                term: 1,
+                candidate: 2,
+            },
+        ]);
+        assert!(matches!(
+            check(&votes),
+            Err(Violation::VoteUniqueness { node: 0, .. })
+        ));
+
+        let terms = trace(vec![
+            Event::Term { node: 0, term: 2 },
+            Event::Term { node: 0, term: 2 },
+        ]);
+        assert!(matches!(
+            check(&terms),
+            Err(Violation::TermMonotonicity { node: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+
+/// Opaque client command carried by a log entry, interpreted only by the state machine.
+pub type Command = Vec<u8>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub term: u64,
+    /// None for the no-op entry a leader appends at the start of its term.
+    pub command: Option<Command>,
+}
+
+/// The replicated log. Indices start from 1 as in the paper. Entries up to the snapshot index are
+/// compacted into a snapshot, only the index and term of the last compacted entry are kept. With
+/// no snapshot, index 0 is a sentinel entry of term 0 so that the consistency check of the first
+/// AppendEntries RPC always succeeds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Log {
+    snapshot_index: u64,
+    snapshot_term: u64,
+    entries: Vec<Entry>,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot_index(&self) -> u64 {
+        self.snapshot_index
+    }
+
+    pub fn snapshot_term(&self) -> u64 {
+        self.snapshot_term
+    }
+
+    /// Number of entries not yet compacted.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.snapshot_index + self.entries.len() as u64
+    }
+
+    pub fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.snapshot_term, |entry| entry.term)
+    }
+
+    /// Term of the entry at `index`, None if the log doesn't contain that index or the entry was
+    /// compacted (except for the last compacted entry).
+    pub fn term_at(&self, index: u64) -> Option<u64> {
+        if index == self.snapshot_index {
+            Some(self.snapshot_term)
+        } else {
+            self.get(index).map(|entry| entry.term)
+        }
+    }
+
+    pub fn get(&self, index: u64) -> Option<&Entry> {
+        if index <= self.snapshot_index {
+            None
+        } else {
+            self.entries.get(self.offset(index))
+        }
+    }
+
+    fn offset(&self, index: u64) -> usize {
+        (index - self.snapshot_index - 1) as usize
+    }
+
+    /// Clone all entries starting from `index` (inclusive), which must not be compacted.
+    pub fn entries_from(&self, index: u64) -> Vec<Entry> {
+        assert!(index > self.snapshot_index, "entry {} was compacted", index);
+        self.entries
+            .get(self.offset(index)..)
+            .map_or(Vec::new(), |s| s.to_vec())
+    }
+
+    /// Append a new entry to the end of the log, return its index.
+    pub fn push(&mut self, entry: Entry) -> u64 {
+        self.entries.push(entry);
+        self.last_index()
+    }
+
+    /// Merge entries from an AppendEntries RPC following `prev_index` into the log. An existing
+    /// entry conflicting with a new one (same index but different terms) is deleted together with
+    /// all that follow it, entries already in the log are kept untouched, so that a delayed
+    /// AppendEntries RPC never truncates entries appended by a later one. Compacted entries are
+    /// committed and never conflict.
+    pub fn merge(&mut self, prev_index: u64, entries: Vec<Entry>) {
+        for (index, entry) in (prev_index + 1..).zip(entries) {
+            if index <= self.snapshot_index {
+                continue;
+            }
+
+            match self.term_at(index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => {
+                    let offset = self.offset(index);
+                    self.entries.truncate(offset);
+                }
+                None => (),
+            }
+            self.entries.push(entry);
+        }
+    }
+
+    /// Discard all entries up to `index` (inclusive) after they were captured in a snapshot.
+    pub fn compact(&mut self, index: u64) {
+        if index <= self.snapshot_index {
+            return;
+        }
+
+        self.snapshot_term = self.term_at(index).expect("compacting past the last entry");
+        self.entries.drain(..=self.offset(index));
+        self.snapshot_index = index;
+    }
+
+    /// Replace the log prefix by a snapshot received from the leader. Entries following the
+    /// snapshot are retained if the log has the last entry covered by the snapshot, otherwise the
+    /// whole log is discarded.
+    pub fn install(&mut self, index: u64, term: u64) {
+        if index <= self.snapshot_index {
+            return;
+        }
+
+        if self.term_at(index) == Some(term) {
+            self.compact(index);
+        } else {
+            self.entries.clear();
+            self.snapshot_index = index;
+            self.snapshot_term = term;
+        }
+    }
+
+    /// Whether a log ending with (`last_term`, `last_index`) is at least as up-to-date as this
+    /// log (§5.4.1).
+    pub fn is_up_to_date(&self, last_term: u64, last_index: u64) -> bool {
+        (last_term, last_index) >= (self.last_term(), self.last_index())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(term: u64) -> Entry {
+        Entry {
+            term,
+            command: None,
+        }
+    }
+
+    fn log_of(terms: &[u64]) -> Log {
+        let mut log = Log::new();
+        for &term in terms {
+            log.push(entry(term));
+        }
+        log
+    }
+
+    fn terms(log: &Log) -> Vec<u64> {
+        log.entries_from(log.snapshot_index() + 1)
+            .iter()
+            .map(|e| e.term)
+            .collect()
+    }
+
+    #[test]
+    fn log_indices() {
+        let log = log_of(&[1, 1, 2]);
+        assert_eq!(log.last_index(), 3);
+        assert_eq!(log.last_term(), 2);
+        assert_eq!(log.term_at(0), Some(0));
+        assert_eq!(log.term_at(3), Some(2));
+        assert_eq!(log.term_at(4), None);
+        assert_eq!(log.entries_from(3).len(), 1);
+        assert!(log.entries_from(4).is_empty());
+    }
+
+    #[test]
+    fn merge_truncates_conflicts() {
+        let mut log = log_of(&[1, 1, 2, 2]);
+        log.merge(2, vec![entry(3)]);
+        assert_eq!(terms(&log), vec![1, 1, 3]);
+    }
+
+    #[test]
+    fn merge_keeps_newer_entries() {
+        let mut log = log_of(&[1, 1, 2, 2]);
+        log.merge(1, vec![entry(1), entry(2)]);
+        assert_eq!(terms(&log), vec![1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut log = log_of(&[1, 1, 2, 2, 3]);
+        log.compact(3);
+        assert_eq!((log.snapshot_index(), log.snapshot_term()), (3, 2));
+        assert_eq!(log.last_index(), 5);
+        assert_eq!(log.term_at(2), None);
+        assert_eq!(log.term_at(3), Some(2));
+        assert_eq!(log.get(4).map(|e| e.term), Some(2));
+        assert_eq!(terms(&log), vec![2, 3]);
+
+        // compacted entries are skipped, conflicts after the snapshot still truncate the log
+        log.merge(1, vec![entry(1), entry(2), entry(2), entry(4)]);
+        assert_eq!(log.last_index(), 5);
+        assert_eq!(log.last_term(), 4);
+
+        log.compact(5);
+        assert!(log.is_empty());
+        assert_eq!((log.last_index(), log.last_term()), (5, 4));
+    }
+
+    #[test]
+    fn install_snapshot() {
+        let mut log = log_of(&[1, 1, 2, 2]);
+        log.install(3, 2);
+        assert_eq!((log.snapshot_index(), log.len()), (3, 1));
+
+        let mut log = log_of(&[1, 1, 2, 2]);
+        log.install(3, 3);
+        assert_eq!((log.snapshot_index(), log.len()), (3, 0));
+        assert_eq!(log.last_term(), 3);
+
+        let mut log = log_of(&[1]);
+        log.install(6, 3);
+        assert_eq!((log.last_index(), log.last_term()), (6, 3));
+    }
+
+    #[test]
+    fn up_to_date() {
+        let log = log_of(&[1, 2, 2]);
+        assert!(log.is_up_to_date(3, 1));
+        assert!(log.is_up_to_date(2, 3));
+        assert!(!log.is_up_to_date(2, 2));
+        assert!(!log.is_up_to_date(1, 5));
+    }
+}
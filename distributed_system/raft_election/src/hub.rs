@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use actix::clock::Instant;
+use actix::{
+    Actor, Addr, AsyncContext, Context, Handler, MailboxError, Message, MessageResult,
+    ResponseFuture,
+};
+use log::{info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::checker::{Event, Observe, Trace};
+use crate::network::{Fault, Network};
+use crate::raft::{Raft, Stop};
+use crate::state_machine::{Noop, StateMachine};
+use crate::storage::{MemoryStorage, Storage};
+use crate::{NodeId, SNAPSHOT_THRESHOLD};
+
+/// Counters of messages passed between Raft nodes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub sent: u64,
+    pub lost: u64,
+    pub duplicated: u64,
+    pub delivered: u64,
+}
+
+/// Opens the stable storage of a node, called each time the node (re)starts.
+pub type StorageFactory = Box<dyn Fn(NodeId) -> Box<dyn Storage>>;
+
+/// Creates the empty state machine of a node, called each time the node (re)starts.
+pub type StateMachineFactory = Box<dyn Fn(NodeId) -> Box<dyn StateMachine>>;
+
+pub struct MessageHub {
+    peer_count: usize,
+    peers: Vec<Addr<Raft>>,
+    /// Whether each node is running, crashed nodes neither send nor receive messages.
+    alive: Vec<bool>,
+    storage: StorageFactory,
+    state_machine: StateMachineFactory,
+    snapshot_threshold: usize,
+    network: Network,
+    rng: StdRng,
+    /// The partition each node belongs to, nodes may only talk within the same partition.
+    groups: Vec<usize>,
+    /// The latest scheduled delivery on each link, later messages that may not be reordered are
+    /// delivered after it.
+    last_delivery: HashMap<(NodeId, NodeId), Instant>,
+    stats: Stats,
+    start: Instant,
+    /// Events observed by the hub, only recorded on demand.
+    trace: Option<Trace>,
+}
+
+impl MessageHub {
+    /// Create a hub of `peer_count` nodes with in-memory storage, which survives simulated
+    /// crashes but not the hub.
+    pub fn new(peer_count: usize, network: Network) -> Self {
+        let memory: Vec<_> = (0..peer_count).map(|_| MemoryStorage::new()).collect();
+
+        Self {
+            peer_count,
+            peers: Vec::new(),
+            alive: vec![true; peer_count],
+            storage: Box::new(move |id| Box::new(memory[id].clone())),
+            state_machine: Box::new(|_| Box::new(Noop)),
+            snapshot_threshold: SNAPSHOT_THRESHOLD,
+            rng: StdRng::seed_from_u64(network.seed),
+            network,
+            groups: vec![0; peer_count],
+            last_delivery: HashMap::new(),
+            stats: Stats::default(),
+            start: Instant::now(),
+            trace: None,
+        }
+    }
+
+    /// Replace the stable storage of nodes.
+    pub fn storage<F>(mut self, storage: F) -> Self
+    where
+        F: Fn(NodeId) -> Box<dyn Storage> + 'static,
+    {
+        self.storage = Box::new(storage);
+        self
+    }
+
+    /// Replace the state machine replicated by the nodes, a no-op by default.
+    pub fn state_machine<F>(mut self, state_machine: F) -> Self
+    where
+        F: Fn(NodeId) -> Box<dyn StateMachine> + 'static,
+    {
+        self.state_machine = Box::new(state_machine);
+        self
+    }
+
+    /// Compact the log of a node once it holds `threshold` applied entries.
+    pub fn snapshot_threshold(mut self, threshold: usize) -> Self {
+        assert!(threshold > 0, "snapshot threshold must be positive");
+        self.snapshot_threshold = threshold;
+        self
+    }
+
+    /// Record all events reported by the nodes for the safety checker.
+    pub fn traced(mut self) -> Self {
+        self.trace = Some(Trace::new());
+        self
+    }
+
+    fn observe(&mut self, event: Event) {
+        if let Some(trace) = &mut self.trace {
+            trace.push((Instant::now() - self.start, event));
+        }
+    }
+
+    fn connected(&self, from: NodeId, to: NodeId) -> bool {
+        self.alive[from] && self.alive[to] && self.groups[from] == self.groups[to]
+    }
+
+    fn start_node(&mut self, id: NodeId, ctx: &mut <Self as Actor>::Context) -> Addr<Raft> {
+        let rng = StdRng::seed_from_u64(self.rng.gen());
+        let storage = (self.storage)(id);
+        let state_machine = (self.state_machine)(id);
+        Raft::new(
+            id,
+            self.peer_count,
+            ctx.address(),
+            rng,
+            storage,
+            state_machine,
+            self.snapshot_threshold,
+        )
+        .start()
+    }
+
+    fn apply_fault(&mut self, fault: Fault, ctx: &mut <Self as Actor>::Context) {
+        info!("fault: {:?}", fault);
+        self.observe(Event::Fault(fault.clone()));
+
+        match fault {
+            Fault::Partition(groups) => {
+                // every node not mentioned is alone in its own group
+                self.groups = (self.peer_count..self.peer_count * 2).collect();
+                for (group, nodes) in groups.iter().enumerate() {
+                    for &node in nodes {
+                        self.groups[node] = group;
+                    }
+                }
+            }
+            Fault::Heal => {
+                self.groups = vec![0; self.peer_count];
+            }
+            Fault::Crash(id) => {
+                if self.alive[id] {
+                    self.alive[id] = false;
+                    self.peers[id].do_send(Stop);
+                }
+            }
+            Fault::Restart(id) => {
+                if !self.alive[id] {
+                    self.restart(id, ctx);
+                }
+            }
+        }
+    }
+
+    fn restart(&mut self, id: NodeId, ctx: &mut <Self as Actor>::Context) {
+        if self.peers[id].connected() {
+            // the old node may still be working through its mailbox, two nodes must never share
+            // the same storage
+            ctx.run_later(Duration::from_millis(1), move |hub, ctx| {
+                hub.restart(id, ctx);
+            });
+        } else {
+            self.peers[id] = self.start_node(id, ctx);
+            self.alive[id] = true;
+        }
+    }
+}
+
+impl Actor for MessageHub {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.start = Instant::now();
+
+        for id in 0..self.peer_count {
+            let raft = self.start_node(id, ctx);
+            self.peers.push(raft);
+        }
+
+        for (at, fault) in self.network.script().to_vec() {
+            ctx.run_later(at, move |hub, ctx| {
+                hub.apply_fault(fault, ctx);
+            });
+        }
+    }
+}
+
+/// A message between Raft nodes, delivered through the simulated network.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct To<M> {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub msg: M,
+}
+
+impl<M> Handler<To<M>> for MessageHub
+where
+    M: Message + Debug + Clone + Send + 'static,
+    M::Result: Send,
+    Raft: Handler<M>,
+{
+    type Result = ();
+
+    fn handle(&mut self, msg: To<M>, ctx: &mut Self::Context) -> Self::Result {
+        let To { from, to, msg } = msg;
+        let link = self.network.link_between(from, to).clone();
+        self.stats.sent += 1;
+
+        if !self.connected(from, to) || self.rng.gen_bool(link.loss_rate) {
+            self.stats.lost += 1;
+            warn!("message {:?} to node {} was lost", msg, to);
+            return;
+        }
+
+        let copies = if self.rng.gen_bool(link.duplicate_rate) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            // timers are millisecond granular, sub-millisecond delays would be rounded
+            // differently depending on when the simulated clock was paused
+            let delay = self
+                .rng
+                .gen_range(link.min_delay.as_millis()..=link.max_delay.as_millis());
+            let now = Instant::now();
+            let mut at = now + Duration::from_millis(delay as u64);
+            let last = self.last_delivery.get(&(from, to)).copied();
+
+            if let Some(last) = last {
+                if at <= last && !self.rng.gen_bool(link.reorder_rate) {
+                    // keep FIFO messages strictly ordered
+                    at = last + Duration::from_millis(1);
+                }
+            }
+
+            self.last_delivery
+                .insert((from, to), last.map_or(at, |last| last.max(at)));
+
+            let msg = msg.clone();
+            ctx.run_later(at - now, move |hub, _ctx| {
+                // messages in flight are lost as well if the nodes are partitioned meanwhile
+                if hub.connected(from, to) {
+                    hub.stats.delivered += 1;
+                    hub.peers[to].do_send(msg);
+                } else {
+                    hub.stats.lost += 1;
+                }
+            });
+        }
+    }
+}
+
+impl Handler<Fault> for MessageHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: Fault, ctx: &mut Self::Context) -> Self::Result {
+        self.apply_fault(msg, ctx);
+    }
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "Stats")]
+pub struct GetStats;
+
+impl Handler<GetStats> for MessageHub {
+    type Result = MessageResult<GetStats>;
+
+    fn handle(&mut self, _msg: GetStats, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.stats)
+    }
+}
+
+impl Handler<Observe> for MessageHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: Observe, _ctx: &mut Self::Context) -> Self::Result {
+        self.observe(msg.0);
+    }
+}
+
+/// Fetch the events recorded so far, empty if the hub isn't traced.
+#[derive(Debug, Message)]
+#[rtype(result = "Trace")]
+pub struct GetTrace;
+
+impl Handler<GetTrace> for MessageHub {
+    type Result = MessageResult<GetTrace>;
+
+    fn handle(&mut self, _msg: GetTrace, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.trace.clone().unwrap_or_default())
+    }
+}
+
+/// A client request to a Raft node, delivered directly and reliably unless the node crashed.
+pub struct Client<M>(pub M, pub NodeId);
+
+impl<M: Message> Message for Client<M>
+where
+    M::Result: Send,
+{
+    type Result = Result<M::Result, MailboxError>;
+}
+
+impl<M> Handler<Client<M>> for MessageHub
+where
+    M: Message + Send + 'static,
+    M::Result: Send,
+    Raft: Handler<M>,
+{
+    type Result = ResponseFuture<Result<M::Result, MailboxError>>;
+
+    fn handle(&mut self, msg: Client<M>, _ctx: &mut Self::Context) -> Self::Result {
+        let Client(msg, peer) = msg;
+        if !self.alive[peer] {
+            return Box::pin(async { Err(MailboxError::Closed) });
+        }
+
+        let request = self.peers[peer].send(msg);
+        Box::pin(request)
+    }
+}
@@ -0,0 +1,293 @@
+//! A key-value service replicated by Raft, in the spirit of lab 3 of MIT 6.824. Every operation,
+//! reads included, goes through the log, retried requests are deduplicated by client ID and
+//! sequence number so that each is applied exactly once.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix::clock::{self, Instant};
+use actix::{Addr, MailboxError};
+use serde::{Deserialize, Serialize};
+
+use crate::hub::{Client, MessageHub};
+use crate::linearizability::{Model, Operation};
+use crate::raft::{Propose, ProposeError};
+use crate::state_machine::StateMachine;
+use crate::{NodeId, HEARTBEAT_INTERVAL, MAX_ELECTION_TIMEOUT};
+
+/// Give up waiting on a node after this long, it may be a leader cut off from the majority.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(MAX_ELECTION_TIMEOUT * 2);
+const RETRY_INTERVAL: Duration = Duration::from_millis(HEARTBEAT_INTERVAL);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Op {
+    Get(String),
+    Put(String, String),
+    /// Append to the value of the key, appending to a missing key acts like Put.
+    Append(String, String),
+}
+
+impl Op {
+    pub fn key(&self) -> &str {
+        match self {
+            Op::Get(key) | Op::Put(key, _) | Op::Append(key, _) => key,
+        }
+    }
+}
+
+/// The command carried by a log entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    client_id: u64,
+    seq: u64,
+    op: Op,
+}
+
+/// The replicated key-value state machine, outputs are the value of the key for Get and empty
+/// otherwise.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KvStore {
+    data: HashMap<String, String>,
+    /// The sequence number and output of the last request applied for each client. Clients issue
+    /// one request at a time, so a request is a retry if its sequence number is not greater.
+    sessions: HashMap<u64, (u64, String)>,
+}
+
+impl KvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.data.get(key).map(String::as_str)
+    }
+}
+
+impl StateMachine for KvStore {
+    fn apply(&mut self, _index: u64, command: &[u8]) -> Vec<u8> {
+        let request: Request = bincode::deserialize(command).expect("malformed kv request");
+
+        if let Some((seq, output)) = self.sessions.get(&request.client_id) {
+            if request.seq <= *seq {
+                // older requests have been answered already, nobody is waiting for the output
+                return output.clone().into_bytes();
+            }
+        }
+
+        let output = match request.op {
+            Op::Get(key) => self.data.get(&key).cloned().unwrap_or_default(),
+            Op::Put(key, value) => {
+                self.data.insert(key, value);
+                String::new()
+            }
+            Op::Append(key, value) => {
+                self.data.entry(key).or_default().push_str(&value);
+                String::new()
+            }
+        };
+
+        self.sessions
+            .insert(request.client_id, (request.seq, output.clone()));
+        output.into_bytes()
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("failed to serialize kv store")
+    }
+
+    fn restore(&mut self, data: &[u8]) {
+        *self = bincode::deserialize(data).expect("malformed kv snapshot");
+    }
+}
+
+/// Operations completed by clients, shared among all clients of a test.
+pub type History = Arc<Mutex<Vec<Operation<Op, String>>>>;
+
+/// A client of the key-value service. Requests are sent to the last known leader and retried
+/// until they succeed, following redirects from nodes that aren't the leader.
+pub struct KvClient {
+    hub: Addr<MessageHub>,
+    peer_count: usize,
+    client_id: u64,
+    seq: u64,
+    leader: NodeId,
+    history: Option<History>,
+}
+
+impl KvClient {
+    pub fn new(hub: Addr<MessageHub>, peer_count: usize, client_id: u64) -> Self {
+        Self {
+            hub,
+            peer_count,
+            client_id,
+            seq: 0,
+            leader: 0,
+            history: None,
+        }
+    }
+
+    /// Record completed operations into `history`.
+    pub fn recorded(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub async fn get(&mut self, key: &str) -> String {
+        self.request(Op::Get(key.to_string())).await
+    }
+
+    pub async fn put(&mut self, key: &str, value: &str) {
+        self.request(Op::Put(key.to_string(), value.to_string()))
+            .await;
+    }
+
+    pub async fn append(&mut self, key: &str, value: &str) {
+        self.request(Op::Append(key.to_string(), value.to_string()))
+            .await;
+    }
+
+    pub async fn request(&mut self, op: Op) -> String {
+        self.seq += 1;
+        let command = bincode::serialize(&Request {
+            client_id: self.client_id,
+            seq: self.seq,
+            op: op.clone(),
+        })
+        .expect("failed to serialize kv request");
+
+        let call = Instant::now();
+        let output = loop {
+            let propose = self.hub.send(Client(Propose(command.clone()), self.leader));
+            let reply = match clock::timeout(REQUEST_TIMEOUT, propose).await {
+                Ok(reply) => reply.expect("message hub stopped"),
+                Err(_) => Err(MailboxError::Timeout),
+            };
+
+            match reply {
+                Ok(Ok(committed)) => {
+                    break String::from_utf8(committed.output).expect("malformed kv output")
+                }
+                Ok(Err(ProposeError::NotLeader(Some(leader)))) => self.leader = leader,
+                // the leader may have changed, the same node redirects to the new one
+                Ok(Err(ProposeError::Overwritten)) | Ok(Err(ProposeError::Compacted)) => (),
+                // crashed, timed out, or doesn't know the leader either
+                Ok(Err(ProposeError::NotLeader(None)))
+                | Ok(Err(ProposeError::Stopped))
+                | Err(_) => self.leader = (self.leader + 1) % self.peer_count,
+            }
+
+            clock::sleep(RETRY_INTERVAL).await;
+        };
+
+        if let Some(history) = &self.history {
+            history.lock().unwrap().push(Operation {
+                client_id: self.client_id,
+                input: op,
+                call,
+                output: output.clone(),
+                ret: Instant::now(),
+            });
+        }
+
+        output
+    }
+}
+
+/// Sequential specification of the key-value service, checked key by key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KvModel;
+
+impl Model for KvModel {
+    /// The value of a single key.
+    type State = String;
+    type Input = Op;
+    type Output = String;
+
+    fn init(&self) -> String {
+        String::new()
+    }
+
+    fn step(&self, state: &String, input: &Op, output: &String) -> Option<String> {
+        match input {
+            Op::Get(_) => Some(state.clone()).filter(|state| state == output),
+            Op::Put(_, value) => Some(value.clone()),
+            Op::Append(_, value) => Some(state.clone() + value),
+        }
+    }
+
+    fn partition(&self, history: &[Operation<Op, String>]) -> Vec<Vec<usize>> {
+        let mut keys: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, op) in history.iter().enumerate() {
+            keys.entry(op.input.key()).or_default().push(i);
+        }
+        keys.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linearizability::is_linearizable;
+
+    fn request(client_id: u64, seq: u64, op: Op) -> Vec<u8> {
+        bincode::serialize(&Request { client_id, seq, op }).unwrap()
+    }
+
+    fn append(key: &str, value: &str) -> Op {
+        Op::Append(key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn retried_requests_applied_once() {
+        let mut store = KvStore::new();
+        store.apply(1, &request(1, 1, append("x", "a")));
+        store.apply(2, &request(2, 1, append("x", "b")));
+        // a retry committed after the original
+        store.apply(3, &request(1, 1, append("x", "a")));
+        store.apply(4, &request(1, 2, append("x", "c")));
+        assert_eq!(store.get("x"), Some("abc"));
+
+        let output = store.apply(5, &request(2, 2, Op::Get("x".to_string())));
+        assert_eq!(output, b"abc");
+        // the retried Get is answered with the original output
+        store.apply(6, &request(1, 3, append("x", "d")));
+        let output = store.apply(7, &request(2, 2, Op::Get("x".to_string())));
+        assert_eq!(output, b"abc");
+    }
+
+    #[test]
+    fn snapshot_keeps_sessions() {
+        let mut store = KvStore::new();
+        store.apply(1, &request(1, 1, append("x", "a")));
+
+        let mut restored = KvStore::new();
+        restored.restore(&store.snapshot());
+        restored.apply(2, &request(1, 1, append("x", "a")));
+        assert_eq!(restored.get("x"), Some("a"));
+    }
+
+    #[test]
+    fn model_checks_keys_independently() {
+        let start = Instant::now();
+        let op = |client_id, input, call, output: &str, ret| Operation {
+            client_id,
+            input,
+            call: start + Duration::from_millis(call),
+            output: output.to_string(),
+            ret: start + Duration::from_millis(ret),
+        };
+
+        let mut history = vec![
+            op(0, append("x", "a"), 0, "", 10),
+            op(1, append("y", "b"), 0, "", 10),
+            op(0, Op::Get("x".to_string()), 20, "a", 30),
+            op(1, Op::Get("y".to_string()), 20, "b", 30),
+        ];
+        assert!(is_linearizable(&KvModel, &history));
+
+        // the append to x is lost
+        history.push(op(0, Op::Get("x".to_string()), 40, "", 50));
+        assert!(!is_linearizable(&KvModel, &history));
+    }
+}
@@ -0,0 +1,30 @@
+mod checker;
+mod entry;
+mod hub;
+mod kv;
+mod linearizability;
+mod network;
+mod raft;
+mod state_machine;
+mod storage;
+
+pub use checker::{check, Event, Observe, Trace, Violation};
+pub use entry::{Command, Entry, Log};
+pub use hub::{
+    Client, GetStats, GetTrace, MessageHub, StateMachineFactory, Stats, StorageFactory, To,
+};
+pub use kv::{History, KvClient, KvModel, KvStore, Op};
+pub use linearizability::{is_linearizable, Model, Operation};
+pub use network::{simulate, Fault, Link, Network};
+pub use raft::{Committed, GetStatus, Propose, ProposeError, Raft, Status};
+pub use state_machine::{Noop, Snapshot, StateMachine};
+pub use storage::{FileStorage, HardState, MemoryStorage, Storage};
+
+pub const SLOW_MOTION: u64 = 20;
+pub const MIN_ELECTION_TIMEOUT: u64 = 150 * SLOW_MOTION;
+pub const MAX_ELECTION_TIMEOUT: u64 = 300 * SLOW_MOTION;
+pub const HEARTBEAT_INTERVAL: u64 = 100 * SLOW_MOTION;
+/// Applied entries a node keeps in its log before taking a snapshot, unless configured otherwise.
+pub const SNAPSHOT_THRESHOLD: usize = 1000;
+
+pub type NodeId = usize;
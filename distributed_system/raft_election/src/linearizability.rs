@@ -0,0 +1,246 @@
+//! A linearizability checker for client histories in the style of Porcupine, searching for a valid
+//! linearization with the algorithm of Wing & Gong improved by Lowe.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use actix::clock::Instant;
+
+/// A completed client operation with the times it was invoked and returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation<I, O> {
+    pub client_id: u64,
+    pub input: I,
+    pub call: Instant,
+    pub output: O,
+    pub ret: Instant,
+}
+
+/// The sequential specification of an object.
+pub trait Model {
+    type State: Clone + Eq + Hash;
+    type Input;
+    type Output;
+
+    fn init(&self) -> Self::State;
+
+    /// The state after applying `input`, None if the object would not have produced `output` in
+    /// `state`.
+    fn step(
+        &self,
+        state: &Self::State,
+        input: &Self::Input,
+        output: &Self::Output,
+    ) -> Option<Self::State>;
+
+    /// Split a history into independent sub-histories (e.g. by key) which are checked separately,
+    /// indices of operations are returned. The whole history is checked at once by default.
+    fn partition(&self, history: &[Operation<Self::Input, Self::Output>]) -> Vec<Vec<usize>> {
+        vec![(0..history.len()).collect()]
+    }
+}
+
+/// Whether some total order of the operations, consistent with their real-time order, is allowed
+/// by the sequential specification.
+pub fn is_linearizable<M: Model>(model: &M, history: &[Operation<M::Input, M::Output>]) -> bool {
+    model
+        .partition(history)
+        .iter()
+        .all(|ops| check_partition(model, history, ops))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    // calls sort before returns at the same instant, operations touching at an instant are
+    // considered concurrent
+    Call,
+    Return,
+}
+
+/// Call and return events of a partition in a doubly linked list, calls are removed from the list
+/// together with their returns once linearized, and put back on backtracking.
+struct Events {
+    /// Operation of each event, index 0 is the head of the list.
+    op: Vec<usize>,
+    kind: Vec<Kind>,
+    /// The return event of each call event.
+    matched: Vec<usize>,
+    prev: Vec<usize>,
+    next: Vec<Option<usize>>,
+}
+
+const HEAD: usize = 0;
+
+impl Events {
+    fn new<I, O>(history: &[Operation<I, O>], ops: &[usize]) -> Self {
+        let mut sorted = Vec::with_capacity(ops.len() * 2);
+        for (i, &op) in ops.iter().enumerate() {
+            sorted.push((history[op].call, Kind::Call, i));
+            sorted.push((history[op].ret, Kind::Return, i));
+        }
+        sorted.sort();
+
+        let len = sorted.len() + 1;
+        let mut events = Self {
+            op: vec![0; len],
+            kind: vec![Kind::Call; len],
+            matched: vec![0; len],
+            prev: (0..len).map(|i| i.saturating_sub(1)).collect(),
+            next: (1..=len).map(|i| Some(i).filter(|&i| i < len)).collect(),
+        };
+
+        let mut calls = vec![0; ops.len()];
+        for (i, &(_, kind, op)) in sorted.iter().enumerate() {
+            let event = i + 1;
+            events.op[event] = op;
+            events.kind[event] = kind;
+            match kind {
+                Kind::Call => calls[op] = event,
+                Kind::Return => events.matched[calls[op]] = event,
+            }
+        }
+
+        events
+    }
+
+    fn unlink(&mut self, event: usize) {
+        let (prev, next) = (self.prev[event], self.next[event]);
+        self.next[prev] = next;
+        if let Some(next) = next {
+            self.prev[next] = prev;
+        }
+    }
+
+    fn relink(&mut self, event: usize) {
+        self.next[self.prev[event]] = Some(event);
+        if let Some(next) = self.next[event] {
+            self.prev[next] = event;
+        }
+    }
+
+    fn lift(&mut self, call: usize) {
+        self.unlink(call);
+        self.unlink(self.matched[call]);
+    }
+
+    fn unlift(&mut self, call: usize) {
+        self.relink(self.matched[call]);
+        self.relink(call);
+    }
+}
+
+fn check_partition<M: Model>(
+    model: &M,
+    history: &[Operation<M::Input, M::Output>],
+    ops: &[usize],
+) -> bool {
+    let mut events = Events::new(history, ops);
+    let mut linearized = vec![0u64; ops.len().div_ceil(64)];
+    // (linearized operations, state) already explored without success
+    let mut cache = HashSet::new();
+    let mut stack: Vec<(usize, M::State)> = Vec::new();
+    let mut state = model.init();
+    let mut entry = events.next[HEAD];
+
+    while let Some(event) = entry {
+        let i = events.op[event];
+        match events.kind[event] {
+            Kind::Call => {
+                let op = &history[ops[i]];
+                if let Some(next_state) = model.step(&state, &op.input, &op.output) {
+                    let mut next_linearized = linearized.clone();
+                    next_linearized[i / 64] |= 1 << (i % 64);
+
+                    if cache.insert((next_linearized.clone(), next_state.clone())) {
+                        stack.push((event, std::mem::replace(&mut state, next_state)));
+                        linearized = next_linearized;
+                        events.lift(event);
+                        entry = events.next[HEAD];
+                        continue;
+                    }
+                }
+                entry = events.next[event];
+            }
+            Kind::Return => {
+                // the operation must have taken effect before it returned, undo the last choice
+                let (call, prev_state) = match stack.pop() {
+                    Some(top) => top,
+                    None => return false,
+                };
+                let i = events.op[call];
+                linearized[i / 64] &= !(1 << (i % 64));
+                state = prev_state;
+                events.unlift(call);
+                entry = events.next[call];
+            }
+        }
+    }
+
+    // every operation was linearized
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A register holding a single integer, inputs are None for reads and Some for writes.
+    struct Register;
+
+    impl Model for Register {
+        type State = u64;
+        type Input = Option<u64>;
+        type Output = u64;
+
+        fn init(&self) -> u64 {
+            0
+        }
+
+        fn step(&self, state: &u64, input: &Option<u64>, output: &u64) -> Option<u64> {
+            match input {
+                Some(value) => Some(*value),
+                None => Some(*state).filter(|state| state == output),
+            }
+        }
+    }
+
+    fn history(ops: &[(Option<u64>, u64, u64, u64)]) -> Vec<Operation<Option<u64>, u64>> {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        ops.iter()
+            .enumerate()
+            .map(|(client_id, &(input, call, output, ret))| Operation {
+                client_id: client_id as u64,
+                input,
+                call: at(call),
+                output,
+                ret: at(ret),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn concurrent_reads_see_either_value() {
+        let history = history(&[
+            (Some(1), 0, 0, 10),
+            (None, 5, 0, 15),
+            (None, 6, 1, 8),
+            (None, 20, 1, 30),
+        ]);
+        assert!(is_linearizable(&Register, &history));
+    }
+
+    #[test]
+    fn stale_read() {
+        let history = history(&[(Some(1), 0, 0, 10), (None, 11, 0, 15)]);
+        assert!(!is_linearizable(&Register, &history));
+    }
+
+    #[test]
+    fn reads_never_go_back() {
+        // both reads overlap the write, but the second starts after the first returned
+        let history = history(&[(Some(1), 0, 0, 100), (None, 10, 1, 20), (None, 30, 0, 40)]);
+        assert!(!is_linearizable(&Register, &history));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use actix::{Message, System};
+
+use crate::{NodeId, SLOW_MOTION};
+
+/// Behavior of a directed link between two nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub loss_rate: f64,
+    pub duplicate_rate: f64,
+    /// Probability that a message is allowed to overtake messages sent before it on the same
+    /// link, otherwise the link is FIFO.
+    pub reorder_rate: f64,
+    /// One way delay, sampled uniformly in whole milliseconds from the closed interval.
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Link {
+    pub fn new(loss_rate: f64, duplicate_rate: f64, reorder_rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&loss_rate));
+        assert!((0.0..=1.0).contains(&duplicate_rate));
+        assert!((0.0..=1.0).contains(&reorder_rate));
+
+        Self {
+            loss_rate,
+            duplicate_rate,
+            reorder_rate,
+            ..Self::default()
+        }
+    }
+
+    pub fn delay(mut self, min_delay: Duration, max_delay: Duration) -> Self {
+        assert!(min_delay <= max_delay);
+        self.min_delay = min_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// A link that never loses, duplicates or reorders messages.
+    pub fn reliable() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            loss_rate: 0.1,
+            duplicate_rate: 0.0,
+            reorder_rate: 1.0,
+            min_delay: Duration::from_millis(25 * SLOW_MOTION),
+            max_delay: Duration::from_millis(75 * SLOW_MOTION),
+        }
+    }
+}
+
+/// Faults injected by the hub, either scripted in a [Network] or sent to the hub at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+#[rtype(result = "()")]
+pub enum Fault {
+    /// Split nodes into groups, messages only travel within a group. Nodes not mentioned are
+    /// isolated from everyone else.
+    Partition(Vec<Vec<NodeId>>),
+    /// Reconnect all nodes.
+    Heal,
+    /// Stop a node, messages to and from it are lost until it restarts.
+    Crash(NodeId),
+    /// Restart a crashed node from its stable storage.
+    Restart(NodeId),
+}
+
+impl Fault {
+    /// The node crashed or restarted.
+    pub fn node(&self) -> Option<NodeId> {
+        match *self {
+            Fault::Crash(node) | Fault::Restart(node) => Some(node),
+            Fault::Partition(..) | Fault::Heal => None,
+        }
+    }
+}
+
+/// Configuration of the simulated network between Raft nodes. All randomness in a cluster, from
+/// message loss to election timeouts, is derived from the seed.
+#[derive(Debug, Clone)]
+pub struct Network {
+    pub seed: u64,
+    default_link: Link,
+    links: HashMap<(NodeId, NodeId), Link>,
+    script: Vec<(Duration, Fault)>,
+}
+
+impl Network {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            default_link: Link::default(),
+            links: HashMap::new(),
+            script: Vec::new(),
+        }
+    }
+
+    /// Set the behavior of all links without a specific configuration.
+    pub fn default_link(mut self, link: Link) -> Self {
+        self.default_link = link;
+        self
+    }
+
+    /// Set the behavior of the directed link from `from` to `to`.
+    pub fn link(mut self, from: NodeId, to: NodeId, link: Link) -> Self {
+        self.links.insert((from, to), link);
+        self
+    }
+
+    /// Schedule a fault at `at` after the cluster started, in whole milliseconds for the same
+    /// reason as message delays.
+    pub fn at(mut self, at: Duration, fault: Fault) -> Self {
+        self.script.push((at, fault));
+        self
+    }
+
+    pub fn link_between(&self, from: NodeId, to: NodeId) -> &Link {
+        self.links.get(&(from, to)).unwrap_or(&self.default_link)
+    }
+
+    pub fn script(&self) -> &[(Duration, Fault)] {
+        &self.script
+    }
+}
+
+/// Run `f` in a fresh actix system driven by a simulated clock: time stands still while any actor
+/// has work to do, then jumps straight to the next timer. Together with a seeded [Network] every
+/// run is exactly reproducible and takes no wall-clock time waiting on timers.
+pub fn simulate<F, Fut>(f: F) -> Fut::Output
+where
+    F: FnOnce() -> Fut,
+    Fut: Future,
+{
+    System::new().block_on(async {
+        tokio::time::pause();
+        f().await
+    })
+}
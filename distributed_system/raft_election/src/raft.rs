@@ -0,0 +1,785 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{
+    Actor, ActorContext, Addr, AsyncContext, Context, Handler, Message, MessageResult,
+    ResponseFuture, SpawnHandle,
+};
+use log::info;
+use rand::rngs::StdRng;
+use rand::Rng;
+use tokio::sync::oneshot;
+
+use crate::checker::{Event, Observe};
+use crate::entry::{Command, Entry, Log};
+use crate::hub::{MessageHub, To};
+use crate::state_machine::{Snapshot, StateMachine};
+use crate::storage::{HardState, Storage};
+use crate::{NodeId, HEARTBEAT_INTERVAL, MAX_ELECTION_TIMEOUT, MIN_ELECTION_TIMEOUT};
+
+enum Role {
+    Follower,
+    Candidate(Vec<bool>),
+    Leader(Progress),
+}
+
+/// Replication progress of each peer as seen by the leader, reinitialized after election.
+struct Progress {
+    /// Index of the next log entry to send to each peer.
+    next_index: Vec<u64>,
+    /// Index of the highest log entry known to be replicated on each peer.
+    match_index: Vec<u64>,
+}
+
+/// A client proposal waiting for its entry to be committed.
+struct Proposal {
+    term: u64,
+    reply: oneshot::Sender<Result<Committed, ProposeError>>,
+}
+
+/// A proposed command committed and applied to the state machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Committed {
+    pub index: u64,
+    pub output: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProposeError {
+    /// The node is not the leader, the leader of the current term is attached if known.
+    NotLeader(Option<NodeId>),
+    /// The entry was overwritten by another leader before it was committed.
+    Overwritten,
+    /// The node crashed before the entry was committed, the entry may still be committed later.
+    Stopped,
+    /// The node installed a snapshot covering the entry from the leader, whether the entry was
+    /// committed or overwritten is unknown.
+    Compacted,
+}
+
+pub struct Raft {
+    // configurations and references
+    me: NodeId,
+    peer_count: usize,
+    hub: Addr<MessageHub>,
+    timeout: Option<SpawnHandle>,
+    rng: StdRng,
+    storage: Box<dyn Storage>,
+    state_machine: Box<dyn StateMachine>,
+    /// Take a snapshot once this many applied entries are in the log.
+    snapshot_threshold: usize,
+
+    // persistent states
+    current_term: u64,
+    voted_for: Option<NodeId>,
+    log: Log,
+    snapshot: Snapshot,
+
+    // volatile states
+    role: Role,
+    leader_id: Option<NodeId>,
+    commit_index: u64,
+    last_applied: u64,
+    pending: HashMap<u64, Proposal>,
+}
+
+impl Raft {
+    /// Create a node with states recovered from the storage, or a fresh node if the storage is
+    /// empty. The state machine is restored from the saved snapshot if there is one.
+    pub fn new(
+        id: NodeId,
+        peer_count: usize,
+        hub: Addr<MessageHub>,
+        rng: StdRng,
+        mut storage: Box<dyn Storage>,
+        mut state_machine: Box<dyn StateMachine>,
+        snapshot_threshold: usize,
+    ) -> Self {
+        let mut state = storage
+            .load()
+            .expect("failed to load raft state")
+            .unwrap_or_default();
+        let snapshot = storage
+            .load_snapshot()
+            .expect("failed to load raft snapshot")
+            .unwrap_or_default();
+
+        // the node may have crashed after saving a snapshot but before compacting the log
+        if snapshot.last_index > 0 {
+            state.log.install(snapshot.last_index, snapshot.last_term);
+            state_machine.restore(&snapshot.data);
+        }
+
+        Self {
+            me: id,
+            peer_count,
+            hub,
+            timeout: None,
+            rng,
+            storage,
+            state_machine,
+            snapshot_threshold,
+            current_term: state.current_term,
+            voted_for: state.voted_for,
+            log: state.log,
+            role: Role::Follower,
+            leader_id: None,
+            commit_index: snapshot.last_index,
+            last_applied: snapshot.last_index,
+            snapshot,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn majority(&self) -> usize {
+        self.peer_count / 2 + 1
+    }
+
+    fn peers(&self) -> impl Iterator<Item = NodeId> {
+        let me = self.me;
+        (0..self.peer_count).filter(move |id| *id != me)
+    }
+
+    fn send<M>(&self, to: NodeId, msg: M)
+    where
+        MessageHub: Handler<To<M>>,
+        M: Send + 'static,
+    {
+        self.hub.do_send(To {
+            from: self.me,
+            to,
+            msg,
+        });
+    }
+
+    fn hard_state(&self) -> HardState {
+        HardState {
+            current_term: self.current_term,
+            voted_for: self.voted_for,
+            log: self.log.clone(),
+        }
+    }
+
+    /// Save persistent states to stable storage, must be called before sending any message
+    /// depending on the updated states.
+    fn persist(&mut self) {
+        let state = self.hard_state();
+        self.storage
+            .save(&state)
+            .expect("failed to persist raft state");
+    }
+
+    /// Save the snapshot together with the compacted log.
+    fn persist_snapshot(&mut self) {
+        let state = self.hard_state();
+        self.storage
+            .save_snapshot(&state, &self.snapshot)
+            .expect("failed to persist raft snapshot");
+    }
+
+    fn observe(&self, event: Event) {
+        self.hub.do_send(Observe(event));
+    }
+
+    fn cancel_timeout(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(handle) = self.timeout.take() {
+            ctx.cancel_future(handle);
+        }
+    }
+
+    fn reset_timeout(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.cancel_timeout(ctx);
+
+        let duration = Duration::from_millis(
+            self.rng
+                .gen_range(MIN_ELECTION_TIMEOUT..=MAX_ELECTION_TIMEOUT),
+        );
+        self.timeout = Some(ctx.run_later(duration, |raft, ctx| {
+            raft.timeout(ctx);
+        }))
+    }
+
+    fn timeout(&mut self, ctx: &mut <Self as Actor>::Context) {
+        match self.role {
+            Role::Follower | Role::Candidate(..) => {
+                self.reset_timeout(ctx);
+                self.start_election(ctx);
+            }
+            Role::Leader(..) => {
+                unreachable!("Should have been cancelled during transition");
+            }
+        }
+    }
+
+    fn update_term(&mut self, term: u64, ctx: &mut <Self as Actor>::Context) {
+        if term > self.current_term {
+            self.current_term = term;
+            self.voted_for = None;
+            self.leader_id = None;
+            self.persist();
+            self.observe(Event::Term {
+                node: self.me,
+                term,
+            });
+
+            if matches!(self.role, Role::Leader(..)) {
+                info!("Node {} stepped down in term {}", self.me, term);
+                // replaces the heartbeat interval by an election timeout
+                self.reset_timeout(ctx);
+            }
+            self.role = Role::Follower;
+        }
+    }
+
+    fn start_election(&mut self, ctx: &mut <Self as Actor>::Context) {
+        self.current_term += 1;
+        self.leader_id = None;
+        self.observe(Event::Term {
+            node: self.me,
+            term: self.current_term,
+        });
+
+        info!(
+            "Node {} started election of term {}",
+            self.me, self.current_term
+        );
+
+        let mut votes = vec![false; self.peer_count];
+        votes[self.me] = true;
+        self.voted_for = Some(self.me);
+        self.role = Role::Candidate(votes);
+        self.persist();
+        self.observe(Event::Vote {
+            node: self.me,
+            term: self.current_term,
+            candidate: self.me,
+        });
+
+        let request_vote = RequestVoteArgs {
+            term: self.current_term,
+            candidate_id: self.me,
+            last_log_index: self.log.last_index(),
+            last_log_term: self.log.last_term(),
+        };
+
+        for peer in self.peers() {
+            self.send(peer, request_vote.clone());
+        }
+
+        self.count_votes(ctx);
+    }
+
+    fn count_votes(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Role::Candidate(votes) = &self.role {
+            if votes.iter().filter(|b| **b).count() >= self.majority() {
+                self.start_leadership(ctx);
+            }
+        }
+    }
+
+    fn start_leadership(&mut self, ctx: &mut <Self as Actor>::Context) {
+        info!(
+            "Node {} became the leader of term {}",
+            self.me, self.current_term
+        );
+
+        self.observe(Event::Leader {
+            node: self.me,
+            term: self.current_term,
+        });
+
+        self.cancel_timeout(ctx);
+        self.leader_id = Some(self.me);
+        self.role = Role::Leader(Progress {
+            next_index: vec![self.log.last_index() + 1; self.peer_count],
+            match_index: vec![0; self.peer_count],
+        });
+
+        // commit a no-op entry to learn which entries are committed at the start of the term
+        // (§8), which in turn resolves pending proposals left by previous leaders
+        self.log.push(Entry {
+            term: self.current_term,
+            command: None,
+        });
+        self.persist();
+        self.advance_commit_index();
+
+        self.heartbeat();
+
+        let handle = ctx.run_interval(Duration::from_millis(HEARTBEAT_INTERVAL), |raft, _ctx| {
+            raft.heartbeat();
+        });
+
+        self.timeout = Some(handle);
+    }
+
+    fn heartbeat(&self) {
+        for peer in self.peers() {
+            self.replicate_to(peer);
+        }
+    }
+
+    /// Send all entries the peer may be missing, or an empty heartbeat if there is none. Peers
+    /// missing compacted entries receive the snapshot instead.
+    fn replicate_to(&self, peer: NodeId) {
+        if let Role::Leader(progress) = &self.role {
+            let next_index = progress.next_index[peer];
+            if next_index <= self.log.snapshot_index() {
+                self.send(
+                    peer,
+                    InstallSnapshotArgs {
+                        term: self.current_term,
+                        leader_id: self.me,
+                        snapshot: self.snapshot.clone(),
+                    },
+                );
+                return;
+            }
+
+            let prev_log_index = next_index - 1;
+            let prev_log_term = self
+                .log
+                .term_at(prev_log_index)
+                .expect("next index must be within the log");
+
+            self.send(
+                peer,
+                AppendEntriesArgs {
+                    term: self.current_term,
+                    leader_id: self.me,
+                    prev_log_index,
+                    prev_log_term,
+                    entries: self.log.entries_from(next_index),
+                    leader_commit: self.commit_index,
+                },
+            );
+        }
+    }
+
+    /// Commit the highest index replicated on a majority of nodes, given the entry at that index
+    /// was created in the current term (§5.4.2).
+    fn advance_commit_index(&mut self) {
+        if let Role::Leader(progress) = &self.role {
+            let mut matched = progress.match_index.clone();
+            matched[self.me] = self.log.last_index();
+            matched.sort_unstable();
+            let n = matched[self.peer_count - self.majority()];
+
+            if n > self.commit_index && self.log.term_at(n) == Some(self.current_term) {
+                self.commit_index = n;
+                self.apply();
+            }
+        }
+    }
+
+    fn apply(&mut self) {
+        while self.last_applied < self.commit_index {
+            self.last_applied += 1;
+            let index = self.last_applied;
+            let entry = self.log.get(index).expect("committed entry missing");
+
+            info!(
+                "Node {} applied entry {} of term {}",
+                self.me, index, entry.term
+            );
+
+            let output = match &entry.command {
+                Some(command) => self.state_machine.apply(index, command),
+                None => Vec::new(),
+            };
+
+            if let Some(proposal) = self.pending.remove(&index) {
+                let result = if proposal.term == entry.term {
+                    Ok(Committed { index, output })
+                } else {
+                    Err(ProposeError::Overwritten)
+                };
+                // the client may have given up waiting
+                let _ = proposal.reply.send(result);
+            }
+        }
+
+        if self.last_applied - self.log.snapshot_index() >= self.snapshot_threshold as u64 {
+            self.take_snapshot();
+        }
+    }
+
+    /// Capture the state machine and discard all applied entries from the log (§7).
+    fn take_snapshot(&mut self) {
+        let index = self.last_applied;
+        info!("Node {} took a snapshot at entry {}", self.me, index);
+
+        self.log.compact(index);
+        self.snapshot = Snapshot {
+            last_index: index,
+            last_term: self.log.snapshot_term(),
+            data: self.state_machine.snapshot(),
+        };
+        self.persist_snapshot();
+    }
+}
+
+impl Actor for Raft {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.reset_timeout(ctx);
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct RequestVoteArgs {
+    term: u64,
+    candidate_id: NodeId,
+    last_log_index: u64,
+    last_log_term: u64,
+}
+
+impl Handler<RequestVoteArgs> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: RequestVoteArgs, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        let vote_granted = self.current_term == msg.term
+            && (self.voted_for.is_none() || self.voted_for == Some(msg.candidate_id))
+            && self
+                .log
+                .is_up_to_date(msg.last_log_term, msg.last_log_index);
+
+        if vote_granted {
+            info!("Node {} voted for node {}", self.me, msg.candidate_id);
+
+            self.voted_for = Some(msg.candidate_id);
+            self.persist();
+            self.observe(Event::Vote {
+                node: self.me,
+                term: self.current_term,
+                candidate: msg.candidate_id,
+            });
+            self.reset_timeout(ctx);
+        }
+
+        self.send(
+            msg.candidate_id,
+            RequestVoteReply {
+                from: self.me,
+                term: self.current_term,
+                vote_granted,
+            },
+        );
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct RequestVoteReply {
+    from: NodeId,
+    term: u64,
+    vote_granted: bool,
+}
+
+impl Handler<RequestVoteReply> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: RequestVoteReply, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        if msg.term != self.current_term {
+            return;
+        }
+
+        if let Role::Candidate(votes) = &mut self.role {
+            if msg.vote_granted {
+                votes[msg.from] = true;
+            }
+        }
+
+        self.count_votes(ctx);
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct AppendEntriesArgs {
+    term: u64,
+    leader_id: NodeId,
+    prev_log_index: u64,
+    prev_log_term: u64,
+    entries: Vec<Entry>,
+    leader_commit: u64,
+}
+
+impl Handler<AppendEntriesArgs> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: AppendEntriesArgs, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        let mut success = false;
+        let mut index = self.log.last_index();
+
+        if msg.term == self.current_term {
+            match self.role {
+                Role::Follower => (),
+                Role::Candidate(..) => {
+                    self.role = Role::Follower;
+                }
+                Role::Leader(..) => {
+                    unreachable!("Multiple leader in the same term");
+                }
+            }
+
+            self.leader_id = Some(msg.leader_id);
+            self.reset_timeout(ctx);
+
+            // compacted entries are committed, hence match the leader's log
+            if msg.prev_log_index < self.log.snapshot_index()
+                || self.log.term_at(msg.prev_log_index) == Some(msg.prev_log_term)
+            {
+                success = true;
+                index =
+                    (msg.prev_log_index + msg.entries.len() as u64).max(self.log.snapshot_index());
+                if !msg.entries.is_empty() {
+                    self.log.merge(msg.prev_log_index, msg.entries);
+                    self.persist();
+                }
+
+                let commit_index = msg.leader_commit.min(index);
+                if commit_index > self.commit_index {
+                    self.commit_index = commit_index;
+                    self.apply();
+                }
+            }
+        }
+
+        self.send(
+            msg.leader_id,
+            AppendEntriesReply {
+                from: self.me,
+                term: self.current_term,
+                success,
+                index,
+            },
+        );
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct AppendEntriesReply {
+    from: NodeId,
+    term: u64,
+    success: bool,
+    /// On success, the index of the last entry known to match the leader's log. On failure, the
+    /// last index of the follower's log, so the leader may skip entries the follower never had.
+    index: u64,
+}
+
+impl Handler<AppendEntriesReply> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: AppendEntriesReply, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        if msg.term != self.current_term {
+            return;
+        }
+
+        if let Role::Leader(progress) = &mut self.role {
+            let peer = msg.from;
+            if msg.success {
+                // replies may arrive out of order, never move backward
+                progress.match_index[peer] = progress.match_index[peer].max(msg.index);
+                progress.next_index[peer] = progress.next_index[peer].max(msg.index + 1);
+            } else {
+                let next_index = progress.next_index[peer] - 1;
+                progress.next_index[peer] = next_index.min(msg.index + 1).max(1);
+            }
+
+            self.advance_commit_index();
+            // AppendEntries RPCs carry all entries to the end of the log and new entries are sent
+            // as soon as they are proposed, only a rejection calls for an immediate retry
+            if !msg.success {
+                self.replicate_to(peer);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct InstallSnapshotArgs {
+    term: u64,
+    leader_id: NodeId,
+    snapshot: Snapshot,
+}
+
+impl Handler<InstallSnapshotArgs> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: InstallSnapshotArgs, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        if msg.term == self.current_term {
+            if let Role::Candidate(..) = self.role {
+                self.role = Role::Follower;
+            }
+            self.leader_id = Some(msg.leader_id);
+            self.reset_timeout(ctx);
+
+            // a stale snapshot would roll back applied entries
+            let snapshot = msg.snapshot;
+            if snapshot.last_index > self.commit_index {
+                info!(
+                    "Node {} installed a snapshot at entry {}",
+                    self.me, snapshot.last_index
+                );
+
+                self.log.install(snapshot.last_index, snapshot.last_term);
+                self.state_machine.restore(&snapshot.data);
+                self.commit_index = snapshot.last_index;
+                self.last_applied = snapshot.last_index;
+                self.snapshot = snapshot;
+                self.persist_snapshot();
+
+                let covered: Vec<u64> = self
+                    .pending
+                    .keys()
+                    .copied()
+                    .filter(|&index| index <= self.last_applied)
+                    .collect();
+                for index in covered {
+                    let proposal = self.pending.remove(&index).unwrap();
+                    let _ = proposal.reply.send(Err(ProposeError::Compacted));
+                }
+            }
+        }
+
+        self.send(
+            msg.leader_id,
+            InstallSnapshotReply {
+                from: self.me,
+                term: self.current_term,
+                last_index: self.log.snapshot_index(),
+            },
+        );
+    }
+}
+
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct InstallSnapshotReply {
+    from: NodeId,
+    term: u64,
+    /// The last entry covered by the follower's snapshot, which matches the leader's log.
+    last_index: u64,
+}
+
+impl Handler<InstallSnapshotReply> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, msg: InstallSnapshotReply, ctx: &mut Self::Context) -> Self::Result {
+        self.update_term(msg.term, ctx);
+
+        if msg.term != self.current_term {
+            return;
+        }
+
+        let last_index = self.log.last_index();
+        if let Role::Leader(progress) = &mut self.role {
+            let peer = msg.from;
+            progress.match_index[peer] = progress.match_index[peer].max(msg.last_index);
+            progress.next_index[peer] = progress.next_index[peer].max(msg.last_index + 1);
+            let lagging = progress.next_index[peer] <= last_index;
+
+            self.advance_commit_index();
+            if lagging {
+                self.replicate_to(peer);
+            }
+        }
+    }
+}
+
+/// Client request to append a command to the replicated log. Resolves to the index of the entry
+/// and the output of the state machine once it's committed.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Committed, ProposeError>")]
+pub struct Propose(pub Command);
+
+impl Handler<Propose> for Raft {
+    type Result = ResponseFuture<Result<Committed, ProposeError>>;
+
+    fn handle(&mut self, msg: Propose, _ctx: &mut Self::Context) -> Self::Result {
+        if !matches!(self.role, Role::Leader(..)) {
+            let leader = self.leader_id;
+            return Box::pin(async move { Err(ProposeError::NotLeader(leader)) });
+        }
+
+        let term = self.current_term;
+        let index = self.log.push(Entry {
+            term,
+            command: Some(msg.0),
+        });
+
+        info!("Node {} appended entry {} of term {}", self.me, index, term);
+
+        self.persist();
+
+        let (reply, receiver) = oneshot::channel();
+        if let Some(stale) = self.pending.insert(index, Proposal { term, reply }) {
+            // left at the same index by a previous term, can no longer be committed
+            let _ = stale.reply.send(Err(ProposeError::Overwritten));
+        }
+
+        self.heartbeat();
+        self.advance_commit_index();
+
+        // the sender is dropped only if the node crashed
+        Box::pin(async move { receiver.await.unwrap_or(Err(ProposeError::Stopped)) })
+    }
+}
+
+/// A snapshot of a node's state, for observation only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub term: u64,
+    pub leader: bool,
+    pub commit_index: u64,
+    pub last_index: u64,
+    /// The last entry compacted into the snapshot.
+    pub snapshot_index: u64,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "Status")]
+pub struct GetStatus;
+
+impl Handler<GetStatus> for Raft {
+    type Result = MessageResult<GetStatus>;
+
+    fn handle(&mut self, _msg: GetStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(Status {
+            term: self.current_term,
+            leader: matches!(self.role, Role::Leader(..)),
+            commit_index: self.commit_index,
+            last_index: self.log.last_index(),
+            snapshot_index: self.log.snapshot_index(),
+        })
+    }
+}
+
+/// Stop the node as if it crashed, volatile states are lost.
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Stop;
+
+impl Handler<Stop> for Raft {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Stop, ctx: &mut Self::Context) -> Self::Result {
+        info!("Node {} crashed", self.me);
+        ctx.stop();
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// State machine replicated by Raft, committed commands are applied to it in log order.
+pub trait StateMachine: Send {
+    /// Apply the command of the committed entry at `index`, the output is passed back to the
+    /// client that proposed the command.
+    fn apply(&mut self, index: u64, command: &[u8]) -> Vec<u8>;
+
+    /// Serialize the state after the last applied command.
+    fn snapshot(&self) -> Vec<u8>;
+
+    /// Replace the state by a snapshot taken by [`StateMachine::snapshot`], possibly on another
+    /// node.
+    fn restore(&mut self, data: &[u8]);
+}
+
+/// A state machine that ignores all commands, the default of the hub.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Noop;
+
+impl StateMachine for Noop {
+    fn apply(&mut self, _: u64, _: &[u8]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn restore(&mut self, _: &[u8]) {}
+}
+
+/// State machine snapshot replacing all log entries up to and including `last_index` (§7).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub last_index: u64,
+    pub last_term: u64,
+    pub data: Vec<u8>,
+}
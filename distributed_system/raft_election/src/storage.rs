@@ -0,0 +1,209 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::entry::Log;
+use crate::state_machine::Snapshot;
+use crate::NodeId;
+
+/// States a node must persist before responding to any RPC (Figure 2).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HardState {
+    pub current_term: u64,
+    pub voted_for: Option<NodeId>,
+    pub log: Log,
+}
+
+/// Stable storage of a Raft node, survives crashes of the node.
+pub trait Storage: Send {
+    /// Read the last saved state, None if nothing was ever saved.
+    fn load(&mut self) -> io::Result<Option<HardState>>;
+
+    /// Read the last saved snapshot, None if nothing was ever compacted.
+    fn load_snapshot(&mut self) -> io::Result<Option<Snapshot>>;
+
+    /// Replace the saved state, must be durable when the call returns.
+    fn save(&mut self, state: &HardState) -> io::Result<()>;
+
+    /// Replace both the saved snapshot and the state whose log was compacted by it. A crash in the
+    /// middle may leave the new snapshot next to the old state but never the other way around, the
+    /// node discards log entries covered by the snapshot when it restarts.
+    fn save_snapshot(&mut self, state: &HardState, snapshot: &Snapshot) -> io::Result<()>;
+}
+
+/// An in-memory fake of stable storage, clones share the same state. Keep a clone outside of the
+/// node to let the state survive a simulated crash.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<Option<HardState>>>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> io::Result<Option<HardState>> {
+        Ok(self.state.lock().unwrap().clone())
+    }
+
+    fn load_snapshot(&mut self) -> io::Result<Option<Snapshot>> {
+        Ok(self.snapshot.lock().unwrap().clone())
+    }
+
+    fn save(&mut self, state: &HardState) -> io::Result<()> {
+        *self.state.lock().unwrap() = Some(state.clone());
+        Ok(())
+    }
+
+    fn save_snapshot(&mut self, state: &HardState, snapshot: &Snapshot) -> io::Result<()> {
+        *self.snapshot.lock().unwrap() = Some(snapshot.clone());
+        self.save(state)
+    }
+}
+
+/// Stable storage backed by a file, with the snapshot in a second file next to it. Both are written
+/// to a temporary file first then renamed over the old one, so a crash in the middle of saving
+/// never leaves a torn state behind.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".snapshot")
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_owned().into_os_string();
+    path.push(suffix);
+    path.into()
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match fs::read(path) {
+        Ok(bytes) => bincode::deserialize(&bytes).map(Some).map_err(invalid_data),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(value).map_err(invalid_data)?;
+    let temp_path = with_suffix(path, ".tmp");
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+impl Storage for FileStorage {
+    fn load(&mut self) -> io::Result<Option<HardState>> {
+        read(&self.path)
+    }
+
+    fn load_snapshot(&mut self) -> io::Result<Option<Snapshot>> {
+        read(&self.snapshot_path())
+    }
+
+    fn save(&mut self, state: &HardState) -> io::Result<()> {
+        write(&self.path, state)
+    }
+
+    fn save_snapshot(&mut self, state: &HardState, snapshot: &Snapshot) -> io::Result<()> {
+        write(&self.snapshot_path(), snapshot)?;
+        write(&self.path, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Entry;
+
+    fn state() -> HardState {
+        let mut log = Log::new();
+        log.push(Entry {
+            term: 1,
+            command: None,
+        });
+        log.push(Entry {
+            term: 2,
+            command: Some(b"command".to_vec()),
+        });
+
+        HardState {
+            current_term: 3,
+            voted_for: Some(1),
+            log,
+        }
+    }
+
+    fn assert_same(a: &HardState, b: &HardState) {
+        assert_eq!(a.current_term, b.current_term);
+        assert_eq!(a.voted_for, b.voted_for);
+        assert_eq!(a.log.last_index(), b.log.last_index());
+        assert_eq!(
+            a.log.entries_from(a.log.snapshot_index() + 1),
+            b.log.entries_from(b.log.snapshot_index() + 1)
+        );
+    }
+
+    #[test]
+    fn memory_storage_shared_by_clones() {
+        let mut storage = MemoryStorage::new();
+        let mut clone = storage.clone();
+        assert!(storage.load().unwrap().is_none());
+
+        storage.save(&state()).unwrap();
+        assert_same(&clone.load().unwrap().unwrap(), &state());
+    }
+
+    #[test]
+    fn file_storage_round_trip() {
+        let path = std::env::temp_dir().join(format!("raft-storage-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut storage = FileStorage::new(&path);
+        assert!(storage.load().unwrap().is_none());
+
+        storage.save(&HardState::default()).unwrap();
+        storage.save(&state()).unwrap();
+        assert_same(&FileStorage::new(&path).load().unwrap().unwrap(), &state());
+        assert!(storage.load_snapshot().unwrap().is_none());
+
+        let mut compacted = state();
+        compacted.log.compact(1);
+        let snapshot = Snapshot {
+            last_index: 1,
+            last_term: 1,
+            data: b"state".to_vec(),
+        };
+        storage.save_snapshot(&compacted, &snapshot).unwrap();
+
+        let mut reopened = FileStorage::new(&path);
+        assert_eq!(reopened.load_snapshot().unwrap(), Some(snapshot));
+        assert_eq!(reopened.load().unwrap().unwrap().log.snapshot_index(), 1);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(storage.snapshot_path()).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! Randomized clusters under the hub's loss, delay, partition and crash model, checked for
+//! election safety, term monotonicity and vote uniqueness. Logs are compacted aggressively so that
+//! snapshots are installed across crashes and partitions as well.
+//!
+//! RAFT_FUZZ_RUNS overrides the number of clusters, RAFT_SEED replays a single failing cluster.
+
+use std::env;
+use std::time::Duration;
+
+use actix::Actor;
+use playground::{check, simulate, Fault, GetTrace, Link, MessageHub, Network, Trace, SLOW_MOTION};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const DEFAULT_RUNS: u64 = 1000;
+const DURATION: Duration = Duration::from_secs(60);
+
+fn random_link(rng: &mut StdRng) -> Link {
+    let min_delay = rng.gen_range(5..100) * SLOW_MOTION;
+    let max_delay = min_delay + rng.gen_range(0..200) * SLOW_MOTION;
+
+    Link::new(
+        rng.gen_range(0.0..0.3),
+        rng.gen_range(0.0..0.2),
+        rng.gen_range(0.0..=1.0),
+    )
+    .delay(
+        Duration::from_millis(min_delay),
+        Duration::from_millis(max_delay),
+    )
+}
+
+fn random_partition(rng: &mut StdRng, peer_count: usize) -> Fault {
+    let mut nodes: Vec<_> = (0..peer_count).collect();
+    nodes.shuffle(rng);
+
+    let mut groups = vec![vec![]; rng.gen_range(2..=3)];
+    for node in nodes {
+        let group = rng.gen_range(0..groups.len());
+        groups[group].push(node);
+    }
+
+    Fault::Partition(groups)
+}
+
+/// A random cluster size, snapshot threshold and network, everything derived from the seed. Only
+/// no-op entries are committed, tiny thresholds make sure logs are compacted nonetheless.
+fn random_cluster(seed: u64) -> (usize, usize, Network) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let peer_count = rng.gen_range(3..=7);
+    let snapshot_threshold = rng.gen_range(1..=3);
+    let mut network = Network::new(seed).default_link(random_link(&mut rng));
+
+    for _ in 0..rng.gen_range(0..3) {
+        let from = rng.gen_range(0..peer_count);
+        let to = rng.gen_range(0..peer_count);
+        network = network.link(from, to, random_link(&mut rng));
+    }
+
+    for _ in 0..rng.gen_range(0..6) {
+        let at = Duration::from_millis(rng.gen_range(0..DURATION.as_millis() as u64));
+        let fault = match rng.gen_range(0..4) {
+            0 => random_partition(&mut rng, peer_count),
+            1 => Fault::Heal,
+            2 => Fault::Crash(rng.gen_range(0..peer_count)),
+            _ => Fault::Restart(rng.gen_range(0..peer_count)),
+        };
+        network = network.at(at, fault);
+    }
+
+    (peer_count, snapshot_threshold, network)
+}
+
+fn run(seed: u64) -> Trace {
+    let (peer_count, snapshot_threshold, network) = random_cluster(seed);
+
+    simulate(|| async move {
+        let hub = MessageHub::new(peer_count, network)
+            .snapshot_threshold(snapshot_threshold)
+            .traced()
+            .start();
+        actix::clock::sleep(DURATION).await;
+        hub.send(GetTrace).await.unwrap()
+    })
+}
+
+fn fuzz(seed: u64) {
+    let trace = run(seed);
+
+    if let Err(violation) = check(&trace) {
+        let mut report = format!(
+            "seed {} ({:?}): {}\n",
+            seed,
+            random_cluster(seed),
+            violation
+        );
+        for (i, (at, event)) in violation.minimal_trace(&trace) {
+            report += &format!("  #{:<6} {:>10.3}s  {}\n", i, at.as_secs_f64(), event);
+        }
+        report += &format!("replay with RAFT_SEED={}", seed);
+
+        panic!("{}", report);
+    }
+}
+
+#[test]
+fn fuzz_election_safety() {
+    if let Some(seed) = env::var("RAFT_SEED").ok().and_then(|s| s.parse().ok()) {
+        fuzz(seed);
+        return;
+    }
+
+    let runs = env::var("RAFT_FUZZ_RUNS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RUNS);
+
+    for seed in 0..runs {
+        fuzz(seed);
+    }
+}
+
+#[test]
+fn replay_is_exact() {
+    for seed in 2021..2031 {
+        assert_eq!(run(seed), run(seed));
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use actix::Actor;
+use playground::{
+    is_linearizable, simulate, Fault, History, KvClient, KvModel, KvStore, Link, MessageHub,
+    Network, Op, Operation,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const PEER_COUNT: usize = 5;
+const CLIENTS: u64 = 5;
+const OPS_PER_CLIENT: usize = 30;
+const KEYS: [&str; 3] = ["a", "b", "c"];
+
+/// Concurrent clients issuing random operations against a cluster suffering from `network`,
+/// returns the history and the final value of every key.
+fn run(network: Network, snapshot_threshold: usize) -> (History, Vec<String>) {
+    simulate(|| async move {
+        let hub = MessageHub::new(PEER_COUNT, network)
+            .state_machine(|_| Box::new(KvStore::new()))
+            .snapshot_threshold(snapshot_threshold)
+            .start();
+        let history = History::default();
+
+        let clients: Vec<_> = (0..CLIENTS)
+            .map(|client_id| {
+                let mut client =
+                    KvClient::new(hub.clone(), PEER_COUNT, client_id).recorded(history.clone());
+                actix::spawn(async move {
+                    let mut rng = StdRng::seed_from_u64(client_id);
+                    for i in 0..OPS_PER_CLIENT {
+                        let key = KEYS[rng.gen_range(0..KEYS.len())];
+                        let value = format!("{}.{};", client_id, i);
+                        let op = match rng.gen_range(0..3) {
+                            0 => Op::Get(key.to_string()),
+                            1 => Op::Put(key.to_string(), value),
+                            _ => Op::Append(key.to_string(), value),
+                        };
+                        client.request(op).await;
+                    }
+                })
+            })
+            .collect();
+
+        for client in clients {
+            client.await.unwrap();
+        }
+
+        let mut client = KvClient::new(hub, PEER_COUNT, CLIENTS);
+        let mut values = vec![];
+        for key in KEYS.iter() {
+            values.push(client.get(key).await);
+        }
+
+        (history, values)
+    })
+}
+
+#[test]
+fn linearizable_over_lossy_network() {
+    let network = Network::new(11).default_link(Link::new(0.1, 0.1, 1.0));
+    let (history, _) = run(network, 1000);
+
+    let history = history.lock().unwrap();
+    assert_eq!(history.len(), CLIENTS as usize * OPS_PER_CLIENT);
+    assert!(is_linearizable(&KvModel, &history));
+}
+
+#[test]
+fn linearizable_under_partitions_and_crashes() {
+    let network = Network::new(12)
+        .default_link(Link::new(0.05, 0.05, 1.0))
+        .at(
+            Duration::from_secs(20),
+            Fault::Partition(vec![vec![0, 1], vec![2, 3, 4]]),
+        )
+        .at(Duration::from_secs(50), Fault::Crash(3))
+        .at(Duration::from_secs(60), Fault::Heal)
+        .at(Duration::from_secs(70), Fault::Crash(0))
+        .at(Duration::from_secs(90), Fault::Restart(3))
+        .at(Duration::from_secs(120), Fault::Restart(0));
+    // compact frequently so that restarted nodes catch up with snapshots
+    let (history, _) = run(network, 5);
+
+    let history = history.lock().unwrap();
+    assert!(is_linearizable(&KvModel, &history));
+}
+
+#[test]
+fn appends_applied_exactly_once() {
+    simulate(|| async {
+        // duplicated and lost messages make clients retry a lot
+        let network = Network::new(13).default_link(Link::new(0.2, 0.2, 1.0));
+        let hub = MessageHub::new(PEER_COUNT, network)
+            .state_machine(|_| Box::new(KvStore::new()))
+            .start();
+
+        let clients: Vec<_> = (0..CLIENTS)
+            .map(|client_id| {
+                let mut client = KvClient::new(hub.clone(), PEER_COUNT, client_id);
+                actix::spawn(async move {
+                    for i in 0..10 {
+                        client.append("x", &format!("{}.{};", client_id, i)).await;
+                    }
+                })
+            })
+            .collect();
+
+        for client in clients {
+            client.await.unwrap();
+        }
+
+        let value = KvClient::new(hub, PEER_COUNT, CLIENTS).get("x").await;
+        for client_id in 0..CLIENTS {
+            let appended: Vec<_> = value
+                .split(';')
+                .filter(|token| token.starts_with(&format!("{}.", client_id)))
+                .collect();
+            let expected: Vec<_> = (0..10).map(|i| format!("{}.{}", client_id, i)).collect();
+            assert_eq!(appended, expected);
+        }
+    });
+}
+
+#[test]
+fn checker_rejects_lost_writes() {
+    let network = Network::new(11).default_link(Link::new(0.1, 0.1, 1.0));
+    let (history, values) = run(network, 1000);
+
+    let mut history = history.lock().unwrap().clone();
+    let last = history.iter().map(|op| op.ret).max().unwrap();
+    let key = KEYS
+        .iter()
+        .zip(&values)
+        .find(|(_, value)| !value.is_empty())
+        .map(|(key, _)| key.to_string())
+        .unwrap();
+
+    // a read after every operation returned that misses the latest write
+    history.push(Operation {
+        client_id: CLIENTS,
+        input: Op::Get(key),
+        call: last + Duration::from_millis(1),
+        output: "stale".to_string(),
+        ret: last + Duration::from_millis(2),
+    });
+    assert!(!is_linearizable(&KvModel, &history));
+}
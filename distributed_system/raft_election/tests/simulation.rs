@@ -0,0 +1,270 @@
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix::clock::Instant;
+use actix::{Actor, Addr};
+use playground::{
+    simulate, Client, Fault, GetStats, GetStatus, Link, MessageHub, Network, Propose, StateMachine,
+    Stats, Status,
+};
+
+const PEER_COUNT: usize = 5;
+
+/// None if the node crashed.
+async fn status(hub: &Addr<MessageHub>, id: usize) -> Option<Status> {
+    hub.send(Client(GetStatus, id)).await.unwrap().ok()
+}
+
+async fn statuses(hub: &Addr<MessageHub>) -> Vec<Status> {
+    let mut statuses = Vec::new();
+    for id in 0..PEER_COUNT {
+        statuses.push(status(hub, id).await.unwrap());
+    }
+    statuses
+}
+
+fn leaders(statuses: &[Status]) -> Vec<usize> {
+    (0..statuses.len())
+        .filter(|&id| statuses[id].leader)
+        .collect()
+}
+
+fn run(network: Network, duration: Duration) -> (Stats, Vec<Status>) {
+    simulate(|| async move {
+        let hub = MessageHub::new(PEER_COUNT, network).start();
+        actix::clock::sleep(duration).await;
+        (hub.send(GetStats).await.unwrap(), statuses(&hub).await)
+    })
+}
+
+#[test]
+fn same_seed_same_run() {
+    let network = Network::new(42).default_link(Link::new(0.2, 0.1, 0.5));
+    let duration = Duration::from_secs(120);
+
+    let first = run(network.clone(), duration);
+    let second = run(network, duration);
+    assert_eq!(first, second);
+
+    let other = run(
+        Network::new(43).default_link(Link::new(0.2, 0.1, 0.5)),
+        duration,
+    );
+    assert_ne!(first.0, other.0);
+}
+
+#[test]
+fn scripted_partition_and_heal() {
+    simulate(|| async {
+        let network = Network::new(7).default_link(Link::reliable());
+        let hub = MessageHub::new(PEER_COUNT, network).start();
+
+        actix::clock::sleep(Duration::from_secs(30)).await;
+        let before = statuses(&hub).await;
+        let old_leaders = leaders(&before);
+        assert_eq!(old_leaders.len(), 1);
+        let old_leader = old_leaders[0];
+
+        let majority = (0..PEER_COUNT).filter(|&id| id != old_leader).collect();
+        hub.send(Fault::Partition(vec![majority])).await.unwrap();
+        actix::clock::sleep(Duration::from_secs(30)).await;
+
+        let partitioned = statuses(&hub).await;
+        let new_leaders = leaders(&partitioned);
+        // the isolated leader never learns about the new term
+        assert_eq!(new_leaders.len(), 2);
+        assert!(new_leaders.contains(&old_leader));
+        assert!(new_leaders
+            .iter()
+            .all(|&id| id == old_leader || partitioned[id].term > before[old_leader].term));
+
+        hub.send(Fault::Heal).await.unwrap();
+        actix::clock::sleep(Duration::from_secs(30)).await;
+
+        let healed = statuses(&hub).await;
+        assert_eq!(leaders(&healed).len(), 1);
+        assert!(!healed[old_leader].leader);
+    });
+}
+
+#[test]
+fn proposals_commit_over_lossy_network() {
+    simulate(|| async {
+        let network = Network::new(1)
+            .default_link(Link::new(0.1, 0.05, 1.0))
+            .at(
+                Duration::from_secs(60),
+                Fault::Partition(vec![vec![0, 1], vec![2, 3, 4]]),
+            )
+            .at(Duration::from_secs(90), Fault::Heal);
+        let start = Instant::now();
+        let hub = MessageHub::new(PEER_COUNT, network).start();
+
+        let mut committed = 0;
+        while committed < 20 {
+            actix::clock::sleep(Duration::from_secs(1)).await;
+            let status = statuses(&hub).await;
+            if let Some(&leader) = leaders(&status).last() {
+                let command = format!("command {}", committed).into_bytes();
+                let result = hub.send(Client(Propose(command), leader)).await.unwrap();
+                if matches!(result, Ok(Ok(_))) {
+                    committed += 1;
+                }
+            }
+        }
+
+        // long after the partition healed
+        actix::clock::sleep_until(start + Duration::from_secs(150)).await;
+        let status = statuses(&hub).await;
+        let commit_index = status.iter().map(|s| s.commit_index).max().unwrap();
+        // one no-op entry per term besides the commands
+        assert!(commit_index > 20);
+        assert!(status.iter().all(|s| s.commit_index == commit_index));
+    });
+}
+
+#[test]
+fn crashed_leader_recovers_from_storage() {
+    simulate(|| async {
+        let network = Network::new(3).default_link(Link::new(0.05, 0.0, 1.0));
+        let hub = MessageHub::new(PEER_COUNT, network).start();
+
+        actix::clock::sleep(Duration::from_secs(30)).await;
+        let leader = leaders(&statuses(&hub).await)[0];
+        for i in 0..5 {
+            let command = format!("command {}", i).into_bytes();
+            let result = hub.send(Client(Propose(command), leader)).await.unwrap();
+            assert!(matches!(result, Ok(Ok(_))));
+        }
+        let before = status(&hub, leader).await.unwrap();
+
+        hub.send(Fault::Crash(leader)).await.unwrap();
+        assert!(status(&hub, leader).await.is_none());
+
+        actix::clock::sleep(Duration::from_secs(30)).await;
+        let mut new_leaders = vec![];
+        for id in (0..PEER_COUNT).filter(|&id| id != leader) {
+            if status(&hub, id).await.unwrap().leader {
+                new_leaders.push(id);
+            }
+        }
+        assert_eq!(new_leaders.len(), 1);
+
+        hub.send(Fault::Restart(leader)).await.unwrap();
+        actix::clock::sleep(Duration::from_millis(10)).await;
+        let restarted = status(&hub, leader).await.unwrap();
+        // persistent states survived the crash
+        assert!(!restarted.leader);
+        assert!(restarted.term >= before.term);
+        assert!(restarted.last_index >= before.last_index);
+
+        actix::clock::sleep(Duration::from_secs(30)).await;
+        let recovered = statuses(&hub).await;
+        let commit_index = recovered.iter().map(|s| s.commit_index).max().unwrap();
+        assert!(commit_index > before.commit_index);
+        assert!(recovered.iter().all(|s| s.commit_index == commit_index));
+    });
+}
+
+/// Folds every applied command into a hash, published per node so that tests can compare the
+/// state machines.
+struct Digest {
+    id: usize,
+    state: Arc<Mutex<Vec<(u64, u64)>>>,
+}
+
+impl Digest {
+    fn update(&self, index: u64, digest: u64) {
+        self.state.lock().unwrap()[self.id] = (index, digest);
+    }
+}
+
+impl StateMachine for Digest {
+    fn apply(&mut self, index: u64, command: &[u8]) -> Vec<u8> {
+        let (_, mut digest) = self.state.lock().unwrap()[self.id];
+        // FNV-1a
+        for &byte in command {
+            digest = (digest ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+        }
+        self.update(index, digest);
+        Vec::new()
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        let (index, digest) = self.state.lock().unwrap()[self.id];
+        [index.to_le_bytes(), digest.to_le_bytes()].concat()
+    }
+
+    fn restore(&mut self, data: &[u8]) {
+        let index = u64::from_le_bytes(data[..8].try_into().unwrap());
+        let digest = u64::from_le_bytes(data[8..].try_into().unwrap());
+        self.update(index, digest);
+    }
+}
+
+#[test]
+fn lagging_node_catches_up_with_snapshot() {
+    const THRESHOLD: usize = 10;
+
+    simulate(|| async {
+        let digests = Arc::new(Mutex::new(vec![(0, 0); PEER_COUNT]));
+        let shared = digests.clone();
+        let network = Network::new(5).default_link(Link::new(0.05, 0.0, 1.0));
+        let hub = MessageHub::new(PEER_COUNT, network)
+            .state_machine(move |id| {
+                // a restarted node starts from scratch until it restores a snapshot
+                shared.lock().unwrap()[id] = (0, 0);
+                Box::new(Digest {
+                    id,
+                    state: shared.clone(),
+                })
+            })
+            .snapshot_threshold(THRESHOLD)
+            .start();
+
+        actix::clock::sleep(Duration::from_secs(30)).await;
+        let leader = leaders(&statuses(&hub).await)[0];
+        let victim = (leader + 1) % PEER_COUNT;
+        hub.send(Fault::Crash(victim)).await.unwrap();
+
+        let mut committed = 0;
+        while committed < 5 * THRESHOLD {
+            let mut leader = None;
+            for id in (0..PEER_COUNT).filter(|&id| id != victim) {
+                if status(&hub, id).await.unwrap().leader {
+                    leader = Some(id);
+                }
+            }
+
+            if let Some(leader) = leader {
+                let command = format!("command {}", committed).into_bytes();
+                let result = hub.send(Client(Propose(command), leader)).await.unwrap();
+                if matches!(result, Ok(Ok(_))) {
+                    committed += 1;
+                }
+            } else {
+                actix::clock::sleep(Duration::from_secs(1)).await;
+            }
+        }
+
+        for id in (0..PEER_COUNT).filter(|&id| id != victim) {
+            let status = status(&hub, id).await.unwrap();
+            // the log never grows far beyond the threshold
+            assert!(status.snapshot_index > 0);
+            assert!(status.last_index - status.snapshot_index <= 2 * THRESHOLD as u64);
+        }
+
+        hub.send(Fault::Restart(victim)).await.unwrap();
+        actix::clock::sleep(Duration::from_secs(30)).await;
+
+        let recovered = statuses(&hub).await;
+        let commit_index = recovered[leader].commit_index;
+        assert!(recovered.iter().all(|s| s.commit_index == commit_index));
+        // entries the victim missed were compacted long before it restarted
+        assert!(recovered[victim].snapshot_index > 0);
+
+        let digests = digests.lock().unwrap();
+        assert!(digests.iter().all(|&digest| digest == digests[leader]));
+    });
+}
@@ -0,0 +1,119 @@
+//! A cache of answered record sets, each entry expires with the lowest TTL of its records.
+
+use crate::message::{Record, RecordType};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Entries kept at most, expired entries are purged first when the cache is full.
+const MAX_ENTRIES: usize = 1024;
+
+struct Entry {
+    expires: Instant,
+    records: Vec<Record>,
+}
+
+/// Record sets keyed by their lowercase owner name and type.
+#[derive(Default)]
+pub(crate) struct Cache {
+    entries: HashMap<(String, RecordType), Entry>,
+}
+
+fn key(name: &str, rtype: RecordType) -> (String, RecordType) {
+    (name.trim_end_matches('.').to_ascii_lowercase(), rtype)
+}
+
+impl Cache {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached records of a type at a domain name, their TTL lowered by the time spent in cache.
+    pub(crate) fn get(&mut self, name: &str, rtype: RecordType) -> Option<Vec<Record>> {
+        self.get_at(name, rtype, Instant::now())
+    }
+
+    fn get_at(&mut self, name: &str, rtype: RecordType, now: Instant) -> Option<Vec<Record>> {
+        let key = key(name, rtype);
+        let entry = self.entries.get(&key)?;
+        if entry.expires <= now {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        let remaining = (entry.expires - now).as_secs() as u32;
+        let records = entry
+            .records
+            .iter()
+            .map(|record| Record {
+                ttl: remaining,
+                ..record.clone()
+            })
+            .collect();
+        Some(records)
+    }
+
+    /// Cache the answers of a response, grouped into record sets by owner name and type.
+    pub(crate) fn insert(&mut self, answers: &[Record]) {
+        self.insert_at(answers, Instant::now())
+    }
+
+    fn insert_at(&mut self, answers: &[Record], now: Instant) {
+        let mut rrsets: HashMap<(String, RecordType), Vec<Record>> = HashMap::new();
+        for record in answers {
+            rrsets
+                .entry(key(&record.name, record.rtype()))
+                .or_default()
+                .push(record.clone());
+        }
+
+        for (key, records) in rrsets {
+            let ttl = records.iter().map(|record| record.ttl).min().unwrap_or(0);
+            // a TTL of zero means the records must not be cached
+            if ttl == 0 {
+                continue;
+            }
+            if self.entries.len() >= MAX_ENTRIES && !self.entries.contains_key(&key) {
+                self.entries.retain(|_, entry| entry.expires > now);
+                if self.entries.len() >= MAX_ENTRIES {
+                    continue;
+                }
+            }
+
+            let expires = now + Duration::from_secs(u64::from(ttl));
+            self.entries.insert(key, Entry { expires, records });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::RData;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn expiry() {
+        let now = Instant::now();
+        let mut cache = Cache::new();
+        cache.insert_at(
+            &[
+                Record::new("Example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+                Record::new("example.com", 30, RData::A(Ipv4Addr::new(192, 0, 2, 2))),
+                Record::new("example.com", 0, RData::TXT(vec!["volatile".to_string()])),
+            ],
+            now,
+        );
+
+        let records = cache
+            .get_at("EXAMPLE.COM.", RecordType::A, now + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.ttl == 20));
+        assert!(cache.get_at("example.com", RecordType::TXT, now).is_none());
+        assert!(cache
+            .get_at("example.com", RecordType::A, now + Duration::from_secs(30))
+            .is_none());
+    }
+}
@@ -0,0 +1,71 @@
+#![deny(missing_docs)]
+
+//! A DNS stub resolver shared by the mail client and the web proxy.\
+//! Queries are sent over UDP and retried over TCP when the response is truncated, answers are
+//! cached by their TTL and CNAME chains are followed, defined by:\
+//! [https://tools.ietf.org/html/rfc1034](https://tools.ietf.org/html/rfc1034)\
+//! [https://tools.ietf.org/html/rfc1035](https://tools.ietf.org/html/rfc1035)\
+//! A local authoritative [`StubServer`] answers from a fixed set of records for tests.
+
+mod cache;
+pub mod message;
+mod resolver;
+mod server;
+
+pub use message::{Message, Mx, RData, Rcode, Record, RecordType};
+pub use resolver::Resolver;
+pub use server::StubServer;
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, ErrorKind},
+};
+
+/// Crate universal error type.
+#[derive(Debug)]
+pub enum Error {
+    /// A DNS message could not be parsed
+    Malformed,
+    /// A domain name with a label longer than 63 bytes, or longer than 255 bytes in total
+    InvalidName,
+    /// The domain name does not exist (NXDOMAIN)
+    NameNotFound,
+    /// The server could not or would not answer
+    ServerFailure(Rcode),
+    /// A CNAME chain too long to be followed, most likely a loop
+    CnameLoop,
+    /// The server didn't respond in time to any attempt
+    Timeout,
+    /// Error propagated from std::io
+    IOError(io::Error),
+}
+
+use Error::*;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Malformed => write!(f, "malformed DNS message"),
+            InvalidName => write!(f, "invalid domain name"),
+            NameNotFound => write!(f, "domain name not found"),
+            ServerFailure(rcode) => write!(f, "DNS server failure: {:?}", rcode),
+            CnameLoop => write!(f, "CNAME chain too long"),
+            Timeout => write!(f, "DNS server timed out"),
+            IOError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => Timeout,
+            _ => IOError(err),
+        }
+    }
+}
+
+/// Crate universal result type.
+pub type Result<T> = std::result::Result<T, Error>;
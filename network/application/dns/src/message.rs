@@ -0,0 +1,666 @@
+//! DNS messages in wire format, defined by:\
+//! [https://tools.ietf.org/html/rfc1035#section-4](https://tools.ietf.org/html/rfc1035#section-4)\
+//! Domain names are written without a trailing dot, the root is the empty string. Names are
+//! never compressed when encoded, compressed names are understood when parsed.
+
+use crate::{Error, Result};
+use std::{
+    convert::TryFrom,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+/// Size of the fixed message header in bytes.
+const HEADER_LEN: usize = 12;
+/// Longest label in a domain name.
+const MAX_LABEL: usize = 63;
+/// Longest domain name in wire format.
+const MAX_NAME: usize = 255;
+/// The Internet class, the only class supported.
+pub const CLASS_IN: u16 = 1;
+
+/// Types of resource records.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    NS,
+    CNAME,
+    SOA,
+    MX,
+    TXT,
+    AAAA,
+    /// Any other type, kept as its numeric value
+    Other(u16),
+}
+
+impl RecordType {
+    /// The numeric value of the type.
+    pub fn code(self) -> u16 {
+        use RecordType::*;
+        match self {
+            A => 1,
+            NS => 2,
+            CNAME => 5,
+            SOA => 6,
+            MX => 15,
+            TXT => 16,
+            AAAA => 28,
+            Other(code) => code,
+        }
+    }
+
+    /// The type of a numeric value.
+    pub fn from_code(code: u16) -> Self {
+        use RecordType::*;
+        match code {
+            1 => A,
+            2 => NS,
+            5 => CNAME,
+            6 => SOA,
+            15 => MX,
+            16 => TXT,
+            28 => AAAA,
+            _ => Other(code),
+        }
+    }
+}
+
+/// Response codes.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rcode {
+    NoError,
+    FormErr,
+    ServFail,
+    NXDomain,
+    NotImp,
+    Refused,
+    /// Any other code, kept as its numeric value
+    Other(u8),
+}
+
+impl Rcode {
+    fn code(self) -> u8 {
+        use Rcode::*;
+        match self {
+            NoError => 0,
+            FormErr => 1,
+            ServFail => 2,
+            NXDomain => 3,
+            NotImp => 4,
+            Refused => 5,
+            Other(code) => code,
+        }
+    }
+
+    fn from_code(code: u8) -> Self {
+        use Rcode::*;
+        match code {
+            0 => NoError,
+            1 => FormErr,
+            2 => ServFail,
+            3 => NXDomain,
+            4 => NotImp,
+            5 => Refused,
+            _ => Other(code),
+        }
+    }
+}
+
+/// A mail exchange of a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mx {
+    /// Lower values are preferred
+    pub preference: u16,
+    /// Domain name of the mail server
+    pub exchange: String,
+}
+
+/// Data of a resource record, interpreted by its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    /// IPv4 address
+    A(Ipv4Addr),
+    /// Authoritative name server
+    NS(String),
+    /// Canonical name of an alias
+    CNAME(String),
+    /// Start of a zone of authority
+    SOA {
+        /// Primary name server of the zone
+        mname: String,
+        /// Mailbox of the person responsible for the zone
+        rname: String,
+        /// Version of the zone
+        serial: u32,
+        /// Seconds before secondary servers refresh the zone
+        refresh: u32,
+        /// Seconds before a failed refresh is retried
+        retry: u32,
+        /// Seconds before secondary servers stop answering for the zone
+        expire: u32,
+        /// TTL of negative answers
+        minimum: u32,
+    },
+    /// Mail exchange
+    MX(Mx),
+    /// Character strings
+    TXT(Vec<String>),
+    /// IPv6 address
+    AAAA(Ipv6Addr),
+    /// Data of any other type, uninterpreted
+    Other(RecordType, Vec<u8>),
+}
+
+impl RData {
+    /// Type of the record carrying the data.
+    pub fn rtype(&self) -> RecordType {
+        match self {
+            RData::A(..) => RecordType::A,
+            RData::NS(..) => RecordType::NS,
+            RData::CNAME(..) => RecordType::CNAME,
+            RData::SOA { .. } => RecordType::SOA,
+            RData::MX(..) => RecordType::MX,
+            RData::TXT(..) => RecordType::TXT,
+            RData::AAAA(..) => RecordType::AAAA,
+            RData::Other(rtype, _) => *rtype,
+        }
+    }
+}
+
+/// A question for records of a type at a domain name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    /// Domain name queried
+    pub name: String,
+    /// Type of records queried
+    pub rtype: RecordType,
+    /// Class of records queried, always [`CLASS_IN`] in practice
+    pub class: u16,
+}
+
+/// A resource record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Domain name owning the record
+    pub name: String,
+    /// Class of the record, always [`CLASS_IN`] in practice
+    pub class: u16,
+    /// Seconds the record may be cached
+    pub ttl: u32,
+    /// Data of the record
+    pub data: RData,
+}
+
+impl Record {
+    /// A record of the Internet class.
+    pub fn new(name: &str, ttl: u32, data: RData) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_string(),
+            class: CLASS_IN,
+            ttl,
+            data,
+        }
+    }
+
+    /// Type of the record.
+    pub fn rtype(&self) -> RecordType {
+        self.data.rtype()
+    }
+}
+
+/// A DNS query or response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Identifier matching responses to queries
+    pub id: u16,
+    /// Whether the message is a response
+    pub response: bool,
+    /// Kind of query, 0 for a standard query
+    pub opcode: u8,
+    /// Whether the responding server is an authority for the domain name
+    pub authoritative: bool,
+    /// Whether the message was truncated to fit in a UDP datagram
+    pub truncated: bool,
+    /// Whether the client asks the server to resolve the query recursively
+    pub recursion_desired: bool,
+    /// Whether the server supports recursive queries
+    pub recursion_available: bool,
+    /// Outcome of the query
+    pub rcode: Rcode,
+    /// Questions of the query, repeated in the response
+    pub questions: Vec<Question>,
+    /// Records answering the questions
+    pub answers: Vec<Record>,
+    /// Records pointing toward an authority
+    pub authorities: Vec<Record>,
+    /// Records related to the query but not strictly answering it
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    /// A recursive standard query for records of a type at a domain name.
+    pub fn query(id: u16, name: &str, rtype: RecordType) -> Self {
+        Self {
+            id,
+            response: false,
+            opcode: 0,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: true,
+            recursion_available: false,
+            rcode: Rcode::NoError,
+            questions: vec![Question {
+                name: name.trim_end_matches('.').to_string(),
+                rtype,
+                class: CLASS_IN,
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+        }
+    }
+
+    /// An empty response to the query, with the same id and questions.
+    pub fn response_to(query: &Message) -> Self {
+        Self {
+            response: true,
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+            ..query.clone()
+        }
+    }
+
+    /// Encode the message in wire format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        put_u16(&mut buf, self.id);
+        let flags = (self.response as u16) << 15
+            | (u16::from(self.opcode) & 0xf) << 11
+            | (self.authoritative as u16) << 10
+            | (self.truncated as u16) << 9
+            | (self.recursion_desired as u16) << 8
+            | (self.recursion_available as u16) << 7
+            | u16::from(self.rcode.code()) & 0xf;
+        put_u16(&mut buf, flags);
+        for count in &[
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            put_u16(&mut buf, *count as u16);
+        }
+
+        for question in &self.questions {
+            put_name(&mut buf, &question.name)?;
+            put_u16(&mut buf, question.rtype.code());
+            put_u16(&mut buf, question.class);
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            put_record(&mut buf, record)?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Parse a message in wire format.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let mut parser = Parser { buf, pos: 0 };
+        let id = parser.u16()?;
+        let flags = parser.u16()?;
+        let qdcount = parser.u16()?;
+        let ancount = parser.u16()?;
+        let nscount = parser.u16()?;
+        let arcount = parser.u16()?;
+
+        let questions = (0..qdcount)
+            .map(|_| {
+                Ok(Question {
+                    name: parser.name()?,
+                    rtype: RecordType::from_code(parser.u16()?),
+                    class: parser.u16()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let mut records = |count| (0..count).map(|_| parser.record()).collect::<Result<_>>();
+        let answers = records(ancount)?;
+        let authorities = records(nscount)?;
+        let additionals = records(arcount)?;
+
+        Ok(Self {
+            id,
+            response: flags & 0x8000 != 0,
+            opcode: (flags >> 11 & 0xf) as u8,
+            authoritative: flags & 0x0400 != 0,
+            truncated: flags & 0x0200 != 0,
+            recursion_desired: flags & 0x0100 != 0,
+            recursion_available: flags & 0x0080 != 0,
+            rcode: Rcode::from_code((flags & 0xf) as u8),
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, n: u16) {
+    buf.extend_from_slice(&n.to_be_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_be_bytes());
+}
+
+fn put_name(buf: &mut Vec<u8>, name: &str) -> Result<()> {
+    let begin = buf.len();
+    let name = name.trim_end_matches('.');
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > MAX_LABEL {
+                return Err(Error::InvalidName);
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+
+    if buf.len() - begin > MAX_NAME {
+        Err(Error::InvalidName)
+    } else {
+        Ok(())
+    }
+}
+
+fn put_character_string(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    // a character string is at most 255 bytes, longer strings must be split by the caller
+    let len = u8::try_from(s.len()).map_err(|_| Error::Malformed)?;
+    buf.push(len);
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn put_record(buf: &mut Vec<u8>, record: &Record) -> Result<()> {
+    put_name(buf, &record.name)?;
+    put_u16(buf, record.rtype().code());
+    put_u16(buf, record.class);
+    put_u32(buf, record.ttl);
+
+    // RDLENGTH is patched once the data is written
+    let len_pos = buf.len();
+    put_u16(buf, 0);
+    match &record.data {
+        RData::A(ip) => buf.extend_from_slice(&ip.octets()),
+        RData::AAAA(ip) => buf.extend_from_slice(&ip.octets()),
+        RData::NS(name) | RData::CNAME(name) => put_name(buf, name)?,
+        RData::SOA {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        } => {
+            put_name(buf, mname)?;
+            put_name(buf, rname)?;
+            for n in &[serial, refresh, retry, expire, minimum] {
+                put_u32(buf, **n);
+            }
+        }
+        RData::MX(mx) => {
+            put_u16(buf, mx.preference);
+            put_name(buf, &mx.exchange)?;
+        }
+        RData::TXT(strings) => {
+            for s in strings {
+                put_character_string(buf, s)?;
+            }
+        }
+        RData::Other(_, data) => buf.extend_from_slice(data),
+    }
+
+    let len = u16::try_from(buf.len() - len_pos - 2).map_err(|_| Error::Malformed)?;
+    buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+struct Parser<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::Malformed)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Parse a possibly compressed domain name, defined by:\
+    /// [https://tools.ietf.org/html/rfc1035#section-4.1.4](https://tools.ietf.org/html/rfc1035#section-4.1.4)
+    fn name(&mut self) -> Result<String> {
+        let mut labels: Vec<String> = vec![];
+        let mut len = 0;
+        // position after the name, set at the first pointer
+        let mut end = None;
+        let mut pos = self.pos;
+
+        loop {
+            let byte = *self.buf.get(pos).ok_or(Error::Malformed)?;
+            match byte >> 6 {
+                0b00 if byte == 0 => {
+                    pos += 1;
+                    break;
+                }
+                0b00 => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + byte as usize)
+                        .ok_or(Error::Malformed)?;
+                    len += label.len() + 1;
+                    if len >= MAX_NAME {
+                        return Err(Error::InvalidName);
+                    }
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + byte as usize;
+                }
+                0b11 => {
+                    let low = *self.buf.get(pos + 1).ok_or(Error::Malformed)?;
+                    let target = usize::from(byte & 0x3f) << 8 | usize::from(low);
+                    // pointers only point backwards, ruling out loops
+                    if target >= pos {
+                        return Err(Error::Malformed);
+                    }
+                    end.get_or_insert(pos + 2);
+                    pos = target;
+                }
+                _ => return Err(Error::Malformed),
+            }
+        }
+
+        self.pos = end.unwrap_or(pos);
+        Ok(labels.join("."))
+    }
+
+    fn record(&mut self) -> Result<Record> {
+        let name = self.name()?;
+        let rtype = RecordType::from_code(self.u16()?);
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = usize::from(self.u16()?);
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(Error::Malformed);
+        }
+
+        let data = match rtype {
+            RecordType::A if len == 4 => {
+                let b = self.bytes(4)?;
+                RData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            RecordType::AAAA if len == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(self.bytes(16)?);
+                RData::AAAA(Ipv6Addr::from(octets))
+            }
+            RecordType::NS => RData::NS(self.name()?),
+            RecordType::CNAME => RData::CNAME(self.name()?),
+            RecordType::SOA => RData::SOA {
+                mname: self.name()?,
+                rname: self.name()?,
+                serial: self.u32()?,
+                refresh: self.u32()?,
+                retry: self.u32()?,
+                expire: self.u32()?,
+                minimum: self.u32()?,
+            },
+            RecordType::MX => RData::MX(Mx {
+                preference: self.u16()?,
+                exchange: self.name()?,
+            }),
+            RecordType::TXT => {
+                let mut strings = vec![];
+                while self.pos < end {
+                    let len = usize::from(self.u8()?);
+                    strings.push(String::from_utf8_lossy(self.bytes(len)?).into_owned());
+                }
+                RData::TXT(strings)
+            }
+            RecordType::A | RecordType::AAAA => return Err(Error::Malformed),
+            RecordType::Other(_) => RData::Other(rtype, self.bytes(len)?.to_vec()),
+        };
+
+        // the data must fill RDLENGTH exactly
+        if self.pos != end {
+            return Err(Error::Malformed);
+        }
+        Ok(Record {
+            name,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut message =
+            Message::response_to(&Message::query(0xbeef, "example.com.", RecordType::MX));
+        message.authoritative = true;
+        message.answers = vec![
+            Record::new(
+                "example.com",
+                300,
+                RData::CNAME("mail.example.com".to_string()),
+            ),
+            Record::new(
+                "mail.example.com",
+                300,
+                RData::MX(Mx {
+                    preference: 10,
+                    exchange: "mx1.example.com".to_string(),
+                }),
+            ),
+            Record::new("mx1.example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            Record::new("mx1.example.com", 60, RData::AAAA(Ipv6Addr::LOCALHOST)),
+            Record::new(
+                "example.com",
+                60,
+                RData::TXT(vec!["v=spf1 -all".to_string(), String::new()]),
+            ),
+        ];
+        message.authorities = vec![Record::new(
+            "example.com",
+            3600,
+            RData::SOA {
+                mname: "ns.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 1,
+                refresh: 2,
+                retry: 3,
+                expire: 4,
+                minimum: 5,
+            },
+        )];
+        message.additionals = vec![Record::new(
+            "",
+            0,
+            RData::Other(RecordType::Other(41), vec![1, 2, 3]),
+        )];
+
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(Message::parse(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn compression() {
+        // a response for www.example.com A, the answer owner points at the question name
+        let mut bytes = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        bytes.extend_from_slice(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+        bytes.extend_from_slice(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x06");
+        // CNAME data: "web" followed by a pointer to example.com
+        bytes.extend_from_slice(b"\x03web\xc0\x10");
+
+        let message = Message::parse(&bytes).unwrap();
+        assert!(message.response && message.recursion_available);
+        assert_eq!(message.questions[0].name, "www.example.com");
+        assert_eq!(message.answers[0].name, "www.example.com");
+        assert_eq!(
+            message.answers[0].data,
+            RData::CNAME("web.example.com".to_string())
+        );
+
+        // a pointer to itself
+        let mut looping = bytes[..12].to_vec();
+        looping.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01");
+        assert!(matches!(Message::parse(&looping), Err(Error::Malformed)));
+        // truncated in the middle of a record
+        assert!(matches!(
+            Message::parse(&bytes[..bytes.len() - 1]),
+            Err(Error::Malformed)
+        ));
+    }
+
+    #[test]
+    fn invalid_names() {
+        let long_label = format!("{}.com", "a".repeat(64));
+        let query = Message::query(1, &long_label, RecordType::A);
+        assert!(matches!(query.to_bytes(), Err(Error::InvalidName)));
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        let query = Message::query(1, &long_name, RecordType::A);
+        assert!(matches!(query.to_bytes(), Err(Error::InvalidName)));
+        let query = Message::query(1, "a..com", RecordType::A);
+        assert!(matches!(query.to_bytes(), Err(Error::InvalidName)));
+    }
+}
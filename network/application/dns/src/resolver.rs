@@ -0,0 +1,297 @@
+//! A stub resolver relying on a recursive server to do the actual resolution.
+
+use crate::{
+    cache::Cache,
+    message::{Message, Mx, RData, Rcode, Record, RecordType},
+    Error, Result,
+};
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Server used when none is configured in /etc/resolv.conf.
+const FALLBACK_SERVER: Ipv4Addr = Ipv4Addr::new(8, 8, 8, 8);
+const DNS_PORT: u16 = 53;
+/// Queries sent over UDP before giving up.
+const ATTEMPTS: usize = 3;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// CNAME records followed at most for a single lookup.
+const MAX_CNAME_HOPS: usize = 8;
+/// Responses over UDP are limited to 512 bytes without EDNS, some servers send more anyway.
+const UDP_BUFFER_LEN: usize = 4096;
+
+/// A caching DNS stub resolver, can be shared between threads.
+pub struct Resolver {
+    server: SocketAddr,
+    timeout: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl Resolver {
+    /// Construct a resolver sending queries to the given recursive server.
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: DEFAULT_TIMEOUT,
+            cache: Mutex::new(Cache::new()),
+        }
+    }
+
+    /// Construct a resolver with the first name server in /etc/resolv.conf, or google dns
+    /// services when none is configured.
+    pub fn system() -> Self {
+        let configured = fs::read_to_string("/etc/resolv.conf")
+            .ok()
+            .and_then(|conf| {
+                conf.lines().find_map(|line| {
+                    let mut words = line.split_whitespace();
+                    if words.next() == Some("nameserver") {
+                        words.next()?.parse::<IpAddr>().ok()
+                    } else {
+                        None
+                    }
+                })
+            });
+        let ip = configured.unwrap_or(IpAddr::V4(FALLBACK_SERVER));
+        Self::new(SocketAddr::new(ip, DNS_PORT))
+    }
+
+    /// Set how long to wait for each response before retrying.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Lookup records of a type at a domain name, following CNAME records to the canonical
+    /// name. Returns an empty list when the name exists without records of that type.
+    pub fn lookup(&self, name: &str, rtype: RecordType) -> Result<Vec<Record>> {
+        let mut name = name.trim_end_matches('.').to_string();
+        let mut hops = 0;
+
+        loop {
+            let answers = match self.cached(&name, rtype) {
+                Some(answers) => answers,
+                None => {
+                    let response = self.query(&name, rtype)?;
+                    self.cache.lock().unwrap().insert(&response.answers);
+                    response.answers
+                }
+            };
+
+            // the server may or may not have followed the chain on our behalf
+            let mut current = name.clone();
+            loop {
+                let matched: Vec<Record> = answers
+                    .iter()
+                    .filter(|record| {
+                        record.rtype() == rtype && record.name.eq_ignore_ascii_case(&current)
+                    })
+                    .cloned()
+                    .collect();
+                if !matched.is_empty() {
+                    return Ok(matched);
+                }
+
+                let canonical = answers.iter().find_map(|record| match &record.data {
+                    RData::CNAME(target) if record.name.eq_ignore_ascii_case(&current) => {
+                        Some(target.clone())
+                    }
+                    _ => None,
+                });
+                match canonical {
+                    Some(target) => {
+                        hops += 1;
+                        if hops > MAX_CNAME_HOPS {
+                            return Err(Error::CnameLoop);
+                        }
+                        current = target;
+                    }
+                    None => break,
+                }
+            }
+
+            if current.eq_ignore_ascii_case(&name) {
+                // no records of the type and no alias
+                return Ok(vec![]);
+            }
+            // the chain leads out of the answers, resolve the rest separately
+            name = current;
+        }
+    }
+
+    /// Lookup IPv4 addresses of a domain name.
+    pub fn ipv4_lookup(&self, name: &str) -> Result<Vec<Ipv4Addr>> {
+        let records = self.lookup(name, RecordType::A)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record.data {
+                RData::A(ip) => Some(ip),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Lookup IPv6 addresses of a domain name.
+    pub fn ipv6_lookup(&self, name: &str) -> Result<Vec<Ipv6Addr>> {
+        let records = self.lookup(name, RecordType::AAAA)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record.data {
+                RData::AAAA(ip) => Some(ip),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Lookup mail exchanges of a domain name, the most preferred first.
+    pub fn mx_lookup(&self, name: &str) -> Result<Vec<Mx>> {
+        let records = self.lookup(name, RecordType::MX)?;
+        let mut exchanges: Vec<Mx> = records
+            .into_iter()
+            .filter_map(|record| match record.data {
+                RData::MX(mx) => Some(mx),
+                _ => None,
+            })
+            .collect();
+        exchanges.sort_by_key(|mx| mx.preference);
+        Ok(exchanges)
+    }
+
+    /// Lookup text records of a domain name, character strings of each record concatenated.
+    pub fn txt_lookup(&self, name: &str) -> Result<Vec<String>> {
+        let records = self.lookup(name, RecordType::TXT)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record.data {
+                RData::TXT(strings) => Some(strings.concat()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Lookup authoritative name servers of a domain name.
+    pub fn ns_lookup(&self, name: &str) -> Result<Vec<String>> {
+        let records = self.lookup(name, RecordType::NS)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record.data {
+                RData::NS(ns) => Some(ns),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Cached records of the type, or the CNAME record of the name.
+    fn cached(&self, name: &str, rtype: RecordType) -> Option<Vec<Record>> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.get(name, rtype).or_else(|| {
+            if rtype == RecordType::CNAME {
+                None
+            } else {
+                cache.get(name, RecordType::CNAME)
+            }
+        })
+    }
+
+    /// Send a query to the server, over TCP if the UDP response was truncated.
+    fn query(&self, name: &str, rtype: RecordType) -> Result<Message> {
+        let query = Message::query(query_id(), name, rtype);
+        let bytes = query.to_bytes()?;
+        let mut response = self.exchange_udp(&query, &bytes)?;
+        if response.truncated {
+            response = self.exchange_tcp(&query, &bytes)?;
+        }
+
+        match response.rcode {
+            Rcode::NoError => Ok(response),
+            Rcode::NXDomain => Err(Error::NameNotFound),
+            rcode => Err(Error::ServerFailure(rcode)),
+        }
+    }
+
+    fn exchange_udp(&self, query: &Message, bytes: &[u8]) -> Result<Message> {
+        let local = if self.server.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(self.server)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+
+        let mut buf = [0; UDP_BUFFER_LEN];
+        for _ in 0..ATTEMPTS {
+            socket.send(bytes)?;
+            let deadline = Instant::now() + self.timeout;
+            // unrelated or forged datagrams are dropped until the deadline
+            while Instant::now() < deadline {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(err) => match Error::from(err) {
+                        Error::Timeout => break,
+                        err => return Err(err),
+                    },
+                };
+                if let Ok(response) = Message::parse(&buf[..len]) {
+                    if answers(query, &response) {
+                        return Ok(response);
+                    }
+                }
+            }
+        }
+
+        Err(Error::Timeout)
+    }
+
+    /// Messages over TCP are prefixed by their length, defined by:\
+    /// [https://tools.ietf.org/html/rfc1035#section-4.2.2](https://tools.ietf.org/html/rfc1035#section-4.2.2)
+    fn exchange_tcp(&self, query: &Message, bytes: &[u8]) -> Result<Message> {
+        let mut stream = TcpStream::connect_timeout(&self.server, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(bytes);
+        stream.write_all(&framed)?;
+
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let mut buf = vec![0; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut buf)?;
+
+        let response = Message::parse(&buf)?;
+        if answers(query, &response) {
+            Ok(response)
+        } else {
+            Err(Error::Malformed)
+        }
+    }
+}
+
+/// Whether the message is a response to the query.
+fn answers(query: &Message, response: &Message) -> bool {
+    response.response
+        && response.id == query.id
+        && response.questions.len() == query.questions.len()
+        && response
+            .questions
+            .iter()
+            .zip(&query.questions)
+            .all(|(r, q)| r.name.eq_ignore_ascii_case(&q.name) && r.rtype == q.rtype)
+}
+
+/// An unpredictable query id, making forged responses harder to pass as genuine.
+fn query_id() -> u16 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hasher.write_u128(nanos);
+    hasher.finish() as u16
+}
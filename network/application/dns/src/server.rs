@@ -0,0 +1,243 @@
+//! A local authoritative DNS server answering from a fixed set of records, for tests.
+
+use crate::message::{Message, RData, Rcode, Record, RecordType};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Largest response sent over UDP, longer responses are truncated.
+const MAX_UDP_LEN: usize = 512;
+/// How often the server threads check for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// CNAME records followed at most within the zone.
+const MAX_CNAME_HOPS: usize = 8;
+/// Ports tried before giving up finding one free for both UDP and TCP.
+const BIND_ATTEMPTS: usize = 16;
+
+/// An authoritative server listening on UDP and TCP on the same port of localhost, stopped
+/// when dropped.
+pub struct StubServer {
+    local_addr: SocketAddr,
+    queries: Arc<AtomicUsize>,
+    tcp_queries: Arc<AtomicUsize>,
+    shutdown: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl StubServer {
+    /// Serve the records on an arbitrary port of localhost.
+    pub fn spawn(records: Vec<Record>) -> io::Result<Self> {
+        let (udp, tcp) = bind_pair()?;
+        let local_addr = udp.local_addr()?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))?;
+        tcp.set_nonblocking(true)?;
+
+        let zone = Arc::new(records);
+        let queries = Arc::new(AtomicUsize::new(0));
+        let tcp_queries = Arc::new(AtomicUsize::new(0));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let udp_handle = {
+            let (zone, queries, shutdown) = (zone.clone(), queries.clone(), shutdown.clone());
+            thread::spawn(move || serve_udp(udp, &zone, &queries, &shutdown))
+        };
+        let tcp_handle = {
+            let (queries, tcp_queries, shutdown) =
+                (queries.clone(), tcp_queries.clone(), shutdown.clone());
+            thread::spawn(move || serve_tcp(tcp, &zone, &queries, &tcp_queries, &shutdown))
+        };
+
+        Ok(Self {
+            local_addr,
+            queries,
+            tcp_queries,
+            shutdown,
+            handles: vec![udp_handle, tcp_handle],
+        })
+    }
+
+    /// The address resolvers should send queries to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Number of queries received over both UDP and TCP.
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::SeqCst)
+    }
+
+    /// Number of queries received over TCP.
+    pub fn tcp_queries(&self) -> usize {
+        self.tcp_queries.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Bind UDP and TCP to the same port, the port picked for UDP may already be taken for TCP.
+fn bind_pair() -> io::Result<(UdpSocket, TcpListener)> {
+    let mut last_err = None;
+    for _ in 0..BIND_ATTEMPTS {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        match TcpListener::bind(udp.local_addr()?) {
+            Ok(tcp) => return Ok((udp, tcp)),
+            Err(err) if err.kind() == ErrorKind::AddrInUse => last_err = Some(err),
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+fn serve_udp(socket: UdpSocket, zone: &[Record], queries: &AtomicUsize, shutdown: &AtomicBool) {
+    let mut buf = [0; MAX_UDP_LEN];
+    while !shutdown.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) if is_timeout(&err) => continue,
+            Err(_) => return,
+        };
+        queries.fetch_add(1, Ordering::SeqCst);
+
+        let mut response = match answer(zone, &buf[..len]) {
+            Some(response) => response,
+            None => continue,
+        };
+        let mut bytes = match response.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+        if bytes.len() > MAX_UDP_LEN {
+            // the client is expected to retry over TCP
+            response.truncated = true;
+            response.answers.clear();
+            response.authorities.clear();
+            response.additionals.clear();
+            bytes = match response.to_bytes() {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+        }
+        let _ = socket.send_to(&bytes, peer);
+    }
+}
+
+fn serve_tcp(
+    listener: TcpListener,
+    zone: &[Record],
+    queries: &AtomicUsize,
+    tcp_queries: &AtomicUsize,
+    shutdown: &AtomicBool,
+) {
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                queries.fetch_add(1, Ordering::SeqCst);
+                tcp_queries.fetch_add(1, Ordering::SeqCst);
+                // one query per connection, a misbehaving client only costs a timeout
+                let _ = serve_tcp_query(stream, zone);
+            }
+            Err(err) if is_timeout(&err) => thread::sleep(POLL_INTERVAL),
+            Err(_) => return,
+        }
+    }
+}
+
+fn serve_tcp_query(mut stream: TcpStream, zone: &[Record]) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL * 10))?;
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut buf)?;
+
+    if let Some(bytes) = answer(zone, &buf).and_then(|response| response.to_bytes().ok()) {
+        let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&bytes);
+        stream.write_all(&framed)?;
+    }
+    Ok(())
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut
+}
+
+/// Answer a query from the zone, following CNAME records within the zone. Unparsable messages
+/// are dropped.
+fn answer(zone: &[Record], query: &[u8]) -> Option<Message> {
+    let query = Message::parse(query).ok()?;
+    if query.response {
+        return None;
+    }
+
+    let mut response = Message::response_to(&query);
+    response.authoritative = true;
+    response.recursion_available = false;
+    if query.opcode != 0 {
+        response.rcode = Rcode::NotImp;
+        return Some(response);
+    }
+    let question = match query.questions.as_slice() {
+        [question] => question,
+        _ => {
+            response.rcode = Rcode::FormErr;
+            return Some(response);
+        }
+    };
+
+    let mut name = question.name.clone();
+    for _ in 0..MAX_CNAME_HOPS {
+        let owned: Vec<&Record> = zone
+            .iter()
+            .filter(|record| record.name.eq_ignore_ascii_case(&name))
+            .collect();
+        if owned.is_empty() {
+            // an alias pointing outside the zone is left for the client to resolve
+            if response.answers.is_empty() {
+                response.rcode = Rcode::NXDomain;
+            }
+            break;
+        }
+
+        let matched: Vec<Record> = owned
+            .iter()
+            .filter(|record| record.rtype() == question.rtype)
+            .map(|record| (*record).clone())
+            .collect();
+        if !matched.is_empty() {
+            response.answers.extend(matched);
+            break;
+        }
+
+        let cname = owned
+            .iter()
+            .find(|record| record.rtype() == RecordType::CNAME);
+        match cname {
+            Some(record) => {
+                response.answers.push((*record).clone());
+                if let RData::CNAME(target) = &record.data {
+                    name = target.clone();
+                }
+            }
+            // the name exists without records of the type
+            None => break,
+        }
+    }
+
+    Some(response)
+}
@@ -0,0 +1,146 @@
+use dns::{Error, Mx, RData, Record, RecordType, Resolver, StubServer};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, UdpSocket},
+    time::Duration,
+};
+
+fn a(name: &str, ip: [u8; 4]) -> Record {
+    Record::new(name, 300, RData::A(Ipv4Addr::from(ip)))
+}
+
+fn cname(name: &str, target: &str) -> Record {
+    Record::new(name, 300, RData::CNAME(target.to_string()))
+}
+
+fn mx(name: &str, preference: u16, exchange: &str) -> Record {
+    Record::new(
+        name,
+        300,
+        RData::MX(Mx {
+            preference,
+            exchange: exchange.to_string(),
+        }),
+    )
+}
+
+fn zone() -> Vec<Record> {
+    vec![
+        cname("www.example.test", "web.example.test"),
+        cname("web.example.test", "host.example.test"),
+        a("host.example.test", [192, 0, 2, 1]),
+        a("host.example.test", [192, 0, 2, 2]),
+        Record::new(
+            "host.example.test",
+            300,
+            RData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        ),
+        cname("outside.example.test", "elsewhere.test"),
+        cname("loop1.example.test", "loop2.example.test"),
+        cname("loop2.example.test", "loop1.example.test"),
+        mx("example.test", 20, "mx2.example.test"),
+        mx("example.test", 10, "mx1.example.test"),
+        mx("example.test", 30, "mx3.example.test"),
+        Record::new(
+            "example.test",
+            300,
+            RData::NS("ns.example.test".to_string()),
+        ),
+    ]
+}
+
+#[test]
+fn cname_chain_and_cache() -> Result<(), Error> {
+    let server = StubServer::spawn(zone())?;
+    let resolver = Resolver::new(server.local_addr());
+
+    let ips = resolver.ipv4_lookup("WWW.example.test.")?;
+    assert_eq!(
+        ips,
+        vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)]
+    );
+    assert_eq!(server.queries(), 1);
+
+    // answered from the cache, the chain included
+    resolver.ipv4_lookup("www.example.test")?;
+    resolver.ipv4_lookup("web.example.test")?;
+    assert_eq!(server.queries(), 1);
+
+    // the cached alias leads to a query for the canonical name only
+    let ips = resolver.ipv6_lookup("www.example.test")?;
+    assert_eq!(ips, vec![Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)]);
+    assert_eq!(server.queries(), 2);
+
+    let ns = resolver.ns_lookup("example.test")?;
+    assert_eq!(ns, vec!["ns.example.test".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn missing_records() -> Result<(), Error> {
+    let server = StubServer::spawn(zone())?;
+    let resolver = Resolver::new(server.local_addr());
+
+    assert!(matches!(
+        resolver.ipv4_lookup("nowhere.example.test"),
+        Err(Error::NameNotFound)
+    ));
+    // the alias is followed with a second query
+    assert!(matches!(
+        resolver.ipv4_lookup("outside.example.test"),
+        Err(Error::NameNotFound)
+    ));
+    assert_eq!(server.queries(), 3);
+    assert!(resolver.txt_lookup("host.example.test")?.is_empty());
+    assert!(matches!(
+        resolver.ipv4_lookup("loop1.example.test"),
+        Err(Error::CnameLoop)
+    ));
+    Ok(())
+}
+
+#[test]
+fn mx_preference() -> Result<(), Error> {
+    let server = StubServer::spawn(zone())?;
+    let resolver = Resolver::new(server.local_addr());
+
+    let exchanges: Vec<String> = resolver
+        .mx_lookup("example.test")?
+        .into_iter()
+        .map(|mx| mx.exchange)
+        .collect();
+    assert_eq!(
+        exchanges,
+        vec!["mx1.example.test", "mx2.example.test", "mx3.example.test"]
+    );
+    Ok(())
+}
+
+#[test]
+fn tcp_fallback() -> Result<(), Error> {
+    let texts: Vec<String> = (0..4).map(|i| i.to_string().repeat(200)).collect();
+    let records = texts
+        .iter()
+        .map(|text| Record::new("big.example.test", 300, RData::TXT(vec![text.clone()])))
+        .collect();
+    let server = StubServer::spawn(records)?;
+    let resolver = Resolver::new(server.local_addr());
+
+    assert_eq!(resolver.txt_lookup("big.example.test")?, texts);
+    assert_eq!(server.queries(), 2);
+    assert_eq!(server.tcp_queries(), 1);
+    Ok(())
+}
+
+#[test]
+fn timeout() -> Result<(), Error> {
+    // a socket never answering
+    let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let mut resolver = Resolver::new(silent.local_addr()?);
+    resolver.set_timeout(Duration::from_millis(50));
+
+    assert!(matches!(
+        resolver.lookup("example.test", RecordType::A),
+        Err(Error::Timeout)
+    ));
+    Ok(())
+}
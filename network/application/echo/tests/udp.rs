@@ -1,5 +1,12 @@
 use assert_cmd::prelude::*;
-use std::{io, process::Command, thread, time::Duration};
+use relay::{Config, Relay};
+use std::{
+    io,
+    net::{Ipv4Addr, UdpSocket},
+    process::{Child, Command},
+    thread,
+    time::Duration,
+};
 
 #[test]
 fn udp_test() -> io::Result<()> {
@@ -27,3 +34,61 @@ fn udp_test() -> io::Result<()> {
 
     Ok(())
 }
+
+/// A child process killed and waited for when dropped, even if the test panics.
+struct Reaped(Child);
+
+impl Drop for Reaped {
+    fn drop(&mut self) {
+        // the server may have exited already after echoing its one line
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A port no socket on localhost is bound to at the time of the call.
+fn free_port() -> io::Result<u16> {
+    Ok(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?
+        .local_addr()?
+        .port())
+}
+
+#[test]
+fn udp_relay_test() -> io::Result<()> {
+    // the client binds a port of its own choosing
+    let client = 0;
+    let server = free_port()?;
+
+    let _server = Reaped(
+        Command::cargo_bin("udp_server")
+            .unwrap()
+            .arg(client.to_string())
+            .arg(server.to_string())
+            .spawn()?,
+    );
+
+    // the client neither times out nor retransmits, only delay and duplication are survivable
+    let config = Config {
+        rtt: Duration::from_millis(100),
+        duplicate_rate: 1.0,
+        ..Config::default()
+    };
+    let relay = Relay::spawn((Ipv4Addr::LOCALHOST, server).into(), config)?;
+
+    // ensure server is fully set up
+    thread::sleep(Duration::from_millis(200));
+
+    Command::cargo_bin("udp_client")
+        .unwrap()
+        .arg(client.to_string())
+        .arg(relay.local_addr().port().to_string())
+        .with_stdin()
+        .buffer("abab")
+        .assert()
+        .stdout("ABAB")
+        .success();
+
+    assert!(relay.statistics().duplicated >= 2);
+
+    Ok(())
+}
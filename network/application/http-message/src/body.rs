@@ -0,0 +1,272 @@
+use crate::{is_empty_line, read_line, Error, Limits, Result};
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Read, Write},
+};
+
+/// How the end of a message body is determined, defined by:\
+/// [https://tools.ietf.org/html/rfc7230#section-3.3.3](https://tools.ietf.org/html/rfc7230#section-3.3.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// The message has no body
+    Empty,
+    /// The body has the given length in bytes
+    Length(u64),
+    /// The body is a series of chunks ended by a zero-sized chunk
+    Chunked,
+    /// The body ends when the server closes the connection, only possible for responses
+    Close,
+}
+
+enum State {
+    /// Bytes remaining in the body
+    Length(u64),
+    /// Expecting a chunk size line, preceded by CRLF unless it's the first chunk
+    ChunkSize(bool),
+    /// Bytes remaining in the current chunk
+    Chunk(u64),
+    /// Read until EOF
+    Close,
+    /// The body has been read completely
+    Done,
+}
+
+/// Reader over a message body, decoding chunks and stopping at the end of the body so that the
+/// next message on the same connection is left in the underlying reader.\
+/// Chunk extensions and trailer fields are discarded.\
+/// Errors in the body are reported as io::Error of kind InvalidData carrying an [`Error`].
+pub struct BodyReader<R> {
+    reader: R,
+    state: State,
+    limits: Limits,
+}
+
+impl<R> BodyReader<R>
+where
+    R: BufRead,
+{
+    /// Wrap the reader positioned right after the message head.
+    pub fn new(reader: R, framing: Framing) -> Self {
+        Self::with_limits(reader, framing, Limits::default())
+    }
+
+    /// Wrap the reader, chunk size lines and trailers are bounded by the given limits.
+    pub fn with_limits(reader: R, framing: Framing, limits: Limits) -> Self {
+        let state = match framing {
+            Framing::Empty => State::Done,
+            Framing::Length(len) => State::Length(len),
+            Framing::Chunked => State::ChunkSize(true),
+            Framing::Close => State::Close,
+        };
+
+        Self {
+            reader,
+            state,
+            limits,
+        }
+    }
+
+    /// Read the rest of the body into a buffer.
+    pub fn read_to_vec(&mut self) -> Result<Vec<u8>> {
+        let mut body = vec![];
+        self.read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Start a new chunk, or finish the body on the last chunk.
+    fn next_chunk(&mut self, first: bool) -> Result<()> {
+        let mut line = vec![];
+        if !first {
+            // CRLF after the data of the previous chunk
+            read_line(&mut self.reader, &mut line, self.limits.max_line)?;
+            if !is_empty_line(&line) {
+                return Err(Error::InvalidChunk);
+            }
+            line.clear();
+        }
+
+        read_line(&mut self.reader, &mut line, self.limits.max_line)?;
+        let size = decode_size(&line)?;
+        if size > 0 {
+            self.state = State::Chunk(size);
+            return Ok(());
+        }
+
+        // skip all trailers
+        let mut trailer = 0;
+        loop {
+            line.clear();
+            trailer += read_line(&mut self.reader, &mut line, self.limits.max_line)?;
+            if trailer > self.limits.max_head {
+                return Err(Error::HeaderTooLarge);
+            }
+            if is_empty_line(&line) {
+                self.state = State::Done;
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R> Read for BodyReader<R>
+where
+    R: BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let remaining = match self.state {
+                State::Done => return Ok(0),
+                State::Close => return self.reader.read(buf),
+                State::ChunkSize(first) => {
+                    self.next_chunk(first)?;
+                    continue;
+                }
+                State::Length(0) => {
+                    self.state = State::Done;
+                    continue;
+                }
+                State::Chunk(0) => {
+                    self.state = State::ChunkSize(false);
+                    continue;
+                }
+                State::Length(remaining) | State::Chunk(remaining) => remaining,
+            };
+
+            let max = buf
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            let len = self.reader.read(&mut buf[..max])?;
+            if len == 0 && max > 0 {
+                return Err(Error::Incomplete.into());
+            }
+            match &mut self.state {
+                State::Length(remaining) | State::Chunk(remaining) => *remaining -= len as u64,
+                _ => unreachable!(),
+            }
+            return Ok(len);
+        }
+    }
+}
+
+/// Parse a chunk size line, chunk extensions after the size are ignored.
+fn decode_size(line: &[u8]) -> Result<u64> {
+    let digits = line.iter().take_while(|b| b.is_ascii_hexdigit()).count();
+    let rest = &line[digits..];
+    let valid_rest = is_empty_line(rest) || rest.starts_with(b";") || rest.starts_with(b" ");
+    if digits == 0 || digits > 16 || !valid_rest {
+        return Err(Error::InvalidChunk);
+    }
+    let digits = std::str::from_utf8(&line[..digits]).map_err(|_| Error::InvalidChunk)?;
+    u64::from_str_radix(digits, 16).map_err(|_| Error::InvalidChunk)
+}
+
+/// Writer encoding everything written into chunks, each write call produces one chunk.
+/// [`ChunkedWriter::finish`] must be called to send the last chunk ending the body.
+pub struct ChunkedWriter<W>
+where
+    W: Write,
+{
+    writer: W,
+}
+
+impl<W> ChunkedWriter<W>
+where
+    W: Write,
+{
+    /// Wrap the writer positioned right after the message head.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Send the last chunk and an empty trailer, returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(b"0\r\n\r\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W> Write for ChunkedWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a chunk of size 0 would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.writer, "{:x}\r\n", buf.len())?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &[u8], framing: Framing) -> (Result<Vec<u8>>, Vec<u8>) {
+        let mut reader = BodyReader::new(input, framing);
+        let body = reader.read_to_vec();
+        let mut rest = vec![];
+        reader.into_inner().read_to_end(&mut rest).unwrap();
+        (body, rest)
+    }
+
+    #[test]
+    fn framing() {
+        let (body, rest) = read(b"hello, worldGET", Framing::Length(12));
+        assert_eq!(body.unwrap(), b"hello, world");
+        assert_eq!(rest, b"GET");
+
+        let (body, rest) = read(b"hello, world", Framing::Close);
+        assert_eq!(body.unwrap(), b"hello, world");
+        assert!(rest.is_empty());
+
+        let (body, rest) = read(b"GET", Framing::Empty);
+        assert!(body.unwrap().is_empty());
+        assert_eq!(rest, b"GET");
+
+        let (body, _) = read(b"hello", Framing::Length(12));
+        assert!(matches!(body, Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn chunked() {
+        let input = b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nExpires: never\r\n\r\nGET";
+        let (body, rest) = read(input, Framing::Chunked);
+        assert_eq!(body.unwrap(), b"hello, world");
+        assert_eq!(rest, b"GET");
+
+        let (body, _) = read(b"5\r\nhello, world\r\n0\r\n\r\n", Framing::Chunked);
+        assert!(matches!(body, Err(Error::InvalidChunk)));
+        let (body, _) = read(b"x\r\n", Framing::Chunked);
+        assert!(matches!(body, Err(Error::InvalidChunk)));
+        let (body, _) = read(b"5\r\nhel", Framing::Chunked);
+        assert!(matches!(body, Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b", world").unwrap();
+        let encoded = writer.finish().unwrap();
+        assert_eq!(encoded, b"5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n");
+
+        let (body, rest) = read(&encoded, Framing::Chunked);
+        assert_eq!(body.unwrap(), b"hello, world");
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{Error, Result};
+use httparse::Header;
+use std::fmt::{self, Display, Formatter};
+
+/// Fields meaningful only to a single connection, defined by:\
+/// [https://tools.ietf.org/html/rfc7230#section-6.1](https://tools.ietf.org/html/rfc7230#section-6.1)
+const HOP_BY_HOP: &[&str] = &[
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// Header fields of an HTTP message in the order they were received. Field names are
+/// case-insensitive, a name may appear multiple times.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// An empty set of header fields.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn from_parsed(headers: &[Header]) -> Self {
+        let fields = headers
+            .iter()
+            .map(|header| {
+                // obsolete non-ASCII bytes in field values are tolerated
+                let value = String::from_utf8_lossy(header.value);
+                (header.name.to_string(), value.into_owned())
+            })
+            .collect();
+
+        Self { fields }
+    }
+
+    /// Value of the first field with the given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of all fields with the given name.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a comma-separated field e.g. Connection: lists the token, case-insensitive.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Insert a field, replacing all fields with the same name.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    /// Append a field, keeping fields with the same name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Remove all fields with the given name.
+    pub fn remove(&mut self, name: &str) {
+        self.fields
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    /// Remove fields meaningful only to a single connection, including those listed in
+    /// Connection:.
+    pub fn remove_hop_by_hop(&mut self) {
+        let listed: Vec<String> = self
+            .get_all("Connection")
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim().to_string())
+            .collect();
+        for name in listed
+            .iter()
+            .map(String::as_str)
+            .chain(HOP_BY_HOP.iter().copied())
+        {
+            self.remove(name);
+        }
+    }
+
+    /// All fields in order as (name, value) pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Number of fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether there's no field at all.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Whether chunked is the final transfer coding, None if Transfer-Encoding: is absent.
+    pub(crate) fn chunked(&self) -> Option<bool> {
+        let last = self
+            .get_all("Transfer-Encoding")
+            .flat_map(|value| value.split(','))
+            .last()?;
+        Some(last.trim().eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Value of Content-Length:, repeated fields must agree.
+    pub(crate) fn content_length(&self) -> Result<Option<u64>> {
+        let mut length = None;
+        for value in self
+            .get_all("Content-Length")
+            .flat_map(|value| value.split(','))
+        {
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::InvalidContentLength);
+            }
+            let value = value.parse().map_err(|_| Error::InvalidContentLength)?;
+            if length.is_some_and(|length| length != value) {
+                return Err(Error::InvalidContentLength);
+            }
+            length = Some(value);
+        }
+        Ok(length)
+    }
+}
+
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl Display for Headers {
+    /// Fields each terminated by CRLF, without the empty line ending the head.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, value) in self {
+            write!(f, "{}: {}\r\n", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let mut headers = Headers::new();
+        headers.append("Accept", "text/html");
+        headers.append("accept", "text/plain");
+        headers.insert("Connection", "Keep-Alive, X-Private");
+        headers.insert("X-Private", "secret");
+        headers.insert("Transfer-Encoding", "gzip, chunked");
+
+        assert_eq!(headers.get("ACCEPT"), Some("text/html"));
+        assert_eq!(
+            headers.get_all("Accept").collect::<Vec<_>>(),
+            vec!["text/html", "text/plain"]
+        );
+        assert!(headers.has_token("connection", "keep-alive"));
+        assert!(!headers.has_token("connection", "close"));
+        assert_eq!(headers.chunked(), Some(true));
+
+        headers.remove_hop_by_hop();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("X-Private"), None);
+        assert_eq!(headers.chunked(), None);
+    }
+
+    #[test]
+    fn content_length() {
+        let mut headers = Headers::new();
+        assert_eq!(headers.content_length().unwrap(), None);
+        headers.insert("Content-Length", "42");
+        assert_eq!(headers.content_length().unwrap(), Some(42));
+        headers.append("Content-Length", "42");
+        assert_eq!(headers.content_length().unwrap(), Some(42));
+        headers.append("Content-Length", "43");
+        assert!(headers.content_length().is_err());
+        headers.insert("Content-Length", "+42");
+        assert!(headers.content_length().is_err());
+    }
+}
@@ -0,0 +1,189 @@
+#![deny(missing_docs)]
+
+//! HTTP/1.1 messages shared by the web server, the web proxy and the Tiny server of CSAPP.\
+//! Message heads are parsed from a byte stream within configurable size limits, bodies are
+//! streamed through [`BodyReader`] and [`ChunkedWriter`] in any framing defined by:\
+//! [https://tools.ietf.org/html/rfc7230#section-3.3.3](https://tools.ietf.org/html/rfc7230#section-3.3.3)
+
+mod body;
+mod headers;
+mod method;
+mod request;
+mod response;
+mod status;
+
+pub use body::{BodyReader, ChunkedWriter, Framing};
+pub use headers::Headers;
+pub use method::Method;
+pub use request::Request;
+pub use response::{Response, ResponseBuilder};
+pub use status::Status;
+
+use httparse::Error as ParseError;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, ErrorKind, Read},
+};
+
+/// Crate universal error type.
+#[derive(Debug)]
+pub enum Error {
+    /// The stream ended before a complete message
+    Incomplete,
+    /// A line of the message head, or the whole head, exceeds the limits
+    HeaderTooLarge,
+    /// The message head has more header fields than the limits allow
+    TooManyHeaders,
+    /// Request method unknown to [`Method`]
+    MethodNotImplemented,
+    /// Content-Length: is not a number, or appears multiple times with different values
+    InvalidContentLength,
+    /// Transfer coding of a request other than chunked
+    UnsupportedTransferCoding,
+    /// Malformed chunk size line, or chunk data not followed by CRLF
+    InvalidChunk,
+    /// Error propagated from httparse
+    ParseError(ParseError),
+    /// Error propagated from std::io
+    IOError(io::Error),
+}
+
+use Error::*;
+
+impl Error {
+    /// Status a server should respond with to a request failed with this error, None if no
+    /// response makes sense e.g. the connection is broken.
+    pub fn status(&self) -> Option<Status> {
+        match self {
+            Incomplete | IOError(..) => None,
+            HeaderTooLarge | TooManyHeaders => Some(Status::RequestHeaderFieldsTooLarge),
+            MethodNotImplemented | UnsupportedTransferCoding => Some(Status::NotImplemented),
+            InvalidContentLength | InvalidChunk | ParseError(..) => Some(Status::BadRequest),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Incomplete => write!(f, "stream ended before a complete HTTP message"),
+            HeaderTooLarge => write!(f, "HTTP message head too large"),
+            TooManyHeaders => write!(f, "too many HTTP header fields"),
+            MethodNotImplemented => write!(f, "HTTP method not implemented"),
+            InvalidContentLength => write!(f, "invalid Content-Length"),
+            UnsupportedTransferCoding => write!(f, "unsupported transfer coding"),
+            InvalidChunk => write!(f, "malformed chunk"),
+            ParseError(err) => write!(f, "malformed HTTP message: {}", err),
+            IOError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        match err {
+            ParseError::TooManyHeaders => TooManyHeaders,
+            _ => ParseError(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Errors raised by a [`BodyReader`] are unwrapped from the io::Error carrying them.
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            *err.into_inner().unwrap().downcast::<Error>().unwrap()
+        } else if err.kind() == ErrorKind::UnexpectedEof {
+            Incomplete
+        } else {
+            IOError(err)
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            IOError(err) => err,
+            Incomplete => io::Error::new(ErrorKind::UnexpectedEof, err),
+            _ => io::Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Crate universal result type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Bounds on the message head, protecting servers from peers sending endless headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Maximum length in bytes of the start line, a header field or a chunk size line
+    pub max_line: usize,
+    /// Maximum length in bytes of the whole message head, or the trailer of a chunked body
+    pub max_head: usize,
+    /// Maximum number of header fields
+    pub max_headers: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_line: 8 * 1024,
+            max_head: 64 * 1024,
+            max_headers: 100,
+        }
+    }
+}
+
+const LF: u8 = b'\n';
+
+/// Read one line terminated by LF no longer than the limit, returns the number of bytes read.
+fn read_line<R>(reader: &mut R, buf: &mut Vec<u8>, max_line: usize) -> Result<usize>
+where
+    R: BufRead,
+{
+    let len = reader
+        .by_ref()
+        .take(max_line as u64 + 1)
+        .read_until(LF, buf)?;
+    if len == 0 || !buf.ends_with(&[LF]) {
+        return Err(if len > max_line {
+            HeaderTooLarge
+        } else {
+            Incomplete
+        });
+    }
+    Ok(len)
+}
+
+fn is_empty_line(line: &[u8]) -> bool {
+    line == b"\r\n" || line == b"\n"
+}
+
+/// Read the message head up to and including the empty line, without consuming the body.
+fn read_head<R>(reader: &mut R, limits: &Limits) -> Result<Vec<u8>>
+where
+    R: BufRead,
+{
+    let mut buf = vec![];
+    let mut skipped = 0;
+    loop {
+        let begin = buf.len();
+        read_line(reader, &mut buf, limits.max_line)?;
+        if skipped + buf.len() > limits.max_head {
+            return Err(HeaderTooLarge);
+        }
+        if is_empty_line(&buf[begin..]) {
+            // empty lines before the start line are ignored, defined by:
+            // https://tools.ietf.org/html/rfc7230#section-3.5
+            if begin == 0 {
+                skipped += buf.len();
+                buf.clear();
+                continue;
+            }
+            return Ok(buf);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Request methods defined by:\
+/// [https://tools.ietf.org/html/rfc7231#section-4](https://tools.ietf.org/html/rfc7231#section-4)\
+/// and PATCH, other methods are rejected by the parser.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    GET,
+    HEAD,
+    POST,
+    PUT,
+    DELETE,
+    CONNECT,
+    OPTIONS,
+    TRACE,
+    PATCH,
+}
+
+use Method::*;
+
+impl Method {
+    /// Parse a method token, method names are case-sensitive.
+    pub fn parse(s: &str) -> Option<Self> {
+        let method = match s {
+            "GET" => GET,
+            "HEAD" => HEAD,
+            "POST" => POST,
+            "PUT" => PUT,
+            "DELETE" => DELETE,
+            "CONNECT" => CONNECT,
+            "OPTIONS" => OPTIONS,
+            "TRACE" => TRACE,
+            "PATCH" => PATCH,
+            _ => return None,
+        };
+        Some(method)
+    }
+
+    /// The method token.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GET => "GET",
+            HEAD => "HEAD",
+            POST => "POST",
+            PUT => "PUT",
+            DELETE => "DELETE",
+            CONNECT => "CONNECT",
+            OPTIONS => "OPTIONS",
+            TRACE => "TRACE",
+            PATCH => "PATCH",
+        }
+    }
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{read_head, BodyReader, Error, Framing, Headers, Limits, Method, Result};
+use httparse::EMPTY_HEADER;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, Write},
+};
+
+/// An HTTP request without body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    method: Method,
+    target: String,
+    /// 0 for HTTP/1.0, 1 for HTTP/1.1
+    minor_version: u8,
+    headers: Headers,
+}
+
+impl Request {
+    /// An HTTP/1.1 request without header fields.
+    pub fn new(method: Method, target: &str) -> Self {
+        Self {
+            method,
+            target: target.to_string(),
+            minor_version: 1,
+            headers: Headers::new(),
+        }
+    }
+
+    /// Parse the head of a request from byte stream within the default limits, the body is left
+    /// in the reader.
+    pub fn from_reader<R>(reader: &mut R) -> Result<Self>
+    where
+        R: BufRead,
+    {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Parse the head of a request from byte stream within the given limits.
+    pub fn from_reader_with_limits<R>(reader: &mut R, limits: &Limits) -> Result<Self>
+    where
+        R: BufRead,
+    {
+        let buf = read_head(reader, limits)?;
+        let mut headers_buf = vec![EMPTY_HEADER; limits.max_headers];
+        let mut parser = httparse::Request::new(&mut headers_buf);
+        if parser.parse(&buf)?.is_partial() {
+            return Err(Error::Incomplete);
+        }
+
+        // all fields are present in a complete request
+        let method = parser.method.unwrap_or_default();
+        let method = Method::parse(method).ok_or(Error::MethodNotImplemented)?;
+
+        Ok(Self {
+            method,
+            target: parser.path.unwrap_or_default().to_string(),
+            minor_version: parser.version.unwrap_or_default(),
+            headers: Headers::from_parsed(parser.headers),
+        })
+    }
+
+    /// Method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Request target as sent by the client, may be a path, an absolute URL or an authority.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Replace the request target.
+    pub fn set_target(&mut self, target: &str) {
+        self.target = target.to_string();
+    }
+
+    /// Path of the request target without the query, "/" for an absolute URL without path.
+    pub fn path(&self) -> &str {
+        let target = self.target.split('?').next().unwrap_or_default();
+        match strip_scheme(target) {
+            Some(url) => url.find('/').map_or("/", |slash| &url[slash..]),
+            None => target,
+        }
+    }
+
+    /// Query of the request target without the question mark.
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Host of the request, taken from an absolute URL, a CONNECT authority or Host:, defined
+    /// by:\
+    /// [https://tools.ietf.org/html/rfc7230#section-5.5](https://tools.ietf.org/html/rfc7230#section-5.5)
+    pub fn host(&self) -> Option<&str> {
+        if self.method == Method::CONNECT {
+            return Some(&self.target);
+        }
+        match strip_scheme(&self.target) {
+            Some(url) => url.split(['/', '?']).next(),
+            None => self.header("Host"),
+        }
+    }
+
+    /// Minor version of HTTP/1.x.
+    pub fn minor_version(&self) -> u8 {
+        self.minor_version
+    }
+
+    /// Header fields of the request.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Mutable header fields of the request.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// Value of a header field, field names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Insert or replace a header field of the request.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name, value);
+    }
+
+    /// Insert or replace a header field of the request, chainable.
+    pub fn attach_header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.set_header(name, value);
+        self
+    }
+
+    /// Remove a header field of the request.
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.remove(name);
+    }
+
+    /// Whether the client expects the connection to persist after the response, defined by:\
+    /// [https://tools.ietf.org/html/rfc7230#section-6.3](https://tools.ietf.org/html/rfc7230#section-6.3)
+    pub fn keep_alive(&self) -> bool {
+        if self.headers.has_token("Connection", "close") {
+            false
+        } else if self.headers.has_token("Connection", "keep-alive") {
+            true
+        } else {
+            self.minor_version >= 1
+        }
+    }
+
+    /// Framing of the request body, a request without Content-Length: or Transfer-Encoding:
+    /// has no body.
+    pub fn framing(&self) -> Result<Framing> {
+        match self.headers.chunked() {
+            Some(true) => Ok(Framing::Chunked),
+            // the server cannot determine the length of the body
+            Some(false) => Err(Error::UnsupportedTransferCoding),
+            None => match self.headers.content_length()? {
+                Some(0) | None => Ok(Framing::Empty),
+                Some(len) => Ok(Framing::Length(len)),
+            },
+        }
+    }
+
+    /// Reader over the body of the request, the reader must be positioned right after the head.
+    pub fn body<R>(&self, reader: R) -> Result<BodyReader<R>>
+    where
+        R: BufRead,
+    {
+        Ok(BodyReader::new(reader, self.framing()?))
+    }
+
+    /// Read the whole body of the request, chunks are decoded.
+    pub fn read_body<R>(&self, reader: &mut R) -> Result<Vec<u8>>
+    where
+        R: BufRead,
+    {
+        self.body(reader)?.read_to_vec()
+    }
+
+    /// Write the head of the request.
+    pub fn write_to<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write!(writer, "{}", self)
+    }
+}
+
+impl Display for Request {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} HTTP/1.{}\r\n{}\r\n",
+            self.method, self.target, self.minor_version, self.headers
+        )
+    }
+}
+
+fn strip_scheme(target: &str) -> Option<&str> {
+    let (scheme, rest) = target.split_once("://")?;
+    if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn parse(input: &[u8]) -> Result<Request> {
+        Request::from_reader(&mut &input[..])
+    }
+
+    #[test]
+    fn pipelined() {
+        let mut input: &[u8] = b"\r\nPOST /form?a=1 HTTP/1.1\r\nHost: example.com\r\n\
+            Transfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n\
+            GET http://example.com HTTP/1.0\r\n\r\n";
+
+        let request = Request::from_reader(&mut input).unwrap();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.path(), "/form");
+        assert_eq!(request.query(), Some("a=1"));
+        assert_eq!(request.host(), Some("example.com"));
+        assert!(request.keep_alive());
+        assert_eq!(request.read_body(&mut input).unwrap(), b"abc");
+
+        let request = Request::from_reader(&mut input).unwrap();
+        assert_eq!(request.method(), Method::GET);
+        assert_eq!(request.path(), "/");
+        assert_eq!(request.host(), Some("example.com"));
+        assert!(!request.keep_alive());
+        assert!(request.read_body(&mut input).unwrap().is_empty());
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\n"),
+            Err(Error::Incomplete)
+        ));
+        assert!(matches!(parse(b""), Err(Error::Incomplete)));
+        assert!(matches!(
+            parse(b"BREW /pot HTTP/1.1\r\n\r\n"),
+            Err(Error::MethodNotImplemented)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nbad header\r\n\r\n"),
+            Err(Error::ParseError(..))
+        ));
+        let request = parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n").unwrap();
+        assert!(matches!(
+            request.framing(),
+            Err(Error::UnsupportedTransferCoding)
+        ));
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits {
+            max_line: 32,
+            max_head: 64,
+            max_headers: 2,
+        };
+        let parse = |input: &[u8]| Request::from_reader_with_limits(&mut &input[..], &limits);
+
+        assert!(parse(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n").is_ok());
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            Err(Error::TooManyHeaders)
+        ));
+        assert!(matches!(
+            parse(format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(32)).as_bytes()),
+            Err(Error::HeaderTooLarge)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nA: 0123456789abcdefghijklmn\r\nB: 0123456789abcdefghijklmn\r\n\r\n"),
+            Err(Error::HeaderTooLarge)
+        ));
+        // the reader is never consumed beyond the limit
+        let mut endless = io::repeat(b'a').take(1 << 20);
+        let mut reader = io::BufReader::new(&mut endless);
+        assert!(matches!(
+            Request::from_reader_with_limits(&mut reader, &limits),
+            Err(Error::HeaderTooLarge)
+        ));
+    }
+
+    #[test]
+    fn display() {
+        let mut request = Request::new(Method::GET, "/index.html");
+        request.attach_header("Host", "example.com");
+        assert_eq!(
+            request.to_string(),
+            "GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n"
+        );
+        assert_eq!(parse(request.to_string().as_bytes()).unwrap(), request);
+    }
+}
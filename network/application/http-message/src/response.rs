@@ -0,0 +1,261 @@
+use crate::{read_head, BodyReader, Error, Framing, Headers, Limits, Method, Result, Status};
+use httparse::EMPTY_HEADER;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, Write},
+    mem,
+};
+
+/// An HTTP response without body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// 0 for HTTP/1.0, 1 for HTTP/1.1
+    minor_version: u8,
+    code: u16,
+    reason: String,
+    headers: Headers,
+}
+
+impl Response {
+    /// An HTTP/1.1 response without header fields.
+    pub fn new(status: Status) -> Self {
+        Self {
+            minor_version: 1,
+            code: status.code(),
+            reason: status.reason().to_string(),
+            headers: Headers::new(),
+        }
+    }
+
+    /// Parse the head of a response from byte stream within the default limits, the body is
+    /// left in the reader.
+    pub fn from_reader<R>(reader: &mut R) -> Result<Self>
+    where
+        R: BufRead,
+    {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Parse the head of a response from byte stream within the given limits.
+    pub fn from_reader_with_limits<R>(reader: &mut R, limits: &Limits) -> Result<Self>
+    where
+        R: BufRead,
+    {
+        let buf = read_head(reader, limits)?;
+        let mut headers_buf = vec![EMPTY_HEADER; limits.max_headers];
+        let mut parser = httparse::Response::new(&mut headers_buf);
+        if parser.parse(&buf)?.is_partial() {
+            return Err(Error::Incomplete);
+        }
+
+        // all fields are present in a complete response
+        Ok(Self {
+            minor_version: parser.version.unwrap_or_default(),
+            code: parser.code.unwrap_or_default(),
+            reason: parser.reason.unwrap_or_default().to_string(),
+            headers: Headers::from_parsed(parser.headers),
+        })
+    }
+
+    /// Status code of the response.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// Reason phrase of the response.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Minor version of HTTP/1.x.
+    pub fn minor_version(&self) -> u8 {
+        self.minor_version
+    }
+
+    /// Set the minor version of HTTP/1.x, e.g. 0 for servers speaking only HTTP/1.0.
+    pub fn set_minor_version(&mut self, minor_version: u8) {
+        self.minor_version = minor_version;
+    }
+
+    /// Header fields of the response.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Mutable header fields of the response.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// Value of a header field, field names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Insert or replace a header field of the response.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name, value);
+    }
+
+    /// Insert or replace a header field of the response, chainable.
+    pub fn attach_header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.set_header(name, value);
+        self
+    }
+
+    /// Remove a header field of the response.
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.remove(name);
+    }
+
+    /// Whether the server keeps the connection open after the response, defined by:\
+    /// [https://tools.ietf.org/html/rfc7230#section-6.3](https://tools.ietf.org/html/rfc7230#section-6.3)
+    pub fn keep_alive(&self) -> bool {
+        if self.headers.has_token("Connection", "close") {
+            false
+        } else if self.headers.has_token("Connection", "keep-alive") {
+            true
+        } else {
+            self.minor_version >= 1
+        }
+    }
+
+    /// Whether the response to a request with the given method carries a body, defined by:\
+    /// [https://tools.ietf.org/html/rfc7230#section-3.3](https://tools.ietf.org/html/rfc7230#section-3.3)
+    pub fn has_body(&self, method: Method) -> bool {
+        let tunnel = method == Method::CONNECT && (200..300).contains(&self.code);
+        method != Method::HEAD
+            && !tunnel
+            && !(100..200).contains(&self.code)
+            && ![204, 304].contains(&self.code)
+    }
+
+    /// Framing of the body of the response to a request with the given method.
+    pub fn framing(&self, method: Method) -> Result<Framing> {
+        if !self.has_body(method) {
+            return Ok(Framing::Empty);
+        }
+        match self.headers.chunked() {
+            Some(true) => Ok(Framing::Chunked),
+            Some(false) => Ok(Framing::Close),
+            None => match self.headers.content_length()? {
+                Some(len) => Ok(Framing::Length(len)),
+                None => Ok(Framing::Close),
+            },
+        }
+    }
+
+    /// Reader over the body of the response, the reader must be positioned right after the
+    /// head.
+    pub fn body<R>(&self, reader: R, method: Method) -> Result<BodyReader<R>>
+    where
+        R: BufRead,
+    {
+        Ok(BodyReader::new(reader, self.framing(method)?))
+    }
+
+    /// Read the whole body of the response, chunks are decoded.
+    pub fn read_body<R>(&self, reader: &mut R, method: Method) -> Result<Vec<u8>>
+    where
+        R: BufRead,
+    {
+        self.body(reader, method)?.read_to_vec()
+    }
+
+    /// Write the head of the response.
+    pub fn write_to<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write!(writer, "{}", self)
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HTTP/1.{} {} {}\r\n{}\r\n",
+            self.minor_version, self.code, self.reason, self.headers
+        )
+    }
+}
+
+/// A helper struct to build HTTP responses.
+pub struct ResponseBuilder {
+    status: Status,
+    headers: Headers,
+}
+
+impl ResponseBuilder {
+    /// Construct a new builder with the given status.
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+        }
+    }
+
+    /// Insert or replace a header field of the response.
+    pub fn attach_header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Build an HTTP/1.1 response.
+    pub fn build(&mut self) -> Response {
+        let mut response = Response::new(self.status);
+        response.headers = mem::take(&mut self.headers);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies() {
+        let mut input: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello\
+            HTTP/1.1 304 Not Modified\r\nContent-Length: 5\r\n\r\n\
+            HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nworld\r\n0\r\n\r\n\
+            HTTP/1.0 200 OK\r\n\r\nuntil closed";
+
+        let response = Response::from_reader(&mut input).unwrap();
+        assert_eq!(response.code(), 200);
+        assert_eq!(
+            response.read_body(&mut input, Method::GET).unwrap(),
+            b"hello"
+        );
+
+        let response = Response::from_reader(&mut input).unwrap();
+        assert_eq!(response.framing(Method::GET).unwrap(), Framing::Empty);
+
+        let response = Response::from_reader(&mut input).unwrap();
+        assert_eq!(response.framing(Method::HEAD).unwrap(), Framing::Empty);
+        assert_eq!(
+            response.read_body(&mut input, Method::GET).unwrap(),
+            b"world"
+        );
+
+        let response = Response::from_reader(&mut input).unwrap();
+        assert!(!response.keep_alive());
+        assert_eq!(
+            response.read_body(&mut input, Method::GET).unwrap(),
+            b"until closed"
+        );
+    }
+
+    #[test]
+    fn display() {
+        let response = ResponseBuilder::new(Status::NotFound)
+            .attach_header("Content-Length", "0")
+            .build();
+        assert_eq!(
+            response.to_string(),
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        );
+        let parsed = Response::from_reader(&mut response.to_string().as_bytes()).unwrap();
+        assert_eq!(parsed, response);
+    }
+}
@@ -0,0 +1,108 @@
+/// Status codes sent by servers built on this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// 200 OK
+    OK,
+    /// 201 Created
+    Created,
+    /// 204 No Content
+    NoContent,
+    /// 206 Partial Content
+    PartialContent,
+    /// 301 Moved Permanently
+    MovedPermanently,
+    /// 302 Found
+    Found,
+    /// 304 Not Modified
+    NotModified,
+    /// 400 Bad Request
+    BadRequest,
+    /// 403 Forbidden
+    Forbidden,
+    /// 404 Not Found
+    NotFound,
+    /// 405 Method Not Allowed
+    MethodNotAllowed,
+    /// 408 Request Timeout
+    RequestTimeout,
+    /// 411 Length Required
+    LengthRequired,
+    /// 413 Payload Too Large
+    PayloadTooLarge,
+    /// 416 Range Not Satisfiable
+    RangeNotSatisfiable,
+    /// 431 Request Header Fields Too Large
+    RequestHeaderFieldsTooLarge,
+    /// 500 Internal Server Error
+    InternalServerError,
+    /// 501 Not Implemented
+    NotImplemented,
+    /// 502 Bad Gateway
+    BadGateway,
+    /// 503 Service Unavailable
+    ServiceUnavailable,
+    /// 504 Gateway Timeout
+    GatewayTimeout,
+    /// 505 HTTP Version Not Supported
+    HTTPVersionNotSupported,
+}
+
+use Status::*;
+
+impl Status {
+    /// The three-digit status code.
+    pub fn code(&self) -> u16 {
+        match self {
+            OK => 200,
+            Created => 201,
+            NoContent => 204,
+            PartialContent => 206,
+            MovedPermanently => 301,
+            Found => 302,
+            NotModified => 304,
+            BadRequest => 400,
+            Forbidden => 403,
+            NotFound => 404,
+            MethodNotAllowed => 405,
+            RequestTimeout => 408,
+            LengthRequired => 411,
+            PayloadTooLarge => 413,
+            RangeNotSatisfiable => 416,
+            RequestHeaderFieldsTooLarge => 431,
+            InternalServerError => 500,
+            NotImplemented => 501,
+            BadGateway => 502,
+            ServiceUnavailable => 503,
+            GatewayTimeout => 504,
+            HTTPVersionNotSupported => 505,
+        }
+    }
+
+    /// The reason phrase recommended by the RFCs.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            OK => "OK",
+            Created => "Created",
+            NoContent => "No Content",
+            PartialContent => "Partial Content",
+            MovedPermanently => "Moved Permanently",
+            Found => "Found",
+            NotModified => "Not Modified",
+            BadRequest => "Bad Request",
+            Forbidden => "Forbidden",
+            NotFound => "Not Found",
+            MethodNotAllowed => "Method Not Allowed",
+            RequestTimeout => "Request Timeout",
+            LengthRequired => "Length Required",
+            PayloadTooLarge => "Payload Too Large",
+            RangeNotSatisfiable => "Range Not Satisfiable",
+            RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            InternalServerError => "Internal Server Error",
+            NotImplemented => "Not Implemented",
+            BadGateway => "Bad Gateway",
+            ServiceUnavailable => "Service Unavailable",
+            GatewayTimeout => "Gateway Timeout",
+            HTTPVersionNotSupported => "HTTP Version Not Supported",
+        }
+    }
+}
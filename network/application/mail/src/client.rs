@@ -1,177 +1,174 @@
-use crate::{resolver::resolve, Mail, MailError, Result};
-use chrono::offset::Local;
-use log::{error, info};
+use crate::{resolver::resolve, smtp::Client, Credentials, Mail, MailError, Result};
+use log::{error, info, warn};
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Write, Read},
-    net::{Ipv4Addr, TcpStream},
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
 };
-use base64_stream::ToBase64Reader;
 
 const SMTP_PORT: u16 = 25;
-const BOUNDARY: &str = "frontier";
-const BUF_LEN: usize = 0x80;
 
-pub fn send(mail: &Mail) -> Result<()> {
-    info!("Raw input data: {:?}", mail);
-    let domain = extract_domain(&mail.to)?;
-    info!("Resolving domain: {}", domain);
-    let server = resolve(&domain)?;
-    info!("Resolved to: {}", server);
-
-    transmit(mail, server)?;
-
-    Ok(())
+/// Outcome of sending a mail, each recipient is accepted or rejected on its own.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub accepted: Vec<String>,
+    /// Rejected recipients with the reason of each
+    pub rejected: Vec<(String, String)>,
 }
 
-fn extract_domain(mailbox: &str) -> Result<&str> {
-    let at = mailbox.find('@').ok_or_else(|| {
-        error!("Found no @ symbol in mailbox");
-        MailError::MalformedInput
-    })?;
-    Ok(&mailbox[at + 1..])
-}
-
-fn transmit(mail: &Mail, server: Ipv4Addr) -> Result<()> {
-    let local_domain = extract_domain(&mail.from)?;
-    let mut stream = TcpStream::connect((server, SMTP_PORT))?;
-    let mut reader = BufReader::new(stream.try_clone()?);
-
-    // greeting message from server
-    exit_on_negative_reply(&mut reader)?;
-
-    info!("HELO {}", local_domain);
-    write!(stream, "HELO {}\r\n", local_domain)?;
-    exit_on_negative_reply(&mut reader)?;
-
-    info!("MAIL FROM:<{}>", &mail.from);
-    write!(stream, "MAIL FROM:<{}>\r\n", &mail.from)?;
-    exit_on_negative_reply(&mut reader)?;
-
-    info!("RCPT TO:<{}>", &mail.to);
-    write!(stream, "RCPT TO:<{}>\r\n", &mail.to)?;
-    exit_on_negative_reply(&mut reader)?;
-
-    info!("DATA");
-    write!(stream, "DATA\r\n")?;
-    exit_on_negative_reply(&mut reader)?;
-
-    transmit_multipart(&mut stream, mail)?;
-
-    write!(stream, ".\r\n")?;
-    exit_on_negative_reply(&mut reader)?;
-
-    info!("QUIT");
-    write!(stream, "QUIT\r\n")?;
-    Ok(())
-}
+impl Report {
+    fn reject_all(&mut self, recipients: &[String], reason: &str) {
+        self.rejected.extend(
+            recipients
+                .iter()
+                .map(|recipient| (recipient.clone(), reason.to_string())),
+        );
+    }
 
-fn transmit_multipart(stream: &mut TcpStream, mail: &Mail) -> Result<()> {
-    info!("Writing headers");
-    write!(stream, "From: {}<{}>\r\n", &mail.send, &mail.from)?;
-    write!(stream, "To: {}<{}>\r\n", &mail.recv, &mail.to)?;
-    write!(stream, "Subject: Test\r\n")?;
-    write!(
-        stream,
-        "Date: {}\r\n",
-        Local::now().format("%d %b %Y %T %z")
-    )?;
-    write!(stream, "Mime-Version: 1.0\r\n")?;
-    write!(stream, "Content-Type: multipart/mixed; boundary={}\r\n", BOUNDARY)?;
-    write!(stream, "\r\n")?;
-
-    write!(stream, "--{}\r\n", BOUNDARY)?;
-    write!(stream, "Content-Type: text/plain\r\n")?;
-    write!(stream, "\r\n")?;
-    for line in mail.body.lines() {
-        info!("BODY: {}", line);
-        write!(stream, "{}\r\n", line)?;
+    fn into_result(self) -> Result<Self> {
+        if self.accepted.is_empty() {
+            Err(MailError::NoRecipientAccepted(self.rejected))
+        } else {
+            Ok(self)
+        }
     }
+}
 
-    if let Some(path) = &mail.image {
-        info!("Sending image");
-        let img = File::open(path)?;
-        let name = path.file_name().expect("File name exist if it can be opened");
-        write!(stream, "--{}\r\n", BOUNDARY)?;
-        write!(stream, "Content-Type: image/jpeg; name={:?}\r\n", name)?;
-        write!(stream, "Content-Transfer-Encoding: base64\r\n")?;
-        write!(stream, "Content-Disposition: attachment; filename={:?}\r\n", name)?;
-        write!(stream, "\r\n")?;
-
-        let mut reader = ToBase64Reader::new(img);
-        let mut buf = [0; BUF_LEN];
-        let mut size = 0;
-        loop {
-            let len = reader.read(&mut buf)?;
-            if len == 0 {
-                break;
+/// Deliver a mail directly to the mail servers of its recipients, one session per domain.\
+/// Fails only if no recipient accepted the mail, partial failures are in the report.
+pub fn send(mail: &Mail) -> Result<Report> {
+    info!("Raw input data: {:?}", mail);
+    let message = mail.to_bytes()?;
+    let helo = mail.from.domain()?;
+
+    let mut report = Report::default();
+    for (domain, recipients) in by_domain(mail)? {
+        info!("Resolving domain: {}", domain);
+        match deliver_to_domain(&domain, helo, &mail.from.address, &recipients, &message) {
+            Ok(domain_report) => {
+                report.accepted.extend(domain_report.accepted);
+                report.rejected.extend(domain_report.rejected);
+            }
+            Err(err) => {
+                error!("Delivery to {} failed: {}", domain, err);
+                report.reject_all(&recipients, &err.to_string());
             }
-            size += len;
-            stream.write_all(&buf[..len])?;
-            write!(stream, "\r\n")?;
         }
-        info!("{} bytes sent", size);
     }
 
-    write!(stream, "--{}--\r\n", BOUNDARY)?;
-
-    // end of DATA section, a single dot on its own line
-    Ok(())
+    report.into_result()
 }
 
-struct SMTPReply {
-    code: u16,
-    message: String,
+/// Submit a mail through a relay, authenticating first if credentials are given.\
+/// Fails only if no recipient accepted the mail, partial failures are in the report.
+pub fn send_via<A>(mail: &Mail, relay: A, credentials: Option<&Credentials>) -> Result<Report>
+where
+    A: ToSocketAddrs,
+{
+    info!("Raw input data: {:?}", mail);
+    let message = mail.to_bytes()?;
+    let recipients = mail
+        .recipients()
+        .map(|mailbox| mailbox.domain().map(|_| mailbox.address.clone()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let stream = TcpStream::connect(relay)?;
+    let helo = mail.from.domain()?;
+    deliver(
+        stream,
+        helo,
+        &mail.from.address,
+        &recipients,
+        &message,
+        credentials,
+    )?
+    .into_result()
 }
 
-impl SMTPReply {
-    fn from_reader<R>(mut reader: R) -> Result<Self>
-    where
-        R: BufRead,
-    {
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-        let space = line.find(' ').ok_or_else(|| {
-            error!("Malformed response from SMTP server: no space");
-            error!("{}", line);
-            MailError::MalfomredResponse
-        })?;
-        let mut message = line.split_off(space);
-        // last character is \r
-        message.pop();
-        let code = line.parse::<u16>().map_err(|_| {
-            error!("Malformed response from SMTP server: missing reply code");
-            error!("{}{}", line, message);
-            MailError::MalfomredResponse
-        })?;
-
-        let reply = SMTPReply { code, message };
-        info!("SMTP server reply: {:?}", reply);
-        Ok(reply)
+/// Run one mail transaction over a fresh SMTP session.\
+/// Recipients rejected by the server are reported, the mail is sent to the others.
+pub fn deliver<S>(
+    stream: S,
+    helo: &str,
+    from: &str,
+    recipients: &[String],
+    message: &[u8],
+    credentials: Option<&Credentials>,
+) -> Result<Report>
+where
+    S: Read + Write,
+{
+    let mut client = Client::connect(stream)?;
+    client.hello(helo)?;
+    if let Some(credentials) = credentials {
+        client.authenticate(credentials)?;
+    }
+    client.mail_from(from, message.len())?;
+
+    let mut report = Report::default();
+    for recipient in recipients {
+        let reply = client.rcpt_to(recipient)?;
+        if reply.is_positive() {
+            report.accepted.push(recipient.clone());
+        } else {
+            warn!("Recipient {} rejected: {}", recipient, reply);
+            report.rejected.push((recipient.clone(), reply.to_string()));
+        }
     }
 
-    fn is_err(&self) -> bool {
-        // 4yz  Transient Negative Completion reply
-        // 5yz  Permanent Negative Completion reply
-        self.code / 100 >= 4
+    if report.accepted.is_empty() {
+        client.reset()?;
+    } else {
+        match client.data(message) {
+            Ok(_) => (),
+            Err(MailError::NegativeReply(reply)) => {
+                let accepted: Vec<String> = report.accepted.drain(..).collect();
+                report.reject_all(&accepted, &reply.to_string());
+            }
+            Err(err) => return Err(err),
+        }
     }
+
+    // the outcome of the transaction is already known
+    if let Err(err) = client.quit() {
+        warn!("Failed to end the session: {}", err);
+    }
+    Ok(report)
 }
 
-impl std::fmt::Debug for SMTPReply {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}{}", self.code, self.message)
+/// Envelope recipients grouped by domain, in order of first appearance.
+fn by_domain(mail: &Mail) -> Result<Vec<(String, Vec<String>)>> {
+    let mut groups: Vec<(String, Vec<String>)> = vec![];
+    for mailbox in mail.recipients() {
+        let domain = mailbox.domain()?.to_ascii_lowercase();
+        match groups.iter_mut().find(|(d, _)| *d == domain) {
+            Some((_, recipients)) => recipients.push(mailbox.address.clone()),
+            None => groups.push((domain, vec![mailbox.address.clone()])),
+        }
     }
+    Ok(groups)
 }
 
-fn exit_on_negative_reply<R>(reader: &mut R) -> Result<()>
-where
-    R: BufRead,
-{
-    let reply = SMTPReply::from_reader(reader)?;
-    if reply.is_err() {
-        error!("Negative server reply: {:?}", reply);
-        Err(MailError::NegativeReply)
-    } else {
-        Ok(())
+fn deliver_to_domain(
+    domain: &str,
+    helo: &str,
+    from: &str,
+    recipients: &[String],
+    message: &[u8],
+) -> Result<Report> {
+    let servers = resolve(domain)?;
+
+    // fall back to less preferred servers only when a server cannot be reached
+    let mut last_err = MailError::DomainNotFound;
+    for server in servers {
+        info!("Resolved to: {}", server);
+        match TcpStream::connect((server, SMTP_PORT)) {
+            Ok(stream) => return deliver(stream, helo, from, recipients, message, None),
+            Err(err) => {
+                error!("Cannot reach {}: {}", server, err);
+                last_err = err.into();
+            }
+        }
     }
-}
\ No newline at end of file
+
+    Err(last_err)
+}
@@ -1,44 +1,60 @@
 pub mod client;
+mod message;
 mod resolver;
+pub mod server;
+pub mod smtp;
 
-use std::{
-    io,
-    path::PathBuf,
-};
-use trust_dns_resolver::error::ResolveError;
+pub use message::{Attachment, Mail, Mailbox};
+pub use smtp::{Credentials, Reply};
+
+use std::io;
 
 #[derive(Debug)]
 pub enum MailError {
     IOError(io::Error),
-    ResolveError(ResolveError),
+    ResolveError(dns::Error),
     DomainNotFound,
     MalformedInput,
     MalfomredResponse,
-    NegativeReply,
+    NegativeReply(Reply),
+    AuthenticationFailed(Reply),
+    /// The server lacks an extension required by the client
+    NotSupported(&'static str),
+    /// The message exceeds the size limit advertised by the server
+    MessageTooLarge(usize),
+    /// Every recipient was rejected, with the reason of each
+    NoRecipientAccepted(Vec<(String, String)>),
 }
 
 use MailError::*;
 
 pub type Result<T> = std::result::Result<T, MailError>;
 
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IOError(err) => write!(f, "{}", err),
+            ResolveError(err) => write!(f, "{}", err),
+            DomainNotFound => write!(f, "no mail server found for the domain"),
+            MalformedInput => write!(f, "malformed input"),
+            MalfomredResponse => write!(f, "malformed response from the SMTP server"),
+            NegativeReply(reply) => write!(f, "negative reply: {}", reply),
+            AuthenticationFailed(reply) => write!(f, "authentication failed: {}", reply),
+            NotSupported(extension) => write!(f, "server does not support {}", extension),
+            MessageTooLarge(max) => write!(f, "message exceeds the limit of {} bytes", max),
+            NoRecipientAccepted(..) => write!(f, "no recipient accepted"),
+        }
+    }
+}
+
 impl From<io::Error> for MailError {
     fn from(err: io::Error) -> Self {
         IOError(err)
     }
 }
 
-impl From<ResolveError> for MailError {
-    fn from(err: ResolveError) -> Self {
+impl From<dns::Error> for MailError {
+    fn from(err: dns::Error) -> Self {
         ResolveError(err)
     }
 }
-
-#[derive(Debug)]
-pub struct Mail {
-    pub send: String,
-    pub from: String,
-    pub recv: String,
-    pub to: String,
-    pub body: String,
-    pub image: Option<PathBuf>,
-}
@@ -1,17 +1,18 @@
-use clap::{App, Arg};
-use mail::{client::send, Mail, MailError, Result};
+use clap::{App, Arg, ArgMatches};
+use mail::{
+    client::{send, send_via},
+    Attachment, Credentials, Mail, MailError, Mailbox, Result,
+};
+use std::{env, path::Path};
 
 const DEFAULT_SEND: &str = "Alice";
 const DEFAULT_FROM: &str = "alice@crepe.fr";
+/// Kept out of the command line, where other users could read it
+const PASSWORD_VAR: &str = "SMTP_PASSWORD";
 
 fn main() -> Result<()> {
     env_logger::init();
 
-    let mail = from_arguments().ok_or(MailError::MalformedInput)?;
-    send(&mail)
-}
-
-fn from_arguments() -> Option<Mail> {
     let matches = App::new("Mail client")
         .version(env!("CARGO_PKG_VERSION"))
         .arg(
@@ -28,46 +29,132 @@ fn from_arguments() -> Option<Mail> {
                 .value_name("MAILBOX")
                 .takes_value(true),
         )
-        .arg(
-            Arg::with_name("recv")
-                .long("recv")
-                .short("r")
-                .value_name("NAME")
-                .takes_value(true)
-                .required(true),
-        )
         .arg(
             Arg::with_name("to")
                 .long("to")
                 .short("t")
                 .value_name("MAILBOX")
                 .takes_value(true)
-                .required(true),
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help("either address or \"Name <address>\""),
         )
         .arg(
-            Arg::with_name("image")
-                .long("img")
-                .short("i")
+            Arg::with_name("cc")
+                .long("cc")
+                .value_name("MAILBOX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("bcc")
+                .long("bcc")
+                .value_name("MAILBOX")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("subject")
+                .long("subject")
+                .value_name("TEXT")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("attach")
+                .long("attach")
+                .short("a")
+                .alias("img")
                 .value_name("FILE")
                 .takes_value(true)
-                .help("supports only jpeg files")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("relay")
+                .long("relay")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .help("submit through this server instead of the recipients' mail servers"),
+        )
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .short("u")
+                .value_name("USERNAME")
+                .takes_value(true)
+                .requires("relay")
+                .help("authenticate to the relay, the password is read from SMTP_PASSWORD"),
         )
         .arg(Arg::with_name("body").value_name("BODY").required(true))
         .get_matches();
 
-    let send = matches.value_of("send").unwrap_or(DEFAULT_SEND).to_string();
-    let from = matches.value_of("from").unwrap_or(DEFAULT_FROM).to_string();
-    let recv = matches.value_of("recv")?.to_string();
-    let to = matches.value_of("to")?.to_string();
-    let body = matches.value_of("body")?.to_string();
-    let image = matches.value_of("image").map(|s| s.into());
+    let mail = from_arguments(&matches)?;
+    let report = match matches.value_of("relay") {
+        Some(relay) => {
+            let credentials = match matches.value_of("user") {
+                Some(user) => {
+                    let password = env::var(PASSWORD_VAR).map_err(|_| {
+                        eprintln!("{} is not set", PASSWORD_VAR);
+                        MailError::MalformedInput
+                    })?;
+                    Some(Credentials::new(user, &password))
+                }
+                None => None,
+            };
+            send_via(&mail, relay, credentials.as_ref())
+        }
+        None => send(&mail),
+    };
+
+    match report {
+        Ok(report) => {
+            for (recipient, reason) in &report.rejected {
+                eprintln!("{}: {}", recipient, reason);
+            }
+            Ok(())
+        }
+        Err(MailError::NoRecipientAccepted(rejected)) => {
+            for (recipient, reason) in &rejected {
+                eprintln!("{}: {}", recipient, reason);
+            }
+            Err(MailError::NoRecipientAccepted(rejected))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn from_arguments(matches: &ArgMatches) -> Result<Mail> {
+    let send = matches.value_of("send").unwrap_or(DEFAULT_SEND);
+    let from = matches.value_of("from").unwrap_or(DEFAULT_FROM);
+    let mut from: Mailbox = from.parse()?;
+    if from.name.is_none() {
+        from.name = Some(send.to_string());
+    }
+    let subject = matches.value_of("subject").unwrap_or("");
+    let body = matches.value_of("body").ok_or(MailError::MalformedInput)?;
+
+    let mut mail = Mail::new(from, subject, body);
+    mail.to = mailboxes(matches, "to")?;
+    mail.cc = mailboxes(matches, "cc")?;
+    mail.bcc = mailboxes(matches, "bcc")?;
+    mail.attachments = matches
+        .values_of("attach")
+        .into_iter()
+        .flatten()
+        .map(|path| Attachment::from_path(Path::new(path)))
+        .collect::<Result<_>>()?;
+
+    Ok(mail)
+}
 
-    Some(Mail {
-        send,
-        from,
-        recv,
-        to,
-        body,
-        image,
-    })
+fn mailboxes(matches: &ArgMatches, name: &str) -> Result<Vec<Mailbox>> {
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(str::parse)
+        .collect()
 }
@@ -0,0 +1,503 @@
+//! Mail messages in the Internet message format, defined by:\
+//! [https://tools.ietf.org/html/rfc5322](https://tools.ietf.org/html/rfc5322)\
+//! Bodies with attachments are sent as multipart MIME messages, defined by:\
+//! [https://tools.ietf.org/html/rfc2046#section-5.1](https://tools.ietf.org/html/rfc2046#section-5.1)
+
+use crate::{MailError, Result};
+use chrono::offset::Local;
+use log::error;
+use std::{
+    collections::hash_map::RandomState,
+    fmt, fs,
+    hash::{BuildHasher, Hasher},
+    path::Path,
+    process,
+    str::FromStr,
+    time::SystemTime,
+};
+
+/// Longest line allowed by SMTP, excluding CRLF.
+const MAX_LINE_LEN: usize = 998;
+/// Base64 content is wrapped at 76 characters as required by MIME.
+const BASE64_LINE_LEN: usize = 76;
+/// Bytes of text in an encoded word, keeping each word under 75 characters.
+const ENCODED_WORD_LEN: usize = 45;
+
+/// A mailbox, optionally with the display name of its owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub address: String,
+}
+
+impl Mailbox {
+    /// A mailbox without display name.
+    pub fn new(address: &str) -> Self {
+        Self {
+            name: None,
+            address: address.to_string(),
+        }
+    }
+
+    /// A mailbox with the display name of its owner.
+    pub fn with_name(name: &str, address: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            address: address.to_string(),
+        }
+    }
+
+    /// The domain part of the address.
+    pub fn domain(&self) -> Result<&str> {
+        validate_address(&self.address)?;
+        let at = self.address.rfind('@').expect("validated address");
+        Ok(&self.address[at + 1..])
+    }
+
+    fn header_value(&self) -> Result<String> {
+        validate_address(&self.address)?;
+        match &self.name {
+            Some(name) if !name.is_empty() => {
+                Ok(format!("{} <{}>", encode_phrase(name)?, self.address))
+            }
+            _ => Ok(self.address.clone()),
+        }
+    }
+}
+
+impl FromStr for Mailbox {
+    type Err = MailError;
+
+    /// Parse either a bare address or `Display Name <address>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let mailbox = match (s.find('<'), s.strip_suffix('>')) {
+            (Some(lt), Some(rest)) => {
+                let name = s[..lt].trim().trim_matches('"');
+                Self {
+                    name: Some(name.to_string()).filter(|name| !name.is_empty()),
+                    address: rest[lt + 1..].to_string(),
+                }
+            }
+            _ => Self::new(s),
+        };
+        validate_address(&mailbox.address)?;
+        Ok(mailbox)
+    }
+}
+
+/// Reject addresses that cannot be sent safely in SMTP commands or header fields.\
+/// Only a sanity check, the full grammar is far more permissive and complicated.
+pub(crate) fn validate_address(address: &str) -> Result<()> {
+    let at = address.rfind('@');
+    let valid = match at {
+        Some(at) => at > 0 && at + 1 < address.len(),
+        None => false,
+    } && !address
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>');
+
+    if valid {
+        Ok(())
+    } else {
+        error!("Invalid mailbox address: {:?}", address);
+        Err(MailError::MalformedInput)
+    }
+}
+
+/// A file attached to a mail.
+#[derive(Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(name: &str, content_type: &str, data: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            data,
+        }
+    }
+
+    /// Read a file as an attachment, its content type guessed from the extension.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string());
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        let content_type = match extension.as_deref() {
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("png") => "image/png",
+            Some("gif") => "image/gif",
+            Some("pdf") => "application/pdf",
+            Some("txt") | Some("log") => "text/plain",
+            Some("csv") => "text/csv",
+            Some("html") | Some("htm") => "text/html",
+            Some("json") => "application/json",
+            Some("zip") => "application/zip",
+            _ => "application/octet-stream",
+        };
+        Ok(Self::new(&name, content_type, data))
+    }
+}
+
+impl fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Attachment({:?}, {}, {} bytes)",
+            self.name,
+            self.content_type,
+            self.data.len()
+        )
+    }
+}
+
+/// A mail with a plain text body and optional attachments.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub from: Mailbox,
+    pub to: Vec<Mailbox>,
+    pub cc: Vec<Mailbox>,
+    /// Blind carbon copies, receive the mail without appearing in the header
+    pub bcc: Vec<Mailbox>,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Mail {
+    /// A mail without recipients nor attachments.
+    pub fn new(from: Mailbox, subject: &str, body: &str) -> Self {
+        Self {
+            from,
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
+            subject: subject.to_string(),
+            body: body.to_string(),
+            attachments: vec![],
+        }
+    }
+
+    /// Every recipient of the mail, blind carbon copies included.
+    pub fn recipients(&self) -> impl Iterator<Item = &Mailbox> {
+        self.to.iter().chain(&self.cc).chain(&self.bcc)
+    }
+
+    /// Format the mail as an Internet message with CRLF line endings, ready for the DATA
+    /// command save dot-stuffing.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut message = String::new();
+        header(&mut message, "From", &self.from.header_value()?);
+        for (name, mailboxes) in &[("To", &self.to), ("Cc", &self.cc)] {
+            if !mailboxes.is_empty() {
+                let value = mailboxes
+                    .iter()
+                    .map(Mailbox::header_value)
+                    .collect::<Result<Vec<_>>>()?
+                    .join(",\r\n ");
+                header(&mut message, name, &value);
+            }
+        }
+        header(&mut message, "Subject", &encode_text(&self.subject)?);
+        header(&mut message, "Date", &Local::now().to_rfc2822());
+        header(
+            &mut message,
+            "Message-ID",
+            &format!(
+                "<{:016x}.{}@{}>",
+                random(),
+                process::id(),
+                self.from.domain()?
+            ),
+        );
+        header(&mut message, "MIME-Version", "1.0");
+
+        let (text_headers, text) = text_part(&self.body);
+        if self.attachments.is_empty() {
+            message += &text_headers;
+            message += "\r\n";
+            message += &text;
+            return Ok(message.into_bytes());
+        }
+
+        // "=_" never appears in base64, only the text part may contain the boundary
+        let boundary = loop {
+            let boundary = format!("=_{:016x}", random());
+            if !text.contains(&boundary) {
+                break boundary;
+            }
+        };
+        header(
+            &mut message,
+            "Content-Type",
+            &format!("multipart/mixed; boundary=\"{}\"", boundary),
+        );
+        message += "\r\nThis is a multipart message in MIME format.\r\n";
+
+        message += &format!("--{}\r\n{}\r\n{}", boundary, text_headers, text);
+        for attachment in &self.attachments {
+            validate_content_type(&attachment.content_type)?;
+            let name = sanitize(&attachment.name);
+            message += &format!("--{}\r\n", boundary);
+            header(
+                &mut message,
+                "Content-Type",
+                &format!("{}; {}", attachment.content_type, parameter("name", &name)),
+            );
+            header(&mut message, "Content-Transfer-Encoding", "base64");
+            header(
+                &mut message,
+                "Content-Disposition",
+                &format!("attachment; {}", parameter("filename", &name)),
+            );
+            message += "\r\n";
+            message += &wrapped_base64(&attachment.data);
+        }
+        message += &format!("--{}--\r\n", boundary);
+
+        Ok(message.into_bytes())
+    }
+}
+
+fn header(message: &mut String, name: &str, value: &str) {
+    *message += name;
+    *message += ": ";
+    *message += value;
+    *message += "\r\n";
+}
+
+/// Header fields and content of the text part. ASCII text with short lines is sent as is,
+/// anything else is base64 encoded.
+fn text_part(body: &str) -> (String, String) {
+    let mut text: String = body.lines().map(|line| format!("{}\r\n", line)).collect();
+    let plain = text.is_ascii() && body.lines().all(|line| line.len() <= MAX_LINE_LEN);
+
+    let mut headers = String::new();
+    if plain {
+        header(&mut headers, "Content-Type", "text/plain; charset=us-ascii");
+        header(&mut headers, "Content-Transfer-Encoding", "7bit");
+    } else {
+        header(&mut headers, "Content-Type", "text/plain; charset=utf-8");
+        header(&mut headers, "Content-Transfer-Encoding", "base64");
+        text = wrapped_base64(text.as_bytes());
+    }
+    (headers, text)
+}
+
+fn wrapped_base64(data: &[u8]) -> String {
+    let encoded = base64::encode(data);
+    encoded
+        .as_bytes()
+        .chunks(BASE64_LINE_LEN)
+        .map(|line| format!("{}\r\n", String::from_utf8_lossy(line)))
+        .collect()
+}
+
+/// Unstructured header text, non-ASCII text is sent as encoded words, defined by:\
+/// [https://tools.ietf.org/html/rfc2047](https://tools.ietf.org/html/rfc2047)
+fn encode_text(text: &str) -> Result<String> {
+    // a line break would end the header field and start another one
+    if text.chars().any(|c| c == '\r' || c == '\n') {
+        error!("Line break in header field: {:?}", text);
+        return Err(MailError::MalformedInput);
+    }
+    if text.is_ascii() {
+        Ok(text.to_string())
+    } else {
+        Ok(encoded_words(text))
+    }
+}
+
+/// A display name, quoted unless it must be encoded.
+fn encode_phrase(name: &str) -> Result<String> {
+    let encoded = encode_text(name)?;
+    if name.is_ascii() {
+        Ok(quote(&encoded))
+    } else {
+        Ok(encoded)
+    }
+}
+
+fn encoded_words(text: &str) -> String {
+    let mut words = vec![];
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = (start + ENCODED_WORD_LEN).min(text.len());
+        // characters are never split between words
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        words.push(format!(
+            "=?UTF-8?B?{}?=",
+            base64::encode(&text.as_bytes()[start..end])
+        ));
+        start = end;
+    }
+    words.join("\r\n ")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// File names come from the file system, control characters are simply dropped.
+fn sanitize(name: &str) -> String {
+    name.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// A MIME parameter, an ASCII value is a quoted string as defined by RFC 2045, anything
+/// else is percent-encoded UTF-8 as defined by:\
+/// [https://tools.ietf.org/html/rfc2231#section-4](https://tools.ietf.org/html/rfc2231#section-4)
+fn parameter(attribute: &str, value: &str) -> String {
+    if value.is_ascii() {
+        return format!("{}={}", attribute, quote(value));
+    }
+    let encoded: String = value
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!("{}*=UTF-8''{}", attribute, encoded)
+}
+
+/// Accept `type/subtype` optionally followed by parameters, tokens as defined by:\
+/// [https://tools.ietf.org/html/rfc2045#section-5.1](https://tools.ietf.org/html/rfc2045#section-5.1)
+fn validate_content_type(content_type: &str) -> Result<()> {
+    let token = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?=".contains(c))
+    };
+    let media_type = content_type.split(';').next().unwrap_or("");
+    let valid = match media_type.trim().split_once('/') {
+        Some((kind, subtype)) => token(kind) && token(subtype),
+        None => false,
+    } && !content_type.chars().any(|c| c.is_control());
+
+    if valid {
+        Ok(())
+    } else {
+        error!("Invalid content type: {:?}", content_type);
+        Err(MailError::MalformedInput)
+    }
+}
+
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hasher.write_u128(nanos);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail() -> Mail {
+        let mut mail = Mail::new(
+            Mailbox::with_name("Alice", "alice@crepe.fr"),
+            "Réunion",
+            "first line\n.second line\r\n",
+        );
+        mail.to = vec![
+            "Bob <bob@galette.fr>".parse().unwrap(),
+            "carol@galette.fr".parse().unwrap(),
+        ];
+        mail.bcc = vec![Mailbox::new("dave@galette.fr")];
+        mail
+    }
+
+    #[test]
+    fn headers() -> Result<()> {
+        let message = String::from_utf8(mail().to_bytes()?).unwrap();
+        assert!(message.starts_with("From: \"Alice\" <alice@crepe.fr>\r\n"));
+        assert!(message.contains("To: \"Bob\" <bob@galette.fr>,\r\n carol@galette.fr\r\n"));
+        assert!(message.contains("Subject: =?UTF-8?B?UsOpdW5pb24=?=\r\n"));
+        assert!(!message.contains("dave"));
+        assert!(message.ends_with("\r\n\r\nfirst line\r\n.second line\r\n"));
+
+        let mut injected = mail();
+        injected.subject = "Hi\r\nBcc: eve@evil.com".to_string();
+        assert!(matches!(
+            injected.to_bytes(),
+            Err(MailError::MalformedInput)
+        ));
+        assert!("Eve <eve@evil.com>\r\nRCPT TO:<x@y>"
+            .parse::<Mailbox>()
+            .is_err());
+        assert!("no-at-sign".parse::<Mailbox>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn multipart() -> Result<()> {
+        let mut mail = mail();
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        mail.attachments = vec![Attachment::new(
+            "data.bin",
+            "application/octet-stream",
+            data.clone(),
+        )];
+        let message = String::from_utf8(mail.to_bytes()?).unwrap();
+
+        let boundary_at = message.find("boundary=\"").unwrap() + "boundary=\"".len();
+        let boundary = &message[boundary_at..boundary_at + 18];
+        let parts: Vec<&str> = message.split(&format!("--{}", boundary)).collect();
+        // preamble, text, attachment and epilogue
+        assert_eq!(parts.len(), 4);
+        assert!(parts[1].contains("first line\r\n.second line\r\n"));
+        assert!(parts[2].contains("Content-Disposition: attachment; filename=\"data.bin\""));
+
+        let encoded = parts[2].split("\r\n\r\n").nth(1).unwrap();
+        assert!(encoded.lines().all(|line| line.len() <= BASE64_LINE_LEN));
+        let decoded = base64::decode(encoded.replace("\r\n", "")).unwrap();
+        assert_eq!(decoded, data);
+        Ok(())
+    }
+    #[test]
+    fn attachment_headers() -> Result<()> {
+        let mut mail = mail();
+        mail.attachments = vec![
+            Attachment::new("say \"hi\"\r\nBcc: eve@evil.com", "text/plain", vec![]),
+            Attachment::new("résumé.pdf", "application/pdf", vec![]),
+        ];
+        let message = String::from_utf8(mail.to_bytes()?).unwrap();
+        assert!(message
+            .contains("Content-Type: text/plain; name=\"say \\\"hi\\\"Bcc: eve@evil.com\"\r\n"));
+        assert!(!message.contains("\r\nBcc:"));
+        assert!(message
+            .contains("Content-Type: application/pdf; name*=UTF-8''r%C3%A9sum%C3%A9.pdf\r\n"));
+        assert!(message.contains(
+            "Content-Disposition: attachment; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf\r\n"
+        ));
+
+        for content_type in &["text/plain\r\nBcc: eve@evil.com", "text", "text/pl ain", ""] {
+            mail.attachments = vec![Attachment::new("a.txt", content_type, vec![])];
+            assert!(matches!(mail.to_bytes(), Err(MailError::MalformedInput)));
+        }
+        mail.attachments = vec![Attachment::new(
+            "a.txt",
+            "text/plain; charset=utf-8",
+            vec![],
+        )];
+        assert!(mail.to_bytes().is_ok());
+        Ok(())
+    }
+}
@@ -1,31 +1,58 @@
 use crate::{MailError, Result};
+use dns::Resolver;
+use log::warn;
 use std::net::Ipv4Addr;
-use trust_dns_resolver::Resolver;
 
-/// Two-step resolve of a mail domain:
-/// 1. MX query to find the canonical names
-/// 2. A query to find the IP
+/// Two-step resolve of a mail domain, defined by:\
+/// [https://tools.ietf.org/html/rfc5321#section-5.1](https://tools.ietf.org/html/rfc5321#section-5.1)
+/// 1. MX query to find the mail exchanges, ordered by preference
+/// 2. A query to find the IPs of each exchange
 ///
-/// this function always choose the first entry in the answer,
-/// the query result may not be a valid mail server.
-pub fn resolve(domain: &str) -> Result<Ipv4Addr> {
+/// A domain without MX records is its own mail exchange. The IPs are returned in the order
+/// they should be tried.
+pub fn resolve(domain: &str) -> Result<Vec<Ipv4Addr>> {
     if domain == "localhost" {
-        return Ok(Ipv4Addr::LOCALHOST);
+        return Ok(vec![Ipv4Addr::LOCALHOST]);
     }
 
-    let resolver = Resolver::default()?;
-    let mx = resolver
-        .mx_lookup(domain)?
-        .into_iter()
-        .next()
-        .ok_or(MailError::DomainNotFound)?;
-    let ip = resolver
-        .ipv4_lookup(&mx.exchange().to_ascii())?
-        .into_iter()
-        .next()
-        .ok_or(MailError::DomainNotFound)?;
-
-    Ok(ip)
+    resolve_with(&Resolver::system(), domain)
+}
+
+/// Resolve a mail domain with the given resolver.
+pub fn resolve_with(resolver: &Resolver, domain: &str) -> Result<Vec<Ipv4Addr>> {
+    let exchanges = resolver.mx_lookup(domain)?;
+    if exchanges.is_empty() {
+        // the implicit MX
+        return non_empty(resolver.ipv4_lookup(domain)?);
+    }
+
+    let mut ips = vec![];
+    for mx in exchanges {
+        // a null MX, the domain accepts no mail
+        if mx.exchange.is_empty() {
+            return Err(MailError::DomainNotFound);
+        }
+        // a broken exchange should not prevent delivery to the others
+        match resolver.ipv4_lookup(&mx.exchange) {
+            Ok(found) => {
+                for ip in found {
+                    if !ips.contains(&ip) {
+                        ips.push(ip);
+                    }
+                }
+            }
+            Err(err) => warn!("Cannot resolve mail exchange {}: {}", mx.exchange, err),
+        }
+    }
+    non_empty(ips)
+}
+
+fn non_empty(ips: Vec<Ipv4Addr>) -> Result<Vec<Ipv4Addr>> {
+    if ips.is_empty() {
+        Err(MailError::DomainNotFound)
+    } else {
+        Ok(ips)
+    }
 }
 
 #[test]
@@ -34,3 +61,49 @@ fn resolve_test() -> Result<()> {
     // println!("{:?}", _ip);
     Ok(())
 }
+
+#[test]
+fn resolve_with_test() -> Result<()> {
+    use dns::{Mx, RData, Record, StubServer};
+
+    let mx = |domain, preference, exchange: &str| {
+        Record::new(
+            domain,
+            300,
+            RData::MX(Mx {
+                preference,
+                exchange: exchange.to_string(),
+            }),
+        )
+    };
+    let a = |name, ip| Record::new(name, 300, RData::A(ip));
+    let records = vec![
+        mx("crepe.fr", 20, "backup.crepe.fr"),
+        mx("crepe.fr", 10, "mail.crepe.fr"),
+        mx("crepe.fr", 5, "broken.crepe.fr"),
+        a("backup.crepe.fr", Ipv4Addr::new(192, 0, 2, 2)),
+        a("mail.crepe.fr", Ipv4Addr::new(192, 0, 2, 1)),
+        a("galette.fr", Ipv4Addr::new(192, 0, 2, 3)),
+        mx("null.fr", 0, ""),
+    ];
+    let server = StubServer::spawn(records)?;
+    let resolver = Resolver::new(server.local_addr());
+
+    assert_eq!(
+        resolve_with(&resolver, "crepe.fr")?,
+        vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)]
+    );
+    assert_eq!(
+        resolve_with(&resolver, "galette.fr")?,
+        vec![Ipv4Addr::new(192, 0, 2, 3)]
+    );
+    assert!(matches!(
+        resolve_with(&resolver, "null.fr"),
+        Err(MailError::DomainNotFound)
+    ));
+    assert!(matches!(
+        resolve_with(&resolver, "nowhere.fr"),
+        Err(MailError::ResolveError(dns::Error::NameNotFound))
+    ));
+    Ok(())
+}
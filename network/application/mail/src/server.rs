@@ -0,0 +1,351 @@
+//! A tiny in-process SMTP server for tests, keeping received mails in memory.
+
+use crate::Credentials;
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the server checks for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Clients silent for longer are disconnected.
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Behavior of the test server.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Require authentication with these credentials before accepting mails
+    pub credentials: Option<Credentials>,
+    /// SASL mechanisms advertised when credentials are required
+    pub mechanisms: Vec<&'static str>,
+    /// Recipients answered with 550
+    pub rejected: Vec<String>,
+    /// Size limit advertised with the SIZE extension
+    pub max_size: Option<usize>,
+    /// Whether EHLO is understood, HELO always is
+    pub extended: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            credentials: None,
+            mechanisms: vec!["PLAIN", "LOGIN"],
+            rejected: vec![],
+            max_size: None,
+            extended: true,
+        }
+    }
+}
+
+/// A mail as received by the server.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub from: String,
+    pub recipients: Vec<String>,
+    /// The message with dot-stuffing removed and CRLF line endings
+    pub data: Vec<u8>,
+}
+
+/// An SMTP server on an arbitrary port of localhost, stopped when dropped.
+pub struct TestServer {
+    local_addr: SocketAddr,
+    mails: Arc<Mutex<Vec<Envelope>>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    pub fn spawn(config: ServerConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+
+        let mails = Arc::new(Mutex::new(vec![]));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (mails, shutdown) = (mails.clone(), shutdown.clone());
+            thread::spawn(move || {
+                while !shutdown.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        // sessions are served one at a time
+                        Ok((stream, _)) => {
+                            let _ = Session::new(&config, &mails).serve(stream);
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                            thread::sleep(POLL_INTERVAL)
+                        }
+                        Err(_) => return,
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            local_addr,
+            mails,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// The address clients should connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Mails accepted so far, in order of arrival.
+    pub fn mails(&self) -> Vec<Envelope> {
+        self.mails.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Session<'a> {
+    config: &'a ServerConfig,
+    mails: &'a Mutex<Vec<Envelope>>,
+    authenticated: bool,
+    from: Option<String>,
+    recipients: Vec<String>,
+}
+
+impl<'a> Session<'a> {
+    fn new(config: &'a ServerConfig, mails: &'a Mutex<Vec<Envelope>>) -> Self {
+        Self {
+            config,
+            mails,
+            authenticated: config.credentials.is_none(),
+            from: None,
+            recipients: vec![],
+        }
+    }
+
+    fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(SESSION_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        reply(&mut stream, "220 localhost ESMTP test server")?;
+
+        loop {
+            let line = read_line(&mut reader)?;
+            if line.is_empty() {
+                return Ok(());
+            }
+            let (verb, argument) = match line.find(' ') {
+                Some(space) => (&line[..space], line[space + 1..].trim()),
+                None => (line.as_str(), ""),
+            };
+
+            let answer = match verb.to_ascii_uppercase().as_str() {
+                "EHLO" if self.config.extended => self.extensions(),
+                "HELO" => "250 localhost".to_string(),
+                "AUTH" => self.auth(argument, &mut reader, &mut stream)?,
+                "MAIL" => self.mail(argument),
+                "RCPT" => self.rcpt(argument),
+                "DATA" if self.recipients.is_empty() => "503 5.5.1 No valid recipients".to_string(),
+                "DATA" => {
+                    reply(&mut stream, "354 End data with <CR><LF>.<CR><LF>")?;
+                    let data = read_data(&mut reader)?;
+                    self.data(data)
+                }
+                "RSET" => {
+                    self.from = None;
+                    self.recipients.clear();
+                    "250 OK".to_string()
+                }
+                "NOOP" => "250 OK".to_string(),
+                "QUIT" => {
+                    reply(&mut stream, "221 Bye")?;
+                    return Ok(());
+                }
+                _ => "502 5.5.1 Command not implemented".to_string(),
+            };
+            reply(&mut stream, &answer)?;
+        }
+    }
+
+    fn extensions(&self) -> String {
+        let mut lines = vec!["localhost".to_string()];
+        if let Some(max_size) = self.config.max_size {
+            lines.push(format!("SIZE {}", max_size));
+        }
+        if self.config.credentials.is_some() {
+            lines.push(format!("AUTH {}", self.config.mechanisms.join(" ")));
+        }
+        lines.push("HELP".to_string());
+
+        let last = lines.len() - 1;
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| format!("250{}{}", if i == last { ' ' } else { '-' }, line))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+
+    fn auth<R: BufRead, W: Write>(
+        &mut self,
+        argument: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> io::Result<String> {
+        let credentials = match &self.config.credentials {
+            Some(credentials) => credentials,
+            None => return Ok("502 5.5.1 Command not implemented".to_string()),
+        };
+        let mut words = argument.split_whitespace();
+        let mechanism = words.next().unwrap_or("").to_ascii_uppercase();
+        if !self.config.mechanisms.contains(&mechanism.as_str()) {
+            return Ok("504 5.5.4 Unrecognized authentication type".to_string());
+        }
+
+        let (username, password) = if mechanism == "PLAIN" {
+            let token = match words.next() {
+                Some(token) => token.to_string(),
+                None => {
+                    reply(writer, "334 ")?;
+                    read_line(reader)?
+                }
+            };
+            let decoded = decode(&token);
+            let mut fields = decoded.split('\0').skip(1);
+            (
+                fields.next().unwrap_or("").to_string(),
+                fields.next().unwrap_or("").to_string(),
+            )
+        } else {
+            // "Username:" and "Password:" in base64
+            reply(writer, "334 VXNlcm5hbWU6")?;
+            let username = decode(&read_line(reader)?);
+            reply(writer, "334 UGFzc3dvcmQ6")?;
+            let password = decode(&read_line(reader)?);
+            (username, password)
+        };
+
+        if username == credentials.username && password == credentials.password {
+            self.authenticated = true;
+            Ok("235 2.7.0 Authentication successful".to_string())
+        } else {
+            Ok("535 5.7.8 Authentication credentials invalid".to_string())
+        }
+    }
+
+    fn mail(&mut self, argument: &str) -> String {
+        if !self.authenticated {
+            return "530 5.7.0 Authentication required".to_string();
+        }
+        let (from, parameters) = match path(argument, "FROM:") {
+            Some(path) => path,
+            None => return "501 5.5.4 Syntax error in MAIL command".to_string(),
+        };
+        let size = parameters
+            .split_whitespace()
+            .find_map(|parameter| parameter.strip_prefix("SIZE="))
+            .and_then(|size| size.parse::<usize>().ok());
+        if let (Some(size), Some(max_size)) = (size, self.config.max_size) {
+            if size > max_size {
+                return "552 5.3.4 Message size exceeds fixed limit".to_string();
+            }
+        }
+
+        self.from = Some(from);
+        self.recipients.clear();
+        "250 OK".to_string()
+    }
+
+    fn rcpt(&mut self, argument: &str) -> String {
+        if self.from.is_none() {
+            return "503 5.5.1 Bad sequence of commands".to_string();
+        }
+        let to = match path(argument, "TO:") {
+            Some((to, _)) => to,
+            None => return "501 5.5.4 Syntax error in RCPT command".to_string(),
+        };
+        if self
+            .config
+            .rejected
+            .iter()
+            .any(|rejected| rejected.eq_ignore_ascii_case(&to))
+        {
+            return format!("550 5.1.1 <{}>: Recipient address rejected", to);
+        }
+
+        self.recipients.push(to);
+        "250 OK".to_string()
+    }
+
+    fn data(&mut self, data: Vec<u8>) -> String {
+        if let Some(max_size) = self.config.max_size {
+            if data.len() > max_size {
+                return "552 5.3.4 Message size exceeds fixed limit".to_string();
+            }
+        }
+        let envelope = Envelope {
+            from: self.from.take().unwrap_or_default(),
+            recipients: self.recipients.drain(..).collect(),
+            data,
+        };
+        self.mails.lock().unwrap().push(envelope);
+        "250 OK queued".to_string()
+    }
+}
+
+/// Send a line of reply in a single write, small segments would wait on delayed
+/// acknowledgements.
+fn reply<W: Write>(writer: &mut W, line: &str) -> io::Result<()> {
+    writer.write_all(format!("{}\r\n", line).as_bytes())
+}
+
+/// A line without its line ending, empty at the end of stream.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Read the message up to the terminating dot, removing dot-stuffing.
+fn read_data<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    loop {
+        let mut line = vec![];
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        if line == b".\r\n" {
+            return Ok(data);
+        }
+        let line = line.strip_prefix(b".").unwrap_or(&line);
+        data.extend_from_slice(line);
+    }
+}
+
+/// Split `FROM:<path> parameters` into the path and parameters.
+fn path(argument: &str, prefix: &str) -> Option<(String, String)> {
+    if !argument.get(..prefix.len())?.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = argument[prefix.len()..].trim_start();
+    let rest = rest.strip_prefix('<')?;
+    let gt = rest.find('>')?;
+    Some((rest[..gt].to_string(), rest[gt + 1..].trim().to_string()))
+}
+
+fn decode(token: &str) -> String {
+    base64::decode(token.trim())
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
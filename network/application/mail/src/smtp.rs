@@ -0,0 +1,417 @@
+//! The client side of an SMTP session, defined by:\
+//! [https://tools.ietf.org/html/rfc5321](https://tools.ietf.org/html/rfc5321)\
+//! The session is generic over the stream, a session upgraded with STARTTLS continues over
+//! whatever TLS stream the caller wraps the connection in.
+
+use crate::{MailError, Result};
+use log::{error, info};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+/// Lines in a multiline reply accepted at most.
+const MAX_REPLY_LINES: usize = 256;
+
+/// A reply from the SMTP server, possibly spanning multiple lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub code: u16,
+    pub lines: Vec<String>,
+}
+
+impl Reply {
+    /// Parse a reply, defined by:\
+    /// [https://tools.ietf.org/html/rfc5321#section-4.2](https://tools.ietf.org/html/rfc5321#section-4.2)
+    pub fn from_reader<R>(reader: &mut R) -> Result<Self>
+    where
+        R: BufRead,
+    {
+        let mut code = None;
+        let mut lines = vec![];
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                error!("SMTP server closed the connection");
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+
+            let this_code = line
+                .get(..3)
+                .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|digits| digits.parse::<u16>().ok());
+            let this_code = match (this_code, code) {
+                (Some(this_code), None) => this_code,
+                (Some(this_code), Some(code)) if this_code == code => this_code,
+                _ => {
+                    error!("Malformed reply from SMTP server: {:?}", line);
+                    return Err(MailError::MalfomredResponse);
+                }
+            };
+            code = Some(this_code);
+
+            let last = match line.as_bytes().get(3) {
+                None | Some(b' ') => true,
+                Some(b'-') => false,
+                _ => {
+                    error!("Malformed reply from SMTP server: {:?}", line);
+                    return Err(MailError::MalfomredResponse);
+                }
+            };
+            lines.push(line.get(4..).unwrap_or("").to_string());
+            if last {
+                break;
+            }
+            if lines.len() >= MAX_REPLY_LINES {
+                error!("Reply from SMTP server too long");
+                return Err(MailError::MalfomredResponse);
+            }
+        }
+
+        let reply = Reply {
+            code: code.expect("at least one line"),
+            lines,
+        };
+        info!("SMTP server reply: {}", reply);
+        Ok(reply)
+    }
+
+    /// 4yz  Transient Negative Completion reply\
+    /// 5yz  Permanent Negative Completion reply
+    pub fn is_positive(&self) -> bool {
+        self.code < 400
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.lines.join(" "))
+    }
+}
+
+/// Service extensions advertised in reply to EHLO, defined by:\
+/// [https://tools.ietf.org/html/rfc5321#section-4.1.1.1](https://tools.ietf.org/html/rfc5321#section-4.1.1.1)
+#[derive(Debug, Clone, Default)]
+pub struct Extensions {
+    keywords: HashMap<String, Vec<String>>,
+}
+
+impl Extensions {
+    fn from_reply(reply: &Reply) -> Self {
+        // the first line greets the client
+        let keywords = reply
+            .lines
+            .iter()
+            .skip(1)
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                let keyword = words.next()?.to_ascii_uppercase();
+                Some((keyword, words.map(str::to_string).collect()))
+            })
+            .collect();
+        Self { keywords }
+    }
+
+    /// Whether the server supports an extension, keywords are case-insensitive.
+    pub fn supports(&self, keyword: &str) -> bool {
+        self.keywords.contains_key(&keyword.to_ascii_uppercase())
+    }
+
+    /// Parameters of an extension.
+    pub fn parameters(&self, keyword: &str) -> Option<&[String]> {
+        self.keywords
+            .get(&keyword.to_ascii_uppercase())
+            .map(Vec::as_slice)
+    }
+
+    /// SASL mechanisms accepted by the AUTH command, in uppercase.
+    pub fn auth_mechanisms(&self) -> Vec<String> {
+        self.parameters("AUTH")
+            .unwrap_or(&[])
+            .iter()
+            .map(|mechanism| mechanism.to_ascii_uppercase())
+            .collect()
+    }
+
+    /// Largest message accepted, defined by:\
+    /// [https://tools.ietf.org/html/rfc1870](https://tools.ietf.org/html/rfc1870)
+    pub fn max_size(&self) -> Option<usize> {
+        let size = self.parameters("SIZE")?.first()?.parse().ok()?;
+        // zero means no fixed limit
+        Some(size).filter(|&size| size > 0)
+    }
+}
+
+/// Credentials for SMTP authentication, sent in the clear unless the stream is encrypted.
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Credentials({:?}, <hidden>)", self.username)
+    }
+}
+
+/// An SMTP session over a stream.
+pub struct Client<S: Read + Write> {
+    stream: BufReader<S>,
+    extensions: Extensions,
+}
+
+impl<S: Read + Write> Client<S> {
+    /// Start a session on a new connection, waiting for the greeting of the server.
+    pub fn connect(stream: S) -> Result<Self> {
+        let mut client = Self::resume(stream);
+        let greeting = Reply::from_reader(&mut client.stream)?;
+        if greeting.code != 220 {
+            error!("SMTP server refused the session: {}", greeting);
+            return Err(MailError::NegativeReply(greeting));
+        }
+        Ok(client)
+    }
+
+    /// Continue a session on a stream without waiting for a greeting, as after STARTTLS.\
+    /// The client must say hello again before anything else.
+    pub fn resume(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            extensions: Extensions::default(),
+        }
+    }
+
+    /// Extensions advertised by the server in its reply to the last hello.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Identify the client with EHLO, falling back to HELO if the server doesn't understand.
+    pub fn hello(&mut self, domain: &str) -> Result<&Extensions> {
+        let reply = self.command(&format!("EHLO {}", domain))?;
+        self.extensions = match reply.code {
+            250 => Extensions::from_reply(&reply),
+            // command not recognized or not implemented
+            500 | 502 => {
+                self.expect(&format!("HELO {}", domain))?;
+                Extensions::default()
+            }
+            _ => return Err(MailError::NegativeReply(reply)),
+        };
+        Ok(&self.extensions)
+    }
+
+    /// Ask the server to upgrade the connection to TLS, defined by:\
+    /// [https://tools.ietf.org/html/rfc3207](https://tools.ietf.org/html/rfc3207)\
+    /// Returns the underlying stream, ready for a TLS handshake. Once encrypted, the session
+    /// continues with [`Client::resume`].
+    pub fn starttls(mut self) -> Result<S> {
+        if !self.extensions.supports("STARTTLS") {
+            return Err(MailError::NotSupported("STARTTLS"));
+        }
+        let reply = self.command("STARTTLS")?;
+        if reply.code != 220 {
+            return Err(MailError::NegativeReply(reply));
+        }
+        // anything already buffered was sent in the clear, possibly injected by an attacker
+        if !self.stream.buffer().is_empty() {
+            error!("SMTP server sent data before the TLS handshake");
+            return Err(MailError::MalfomredResponse);
+        }
+        Ok(self.stream.into_inner())
+    }
+
+    /// Authenticate with PLAIN or LOGIN, whichever the server accepts, preferring PLAIN.\
+    /// [https://tools.ietf.org/html/rfc4954](https://tools.ietf.org/html/rfc4954)
+    pub fn authenticate(&mut self, credentials: &Credentials) -> Result<()> {
+        let mechanisms = self.extensions.auth_mechanisms();
+        let reply = if mechanisms.iter().any(|m| m == "PLAIN") {
+            let token = format!("\0{}\0{}", credentials.username, credentials.password);
+            self.send_secret("AUTH PLAIN", &base64::encode(token))?
+        } else if mechanisms.iter().any(|m| m == "LOGIN") {
+            let reply = self.command("AUTH LOGIN")?;
+            if reply.code != 334 {
+                return Err(MailError::AuthenticationFailed(reply));
+            }
+            let reply = self.command(&base64::encode(&credentials.username))?;
+            if reply.code != 334 {
+                return Err(MailError::AuthenticationFailed(reply));
+            }
+            self.send_secret("", &base64::encode(&credentials.password))?
+        } else {
+            return Err(MailError::NotSupported("AUTH PLAIN or LOGIN"));
+        };
+
+        if reply.code == 235 {
+            Ok(())
+        } else {
+            Err(MailError::AuthenticationFailed(reply))
+        }
+    }
+
+    /// Start a mail transaction, announcing the size of the message if the server cares.
+    pub fn mail_from(&mut self, from: &str, size: usize) -> Result<()> {
+        crate::message::validate_address(from)?;
+        if let Some(max_size) = self.extensions.max_size() {
+            if size > max_size {
+                error!(
+                    "Message of {} bytes exceeds the limit of {}",
+                    size, max_size
+                );
+                return Err(MailError::MessageTooLarge(max_size));
+            }
+        }
+        if self.extensions.supports("SIZE") {
+            self.expect(&format!("MAIL FROM:<{}> SIZE={}", from, size))
+        } else {
+            self.expect(&format!("MAIL FROM:<{}>", from))
+        }
+        .map(|_| ())
+    }
+
+    /// Add a recipient to the transaction, negative replies are returned rather than raised.
+    pub fn rcpt_to(&mut self, to: &str) -> Result<Reply> {
+        crate::message::validate_address(to)?;
+        self.command(&format!("RCPT TO:<{}>", to))
+    }
+
+    /// Send the message of the transaction, lines starting with a dot are escaped.
+    pub fn data(&mut self, message: &[u8]) -> Result<Reply> {
+        let reply = self.command("DATA")?;
+        if reply.code != 354 {
+            return Err(MailError::NegativeReply(reply));
+        }
+
+        info!("Sending {} bytes of message", message.len());
+        let mut stuffed = dot_stuff(message);
+        // end of DATA section, a single dot on its own line
+        stuffed.extend_from_slice(b".\r\n");
+        let stream = self.stream.get_mut();
+        stream.write_all(&stuffed)?;
+        stream.flush()?;
+
+        let reply = Reply::from_reader(&mut self.stream)?;
+        if reply.is_positive() {
+            Ok(reply)
+        } else {
+            Err(MailError::NegativeReply(reply))
+        }
+    }
+
+    /// Abort the current transaction.
+    pub fn reset(&mut self) -> Result<()> {
+        self.expect("RSET").map(|_| ())
+    }
+
+    /// End the session.
+    pub fn quit(mut self) -> Result<()> {
+        self.expect("QUIT").map(|_| ())
+    }
+
+    fn command(&mut self, command: &str) -> Result<Reply> {
+        info!("{}", command);
+        self.send_line(command)
+    }
+
+    /// Send a command ending with a secret argument, kept out of the logs.
+    fn send_secret(&mut self, command: &str, secret: &str) -> Result<Reply> {
+        info!("{} <hidden>", command);
+        if command.is_empty() {
+            self.send_line(secret)
+        } else {
+            self.send_line(&format!("{} {}", command, secret))
+        }
+    }
+
+    fn send_line(&mut self, line: &str) -> Result<Reply> {
+        // a single write, small segments would wait on delayed acknowledgements
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{}\r\n", line).as_bytes())?;
+        stream.flush()?;
+        Reply::from_reader(&mut self.stream)
+    }
+
+    fn expect(&mut self, command: &str) -> Result<Reply> {
+        let reply = self.command(command)?;
+        if reply.is_positive() {
+            Ok(reply)
+        } else {
+            error!("Negative server reply: {}", reply);
+            Err(MailError::NegativeReply(reply))
+        }
+    }
+}
+
+/// Escape lines starting with a dot and normalize line endings to CRLF, defined by:\
+/// [https://tools.ietf.org/html/rfc5321#section-4.5.2](https://tools.ietf.org/html/rfc5321#section-4.5.2)
+pub fn dot_stuff(message: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(message.len() + 2);
+    let mut lines = message.split(|&b| b == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        // the message ends with a line break
+        if line.is_empty() && lines.peek().is_none() {
+            break;
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(b".") {
+            stuffed.push(b'.');
+        }
+        stuffed.extend_from_slice(line);
+        stuffed.extend_from_slice(b"\r\n");
+    }
+    stuffed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies() -> Result<()> {
+        let mut input: &[u8] =
+            b"250-smtp.crepe.fr greets you\r\n250-SIZE 1024\r\n250-auth plain LOGIN\r\n250 STARTTLS\r\n";
+        let reply = Reply::from_reader(&mut input)?;
+        assert_eq!(reply.code, 250);
+        assert_eq!(reply.lines.len(), 4);
+
+        let extensions = Extensions::from_reply(&reply);
+        assert!(extensions.supports("starttls"));
+        assert!(!extensions.supports("PIPELINING"));
+        assert_eq!(extensions.max_size(), Some(1024));
+        assert_eq!(extensions.auth_mechanisms(), vec!["PLAIN", "LOGIN"]);
+
+        let mut input: &[u8] = b"250-first\r\n251 second\r\n";
+        assert!(matches!(
+            Reply::from_reader(&mut input),
+            Err(MailError::MalfomredResponse)
+        ));
+        let mut input: &[u8] = b"25O nope\r\n";
+        assert!(matches!(
+            Reply::from_reader(&mut input),
+            Err(MailError::MalfomredResponse)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn dot_stuffing() {
+        assert_eq!(
+            dot_stuff(b".\r\n..two\nthree.\r\n."),
+            b"..\r\n...two\r\nthree.\r\n..\r\n".to_vec()
+        );
+        assert_eq!(dot_stuff(b"line\r\n"), b"line\r\n".to_vec());
+        assert_eq!(dot_stuff(b""), b"".to_vec());
+    }
+}
@@ -0,0 +1,122 @@
+use mail::{
+    client::send_via,
+    server::{ServerConfig, TestServer},
+    Attachment, Credentials, Mail, MailError, Mailbox,
+};
+
+fn mail() -> Mail {
+    let mut mail = Mail::new(
+        Mailbox::with_name("Alice", "alice@crepe.fr"),
+        "Build failed",
+        "The nightly build failed.\n.\n..and a line starting with dots\n",
+    );
+    mail.to = vec![
+        Mailbox::with_name("Bob", "bob@galette.fr"),
+        Mailbox::new("carol@galette.fr"),
+    ];
+    mail.bcc = vec![Mailbox::new("dave@crepe.fr")];
+    mail
+}
+
+#[test]
+fn recipients() -> mail::Result<()> {
+    let config = ServerConfig {
+        rejected: vec!["carol@galette.fr".to_string()],
+        ..ServerConfig::default()
+    };
+    let server = TestServer::spawn(config)?;
+
+    let report = send_via(&mail(), server.local_addr(), None)?;
+    assert_eq!(report.accepted, vec!["bob@galette.fr", "dave@crepe.fr"]);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].0, "carol@galette.fr");
+    assert!(report.rejected[0].1.starts_with("550 "));
+
+    let mails = server.mails();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].from, "alice@crepe.fr");
+    assert_eq!(mails[0].recipients, vec!["bob@galette.fr", "dave@crepe.fr"]);
+    let data = String::from_utf8(mails[0].data.clone()).unwrap();
+    assert!(data.contains("To: \"Bob\" <bob@galette.fr>,\r\n carol@galette.fr\r\n"));
+    assert!(!data.contains("dave"));
+    // dot-stuffing is transparent
+    assert!(data.ends_with("failed.\r\n.\r\n..and a line starting with dots\r\n"));
+
+    let config = ServerConfig {
+        rejected: vec!["bob@galette.fr".to_string(), "carol@galette.fr".to_string()],
+        ..ServerConfig::default()
+    };
+    let server = TestServer::spawn(config)?;
+    let mut mail = mail();
+    mail.bcc.clear();
+    match send_via(&mail, server.local_addr(), None) {
+        Err(MailError::NoRecipientAccepted(rejected)) => assert_eq!(rejected.len(), 2),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(server.mails().is_empty());
+    Ok(())
+}
+
+#[test]
+fn authentication() -> mail::Result<()> {
+    let credentials = Credentials::new("alice", "s3cret");
+    for mechanisms in &[vec!["PLAIN", "LOGIN"], vec!["LOGIN"]] {
+        let config = ServerConfig {
+            credentials: Some(credentials.clone()),
+            mechanisms: mechanisms.clone(),
+            ..ServerConfig::default()
+        };
+        let server = TestServer::spawn(config)?;
+
+        assert!(matches!(
+            send_via(&mail(), server.local_addr(), None),
+            Err(MailError::NegativeReply(reply)) if reply.code == 530
+        ));
+        let wrong = Credentials::new("alice", "guess");
+        assert!(matches!(
+            send_via(&mail(), server.local_addr(), Some(&wrong)),
+            Err(MailError::AuthenticationFailed(reply)) if reply.code == 535
+        ));
+        send_via(&mail(), server.local_addr(), Some(&credentials))?;
+        assert_eq!(server.mails().len(), 1);
+    }
+
+    // no AUTH extension without HELO
+    let config = ServerConfig {
+        extended: false,
+        ..ServerConfig::default()
+    };
+    let server = TestServer::spawn(config)?;
+    assert!(matches!(
+        send_via(&mail(), server.local_addr(), Some(&credentials)),
+        Err(MailError::NotSupported(_))
+    ));
+    send_via(&mail(), server.local_addr(), None)?;
+    assert_eq!(server.mails().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn attachments() -> mail::Result<()> {
+    let server = TestServer::spawn(ServerConfig::default())?;
+    let mut mail = mail();
+    let csv = b"date,status\n2026-10-18,failed\n".to_vec();
+    mail.attachments = vec![Attachment::new("report.csv", "text/csv", csv)];
+    send_via(&mail, server.local_addr(), None)?;
+
+    let data = String::from_utf8(server.mails()[0].data.clone()).unwrap();
+    assert!(data.contains("Content-Type: multipart/mixed; boundary="));
+    assert!(data.contains("Content-Type: text/csv; name=\"report.csv\"\r\n"));
+    assert!(data.contains("ZGF0ZSxzdGF0dXMKMjAyNi0xMC0xOCxmYWlsZWQK\r\n"));
+
+    let config = ServerConfig {
+        max_size: Some(256),
+        ..ServerConfig::default()
+    };
+    let server = TestServer::spawn(config)?;
+    assert!(matches!(
+        send_via(&mail, server.local_addr(), None),
+        Err(MailError::MessageTooLarge(256))
+    ));
+    Ok(())
+}
@@ -67,7 +67,7 @@ fn statistics(server: SocketAddr, rtts: &[u128]) {
         "    Packets: Sent = {}, Received = {}, Lost = {}",
         TRIALS,
         rtts.len(),
-        usize::from(TRIALS)- rtts.len()
+        usize::from(TRIALS) - rtts.len()
     );
     println!("Approximate round trip times in milli-seconds:");
     println!(
@@ -82,7 +82,12 @@ fn recv_or_timeout(socket: &UdpSocket, identifier: u16, sequence_number: u16) ->
     loop {
         let mut packet = [0; PACKET_LEN];
         socket.recv(&mut packet)?;
-        let reply_packet = PingPacket::from_be_bytes(packet).map_err(|err| err.into_io_error())?;
+        let reply_packet = match PingPacket::from_be_bytes(packet) {
+            Ok(reply_packet) => reply_packet,
+            // corrupted in transit, wait until time out
+            Err(PingError::InvalidChecksum) => continue,
+            Err(err) => return Err(err.into_io_error()),
+        };
 
         if identifier != reply_packet.identifier() {
             return Err(PingError::IdentifierMismatch.into());
@@ -15,14 +15,17 @@ fn recv_ping(port: u16) -> io::Result<()> {
     loop {
         let mut packet = [0; PACKET_LEN];
         let (_, client) = socket.recv_from(&mut packet)?;
-        let request_packet =
-            PingPacket::from_be_bytes(packet).map_err(|err| err.into_io_error())?;
+        let request_packet = match PingPacket::from_be_bytes(packet) {
+            Ok(request_packet) => request_packet,
+            // corrupted in transit, the client will time out
+            Err(_) => continue,
+        };
         let reply_packet = PingPacket::new(
             Type::Reply,
             request_packet.identifier(),
             request_packet.sequence_number(),
         );
-        socket.send_to(&reply_packet.packet(), client)?;
+        socket.send_to(reply_packet.packet(), client)?;
     }
 }
 
@@ -1,5 +1,5 @@
 use internet_checksum::checksum;
-use std::io::{self, ErrorKind};
+use std::io;
 use std::ops::Deref;
 
 pub mod consts {
@@ -45,9 +45,9 @@ impl PingError {
     }
 }
 
-impl Into<io::Error> for PingError {
-    fn into(self) -> io::Error {
-        io::Error::new(ErrorKind::Other, self.message())
+impl From<PingError> for io::Error {
+    fn from(err: PingError) -> Self {
+        io::Error::other(err.message())
     }
 }
 
@@ -168,11 +168,11 @@ impl Deref for PingPacket {
 
 #[test]
 fn checksum_test() {
-    use random_fast_rng::{Random, local_rng}; 
+    use random_fast_rng::{local_rng, Random};
 
     let mut rng = local_rng();
     let packet = PingPacket::new(Type::Request, rng.gen(), rng.gen());
     let mut datagram = [0; PACKET_LEN];
     datagram.copy_from_slice(&packet);
     assert!(PingPacket::from_be_bytes(datagram).is_ok());
-}
\ No newline at end of file
+}
@@ -0,0 +1,45 @@
+use relay::{Config, Relay};
+use std::{io, net::Ipv4Addr, process::Command, thread, time::Duration};
+
+#[test]
+fn unreliable_network() -> io::Result<()> {
+    let client = 7071;
+    let server = 7070;
+
+    let mut server_process = Command::new(env!("CARGO_BIN_EXE_ping_server"))
+        .arg(server.to_string())
+        .spawn()?;
+    // ensure server is fully set up
+    thread::sleep(Duration::from_millis(200));
+
+    let config = Config {
+        loss_rate: 0.1,
+        corrupt_rate: 0.1,
+        rtt: Duration::from_millis(20),
+        seed: Some(0),
+        ..Config::default()
+    };
+    let relay = Relay::spawn((Ipv4Addr::LOCALHOST, server).into(), config)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ping_client"))
+        .arg(client.to_string())
+        .arg(relay.local_addr().to_string())
+        .output()?;
+    server_process.kill()?;
+
+    // corrupted packets are dropped instead of failing either end
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stats = relay.statistics();
+    assert!(stats.lost > 0 && stats.corrupted > 0);
+    assert!(stdout.contains("Request timed out."));
+    assert!(stdout.contains("Sent = 10"));
+
+    // every reply crossed the delayed link twice
+    for line in stdout.lines().filter(|line| line.starts_with("Reply")) {
+        let time = line.rsplit("time=").next().unwrap().trim_end_matches("ms");
+        assert!(time.parse::<u64>().unwrap() >= 20);
+    }
+
+    Ok(())
+}
@@ -1,69 +1,546 @@
-//! In-memory cache of HTTP response bodies.\
-//! WIP, not yet integrated into the proxy server.
+//! Shared in-memory HTTP cache defined by:\
+//! [https://tools.ietf.org/html/rfc7234](https://tools.ietf.org/html/rfc7234)\
+//! Responses are keyed by host and path, then by the request header fields named in Vary:.\
+//! Least recently used responses are evicted once the cache grows over its capacity in bytes.
 
-use crate::GMTDateTime;
+use crate::{
+    http::{HTTPRequest, HTTPResponse, HTTPResponseBuilder, Method, Status},
+    GMTDateTime,
+};
 use std::collections::HashMap;
 
-/// An entry in the HTTP cache.
+/// Default capacity of the cache in bytes.
+pub const DEFAULT_CAPACITY: usize = 64 * 1024 * 1024;
+
+/// Status codes cacheable by default, defined by:\
+/// [https://tools.ietf.org/html/rfc7231#section-6.1](https://tools.ietf.org/html/rfc7231#section-6.1)
+const CACHEABLE_BY_DEFAULT: &[u16] = &[200, 203, 204, 300, 301, 404, 405, 410, 414, 501];
+
+/// Upper bound of the heuristic freshness lifetime in seconds.
+const MAX_HEURISTIC_LIFETIME: i64 = 24 * 60 * 60;
+
+/// Cache-Control: directives relevant to a shared cache, defined by:\
+/// [https://tools.ietf.org/html/rfc7234#section-5.2](https://tools.ietf.org/html/rfc7234#section-5.2)
+#[derive(Debug, Default, PartialEq)]
+pub struct CacheControl {
+    /// no-store: never store the request or the response
+    pub no_store: bool,
+    /// no-cache: revalidate with the origin server before every use
+    pub no_cache: bool,
+    /// private: only a private cache may store the response
+    pub private: bool,
+    /// public: any cache may store the response
+    pub public: bool,
+    /// must-revalidate or proxy-revalidate: never serve the response stale
+    pub must_revalidate: bool,
+    /// only-if-cached: never contact the origin server
+    pub only_if_cached: bool,
+    /// max-age=N: maximum age in seconds
+    pub max_age: Option<i64>,
+    /// s-maxage=N: maximum age in seconds in shared caches, overrides max-age
+    pub s_maxage: Option<i64>,
+}
+
+impl CacheControl {
+    /// Parse the value of a Cache-Control: header, ignoring unknown directives.\
+    /// Qualified no-cache and private directives are applied to the whole response.
+    pub fn parse(value: &str) -> Self {
+        let mut directives = Self::default();
+
+        for directive in value.split(',') {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            // an invalid delta-seconds is as good as zero
+            let seconds = parts
+                .next()
+                .map(|argument| argument.trim().trim_matches('"').parse().unwrap_or(0));
+
+            match name.as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                "only-if-cached" => directives.only_if_cached = true,
+                "max-age" => directives.max_age = seconds,
+                "s-maxage" => directives.s_maxage = seconds,
+                _ => (),
+            }
+        }
+
+        directives
+    }
+
+    /// Directives of a request, Pragma: no-cache is honored when Cache-Control: is absent.
+    pub fn of_request(request: &HTTPRequest) -> Self {
+        match request.header("Cache-Control") {
+            Some(value) => Self::parse(value),
+            None => Self {
+                no_cache: request
+                    .header("Pragma")
+                    .is_some_and(|pragma| pragma.contains("no-cache")),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Directives of a response.
+    pub fn of_response(response: &HTTPResponse) -> Self {
+        response
+            .header("Cache-Control")
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// A stored response together with its age.
+#[derive(Clone)]
 pub struct CacheEntry {
-    /// Last-Modified: date upon dispose
-    pub last_modified: GMTDateTime,
-    /// Whether the stored body has Transfer-Encoding: chunked set
-    pub is_chunked: bool,
-    /// Stored body in bytes
-    pub body: Vec<u8>,
+    response: HTTPResponse,
+    body: Vec<u8>,
+    /// Request header fields named in Vary: and their values in the request that stored the entry.
+    vary: Vec<(String, Option<String>)>,
+    /// Age of the response when received, corrected for the delay of the origin server.
+    initial_age: i64,
+    /// When the response was received.
+    response_time: GMTDateTime,
+    /// Logical time of the last use, for LRU eviction.
+    last_used: u64,
 }
 
 impl CacheEntry {
-    /// Construct a new cache entry from fields.
-    pub fn new(last_modified: GMTDateTime, is_chunked: bool, body: Vec<u8>) -> Self {
-        Self {
-            last_modified,
-            is_chunked,
-            body,
+    /// Construct a new cache entry from a response to the request, sent at request_time and
+    /// received at response_time.
+    pub fn new(
+        request: &HTTPRequest,
+        response: &HTTPResponse,
+        body: &[u8],
+        request_time: &GMTDateTime,
+        response_time: &GMTDateTime,
+    ) -> Self {
+        let vary = response
+            .header("Vary")
+            .map(|vary| {
+                vary.split(',')
+                    .map(|name| name.trim())
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        let value = request.header(name).map(|value| value.trim().to_string());
+                        (name.to_string(), value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut entry = Self {
+            response: response.clone(),
+            body: body.to_vec(),
+            vary,
+            initial_age: 0,
+            response_time: response_time.clone(),
+            last_used: 0,
+        };
+        entry.initial_age = entry.corrected_initial_age(request_time);
+        entry
+    }
+
+    /// [https://tools.ietf.org/html/rfc7234#section-4.2.3](https://tools.ietf.org/html/rfc7234#section-4.2.3)
+    fn corrected_initial_age(&self, request_time: &GMTDateTime) -> i64 {
+        let apparent_age = self.response_time.seconds_since(&self.date()).max(0);
+        let age_value = self
+            .response
+            .header("Age")
+            .and_then(|age| age.trim().parse().ok())
+            .unwrap_or(0);
+        let response_delay = self.response_time.seconds_since(request_time).max(0);
+        apparent_age.max(age_value + response_delay)
+    }
+
+    /// Date: of the response, or when it was received if absent.
+    fn date(&self) -> GMTDateTime {
+        self.response
+            .header("Date")
+            .and_then(GMTDateTime::parse_from_rfc2822)
+            .unwrap_or_else(|| self.response_time.clone())
+    }
+
+    /// Entity tag of the stored response.
+    pub fn etag(&self) -> Option<&str> {
+        self.response.header("ETag")
+    }
+
+    /// Last-Modified: of the stored response, as sent by the origin server.
+    pub fn last_modified(&self) -> Option<&str> {
+        self.response.header("Last-Modified")
+    }
+
+    /// Stored body in bytes, framed by Content-Length: of the stored response.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Current age of the response in seconds.
+    pub fn age(&self, now: &GMTDateTime) -> i64 {
+        self.initial_age + now.seconds_since(&self.response_time).max(0)
+    }
+
+    /// Seconds the response stays fresh after generated by the origin server, defined by:\
+    /// [https://tools.ietf.org/html/rfc7234#section-4.2.1](https://tools.ietf.org/html/rfc7234#section-4.2.1)
+    pub fn freshness_lifetime(&self) -> i64 {
+        let directives = CacheControl::of_response(&self.response);
+        if let Some(seconds) = directives.s_maxage.or(directives.max_age) {
+            return seconds;
+        }
+
+        if let Some(expires) = self.response.header("Expires") {
+            // an invalid date represents a time in the past
+            return GMTDateTime::parse_from_rfc2822(expires)
+                .map_or(0, |expires| expires.seconds_since(&self.date()));
+        }
+
+        // heuristic freshness: 10% of the time since last modified
+        match self
+            .last_modified()
+            .and_then(GMTDateTime::parse_from_rfc2822)
+        {
+            Some(last_modified) if CACHEABLE_BY_DEFAULT.contains(&self.response.code()) => {
+                (self.date().seconds_since(&last_modified) / 10).clamp(0, MAX_HEURISTIC_LIFETIME)
+            }
+            _ => 0,
         }
     }
 
-    /// Length of the stored body in bytes.
-    pub fn content_length(&self) -> usize {
-        self.body.len()
+    /// Whether the response may be served without revalidation.
+    pub fn is_fresh(&self, now: &GMTDateTime) -> bool {
+        self.freshness_lifetime() > self.age(now)
     }
+
+    /// Whether the stored response can be revalidated with a conditional request.
+    pub fn has_validator(&self) -> bool {
+        self.etag().is_some() || self.last_modified().is_some()
+    }
+
+    /// Whether the stored response was selected by the same header fields as the request.
+    fn matches(&self, request: &HTTPRequest) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request.header(name).map(|value| value.trim()) == value.as_deref())
+    }
+
+    fn size(&self) -> usize {
+        self.response.to_string().len() + self.body.len()
+    }
+
+    /// The stored response as served to a client, with its current age.
+    pub fn response(&self, now: &GMTDateTime) -> HTTPResponse {
+        let mut response = self.response.clone();
+        response.set_header("Age", &self.age(now).to_string());
+        response
+    }
+
+    /// Whether a conditional request from the client is satisfied by the stored response, defined
+    /// by:\
+    /// [https://tools.ietf.org/html/rfc7232#section-6](https://tools.ietf.org/html/rfc7232#section-6)
+    pub fn not_modified(&self, request: &HTTPRequest) -> bool {
+        if let Some(tags) = request.header("If-None-Match") {
+            return match self.etag() {
+                _ if tags.trim() == "*" => true,
+                Some(etag) => tags.split(',').any(|tag| weak_match(tag.trim(), etag)),
+                None => false,
+            };
+        }
+
+        let since = request
+            .header("If-Modified-Since")
+            .and_then(GMTDateTime::parse_from_rfc2822);
+        let last_modified = self
+            .last_modified()
+            .and_then(GMTDateTime::parse_from_rfc2822);
+        match (since, last_modified) {
+            (Some(since), Some(last_modified)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    /// A 304 response to a satisfied conditional request, defined by:\
+    /// [https://tools.ietf.org/html/rfc7232#section-4.1](https://tools.ietf.org/html/rfc7232#section-4.1)
+    pub fn not_modified_response(&self, now: &GMTDateTime) -> HTTPResponse {
+        let mut builder = HTTPResponseBuilder::new(Status::NotModified);
+        for name in &[
+            "Cache-Control",
+            "Date",
+            "ETag",
+            "Expires",
+            "Last-Modified",
+            "Vary",
+        ] {
+            if let Some(value) = self.response.header(name) {
+                builder.attach_header(name, value);
+            }
+        }
+        builder.attach_header("Age", &self.age(now).to_string());
+        builder.build()
+    }
+
+    /// Update the stored response with a 304 response of the origin server, defined by:\
+    /// [https://tools.ietf.org/html/rfc7234#section-4.3.4](https://tools.ietf.org/html/rfc7234#section-4.3.4)
+    fn freshen(
+        &mut self,
+        not_modified: &HTTPResponse,
+        request_time: &GMTDateTime,
+        response_time: &GMTDateTime,
+    ) {
+        for (name, value) in not_modified.headers() {
+            // the stored body keeps its framing
+            if !name.eq_ignore_ascii_case("Content-Length")
+                && !name.eq_ignore_ascii_case("Transfer-Encoding")
+            {
+                self.response.set_header(name, value);
+            }
+        }
+        if not_modified.header("Age").is_none() {
+            self.response.remove_header("Age");
+        }
+        self.response_time = response_time.clone();
+        self.initial_age = self.corrected_initial_age(request_time);
+    }
+}
+
+/// Weak comparison of entity tags, defined by:\
+/// [https://tools.ietf.org/html/rfc7232#section-2.3.2](https://tools.ietf.org/html/rfc7232#section-2.3.2)
+fn weak_match(lhs: &str, rhs: &str) -> bool {
+    lhs.trim_start_matches("W/") == rhs.trim_start_matches("W/")
+}
+
+/// Outcome of looking a request up in the cache.
+pub enum Lookup {
+    /// A fresh response, served without contacting the origin server.
+    Fresh(CacheEntry),
+    /// A stale response, the origin server must validate it with this conditional request.
+    Stale(HTTPRequest),
+    /// No response can be used, the request must be forwarded.
+    Miss,
 }
 
-/// In-memory cache of HTTP response bodies.
-#[derive(Default)]
+/// Shared in-memory HTTP cache bounded in bytes.
 pub struct Cache {
-    entries: HashMap<String, CacheEntry>,
+    capacity: usize,
+    size: usize,
+    clock: u64,
+    entries: HashMap<String, Vec<CacheEntry>>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
 }
 
 impl Cache {
-    /// Construct a new empty in-memory cache.
-    pub fn new() -> Self {
-        Self::default()
+    /// Construct a new empty in-memory cache holding at most capacity bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Bytes taken by stored responses.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Find a response to the request, defined by:\
+    /// [https://tools.ietf.org/html/rfc7234#section-4](https://tools.ietf.org/html/rfc7234#section-4)
+    pub fn lookup(&mut self, request: &HTTPRequest, now: &GMTDateTime) -> Lookup {
+        if request.method() == Method::POST {
+            return Lookup::Miss;
+        }
+
+        self.clock += 1;
+        let clock = self.clock;
+        let key = construct_key(request.host(), request.path());
+        let entry = match self
+            .entries
+            .get_mut(&key)
+            .and_then(|variants| variants.iter_mut().find(|entry| entry.matches(request)))
+        {
+            Some(entry) => entry,
+            None => return Lookup::Miss,
+        };
+        entry.last_used = clock;
+
+        let request_directives = CacheControl::of_request(request);
+        let response_directives = CacheControl::of_response(&entry.response);
+        let revalidate = request_directives.no_cache
+            || response_directives.no_cache
+            || !entry.is_fresh(now)
+            || request_directives
+                .max_age
+                .is_some_and(|max_age| entry.age(now) > max_age);
+
+        if !revalidate {
+            Lookup::Fresh(entry.clone())
+        } else if entry.has_validator() {
+            let mut conditional = request.clone();
+            conditional.remove_header("If-None-Match");
+            conditional.remove_header("If-Modified-Since");
+            if let Some(etag) = entry.etag() {
+                conditional.set_header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = entry.last_modified() {
+                conditional.set_header("If-Modified-Since", last_modified);
+            }
+            Lookup::Stale(conditional)
+        } else {
+            Lookup::Miss
+        }
     }
 
-    /// Insert an entry into the cache, possibly overwrite an older cache.\
-    /// TODO: should compare last_modified of both and insert the more recent.
-    pub fn insert(&mut self, host: &str, path: &str, entry: CacheEntry) {
-        let key = construct_key(host, path);
-        self.entries.insert(key, entry);
+    /// Whether the response to the request may be stored, defined by:\
+    /// [https://tools.ietf.org/html/rfc7234#section-3](https://tools.ietf.org/html/rfc7234#section-3)
+    fn storable(request: &HTTPRequest, response: &HTTPResponse) -> bool {
+        let request_directives = CacheControl::of_request(request);
+        let response_directives = CacheControl::of_response(response);
+
+        let explicitly_cacheable = response_directives.public
+            || response_directives.max_age.is_some()
+            || response_directives.s_maxage.is_some()
+            || response.header("Expires").is_some();
+        // https://tools.ietf.org/html/rfc7234#section-3.2
+        let authorized = request.header("Authorization").is_none()
+            || response_directives.public
+            || response_directives.s_maxage.is_some()
+            || response_directives.must_revalidate;
+
+        // partial and 304 responses are never complete
+        request.method() == Method::GET
+            && ![206, 304].contains(&response.code())
+            && !request_directives.no_store
+            && !response_directives.no_store
+            && !response_directives.private
+            && response.header("Vary").map(str::trim) != Some("*")
+            && authorized
+            && (explicitly_cacheable || CACHEABLE_BY_DEFAULT.contains(&response.code()))
     }
 
-    /// Retrieve or invalidate an cache entry based on the Last-Modified: header from server.
-    pub fn get_or_invalidate(
+    /// Store the response to the request if allowed, replacing the response selected by the same
+    /// request. Returns whether the response was stored.
+    pub fn store(
         &mut self,
-        host: &str,
-        path: &str,
-        last_modified: &GMTDateTime,
-    ) -> Option<&CacheEntry> {
-        let key = construct_key(host, path);
-        let outdated = self.entries.get(&key)?.last_modified < *last_modified;
-
-        if outdated {
-            self.entries.remove(&key);
-            None
+        request: &HTTPRequest,
+        response: &HTTPResponse,
+        body: &[u8],
+        request_time: &GMTDateTime,
+        response_time: &GMTDateTime,
+    ) -> bool {
+        if !Self::storable(request, response) {
+            return false;
+        }
+
+        let mut entry = CacheEntry::new(request, response, body, request_time, response_time);
+        let size = entry.size();
+        // never fresh and cannot be revalidated, or too large
+        if (entry.freshness_lifetime() <= 0 && !entry.has_validator()) || size > self.capacity {
+            return false;
+        }
+
+        let key = construct_key(request.host(), request.path());
+        self.remove(&key, request);
+        self.shrink_to(self.capacity - size);
+
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.size += size;
+        self.entries.entry(key).or_default().push(entry);
+        true
+    }
+
+    /// Update the stored response with a 304 response to the conditional request, returns the
+    /// updated entry.
+    pub fn revalidate(
+        &mut self,
+        request: &HTTPRequest,
+        not_modified: &HTTPResponse,
+        request_time: &GMTDateTime,
+        response_time: &GMTDateTime,
+    ) -> Option<CacheEntry> {
+        let key = construct_key(request.host(), request.path());
+        let entry = self
+            .entries
+            .get_mut(&key)?
+            .iter_mut()
+            .find(|entry| entry.matches(request))?;
+
+        let before = entry.size();
+        entry.freshen(not_modified, request_time, response_time);
+        let after = entry.size();
+        self.clock += 1;
+        entry.last_used = self.clock;
+        let entry = entry.clone();
+        self.size = self.size + after - before;
+
+        // freshened header fields may grow the entry past what the cache holds
+        if after > self.capacity {
+            self.remove(&key, request);
         } else {
-            self.entries.get(&key)
+            // the entry itself is the most recently used, others go first
+            self.shrink_to(self.capacity);
+        }
+
+        Some(entry)
+    }
+
+    /// Remove every response stored for the host and path, e.g. after an unsafe request, defined
+    /// by:\
+    /// [https://tools.ietf.org/html/rfc7234#section-4.4](https://tools.ietf.org/html/rfc7234#section-4.4)
+    pub fn invalidate(&mut self, host: &str, path: &str) {
+        if let Some(variants) = self.entries.remove(&construct_key(host, path)) {
+            self.size -= variants.iter().map(CacheEntry::size).sum::<usize>();
+        }
+    }
+
+    /// Remove the response selected by the request.
+    fn remove(&mut self, key: &str, request: &HTTPRequest) {
+        if let Some(variants) = self.entries.get_mut(key) {
+            if let Some(idx) = variants.iter().position(|entry| entry.matches(request)) {
+                self.size -= variants.swap_remove(idx).size();
+            }
+            if variants.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+    }
+
+    /// Evict least recently used responses until at most `capacity` bytes are stored.
+    fn shrink_to(&mut self, capacity: usize) {
+        while self.size > capacity {
+            self.evict();
+        }
+    }
+
+    /// Remove the least recently used response.
+    fn evict(&mut self) {
+        let victim = self
+            .entries
+            .iter()
+            .flat_map(|(key, variants)| {
+                variants
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, entry)| (entry.last_used, key, idx))
+            })
+            .min()
+            .map(|(_, key, idx)| (key.clone(), idx));
+
+        if let Some((key, idx)) = victim {
+            let variants = self.entries.get_mut(&key).unwrap();
+            self.size -= variants.swap_remove(idx).size();
+            if variants.is_empty() {
+                self.entries.remove(&key);
+            }
         }
     }
 }
@@ -71,3 +548,304 @@ impl Cache {
 fn construct_key(host: &str, path: &str) -> String {
     host.to_string() + path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HTTPRequestBuilder;
+
+    const DATE: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    fn time(seconds: i64) -> GMTDateTime {
+        let date = chrono::DateTime::parse_from_rfc2822(DATE).unwrap()
+            + chrono::Duration::seconds(seconds);
+        GMTDateTime::parse_from_rfc2822(&date.to_rfc2822()).unwrap()
+    }
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> HTTPRequest {
+        let mut builder = HTTPRequestBuilder::new(Method::GET);
+        for (name, value) in headers {
+            builder.attach_header(name, value);
+        }
+        builder.build("example.com", path)
+    }
+
+    fn ok(headers: &[(&str, &str)]) -> HTTPResponse {
+        let mut builder = HTTPResponseBuilder::new(Status::OK);
+        builder.attach_header("Date", DATE);
+        for (name, value) in headers {
+            builder.attach_header(name, value);
+        }
+        builder.build()
+    }
+
+    fn store(cache: &mut Cache, request: &HTTPRequest, response: &HTTPResponse) -> bool {
+        cache.store(request, response, b"body", &time(0), &time(0))
+    }
+
+    #[test]
+    fn cache_control() {
+        let directives =
+            CacheControl::parse("no-cache, Max-Age=60, s-maxage=\"30\", private=\"Set-Cookie\"");
+        assert_eq!(
+            directives,
+            CacheControl {
+                no_cache: true,
+                private: true,
+                max_age: Some(60),
+                s_maxage: Some(30),
+                ..CacheControl::default()
+            }
+        );
+        assert_eq!(CacheControl::parse("max-age=soon").max_age, Some(0));
+
+        let request = get("/", &[("Pragma", "no-cache")]);
+        assert!(CacheControl::of_request(&request).no_cache);
+    }
+
+    #[test]
+    fn freshness() {
+        let request = get("/", &[]);
+
+        let entry = CacheEntry::new(
+            &request,
+            &ok(&[("Cache-Control", "max-age=60, s-maxage=30")]),
+            b"",
+            &time(0),
+            &time(0),
+        );
+        assert_eq!(entry.freshness_lifetime(), 30);
+        assert!(entry.is_fresh(&time(29)));
+        assert!(!entry.is_fresh(&time(30)));
+
+        let entry = CacheEntry::new(
+            &request,
+            &ok(&[("Expires", &time(100).to_rfc2822())]),
+            b"",
+            &time(0),
+            &time(0),
+        );
+        assert_eq!(entry.freshness_lifetime(), 100);
+        let entry = CacheEntry::new(&request, &ok(&[("Expires", "0")]), b"", &time(0), &time(0));
+        assert_eq!(entry.freshness_lifetime(), 0);
+
+        // heuristic: a tenth of the time since last modified
+        let entry = CacheEntry::new(
+            &request,
+            &ok(&[("Last-Modified", &time(-1000).to_rfc2822())]),
+            b"",
+            &time(0),
+            &time(0),
+        );
+        assert_eq!(entry.freshness_lifetime(), 100);
+
+        // aged in upstream caches and on the way
+        let entry = CacheEntry::new(&request, &ok(&[("Age", "10")]), b"", &time(0), &time(2));
+        assert_eq!(entry.age(&time(2)), 12);
+        assert_eq!(entry.response(&time(5)).header("Age"), Some("15"));
+    }
+
+    #[test]
+    fn storability() {
+        let mut cache = Cache::default();
+        let request = get("/", &[]);
+
+        assert!(store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "max-age=60")])
+        ));
+        assert!(!store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "no-store")])
+        ));
+        assert!(!store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "private, max-age=60")])
+        ));
+        assert!(!store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "max-age=60"), ("Vary", "*")])
+        ));
+        // neither fresh nor revalidatable
+        assert!(!store(&mut cache, &request, &ok(&[])));
+        assert!(store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "no-cache"), ("ETag", "\"a\"")])
+        ));
+
+        let request = get("/", &[("Cache-Control", "no-store")]);
+        assert!(!store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "max-age=60")])
+        ));
+        let request = get("/", &[("Authorization", "Basic")]);
+        assert!(!store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "max-age=60")])
+        ));
+        assert!(store(
+            &mut cache,
+            &request,
+            &ok(&[("Cache-Control", "public, max-age=60")])
+        ));
+    }
+
+    #[test]
+    fn revalidation() {
+        let mut cache = Cache::default();
+        let request = get("/", &[]);
+        let response = ok(&[
+            ("Cache-Control", "max-age=10"),
+            ("ETag", "\"v1\""),
+            ("Last-Modified", DATE),
+        ]);
+        assert!(store(&mut cache, &request, &response));
+
+        assert!(matches!(cache.lookup(&request, &time(5)), Lookup::Fresh(_)));
+        let no_cache = get("/", &[("Cache-Control", "no-cache")]);
+        assert!(matches!(
+            cache.lookup(&no_cache, &time(5)),
+            Lookup::Stale(_)
+        ));
+        let max_age = get("/", &[("Cache-Control", "max-age=1")]);
+        assert!(matches!(cache.lookup(&max_age, &time(5)), Lookup::Stale(_)));
+
+        let conditional = match cache.lookup(&request, &time(20)) {
+            Lookup::Stale(conditional) => conditional,
+            _ => panic!("stale entry should be revalidated"),
+        };
+        assert_eq!(conditional.header("If-None-Match"), Some("\"v1\""));
+        assert_eq!(conditional.header("If-Modified-Since"), Some(DATE));
+
+        let mut not_modified = HTTPResponseBuilder::new(Status::NotModified);
+        not_modified.attach_header("Date", &time(20).to_rfc2822());
+        not_modified.attach_header("Cache-Control", "max-age=100");
+        let entry = cache
+            .revalidate(&conditional, &not_modified.build(), &time(20), &time(20))
+            .unwrap();
+        assert_eq!(entry.body(), b"body");
+        assert!(matches!(
+            cache.lookup(&request, &time(50)),
+            Lookup::Fresh(_)
+        ));
+
+        // conditional requests from the client
+        assert!(entry.not_modified(&get("/", &[("If-None-Match", "\"v0\", W/\"v1\"")])));
+        assert!(!entry.not_modified(&get("/", &[("If-None-Match", "\"v0\"")])));
+        assert!(entry.not_modified(&get("/", &[("If-Modified-Since", DATE)])));
+        assert!(!entry.not_modified(&get("/", &[("If-Modified-Since", &time(-1).to_rfc2822())])));
+        assert_eq!(entry.not_modified_response(&time(20)).code(), 304);
+    }
+
+    #[test]
+    fn vary() {
+        let mut cache = Cache::default();
+        let english = get("/", &[("Accept-Language", "en")]);
+        let french = get("/", &[("Accept-Language", "fr")]);
+        let response = ok(&[("Cache-Control", "max-age=60"), ("Vary", "Accept-Language")]);
+
+        assert!(store(&mut cache, &english, &response));
+        assert!(matches!(cache.lookup(&english, &time(0)), Lookup::Fresh(_)));
+        assert!(matches!(cache.lookup(&french, &time(0)), Lookup::Miss));
+        assert!(matches!(
+            cache.lookup(&get("/", &[]), &time(0)),
+            Lookup::Miss
+        ));
+
+        assert!(store(&mut cache, &french, &response));
+        assert!(matches!(cache.lookup(&english, &time(0)), Lookup::Fresh(_)));
+        assert!(matches!(cache.lookup(&french, &time(0)), Lookup::Fresh(_)));
+
+        cache.invalidate("example.com", "/");
+        assert_eq!(cache.size(), 0);
+        assert!(matches!(cache.lookup(&english, &time(0)), Lookup::Miss));
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let response = ok(&[("Cache-Control", "max-age=60")]);
+        let entry_size =
+            CacheEntry::new(&get("/", &[]), &response, b"body", &time(0), &time(0)).size();
+        let mut cache = Cache::new(entry_size * 2);
+
+        assert!(store(&mut cache, &get("/a", &[]), &response));
+        assert!(store(&mut cache, &get("/b", &[]), &response));
+        // touch /a, /b becomes the least recently used
+        cache.lookup(&get("/a", &[]), &time(0));
+        assert!(store(&mut cache, &get("/c", &[]), &response));
+
+        assert_eq!(cache.size(), entry_size * 2);
+        assert!(matches!(
+            cache.lookup(&get("/a", &[]), &time(0)),
+            Lookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup(&get("/b", &[]), &time(0)),
+            Lookup::Miss
+        ));
+        assert!(matches!(
+            cache.lookup(&get("/c", &[]), &time(0)),
+            Lookup::Fresh(_)
+        ));
+
+        // larger than the whole cache
+        let mut cache = Cache::new(entry_size - 1);
+        assert!(!store(&mut cache, &get("/a", &[]), &response));
+    }
+
+    #[test]
+    fn revalidation_at_capacity() {
+        let request = get("/", &[]);
+        let response = ok(&[("Cache-Control", "max-age=10"), ("ETag", "\"v1\"")]);
+        let entry_size = CacheEntry::new(&request, &response, b"body", &time(0), &time(0)).size();
+        let mut cache = Cache::new(entry_size * 2);
+        assert!(store(&mut cache, &get("/other", &[]), &response));
+        assert!(store(&mut cache, &request, &response));
+        assert_eq!(cache.size(), cache.capacity);
+
+        let conditional = match cache.lookup(&request, &time(20)) {
+            Lookup::Stale(conditional) => conditional,
+            _ => panic!("stale entry should be revalidated"),
+        };
+        let mut not_modified = HTTPResponseBuilder::new(Status::NotModified);
+        not_modified.attach_header("Date", &time(20).to_rfc2822());
+        not_modified.attach_header("Cache-Control", "max-age=100, must-revalidate");
+        not_modified.attach_header("Expires", &time(120).to_rfc2822());
+        let not_modified = not_modified.build();
+        let entry = cache
+            .revalidate(&conditional, &not_modified, &time(20), &time(20))
+            .unwrap();
+        assert!(entry.size() > entry_size);
+
+        // the other entry makes room for the grown one
+        assert!(cache.size() <= cache.capacity);
+        assert!(matches!(
+            cache.lookup(&request, &time(50)),
+            Lookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup(&get("/other", &[]), &time(0)),
+            Lookup::Miss
+        ));
+
+        // grown past the whole cache
+        let mut cache = Cache::new(entry_size);
+        assert!(store(&mut cache, &request, &response));
+        let conditional = match cache.lookup(&request, &time(20)) {
+            Lookup::Stale(conditional) => conditional,
+            _ => panic!("stale entry should be revalidated"),
+        };
+        assert!(cache
+            .revalidate(&conditional, &not_modified, &time(20), &time(20))
+            .is_some());
+        assert_eq!(cache.size(), 0);
+        assert!(matches!(cache.lookup(&request, &time(50)), Lookup::Miss));
+    }
+}
@@ -1,6 +1,6 @@
 #![deny(missing_docs)]
 
-//! A naive HTTP proxy, disregarding most RFCs.
+//! A naive HTTP proxy with a shared HTTP cache, disregarding most other RFCs.
 
 pub mod cache;
 pub mod http;
@@ -11,36 +11,36 @@ use chrono::{
     offset::{FixedOffset, Utc},
     DateTime,
 };
-use httparse::Error as ParseError;
-use trust_dns_resolver::error::ResolveError;
 
 /// Crate universal error type.
 #[derive(Debug)]
 pub enum Error {
-    /// No body found for HTTP request or response
-    BodyNotPresent,
     /// Format error in HTTP request or response
     MalformedHTTP,
     /// Proxy cannot handle this method
     MethodNotImplemented,
-    /// Error propagated from httparse
-    ParseError(ParseError),
-    /// Error propagated from trust-dns-resolver
-    ResolveError(ResolveError),
+    /// Error propagated from http-message
+    HTTPError(http_message::Error),
+    /// Error propagated from the dns resolver
+    ResolveError(dns::Error),
     /// Error propagated from std::io
     IOError(std::io::Error),
 }
 
 use Error::*;
 
-impl From<ParseError> for Error {
-    fn from(err: ParseError) -> Self {
-        ParseError(err)
+impl From<http_message::Error> for Error {
+    fn from(err: http_message::Error) -> Self {
+        match err {
+            http_message::Error::MethodNotImplemented => MethodNotImplemented,
+            http_message::Error::IOError(err) => IOError(err),
+            _ => HTTPError(err),
+        }
     }
 }
 
-impl From<ResolveError> for Error {
-    fn from(err: ResolveError) -> Self {
+impl From<dns::Error> for Error {
+    fn from(err: dns::Error) -> Self {
         ResolveError(err)
     }
 }
@@ -55,7 +55,7 @@ impl From<std::io::Error> for Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Internet time defined by RFC2822 in GMT timezone
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct GMTDateTime {
     inner: DateTime<FixedOffset>,
 }
@@ -63,7 +63,7 @@ pub struct GMTDateTime {
 impl GMTDateTime {
     /// Return current datetime in GMT (UTC) timezone.
     pub fn now() -> Self {
-        let inner = Utc::now().with_timezone(&FixedOffset::east(0));
+        let inner = Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap());
         Self { inner }
     }
 
@@ -77,4 +77,9 @@ impl GMTDateTime {
     pub fn to_rfc2822(&self) -> String {
         self.inner.to_rfc2822()
     }
+
+    /// Seconds elapsed since an earlier date time, negative if it's in fact later.
+    pub fn seconds_since(&self, earlier: &Self) -> i64 {
+        (self.inner - earlier.inner).num_seconds()
+    }
 }
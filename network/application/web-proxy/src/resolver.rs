@@ -1,29 +1,36 @@
-//! A wrapper dns resolver over functionality of trust-dns-resolver.
+//! A wrapper dns resolver over the shared dns crate.
 
 use crate::{Error, Result};
-use std::net::Ipv4Addr;
-use trust_dns_resolver::{error::ResolveError, Resolver};
+use std::net::{Ipv4Addr, SocketAddr};
 
-/// A DNS resolver backed by google dns services.
+/// A caching DNS resolver backed by the system name server, shared by all connections.
 pub struct DNSResolver {
-    inner: Resolver,
+    inner: dns::Resolver,
 }
 
 impl DNSResolver {
-    /// Construct and configure a new dns resolver with google services.
-    pub fn spawn() -> Result<Self> {
-        let inner = Resolver::default()?;
-        Ok(Self { inner })
+    /// Construct a dns resolver with the name server configured in the system, or google
+    /// services when none is configured.
+    pub fn spawn() -> Self {
+        Self {
+            inner: dns::Resolver::system(),
+        }
+    }
+
+    /// Construct a dns resolver sending queries to the given name server.
+    pub fn with_server(server: SocketAddr) -> Self {
+        Self {
+            inner: dns::Resolver::new(server),
+        }
     }
 
     /// Lookup an IPv4 address of the given domain.\
     /// Always retrieve the first entry in answers when available.
     pub fn lookup(&self, domain: &str) -> Result<Ipv4Addr> {
-        let lookup = self.inner.ipv4_lookup(domain)?;
-        lookup
+        self.inner
+            .ipv4_lookup(domain)?
             .into_iter()
             .next()
-            .ok_or_else(|| ResolveError::from("No records found"))
-            .map_err(Error::from)
+            .ok_or(Error::ResolveError(dns::Error::NameNotFound))
     }
 }
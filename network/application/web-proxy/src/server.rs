@@ -1,28 +1,55 @@
-//! Proxy server relay logics, ignoring semantics of most headers.\
-//! In particular, connections are always non-persistent, even when Connection: keep-alive is set.\
+//! Proxy server relay logics with a shared HTTP cache, ignoring semantics of most other headers.\
+//! Connections are served by a pool of workers, persistent unless either side asks to close.\
+//! Requests pipelined on a connection are answered in order. Connections to servers are never
+//! persistent.\
 //! Forge artificial responses when:
 //! - HTTP request has bad format
 //! - DNS resolver cannot find the domain requested
-//! - All but GET and POST method encountered
+//! - All but GET, POST, HEAD and CONNECT method encountered
+//! - A request with Cache-Control: only-if-cached cannot be served from the cache
+//! - The server sends a malformed response
 
 use crate::{
+    cache::{Cache, CacheControl, CacheEntry, Lookup},
     http::{HTTPRequest, HTTPResponse, HTTPResponseBuilder, Method, Status},
     resolver::DNSResolver,
     Error, GMTDateTime, Result,
 };
 use log::{debug, error};
 use std::{
-    io::{BufReader, Write},
-    net::{Ipv4Addr, TcpListener, TcpStream},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Ipv4Addr, Shutdown, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
+use threadpool::ThreadPool;
 
 const HTTP_PORT: u16 = 80;
+/// Workers serving client connections, a persistent connection occupies a worker until closed.
+/// Established tunnels are relayed outside the pool.
+const WORKERS: usize = 64;
+/// Persistent connections idle for longer are closed, releasing their worker.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(15);
 
-/// Run an HTTP proxy server with non-persistent connections on the current thread.
+/// Run an HTTP proxy server on the current thread.
 pub fn run_server(port: u16) -> Result<()> {
+    run_server_with_cache(port, Cache::default())
+}
+
+/// Run an HTTP proxy server on the current thread, all connections share the given cache.
+pub fn run_server_with_cache(port: u16, cache: Cache) -> Result<()> {
+    run_server_with(port, cache, DNSResolver::spawn())
+}
+
+/// Run an HTTP proxy server on the current thread, all connections share the given cache and
+/// resolve domain names with the given resolver.
+pub fn run_server_with(port: u16, cache: Cache, resolver: DNSResolver) -> Result<()> {
     let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
     debug!("TCP listener established at {}", port);
+    let cache = Arc::new(Mutex::new(cache));
+    let resolver = Arc::new(resolver);
+    let pool = ThreadPool::new(WORKERS);
     for result in listener.incoming() {
         let client = result?;
         debug!(
@@ -30,91 +57,387 @@ pub fn run_server(port: u16) -> Result<()> {
             client.peer_addr(),
             client.local_addr()
         );
-        thread::spawn(|| relay_and_report(client));
+        let cache = Arc::clone(&cache);
+        let resolver = Arc::clone(&resolver);
+        pool.execute(move || serve_and_report(client, &cache, &resolver));
     }
 
     Ok(())
 }
 
-fn relay_and_report(mut client: TcpStream) {
-    if let Err(err) = relay(&mut client) {
+fn serve_and_report(client: TcpStream, cache: &Mutex<Cache>, resolver: &DNSResolver) {
+    if let Err(err) = serve(client, cache, resolver) {
         error!("{:?}", err);
     }
 }
 
-fn relay(client: &mut TcpStream) -> Result<()> {
-    let mut server = match relay_request(client) {
+/// Answer requests on a client connection in order, until either side closes the connection.
+fn serve(mut client: TcpStream, cache: &Mutex<Cache>, resolver: &DNSResolver) -> Result<()> {
+    client.set_read_timeout(Some(KEEP_ALIVE_TIMEOUT))?;
+    // kept across requests, pipelined requests may already be buffered
+    let mut client_reader = BufReader::new(client.try_clone()?);
+
+    loop {
+        match client_reader.fill_buf() {
+            Ok([]) => {
+                debug!("Client closed the connection");
+                return Ok(());
+            }
+            Ok(_) => (),
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                debug!("Persistent connection idle, closing connection");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        if !relay(&mut client_reader, &mut client, cache, resolver)? {
+            debug!("Closing connection");
+            return Ok(());
+        }
+    }
+}
+
+/// Answer one request, returns whether the connection persists.
+fn relay(
+    client_reader: &mut BufReader<TcpStream>,
+    client: &mut TcpStream,
+    cache: &Mutex<Cache>,
+    resolver: &DNSResolver,
+) -> Result<bool> {
+    let (request, body) = match read_request(client_reader) {
+        Ok(request) => request,
+        Err(err) => return forge_response(err, client).map(|_| false),
+    };
+    if request.method() == Method::CONNECT {
+        tunnel(&request, resolver, client_reader, client)?;
+        return Ok(false);
+    }
+    let keep_alive = request.keep_alive();
+
+    let directives = CacheControl::of_request(&request);
+    let lookup = cache.lock().unwrap().lookup(&request, &GMTDateTime::now());
+    let conditional = match lookup {
+        Lookup::Fresh(entry) => {
+            debug!("Cache hit, response served from cache");
+            send_cached(client, &request, &entry, keep_alive)?;
+            return Ok(keep_alive);
+        }
+        _ if directives.only_if_cached => {
+            error!("Forge response for only-if-cached request missing the cache");
+            error_response(Status::GatewayTimeout, client)?;
+            return Ok(false);
+        }
+        Lookup::Stale(conditional) => {
+            debug!("Stale cache, revalidating with the server");
+            Some(conditional)
+        }
+        Lookup::Miss => None,
+    };
+
+    if request.method() == Method::POST {
+        cache
+            .lock()
+            .unwrap()
+            .invalidate(request.host(), request.path());
+    }
+
+    let forwarded = conditional.as_ref().unwrap_or(&request);
+    let mut fetched = match fetch(client, forwarded, body.as_deref(), resolver)? {
+        Some(fetched) => fetched,
+        None => return Ok(false),
+    };
+
+    if fetched.response.code() == Status::NotModified.code() {
+        let revalidated = cache.lock().unwrap().revalidate(
+            forwarded,
+            &fetched.response,
+            &fetched.request_time,
+            &fetched.response_time,
+        );
+        if let Some(entry) = revalidated {
+            debug!("Cache revalidated, response served from cache");
+            send_cached(client, &request, &entry, keep_alive)?;
+            return Ok(keep_alive);
+        }
+        // the entry was evicted or invalidated while waiting for the server, a 304 to validators
+        // added by the proxy means nothing to the client
+        if conditional.is_some() && !is_conditional(&request) {
+            debug!("Revalidated entry gone, requesting the full response");
+            fetched = match fetch(client, &request, body.as_deref(), resolver)? {
+                Some(fetched) => fetched,
+                None => return Ok(false),
+            };
+        }
+    }
+
+    if fetched.response.code() != Status::NotModified.code()
+        && cache.lock().unwrap().store(
+            &request,
+            &fetched.response,
+            &fetched.body,
+            &fetched.request_time,
+            &fetched.response_time,
+        )
+    {
+        debug!("Response stored in cache");
+    }
+
+    relay_response(client, &fetched.response, &fetched.body, keep_alive)?;
+    Ok(keep_alive)
+}
+
+/// Whether the client itself asked for a 304 response if its copy is still valid.
+fn is_conditional(request: &HTTPRequest) -> bool {
+    request.header("If-None-Match").is_some() || request.header("If-Modified-Since").is_some()
+}
+
+/// A response from the server with the times the request was sent and the response received.
+struct Fetched {
+    response: HTTPResponse,
+    body: Vec<u8>,
+    request_time: GMTDateTime,
+    response_time: GMTDateTime,
+}
+
+/// Send the request to the server and read its response. On failure the client is answered with
+/// an error instead and None is returned.
+fn fetch(
+    client: &mut TcpStream,
+    request: &HTTPRequest,
+    body: Option<&[u8]>,
+    resolver: &DNSResolver,
+) -> Result<Option<Fetched>> {
+    let mut forwarded = request.clone();
+    forwarded.remove_hop_by_hop();
+    forwarded.set_header("Connection", "close");
+    if let Some(body) = body {
+        // chunks were decoded, the body is forwarded in one piece
+        forwarded.set_header("Content-Length", &body.len().to_string());
+    }
+
+    let request_time = GMTDateTime::now();
+    let mut server = match relay_request(&forwarded, body, resolver) {
         Ok(server) => server,
+        Err(err) => return forge_response(err, client).map(|_| None),
+    };
+    let mut server_reader = BufReader::new(&mut server);
+    let (response, response_body) = match read_response(&mut server_reader, request.method()) {
+        Ok(response) => response,
         Err(err) => {
-            let handled = match err {
-                Error::ResolveError(..) => {
-                    error!("Forge response for dns query failure");
-                    error_response(Status::NotFound, client)
-                }
-                Error::BodyNotPresent | Error::MalformedHTTP => {
-                    error!("Forge response for malformed request");
-                    error_response(Status::BadRequest, client)
-                }
-                Error::MethodNotImplemented => {
-                    error!("Forge response for unexpected HTTP method");
-                    error_response(Status::NotImplemented, client)
-                }
-                _ => Err(err),
-            };
-            return handled;
+            error!("Forge response for bad response from server: {:?}", err);
+            return error_response(Status::BadGateway, client).map(|_| None);
         }
     };
-    relay_response(client, &mut server)?;
-    Ok(())
+    let response_time = GMTDateTime::now();
+
+    Ok(Some(Fetched {
+        response,
+        body: response_body,
+        request_time,
+        response_time,
+    }))
 }
 
-fn relay_request(client: &mut TcpStream) -> Result<TcpStream> {
-    let client_peer = client.peer_addr();
-    let mut client_reader = BufReader::new(client);
-    let request = HTTPRequest::from_reader(&mut client_reader)?;
-    debug!("Received request from client {:?}", client_peer);
+fn read_request<R>(client_reader: &mut R) -> Result<(HTTPRequest, Option<Vec<u8>>)>
+where
+    R: BufRead,
+{
+    let request = HTTPRequest::from_reader(client_reader)?;
+    debug!("Received request from client");
     debug!("\n{:?}", request);
-    let resolver = DNSResolver::spawn()?;
-    debug!("Resolving domain name {}", request.host());
-    let ip = resolver.lookup(request.host())?;
-    debug!("Domain name {} resolved to {:?}", request.host(), ip);
-    let mut server = TcpStream::connect((ip, HTTP_PORT))?;
-    debug!("Connected to server at {:?}", ip);
+    let body = if request.has_body()? {
+        debug!("Request with body, reading body");
+        Some(request.read_body(client_reader)?)
+    } else {
+        None
+    };
+
+    Ok((request, body))
+}
+
+fn connect(host: &str, resolver: &DNSResolver) -> Result<TcpStream> {
+    let (host, port) = split_port(host)?;
+    let ip = match host.parse::<Ipv4Addr>() {
+        Ok(ip) => ip,
+        Err(_) => {
+            debug!("Resolving domain name {}", host);
+            resolver.lookup(host)?
+        }
+    };
+    debug!("Domain name {} resolved to {:?}", host, ip);
+    let server = TcpStream::connect((ip, port))?;
+    debug!("Connected to server at {:?}:{}", ip, port);
+    Ok(server)
+}
+
+fn relay_request(
+    request: &HTTPRequest,
+    body: Option<&[u8]>,
+    resolver: &DNSResolver,
+) -> Result<TcpStream> {
+    let mut server = connect(request.host(), resolver)?;
     write!(server, "{}", request)?;
-    if request.method() == Method::POST {
-        debug!("POST request, sending body");
-        let body = request.read_body(&mut client_reader)?;
-        server.write_all(&body)?;
+    if let Some(body) = body {
+        debug!("Sending request body");
+        server.write_all(body)?;
         debug!("Sent {} bytes", body.len());
     }
     debug!("Request relayed to server, waiting for response");
     Ok(server)
 }
 
-fn relay_response(client: &mut TcpStream, server: &mut TcpStream) -> Result<()> {
-    let server_peer = server.peer_addr();
-    let mut server_reader = BufReader::new(server);
-    let response = HTTPResponse::from_reader(&mut server_reader)?;
-    debug!("Received response from server {:?}", server_peer,);
+fn read_response<R>(server_reader: &mut R, method: Method) -> Result<(HTTPResponse, Vec<u8>)>
+where
+    R: BufRead,
+{
+    let mut response = HTTPResponse::from_reader(server_reader)?;
+    debug!("Received response from server");
     debug!("\n{:?}", response);
+    let body = response.read_body(server_reader, method)?;
+    response.headers_mut().remove_hop_by_hop();
+    if response.has_body(method) {
+        // chunked bodies or bodies delimited by the server closing the connection are decoded,
+        // then framed for the client
+        response.set_header("Content-Length", &body.len().to_string());
+    }
+
+    Ok((response, body))
+}
+
+fn relay_response(
+    client: &mut TcpStream,
+    response: &HTTPResponse,
+    body: &[u8],
+    keep_alive: bool,
+) -> Result<()> {
+    let mut response = response.clone();
+    response.set_header("Connection", connection(keep_alive));
     write!(client, "{}", response)?;
-    match response.read_body(&mut server_reader) {
-        Ok(body) => {
-            debug!("Sending response body");
-            client.write_all(&body)?;
-            debug!("Sent {} bytes", body.len());
-        }
-        Err(Error::BodyNotPresent) => (),
-        Err(err) => return Err(err),
+    debug!("Sending response body");
+    client.write_all(body)?;
+    debug!("Sent {} bytes", body.len());
+    debug!("Response relayed to client");
+
+    Ok(())
+}
+
+fn send_cached(
+    client: &mut TcpStream,
+    request: &HTTPRequest,
+    entry: &CacheEntry,
+    keep_alive: bool,
+) -> Result<()> {
+    let now = GMTDateTime::now();
+    if entry.not_modified(request) {
+        debug!("Conditional request satisfied by cache");
+        relay_response(client, &entry.not_modified_response(&now), &[], keep_alive)
+    } else if request.method() == Method::HEAD {
+        relay_response(client, &entry.response(&now), &[], keep_alive)
+    } else {
+        relay_response(client, &entry.response(&now), entry.body(), keep_alive)
     }
-    debug!("Response relayed to client, closing connection");
+}
+
+/// Relay bytes both ways between the client and the requested host until either side closes
+/// the connection, defined by:\
+/// [https://tools.ietf.org/html/rfc7231#section-4.3.6](https://tools.ietf.org/html/rfc7231#section-4.3.6)\
+/// A tunnel may stay open for minutes, the relay runs on threads of its own instead of holding a
+/// worker from the pool.
+fn tunnel(
+    request: &HTTPRequest,
+    resolver: &DNSResolver,
+    client_reader: &mut BufReader<TcpStream>,
+    client: &mut TcpStream,
+) -> Result<()> {
+    let mut server = match connect(request.host(), resolver) {
+        Ok(server) => server,
+        Err(err) => return forge_response(err, client),
+    };
+    write!(client, "{}", HTTPResponseBuilder::new(Status::OK).build())?;
+    debug!("Tunnel established to {}", request.host());
+
+    // a tunnel is not idle while the server is still talking
+    client.set_read_timeout(None)?;
+    // bytes sent by the client right after the request
+    let buffered = client_reader.buffer().len();
+    server.write_all(client_reader.buffer())?;
+    client_reader.consume(buffered);
+
+    let host = request.host().to_string();
+    let mut client = client.try_clone()?;
+    let mut upstream_client = client.try_clone()?;
+    let mut upstream_server = server.try_clone()?;
+    thread::spawn(move || {
+        let upstream = thread::spawn(move || {
+            let sent = io::copy(&mut upstream_client, &mut upstream_server);
+            let _ = upstream_server.shutdown(Shutdown::Write);
+            sent
+        });
+
+        let received = io::copy(&mut server, &mut client);
+        // the server has nothing more to say, neither direction is useful anymore
+        let _ = client.shutdown(Shutdown::Both);
+        let sent = upstream.join().expect("tunnel thread panicked");
+        debug!(
+            "Tunnel to {} closed, {:?} bytes sent, {:?} bytes received",
+            host, sent, received
+        );
+    });
 
     Ok(())
 }
 
+fn connection(keep_alive: bool) -> &'static str {
+    if keep_alive {
+        "keep-alive"
+    } else {
+        "close"
+    }
+}
+
+/// Split the port from a host, default to the HTTP port if absent.
+fn split_port(host: &str) -> Result<(&str, u16)> {
+    match host.rfind(':') {
+        Some(colon) => {
+            let port = host[colon + 1..]
+                .parse()
+                .map_err(|_| Error::MalformedHTTP)?;
+            Ok((&host[..colon], port))
+        }
+        None => Ok((host, HTTP_PORT)),
+    }
+}
+
+fn forge_response(err: Error, client: &mut TcpStream) -> Result<()> {
+    match err {
+        Error::ResolveError(..) => {
+            error!("Forge response for dns query failure");
+            error_response(Status::NotFound, client)
+        }
+        Error::MalformedHTTP => {
+            error!("Forge response for malformed request");
+            error_response(Status::BadRequest, client)
+        }
+        Error::MethodNotImplemented => {
+            error!("Forge response for unexpected HTTP method");
+            error_response(Status::NotImplemented, client)
+        }
+        Error::HTTPError(err) => match err.status() {
+            Some(status) => {
+                error!("Forge response for bad request: {}", err);
+                error_response(status, client)
+            }
+            None => Err(Error::HTTPError(err)),
+        },
+        _ => Err(err),
+    }
+}
+
 fn error_response(status: Status, client: &mut TcpStream) -> Result<()> {
     let response = HTTPResponseBuilder::new(status)
         .attach_header("Connection", "close")
+        .attach_header("Content-Length", "0")
         .attach_header("Date", &GMTDateTime::now().to_rfc2822())
         .build();
 
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use web_proxy::server::run_server;
+
+const PROXY_PORT: u16 = 4445;
+
+type Hits = Arc<Mutex<HashMap<String, usize>>>;
+
+/// A tiny origin server counting requests per path.
+fn origin() -> (u16, Hits) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let hits = Hits::default();
+
+    let counter = Arc::clone(&hits);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || respond(stream.unwrap(), &counter));
+        }
+    });
+
+    (port, hits)
+}
+
+fn respond(mut stream: TcpStream, hits: &Hits) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut words = request_line.split(' ');
+    let method = words.next().unwrap().to_string();
+    let path = words.next().unwrap().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        let (name, value) = line.split_once(':').unwrap();
+        headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+    }
+    *hits.lock().unwrap().entry(path.clone()).or_default() += 1;
+
+    let (status, fields, body) = match path.as_str() {
+        "/fresh" => (
+            "200 OK",
+            "Cache-Control: max-age=60\r\n",
+            "fresh".to_string(),
+        ),
+        "/no-store" => (
+            "200 OK",
+            "Cache-Control: no-store\r\n",
+            "secret".to_string(),
+        ),
+        "/revalidate" if headers.get("if-none-match").map(String::as_str) == Some("\"v1\"") => {
+            ("304 Not Modified", "ETag: \"v1\"\r\n", String::new())
+        }
+        "/revalidate" => (
+            "200 OK",
+            "Cache-Control: no-cache\r\nETag: \"v1\"\r\n",
+            "validated".to_string(),
+        ),
+        // the cached response is invalidated while its revalidation is in flight
+        "/evicted" if headers.contains_key("if-none-match") => {
+            let port = stream.local_addr().unwrap().port();
+            assert_eq!(send(port, "POST", "/evicted", &["Content-Length: 0"]).0, 200);
+            ("304 Not Modified", "ETag: \"v1\"\r\n", String::new())
+        }
+        "/evicted" if method == "POST" => ("200 OK", "", String::new()),
+        "/evicted" => (
+            "200 OK",
+            "Cache-Control: no-cache\r\nETag: \"v1\"\r\n",
+            "evicted".to_string(),
+        ),
+        "/vary" => (
+            "200 OK",
+            "Cache-Control: max-age=60\r\nVary: Accept-Language\r\n",
+            headers["accept-language"].clone(),
+        ),
+        _ => ("404 Not Found", "", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\n\r\n{}",
+        status,
+        fields,
+        body.len(),
+        body
+    )
+    .unwrap();
+}
+
+/// Send a GET request through the proxy, returns the status code and the body.
+fn get(origin: u16, path: &str, headers: &[&str]) -> (u16, String) {
+    send(origin, "GET", path, headers)
+}
+
+fn send(origin: u16, method: &str, path: &str, headers: &[&str]) -> (u16, String) {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PROXY_PORT)).unwrap();
+    write!(
+        stream,
+        "{} http://127.0.0.1:{}{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n",
+        method, origin, path, origin
+    )
+    .unwrap();
+    for header in headers {
+        write!(stream, "{}\r\n", header).unwrap();
+    }
+    write!(stream, "\r\n").unwrap();
+
+    // the proxy closes the connection after the response
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let code = response[9..12].parse().unwrap();
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or("").to_string();
+    (code, body)
+}
+
+#[test]
+fn http_cache_test() {
+    thread::spawn(|| {
+        run_server(PROXY_PORT).unwrap();
+    });
+    // ensure proxy is fully set up
+    thread::sleep(Duration::from_millis(200));
+
+    let (port, hits) = origin();
+    let hits_of = |path: &str| hits.lock().unwrap().get(path).copied().unwrap_or(0);
+
+    // fresh responses are served from the cache
+    assert_eq!(get(port, "/fresh", &[]), (200, "fresh".to_string()));
+    assert_eq!(get(port, "/fresh", &[]), (200, "fresh".to_string()));
+    assert_eq!(hits_of("/fresh"), 1);
+    // unless the client insists
+    assert_eq!(get(port, "/fresh", &["Cache-Control: no-cache"]).0, 200);
+    assert_eq!(hits_of("/fresh"), 2);
+
+    // no-store responses are never stored
+    get(port, "/no-store", &[]);
+    get(port, "/no-store", &[]);
+    assert_eq!(hits_of("/no-store"), 2);
+    assert_eq!(
+        get(port, "/no-store", &["Cache-Control: only-if-cached"]).0,
+        504
+    );
+
+    // no-cache responses are revalidated on every use, the body only crosses the network once
+    assert_eq!(
+        get(port, "/revalidate", &[]),
+        (200, "validated".to_string())
+    );
+    assert_eq!(
+        get(port, "/revalidate", &[]),
+        (200, "validated".to_string())
+    );
+    assert_eq!(hits_of("/revalidate"), 2);
+    // conditional requests from the client are answered by the cache
+    assert_eq!(get(port, "/revalidate", &["If-None-Match: \"v1\""]).0, 304);
+
+    // a 304 to validators added by the proxy never reaches a client that sent none
+    assert_eq!(get(port, "/evicted", &[]), (200, "evicted".to_string()));
+    assert_eq!(get(port, "/evicted", &[]), (200, "evicted".to_string()));
+    assert_eq!(hits_of("/evicted"), 4);
+
+    // responses are selected by the request header fields named in Vary:
+    assert_eq!(get(port, "/vary", &["Accept-Language: en"]).1, "en");
+    assert_eq!(get(port, "/vary", &["Accept-Language: fr"]).1, "fr");
+    assert_eq!(get(port, "/vary", &["Accept-Language: en"]).1, "en");
+    assert_eq!(hits_of("/vary"), 2);
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+use web_proxy::{
+    cache::Cache,
+    resolver::DNSResolver,
+    server::{run_server, run_server_with},
+};
+
+const PROXY_PORT: u16 = 4446;
+
+/// A tiny origin server answering every request with its path, on a connection closed afterwards.
+/// Responses to paths starting with /unframed have no Content-Length:, those starting with /chunked
+/// are sent in chunks, those starting with /malformed are not valid HTTP.
+fn origin() -> u16 {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line.split(' ').nth(1).unwrap().to_string();
+            while read_line(&mut reader) != "\r\n" {}
+
+            if path.starts_with("/malformed") {
+                write!(stream, "not HTTP at all\r\n\r\n").unwrap();
+            } else if path.starts_with("/unframed") {
+                write!(stream, "HTTP/1.1 200 OK\r\n\r\n{}", path).unwrap();
+            } else if path.starts_with("/chunked") {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+                )
+                .unwrap();
+                for chunk in path.as_bytes().chunks(3) {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    write!(stream, "\r\n").unwrap();
+                }
+                write!(stream, "0\r\n\r\n").unwrap();
+            } else {
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    path.len(),
+                    path
+                )
+                .unwrap();
+            }
+        }
+    });
+
+    port
+}
+
+/// A TCP server echoing everything back.
+fn echo() -> u16 {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            io::copy(&mut stream, &mut writer).unwrap();
+        }
+    });
+
+    port
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    line
+}
+
+/// Read one response framed by Content-Length:, returns the status code, the headers and the body.
+fn read_response<R: BufRead>(reader: &mut R) -> (u16, HashMap<String, String>, String) {
+    let status_line = read_line(reader);
+    let code = status_line[9..12].parse().unwrap();
+
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_line(reader);
+        if line == "\r\n" {
+            break;
+        }
+        let (name, value) = line.split_once(':').unwrap();
+        headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let len = headers
+        .get("content-length")
+        .map_or(0, |len| len.parse().unwrap());
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+
+    (code, headers, String::from_utf8(body).unwrap())
+}
+
+fn start_proxy() {
+    thread::spawn(|| {
+        run_server(PROXY_PORT).unwrap();
+    });
+    // ensure proxy is fully set up
+    thread::sleep(Duration::from_millis(200));
+}
+
+#[test]
+fn connection_test() {
+    start_proxy();
+    let origin = origin();
+
+    // an idle persistent connection does not block other clients
+    let _idle = TcpStream::connect((Ipv4Addr::LOCALHOST, PROXY_PORT)).unwrap();
+
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PROXY_PORT)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // pipelined requests are answered in order on the same connection
+    let mut requests = String::new();
+    for path in &["/first", "/unframed", "/chunked", "/second"] {
+        requests += &format!(
+            "GET http://127.0.0.1:{}{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+            origin, path, origin
+        );
+    }
+    stream.write_all(requests.as_bytes()).unwrap();
+
+    for path in &["/first", "/unframed", "/chunked", "/second"] {
+        let (code, headers, body) = read_response(&mut reader);
+        assert_eq!(code, 200);
+        assert_eq!(headers["connection"], "keep-alive");
+        assert!(!headers.contains_key("transfer-encoding"));
+        assert_eq!(body, *path);
+    }
+
+    // the connection is closed once the client asks to
+    write!(
+        stream,
+        "GET http://127.0.0.1:{}/last HTTP/1.1\r\nConnection: close\r\n\r\n",
+        origin
+    )
+    .unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(headers["connection"], "close");
+    assert_eq!(body, "/last");
+    let mut rest = vec![];
+    assert_eq!(reader.read_to_end(&mut rest).unwrap(), 0);
+
+    // HTTP/1.0 connections are not persistent by default
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PROXY_PORT)).unwrap();
+    write!(
+        stream,
+        "GET http://127.0.0.1:{}/old HTTP/1.0\r\n\r\n",
+        origin
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("/old"));
+
+    // a malformed response from the server is answered with 502 Bad Gateway
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PROXY_PORT)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    write!(
+        stream,
+        "GET http://127.0.0.1:{}/malformed HTTP/1.1\r\n\r\n",
+        origin
+    )
+    .unwrap();
+    let (code, headers, _) = read_response(&mut reader);
+    assert_eq!(code, 502);
+    assert_eq!(headers["connection"], "close");
+}
+
+#[test]
+fn connect_test() {
+    let proxy = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = proxy.local_addr().unwrap().port();
+    drop(proxy);
+    thread::spawn(move || {
+        run_server(port).unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+    let echo = echo();
+
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    write!(
+        stream,
+        "CONNECT 127.0.0.1:{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+        echo, echo
+    )
+    .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let (code, _, _) = read_response(&mut reader);
+    assert_eq!(code, 200);
+
+    // bytes are relayed untouched in both directions
+    stream.write_all(b"\x16\x03\x01 not really TLS").unwrap();
+    let mut buf = [0u8; 18];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"\x16\x03\x01 not really TLS");
+
+    // open tunnels do not occupy the 64 workers of the proxy
+    let tunnels: Vec<_> = (0..80)
+        .map(|_| {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            write!(
+                stream,
+                "CONNECT 127.0.0.1:{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+                echo, echo
+            )
+            .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            assert_eq!(read_response(&mut reader).0, 200);
+            stream
+        })
+        .collect();
+    let origin = origin();
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    write!(
+        stream,
+        "GET http://127.0.0.1:{}/after HTTP/1.1\r\n\r\n",
+        origin
+    )
+    .unwrap();
+    let (code, _, body) = read_response(&mut reader);
+    assert_eq!((code, body.as_str()), (200, "/after"));
+    drop(tunnels);
+}
+
+#[test]
+fn resolve_test() {
+    let records = vec![dns::Record::new(
+        "origin.test",
+        300,
+        dns::RData::A(Ipv4Addr::LOCALHOST),
+    )];
+    let name_server = dns::StubServer::spawn(records).unwrap();
+    let resolver = DNSResolver::with_server(name_server.local_addr());
+    let proxy = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = proxy.local_addr().unwrap().port();
+    drop(proxy);
+    thread::spawn(move || {
+        run_server_with(port, Cache::default(), resolver).unwrap();
+    });
+    thread::sleep(Duration::from_millis(200));
+    let origin = origin();
+
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    for path in &["/resolved", "/cached"] {
+        write!(
+            stream,
+            "GET http://origin.test:{}{} HTTP/1.1\r\nHost: origin.test:{}\r\n\r\n",
+            origin, path, origin
+        )
+        .unwrap();
+        let (code, _, body) = read_response(&mut reader);
+        assert_eq!((code, body.as_str()), (200, *path));
+    }
+    // the second request is resolved from the cache of the shared resolver
+    assert_eq!(name_server.queries(), 1);
+
+    write!(
+        stream,
+        "GET http://nowhere.test/ HTTP/1.1\r\nHost: nowhere.test\r\n\r\n"
+    )
+    .unwrap();
+    let (code, _, _) = read_response(&mut reader);
+    assert_eq!(code, 404);
+}
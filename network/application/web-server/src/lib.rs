@@ -1,33 +1,63 @@
-mod http_response;
+mod mime;
 
-use http_response::{ResponseHeader, Status};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use http_message::{Method, Request, Response, Status};
 use log::{error, info};
 use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, ErrorKind},
+    fs::{self, File, Metadata},
+    io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
     net::{Ipv4Addr, TcpListener, TcpStream},
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
 };
 
 const FILE_ROOT: &str = "static";
+/// Format of dates in HTTP headers (IMF-fixdate), always in GMT.
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
 
+/// Serve files under ./static/.
 pub fn server(port: u16) -> io::Result<()> {
+    server_with_root(port, FILE_ROOT)
+}
+
+/// Serve files under the given directory, no file outside of it is ever served.
+pub fn server_with_root<P: AsRef<Path>>(port: u16, root: P) -> io::Result<()> {
+    let root = root.as_ref();
     let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
     info!("Tcp listener bound to {}", port);
     for result in listener.incoming() {
         let stream = result?;
         info!("Accepted connection from {:?}", stream.peer_addr());
-        if let Err(err) = handle(stream) {
+        if let Err(err) = handle(stream, root) {
             error!("{:?}", err);
         }
     }
     Ok(())
 }
 
-fn handle(mut stream: TcpStream) -> io::Result<()> {
-    let path = request_path(&mut stream)?;
-    let mut file = match File::open(&path) {
-        Ok(file) => file,
+fn handle(mut stream: TcpStream, root: &Path) -> io::Result<()> {
+    let request = match Request::from_reader(&mut BufReader::new(&stream)) {
+        Ok(request) => request,
+        // e.g. malformed request or unknown method, no response if the connection is broken
+        Err(err) => match err.status() {
+            Some(status) => return Err(error_response(&mut stream, status)),
+            None => return Err(err.into()),
+        },
+    };
+    info!("Request read: {} {}", request.method(), request.path());
+
+    // request body (if any) is never read, no other method makes sense on static files
+    if request.method() != Method::GET {
+        return Err(method_not_implemented(&mut stream));
+    }
+
+    let path = match request_path(root, request.path()) {
+        Some(path) => path,
+        None => return Err(error_response(&mut stream, Status::Forbidden)),
+    };
+    info!("Path resolved: {:?}", path);
+
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
         Err(err) => {
             if err.kind() == ErrorKind::NotFound {
                 return Err(file_not_found(&mut stream));
@@ -37,54 +67,268 @@ fn handle(mut stream: TcpStream) -> io::Result<()> {
             }
         }
     };
-    let file_len = file.metadata()?.len();
 
-    ResponseHeader::new(Status::OK)
-        .attach_header("Content-Length", &format!("{}", file_len))
-        .attach_header("Connection", "close")
-        .attach_header("Content-Type", "text/plain")
-        .write_to(&mut stream)?;
+    if metadata.is_dir() {
+        send_listing(&mut stream, request.path(), &path)
+    } else {
+        send_file(&mut stream, &request, &path, &metadata)
+    }
+}
+
+/// Map the percent-encoded path of a request to a file under root. Returns None if the path is
+/// malformed or escapes root, either by .. components or by symbolic links.
+fn request_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(url_path)?;
+    if !decoded.starts_with('/') || decoded.contains('\0') {
+        return None;
+    }
+
+    let mut path = root.to_path_buf();
+    // pushing an absolute path replaces the current path, only normal components are kept
+    // https://doc.rust-lang.org/std/path/struct.PathBuf.html#method.push
+    for component in Path::new(&decoded).components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::RootDir | Component::CurDir => (),
+            Component::ParentDir | Component::Prefix(..) => return None,
+        }
+    }
 
-    io::copy(&mut file, &mut stream)?;
-    info!("File sent: {:?}, {} bytes", path, file_len);
+    // a symbolic link under root may still point outside of it
+    if let (Ok(canonical), Ok(root)) = (path.canonicalize(), root.canonicalize()) {
+        if !canonical.starts_with(root) {
+            return None;
+        }
+    }
+
+    Some(path)
+}
+
+fn send_file(
+    stream: &mut TcpStream,
+    request: &Request,
+    path: &Path,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    let last_modified = modified.map(http_date).unwrap_or_default();
+
+    let since = request
+        .header("If-Modified-Since")
+        .and_then(parse_http_date);
+    if let (Some(modified), Some(since)) = (modified, since) {
+        // HTTP dates have a resolution of one second
+        if modified.timestamp() <= since.timestamp() {
+            Response::new(Status::NotModified)
+                .attach_header("Date", &http_date(Utc::now()))
+                .attach_header("Last-Modified", &last_modified)
+                .attach_header("Connection", "close")
+                .write_to(stream)?;
+            info!("File not modified: {:?}", path);
+            return Ok(());
+        }
+    }
+
+    let file_len = metadata.len();
+    let (status, start, end) = match request.header("Range").map(|r| parse_range(r, file_len)) {
+        Some(ByteRange::Partial(start, end)) => (Status::PartialContent, start, end),
+        Some(ByteRange::Unsatisfiable) => {
+            Response::new(Status::RangeNotSatisfiable)
+                .attach_header("Content-Range", &format!("bytes */{}", file_len))
+                .attach_header("Content-Length", "0")
+                .attach_header("Connection", "close")
+                .write_to(stream)?;
+            return Err(io::Error::other(Status::RangeNotSatisfiable.reason()));
+        }
+        Some(ByteRange::Whole) | None => (Status::OK, 0, file_len),
+    };
+
+    let mut file = File::open(path)?;
+    let mut header = Response::new(status);
+    header
+        .attach_header("Content-Length", &format!("{}", end - start))
+        .attach_header("Content-Type", mime::content_type(path))
+        .attach_header("Accept-Ranges", "bytes")
+        .attach_header("Date", &http_date(Utc::now()))
+        .attach_header("Last-Modified", &last_modified)
+        .attach_header("Connection", "close");
+    if let Status::PartialContent = status {
+        header.attach_header(
+            "Content-Range",
+            &format!("bytes {}-{}/{}", start, end - 1, file_len),
+        );
+    }
+    header.write_to(&mut *stream)?;
+
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(&mut file.take(end - start), stream)?;
+    info!("File sent: {:?}, bytes {}..{}", path, start, end);
 
     Ok(())
 }
 
-// only part of the first line is parsed
-// all headers, request body, even the http version is skipped
-fn request_path(mut stream: &mut TcpStream) -> io::Result<PathBuf> {
-    let mut reader = BufReader::new(stream);
-    let mut line = String::new();
-    reader.read_line(&mut line)?;
-    // remove newline character
-    line.pop();
-    info!("Method line read: {}", line);
-    stream = reader.into_inner();
+fn send_listing(stream: &mut TcpStream, url_path: &str, dir: &Path) -> io::Result<()> {
+    // links in the listing are relative to the directory, which must end with a slash
+    if !url_path.ends_with('/') {
+        Response::new(Status::MovedPermanently)
+            .attach_header("Location", &format!("{}/", url_path))
+            .attach_header("Content-Length", "0")
+            .attach_header("Connection", "close")
+            .write_to(stream)?;
+        return Ok(());
+    }
 
-    if !line.starts_with("GET") {
-        return Err(method_not_implemented(stream));
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let suffix = if entry.file_type()?.is_dir() { "/" } else { "" };
+        entries.push((name, suffix));
     }
+    entries.sort();
 
-    line = line.split_off("GET ".len());
-    line.split_off(line.find(' ').expect("Space between url and HTTP version"));
-    info!("Url extracted: {}", line);
+    let title = escape_html(&percent_decode(url_path).unwrap_or_default());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n\
+         <body>\n<h1>Index of {0}</h1>\n<ul>\n",
+        title
+    );
+    if url_path != "/" {
+        html += "<li><a href=\"../\">../</a></li>\n";
+    }
+    for (name, suffix) in entries {
+        html += &format!(
+            "<li><a href=\"{}{}\">{}{}</a></li>\n",
+            percent_encode(&name),
+            suffix,
+            escape_html(&name),
+            suffix
+        );
+    }
+    html += "</ul>\n</body>\n</html>\n";
 
-    let mut path = PathBuf::from(".");
-    // file under ./static/ is served as text files
-    path.push(FILE_ROOT);
-    // the first slash in the url is skipped, otherwise the url is treated as absolute path, replaces current path
-    // https://doc.rust-lang.org/std/path/struct.PathBuf.html#method.push
-    path.push(&line[1..]);
-    Ok(path)
+    Response::new(Status::OK)
+        .attach_header("Content-Length", &format!("{}", html.len()))
+        .attach_header("Content-Type", "text/html; charset=utf-8")
+        .attach_header("Connection", "close")
+        .write_to(&mut *stream)?;
+    stream.write_all(html.as_bytes())?;
+    info!("Listing sent: {:?}", dir);
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    /// Bytes from start (inclusive) to end (exclusive).
+    Partial(u64, u64),
+    /// No byte of the requested range is in the file.
+    Unsatisfiable,
+    /// Malformed or multipart ranges, ignored and the whole file is sent.
+    Whole,
+}
+
+/// Parse the value of Range: header, only a single byte range is supported, defined by:\
+/// [https://tools.ietf.org/html/rfc7233#section-2.1](https://tools.ietf.org/html/rfc7233#section-2.1)
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec,
+        _ => return ByteRange::Whole,
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some((first, last)) => (first.trim(), last.trim()),
+        None => return ByteRange::Whole,
+    };
+
+    if first.is_empty() {
+        // suffix range: the last n bytes of the file
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial(len.saturating_sub(n), len),
+            Err(_) => ByteRange::Whole,
+        };
+    }
+
+    let start = match first.parse::<u64>() {
+        Ok(start) => start,
+        Err(_) => return ByteRange::Whole,
+    };
+    let end = if last.is_empty() {
+        len
+    } else {
+        match last.parse::<u64>() {
+            Ok(last) if last >= start => len.min(last + 1),
+            _ => return ByteRange::Whole,
+        }
+    };
+
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(start, end)
+    }
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format(HTTP_DATE).to_string()
+}
+
+fn parse_http_date(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, HTTP_DATE)
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded += &format!("%{:02X}", b);
+        }
+    }
+    encoded
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&#39;",
+            _ => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 fn error_response(stream: &mut TcpStream, status: Status) -> io::Error {
-    ResponseHeader::new(status)
+    Response::new(status)
+        .attach_header("Content-Length", "0")
         .attach_header("Connection", "close")
         .write_to(stream)
         .err()
-        .unwrap_or_else(|| io::Error::new(ErrorKind::Other, status.message()))
+        .unwrap_or_else(|| io::Error::other(status.reason()))
 }
 
 fn method_not_implemented(stream: &mut TcpStream) -> io::Error {
@@ -94,3 +338,49 @@ fn method_not_implemented(stream: &mut TcpStream) -> io::Error {
 fn file_not_found(stream: &mut TcpStream) -> io::Error {
     error_response(stream, Status::NotFound)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traversal() {
+        let root = Path::new("static");
+        assert_eq!(
+            request_path(root, "/dir/a%20b.txt"),
+            Some(PathBuf::from("static/dir/a b.txt"))
+        );
+        assert_eq!(request_path(root, "/"), Some(PathBuf::from("static")));
+        assert_eq!(request_path(root, "/../Cargo.toml"), None);
+        assert_eq!(request_path(root, "/dir/%2e%2e/%2E%2E/etc/passwd"), None);
+        assert_eq!(
+            request_path(root, "//etc/passwd"),
+            Some(PathBuf::from("static/etc/passwd"))
+        );
+        assert_eq!(request_path(root, "/bad%zz"), None);
+        assert_eq!(request_path(root, "relative"), None);
+    }
+
+    #[test]
+    fn ranges() {
+        use ByteRange::*;
+
+        assert_eq!(parse_range("bytes=0-499", 1000), Partial(0, 500));
+        assert_eq!(parse_range("bytes=500-", 1000), Partial(500, 1000));
+        assert_eq!(parse_range("bytes=-300", 1000), Partial(700, 1000));
+        assert_eq!(parse_range("bytes=-3000", 1000), Partial(0, 1000));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Partial(900, 1000));
+        assert_eq!(parse_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=500-400", 1000), Whole);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Whole);
+        assert_eq!(parse_range("lines=0-1", 1000), Whole);
+    }
+
+    #[test]
+    fn dates() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(http_date(date), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    }
+}
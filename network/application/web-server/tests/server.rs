@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+use web_server::server_with_root;
+
+const PORT: u16 = 4480;
+
+/// Send a raw request, returns the status code, the headers and the body.
+fn request(request: &str) -> (u16, HashMap<String, String>, Vec<u8>) {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, PORT)).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    // the server closes the connection after the response
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let mut lines = head.split("\r\n");
+    let code = lines.next().unwrap()[9..12].parse().unwrap();
+    let headers = lines
+        .map(|line| {
+            let (name, value) = line.split_once(':').unwrap();
+            (name.to_ascii_lowercase(), value.trim().to_string())
+        })
+        .collect();
+    (code, headers, response[split + 4..].to_vec())
+}
+
+fn get(path: &str, headers: &[&str]) -> (u16, HashMap<String, String>, Vec<u8>) {
+    let mut req = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n", path);
+    for header in headers {
+        req += header;
+        req += "\r\n";
+    }
+    req += "\r\n";
+    request(&req)
+}
+
+fn setup() -> PathBuf {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("static");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("sub dir")).unwrap();
+    fs::write(root.join("index.html"), "<h1>hello</h1>").unwrap();
+    fs::write(root.join("digits.txt"), "0123456789").unwrap();
+    fs::write(
+        root.join("sub dir").join("<b>.png"),
+        [0x89, b'P', b'N', b'G'],
+    )
+    .unwrap();
+
+    let served = root.clone();
+    thread::spawn(move || server_with_root(PORT, served).unwrap());
+    // ensure server is fully set up
+    thread::sleep(Duration::from_millis(200));
+    root
+}
+
+#[test]
+fn static_file_test() {
+    setup();
+
+    // content type follows the extension
+    let (code, headers, body) = get("/index.html", &[]);
+    assert_eq!(code, 200);
+    assert_eq!(headers["content-type"], "text/html; charset=utf-8");
+    assert_eq!(body, b"<h1>hello</h1>");
+    let (_, headers, _) = get("/sub%20dir/%3Cb%3E.png", &[]);
+    assert_eq!(headers["content-type"], "image/png");
+
+    // conditional requests
+    let (_, headers, _) = get("/digits.txt", &[]);
+    let last_modified = format!("If-Modified-Since: {}", headers["last-modified"]);
+    let (code, _, body) = get("/digits.txt", &[&last_modified]);
+    assert_eq!(code, 304);
+    assert!(body.is_empty());
+    let (code, _, _) = get(
+        "/digits.txt",
+        &["If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT"],
+    );
+    assert_eq!(code, 200);
+
+    // range requests
+    let (code, headers, body) = get("/digits.txt", &["Range: bytes=2-4"]);
+    assert_eq!(code, 206);
+    assert_eq!(headers["content-range"], "bytes 2-4/10");
+    assert_eq!(body, b"234");
+    let (_, _, body) = get("/digits.txt", &["Range: bytes=-3"]);
+    assert_eq!(body, b"789");
+    let (code, headers, _) = get("/digits.txt", &["Range: bytes=10-"]);
+    assert_eq!(code, 416);
+    assert_eq!(headers["content-range"], "bytes */10");
+
+    // directory listings
+    let (code, headers, _) = get("/sub%20dir", &[]);
+    assert_eq!(code, 301);
+    assert_eq!(headers["location"], "/sub%20dir/");
+    let (code, headers, body) = get("/", &[]);
+    assert_eq!(code, 200);
+    assert_eq!(headers["content-type"], "text/html; charset=utf-8");
+    let body = String::from_utf8(body).unwrap();
+    assert!(body.contains("<a href=\"digits.txt\">digits.txt</a>"));
+    assert!(body.contains("<a href=\"sub%20dir/\">sub dir/</a>"));
+    let (_, _, body) = get("/sub%20dir/", &[]);
+    let body = String::from_utf8(body).unwrap();
+    assert!(body.contains("<a href=\"%3Cb%3E.png\">&lt;b&gt;.png</a>"));
+
+    // nothing outside of the root is served
+    assert_eq!(get("/../server.rs", &[]).0, 403);
+    assert_eq!(get("/sub%20dir/%2E%2E/%2E%2E/server.rs", &[]).0, 403);
+    assert_eq!(get("/missing", &[]).0, 404);
+    assert_eq!(request("DELETE /index.html HTTP/1.1\r\n\r\n").0, 501);
+    assert_eq!(request("garbage\r\n\r\n").0, 400);
+}
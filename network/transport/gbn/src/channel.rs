@@ -1,6 +1,6 @@
 use crate::protocol::{Message, Packet};
 use log::{error, info, warn};
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::{
     collections::VecDeque,
     sync::mpsc::{Receiver, RecvTimeoutError, Sender},
@@ -15,22 +15,33 @@ pub struct Channel {
     loss_rate: f64,
     corrupt_rate: f64,
     rtt: Duration,
+    seed: Option<u64>,
     queue: VecDeque<(Packet, Instant)>,
 }
 
 impl Channel {
     pub fn new(loss_rate: f64, corrupt_rate: f64, rtt: Duration) -> Self {
-        assert!(0.0 <= loss_rate && loss_rate <= 1.0);
-        assert!(0.0 <= corrupt_rate && corrupt_rate <= 1.0);
+        assert!((0.0..=1.0).contains(&loss_rate));
+        assert!((0.0..=1.0).contains(&corrupt_rate));
 
         Channel {
             loss_rate,
             corrupt_rate,
             rtt,
+            seed: None,
             queue: VecDeque::new(),
         }
     }
 
+    /// A channel losing and corrupting the same packets on every run, i.e. the n-th packet
+    /// through each connected direction always meets the same fate.
+    pub fn seeded(loss_rate: f64, corrupt_rate: f64, rtt: Duration, seed: u64) -> Self {
+        Channel {
+            seed: Some(seed),
+            ..Channel::new(loss_rate, corrupt_rate, rtt)
+        }
+    }
+
     fn next_deadline(&self) -> Option<Instant> {
         self.queue.front().map(|(_, t)| *t)
     }
@@ -66,7 +77,10 @@ impl Channel {
         use RecvTimeoutError::*;
 
         thread::spawn(move || {
-            let mut rng = thread_rng();
+            let mut rng = match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
             loop {
                 match from.recv_timeout(self.next_duration()) {
                     Ok(mut packet) => {
@@ -76,7 +90,7 @@ impl Channel {
                         }
                         if self.corrupt_rate >= rng.gen() {
                             info!("Channel corrupted packet: {:?}", packet);
-                            corrupt(&mut packet);
+                            corrupt(&mut packet, &mut rng);
                         }
                         // one way delay, half the rtt
                         self.queue
@@ -99,8 +113,7 @@ impl Channel {
 const FOREVER: Duration = Duration::from_secs(100_000_000_000);
 
 /// Introduce one byte error to a packet.
-fn corrupt(packet: &mut Packet) {
-    let mut rng = thread_rng();
+fn corrupt<R: Rng>(packet: &mut Packet, rng: &mut R) {
     let len = packet.len();
     let idx = rng.gen_range(0, len);
     packet[idx] = !packet[idx];
@@ -108,7 +121,7 @@ fn corrupt(packet: &mut Packet) {
 
 #[test]
 fn corrupt_test() {
-    let mut rng = thread_rng();
+    let mut rng = rand::thread_rng();
     for _ in 0..100 {
         let sn = rng.gen();
         let mut data = vec![0; rng.gen_range(10, 100)];
@@ -116,7 +129,7 @@ fn corrupt_test() {
 
         let mut packet = Packet::new_packet(sn, &data);
         assert!(!packet.corrupted());
-        corrupt(&mut packet);
+        corrupt(&mut packet, &mut rng);
         // one byte error should always be detectable
         assert!(packet.corrupted());
     }
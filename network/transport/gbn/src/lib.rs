@@ -3,9 +3,12 @@
 //! - Receiver::recv_timeout the duration between now and a deadline updated from time to time
 //! - sender, receiver and channel all live in their own threads
 //! - channel takes one mpsc::Sender and one mpsc::Receiver, pipe item between them unreliably in a separated thread
+//!
 //! may better to do this all in nightly with async / await but a few components of nightly are broken now
 
 pub mod channel;
 pub mod protocol;
+pub mod reno;
+pub mod selective_repeat;
 
-pub use checksum::checksum;
\ No newline at end of file
+pub use checksum::checksum;
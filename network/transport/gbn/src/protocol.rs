@@ -4,7 +4,10 @@ use std::{
     collections::VecDeque,
     mem::size_of,
     ops::{Deref, DerefMut},
-    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
     thread,
     time::{Duration, Instant},
 };
@@ -22,16 +25,16 @@ mod consts {
     pub const CS_OFFSET: usize = SN_OFFSET + SN_LEN;
     pub const DATA_OFFSET: usize = CS_OFFSET + CS_LEN;
 
-    pub const MAX_WINDOW_SIZE: u32 = u32::max_value() / 2;
+    pub const MAX_WINDOW_SIZE: u32 = u32::MAX / 2;
     pub const INIT_SEQUENTIAL_NUMBER: u32 = 0;
 }
 
-use consts::*;
+pub(crate) use consts::*;
 
 #[derive(Clone, Copy, PartialEq)]
 enum Type {
     Packet = 0,
-    ACK = 1,
+    Ack = 1,
 }
 
 #[derive(Clone, PartialEq)]
@@ -54,11 +57,11 @@ impl Packet {
     }
 
     pub fn new_ack(sn: u32) -> Self {
-        Self::new(Type::ACK, sn, &[])
+        Self::new(Type::Ack, sn, &[])
     }
 
     pub fn corrupted(&self) -> bool {
-        checksum(&self) != [0, 0]
+        checksum(self) != [0, 0]
     }
 
     pub fn sn(&self) -> u32 {
@@ -119,6 +122,18 @@ pub enum Message {
     Data(Vec<u8>),
 }
 
+/// Counters of a sender, shared with the thread running it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Packets sent into the channel, retransmissions included.
+    pub sent: usize,
+    pub retransmitted: usize,
+    /// Timer expiries that resent at least one packet.
+    pub timeouts: usize,
+    /// Retransmissions triggered by duplicated ACKs rather than a timeout.
+    pub fast_retransmits: usize,
+}
+
 struct Timer {
     deadline: Instant,
     timeout: Duration,
@@ -181,13 +196,14 @@ impl PacketQueue {
         }
     }
 
-    fn flush(&mut self, channel: &Sender<Packet>) {
+    fn flush(&mut self, channel: &Sender<Packet>, stats: &Mutex<Statistics>) {
         while self.waiting < self.window {
             if let Some(packet) = self.queue.get(self.waiting as usize) {
                 channel.send(packet.clone()).unwrap_or_else(|_| {
                     error!("PacketQueue::flush: Receiver dropped");
                 });
                 info!("Send packet: {:?}", packet);
+                stats.lock().unwrap().sent += 1;
                 self.waiting += 1;
             } else {
                 break;
@@ -195,7 +211,17 @@ impl PacketQueue {
         }
     }
 
-    fn retransmit(&self, channel: &Sender<Packet>) {
+    fn retransmit(&self, channel: &Sender<Packet>, stats: &Mutex<Statistics>) {
+        // the timer keeps running while nothing is outstanding
+        if self.waiting == 0 {
+            return;
+        }
+
+        let mut stats = stats.lock().unwrap();
+        stats.timeouts += 1;
+        stats.sent += self.waiting as usize;
+        stats.retransmitted += self.waiting as usize;
+
         for i in 0..self.waiting as usize {
             info!("Retransmitting packet {}", self.sn.wrapping_add(i as u32));
             channel.send(self.queue[i].clone()).unwrap_or_else(|_| {
@@ -211,6 +237,7 @@ pub struct GBNSender {
     channel: Sender<Packet>,
     timer: Timer,
     queue: PacketQueue,
+    stats: Arc<Mutex<Statistics>>,
 }
 
 impl GBNSender {
@@ -226,6 +253,7 @@ impl GBNSender {
             channel: channel_input,
             timer: Timer::new(timeout),
             queue: PacketQueue::new(window),
+            stats: Arc::default(),
         };
 
         (sender, channel_output)
@@ -235,6 +263,10 @@ impl GBNSender {
         self.event_send.clone()
     }
 
+    pub fn statistics(&self) -> Arc<Mutex<Statistics>> {
+        self.stats.clone()
+    }
+
     pub fn process(mut self) {
         thread::spawn(move || {
             self.handle();
@@ -254,11 +286,11 @@ impl GBNSender {
     }
 
     fn retransmit(&self) {
-        self.queue.retransmit(&self.channel);
+        self.queue.retransmit(&self.channel, &self.stats);
     }
 
     fn flush(&mut self) {
-        self.queue.flush(&self.channel);
+        self.queue.flush(&self.channel, &self.stats);
     }
 
     fn handle(&mut self) {
@@ -0,0 +1,445 @@
+use crate::{
+    protocol::{Message, Packet, Statistics, INIT_SEQUENTIAL_NUMBER, MAX_WINDOW_SIZE},
+    selective_repeat::ReceiveWindow,
+};
+use log::{error, info};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const FOREVER: Duration = Duration::from_secs(100_000_000_000);
+const MIN_TIMEOUT: Duration = Duration::from_millis(10);
+const MAX_TIMEOUT: Duration = Duration::from_secs(60);
+const INIT_SSTHRESH: f64 = 64.0;
+const DUPLICATED_ACK_THRESHOLD: u32 = 3;
+
+/// Retransmission timeout estimated from RTT samples as in RFC 6298.
+struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl RttEstimator {
+    fn new(initial_timeout: Duration) -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::from_secs(0),
+            rto: initial_timeout,
+        }
+    }
+
+    fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let deviation = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar * 3 / 4 + deviation / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+
+        let rto = self.srtt.unwrap() + self.rttvar * 4;
+        self.rto = rto.max(MIN_TIMEOUT).min(MAX_TIMEOUT);
+    }
+
+    fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_TIMEOUT);
+    }
+}
+
+/// A sender with the congestion control of TCP Reno: slow start, congestion avoidance, fast
+/// retransmit and fast recovery on three duplicated ACKs, and an adaptive retransmission timeout.
+/// Windows are counted in packets rather than bytes, the receiver acknowledges cumulatively.
+pub struct RenoSender {
+    event_recv: Receiver<Message>,
+    event_send: Sender<Message>,
+    channel: Sender<Packet>,
+    // the flow control window advertised by the receiver, fixed
+    max_window: u32,
+    cwnd: f64,
+    ssthresh: f64,
+    duplicated_acks: u32,
+    rtt: RttEstimator,
+    deadline: Option<Instant>,
+    // the packet being timed and when it was sent, never a retransmitted one by Karn's algorithm
+    timing: Option<(u32, Instant)>,
+    // sequential number of the first packet in queue
+    sn: u32,
+    // packets sent since the last timeout, and sent ever, from the front of the queue
+    in_flight: usize,
+    high_water: usize,
+    queue: VecDeque<Packet>,
+    stats: Arc<Mutex<Statistics>>,
+}
+
+impl RenoSender {
+    pub fn new(max_window: u32, initial_timeout: Duration) -> (Self, Receiver<Packet>) {
+        assert!(max_window <= MAX_WINDOW_SIZE);
+
+        let (event_send, event_recv) = channel();
+        let (channel_input, channel_output) = channel();
+
+        let sender = Self {
+            event_recv,
+            event_send,
+            channel: channel_input,
+            max_window,
+            cwnd: 1.0,
+            ssthresh: INIT_SSTHRESH,
+            duplicated_acks: 0,
+            rtt: RttEstimator::new(initial_timeout),
+            deadline: None,
+            timing: None,
+            sn: INIT_SEQUENTIAL_NUMBER,
+            in_flight: 0,
+            high_water: 0,
+            queue: VecDeque::new(),
+            stats: Arc::default(),
+        };
+
+        (sender, channel_output)
+    }
+
+    pub fn event_send(&self) -> Sender<Message> {
+        self.event_send.clone()
+    }
+
+    pub fn statistics(&self) -> Arc<Mutex<Statistics>> {
+        self.stats.clone()
+    }
+
+    /// Congestion window in packets.
+    pub fn cwnd(&self) -> f64 {
+        self.cwnd
+    }
+
+    pub fn ssthresh(&self) -> f64 {
+        self.ssthresh
+    }
+
+    /// Current retransmission timeout.
+    pub fn timeout(&self) -> Duration {
+        self.rtt.rto
+    }
+
+    pub fn process(mut self) {
+        thread::spawn(move || {
+            self.handle();
+        });
+    }
+
+    fn window(&self) -> usize {
+        (self.cwnd as usize).clamp(1, self.max_window as usize)
+    }
+
+    fn until_deadline(&self) -> Duration {
+        self.deadline.map_or(FOREVER, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        })
+    }
+
+    fn restart_timer(&mut self) {
+        self.deadline = if self.in_flight > 0 {
+            Some(Instant::now() + self.rtt.rto)
+        } else {
+            None
+        };
+    }
+
+    fn send(&mut self, idx: usize) {
+        let packet = &self.queue[idx];
+        info!("Send packet: {:?}", packet);
+        self.channel.send(packet.clone()).unwrap_or_else(|_| {
+            error!("RenoSender::send: Receiver dropped");
+        });
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.sent += 1;
+        if idx < self.high_water {
+            stats.retransmitted += 1;
+        } else {
+            self.high_water = idx + 1;
+            if self.timing.is_none() {
+                self.timing = Some((packet.sn(), Instant::now()));
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        while self.in_flight < self.window().min(self.queue.len()) {
+            self.send(self.in_flight);
+            self.in_flight += 1;
+            if self.deadline.is_none() {
+                self.restart_timer();
+            }
+        }
+    }
+
+    fn ssthresh_after_loss(&self) -> f64 {
+        (self.in_flight as f64 / 2.0).max(2.0)
+    }
+
+    fn ack(&mut self, ack_sn: u32) {
+        let acked = ack_sn.wrapping_add(1).wrapping_sub(self.sn) as usize;
+
+        if acked == 0 {
+            if self.in_flight > 0 {
+                self.duplicated_ack();
+            }
+            return;
+        }
+        if acked > self.high_water {
+            info!("ACK to packet {} outside of window", ack_sn);
+            return;
+        }
+
+        info!("ACKed packets up to {}", ack_sn);
+        if let Some((sn, sent_at)) = self.timing {
+            if sn.wrapping_sub(self.sn) < acked as u32 {
+                self.rtt.sample(sent_at.elapsed());
+                self.timing = None;
+            }
+        }
+
+        self.queue.drain(..acked);
+        self.sn = ack_sn.wrapping_add(1);
+        self.in_flight = self.in_flight.saturating_sub(acked);
+        self.high_water -= acked;
+
+        if self.duplicated_acks >= DUPLICATED_ACK_THRESHOLD {
+            // fast recovery ends, deflate the window
+            self.cwnd = self.ssthresh;
+        } else if self.cwnd < self.ssthresh {
+            // slow start
+            self.cwnd += 1.0;
+        } else {
+            // congestion avoidance, one packet per round trip
+            self.cwnd += 1.0 / self.cwnd;
+        }
+        self.duplicated_acks = 0;
+        self.restart_timer();
+    }
+
+    fn duplicated_ack(&mut self) {
+        self.duplicated_acks += 1;
+        info!("Duplicated ACK to packet {}", self.sn.wrapping_sub(1));
+
+        if self.duplicated_acks == DUPLICATED_ACK_THRESHOLD {
+            info!("Fast retransmitting packet {}", self.sn);
+            self.ssthresh = self.ssthresh_after_loss();
+            self.cwnd = self.ssthresh + DUPLICATED_ACK_THRESHOLD as f64;
+            self.timing = None;
+            self.stats.lock().unwrap().fast_retransmits += 1;
+            self.send(0);
+            self.restart_timer();
+        } else if self.duplicated_acks > DUPLICATED_ACK_THRESHOLD {
+            // every duplicated ACK is a packet that left the network
+            self.cwnd += 1.0;
+        }
+    }
+
+    fn timeout_expired(&mut self) {
+        info!("Sender timed out, resume from packet {}", self.sn);
+        self.stats.lock().unwrap().timeouts += 1;
+
+        self.ssthresh = self.ssthresh_after_loss();
+        self.cwnd = 1.0;
+        self.duplicated_acks = 0;
+        self.timing = None;
+        self.rtt.backoff();
+        // go back to the first unacknowledged packet, the receiver buffers the rest
+        self.in_flight = 0;
+        self.deadline = None;
+    }
+
+    fn handle_event(&mut self, event: Result<Message, RecvTimeoutError>) -> bool {
+        use Message::*;
+        use RecvTimeoutError::*;
+
+        match event {
+            Ok(Data(data)) => {
+                info!("Received data from upper layer: {:?}", data);
+                let next_sn = self.sn.wrapping_add(self.queue.len() as u32);
+                self.queue
+                    .push_back(crate::protocol::Packet::new_packet(next_sn, &data));
+            }
+            Ok(Packet(ref packet)) if !packet.corrupted() => {
+                info!("Sender received packet: {:?}", packet);
+                self.ack(packet.sn());
+            }
+            Ok(Terminate) | Err(Disconnected) => {
+                info!("Channel disconnected / terminate signal received, tear down sender");
+                return false;
+            }
+            Err(Timeout) => self.timeout_expired(),
+            _ => (),
+        }
+
+        self.flush();
+        true
+    }
+
+    fn handle(&mut self) {
+        while self.handle_event(self.event_recv.recv_timeout(self.until_deadline())) {}
+    }
+}
+
+/// Receiver for `RenoSender`, buffers packets out of order and acknowledges the last packet
+/// received in order, each packet out of order causes a duplicated ACK.
+pub struct RenoReceiver {
+    event_recv: Receiver<Message>,
+    event_send: Sender<Message>,
+    channel: Sender<Packet>,
+    window: ReceiveWindow,
+}
+
+impl RenoReceiver {
+    pub fn new(window: u32) -> (Self, Receiver<Packet>, Receiver<Vec<u8>>) {
+        let (event_send, event_recv) = channel();
+        let (channel_input, channel_output) = channel();
+        let (upper_input, upper_output) = channel();
+
+        let receiver = Self {
+            event_recv,
+            event_send,
+            channel: channel_input,
+            window: ReceiveWindow::new(window, upper_input),
+        };
+
+        (receiver, channel_output, upper_output)
+    }
+
+    pub fn event_send(&self) -> Sender<Message> {
+        self.event_send.clone()
+    }
+
+    pub fn process(mut self) {
+        thread::spawn(move || self.handle());
+    }
+
+    fn handle(&mut self) {
+        for event in &self.event_recv {
+            match event {
+                Message::Packet(packet) => {
+                    info!("Receiver received packet: {:?}", packet);
+                    if packet.corrupted() {
+                        continue;
+                    }
+
+                    self.window.receive(&packet);
+                    let sn = self.window.sn.wrapping_sub(1);
+                    info!("Receiver send ACK {}", sn);
+                    self.channel.send(Packet::new_ack(sn)).unwrap_or_else(|_| {
+                        error!("RenoReceiver::handle: receiver dropped");
+                    });
+                }
+                Message::Terminate => {
+                    info!("Terminate signal received, tear down receiver");
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(sender: &mut RenoSender, sn: u32) {
+        assert!(sender.handle_event(Ok(Message::Packet(Packet::new_ack(sn)))));
+    }
+
+    fn sent(output: &Receiver<Packet>) -> Vec<u32> {
+        output.try_iter().map(|packet| packet.sn()).collect()
+    }
+
+    fn sender_with_data(packets: usize) -> (RenoSender, Receiver<Packet>) {
+        let (mut sender, output) = RenoSender::new(64, Duration::from_millis(100));
+        for _ in 0..packets {
+            assert!(sender.handle_event(Ok(Message::Data(vec![]))));
+        }
+        (sender, output)
+    }
+
+    #[test]
+    fn slow_start_then_congestion_avoidance() {
+        let (mut sender, output) = sender_with_data(100);
+        sender.ssthresh = 4.0;
+        assert_eq!(sent(&output), vec![0]);
+
+        // the window doubles every round trip in slow start
+        ack(&mut sender, 0);
+        assert_eq!(sent(&output), vec![1, 2]);
+        ack(&mut sender, 1);
+        ack(&mut sender, 2);
+        assert_eq!(sent(&output), vec![3, 4, 5, 6]);
+        assert_eq!(sender.cwnd(), 4.0);
+
+        // then grows by one packet per round trip
+        for sn in 3..=6 {
+            ack(&mut sender, sn);
+        }
+        assert!(sender.cwnd() > 4.9 && sender.cwnd() < 5.0);
+    }
+
+    #[test]
+    fn fast_retransmit_on_three_duplicated_acks() {
+        let (mut sender, output) = sender_with_data(100);
+        sender.cwnd = 7.0;
+        ack(&mut sender, 0);
+        assert_eq!(sent(&output).len(), 1 + 8);
+
+        // packet 1 is lost, the receiver keeps acknowledging packet 0
+        ack(&mut sender, 0);
+        ack(&mut sender, 0);
+        assert!(sent(&output).is_empty());
+        ack(&mut sender, 0);
+        assert_eq!(sent(&output), vec![1]);
+        assert_eq!(sender.ssthresh(), 4.0);
+        assert_eq!(sender.cwnd(), 7.0);
+
+        // the retransmission fills the hole, recovery ends
+        ack(&mut sender, 8);
+        assert_eq!(sender.cwnd(), 4.0);
+        let stats = *sender.statistics().lock().unwrap();
+        assert_eq!(
+            (stats.fast_retransmits, stats.retransmitted, stats.timeouts),
+            (1, 1, 0)
+        );
+    }
+
+    #[test]
+    fn timeout_restarts_slow_start() {
+        let (mut sender, output) = sender_with_data(100);
+        sender.cwnd = 7.0;
+        ack(&mut sender, 0);
+        sent(&output);
+        let timeout = sender.timeout();
+
+        assert!(sender.handle_event(Err(RecvTimeoutError::Timeout)));
+        assert_eq!(sent(&output), vec![1]);
+        assert_eq!((sender.cwnd(), sender.ssthresh()), (1.0, 4.0));
+        assert_eq!(sender.timeout(), timeout * 2);
+    }
+
+    #[test]
+    fn rtt_estimation() {
+        let mut rtt = RttEstimator::new(Duration::from_secs(1));
+        rtt.sample(Duration::from_millis(100));
+        assert_eq!(rtt.rto, Duration::from_millis(300));
+        rtt.sample(Duration::from_millis(100));
+        assert_eq!(rtt.rto, Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,351 @@
+use crate::protocol::{Message, Packet, Statistics, INIT_SEQUENTIAL_NUMBER, MAX_WINDOW_SIZE};
+use log::{error, info};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const FOREVER: Duration = Duration::from_secs(100_000_000_000);
+
+struct Outstanding {
+    packet: Packet,
+    acked: bool,
+    // None if the packet has not been sent yet
+    deadline: Option<Instant>,
+}
+
+/// Selective Repeat sender, every packet is acknowledged individually and has its own timer, only
+/// packets timed out are retransmitted.
+pub struct SRSender {
+    event_recv: Receiver<Message>,
+    event_send: Sender<Message>,
+    channel: Sender<Packet>,
+    timeout: Duration,
+    window: u32,
+    // sequential number of the first packet in queue
+    sn: u32,
+    queue: VecDeque<Outstanding>,
+    stats: Arc<Mutex<Statistics>>,
+}
+
+impl SRSender {
+    pub fn new(window: u32, timeout: Duration) -> (Self, Receiver<Packet>) {
+        assert!(window <= MAX_WINDOW_SIZE);
+
+        let (event_send, event_recv) = channel();
+        let (channel_input, channel_output) = channel();
+
+        let sender = Self {
+            event_recv,
+            event_send,
+            channel: channel_input,
+            timeout,
+            window,
+            sn: INIT_SEQUENTIAL_NUMBER,
+            queue: VecDeque::new(),
+            stats: Arc::default(),
+        };
+
+        (sender, channel_output)
+    }
+
+    pub fn event_send(&self) -> Sender<Message> {
+        self.event_send.clone()
+    }
+
+    pub fn statistics(&self) -> Arc<Mutex<Statistics>> {
+        self.stats.clone()
+    }
+
+    pub fn process(mut self) {
+        thread::spawn(move || {
+            self.handle();
+        });
+    }
+
+    fn until_deadline(&self) -> Duration {
+        self.queue
+            .iter()
+            .filter(|outstanding| !outstanding.acked)
+            .filter_map(|outstanding| outstanding.deadline)
+            .min()
+            .map_or(FOREVER, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            })
+    }
+
+    fn send(&self, packet: &Packet) {
+        self.channel.send(packet.clone()).unwrap_or_else(|_| {
+            error!("SRSender::send: Receiver dropped");
+        });
+    }
+
+    fn ack(&mut self, ack_sn: u32) {
+        let offset = ack_sn.wrapping_sub(self.sn) as usize;
+        match self.queue.get_mut(offset) {
+            Some(outstanding) if outstanding.deadline.is_some() => {
+                info!("ACKed packet {}", ack_sn);
+                outstanding.acked = true;
+            }
+            _ => info!("ACK to packet {} outside of window", ack_sn),
+        }
+
+        while self
+            .queue
+            .front()
+            .is_some_and(|outstanding| outstanding.acked)
+        {
+            self.queue.pop_front();
+            self.sn = self.sn.wrapping_add(1);
+        }
+    }
+
+    fn flush(&mut self) {
+        let deadline = Instant::now() + self.timeout;
+        let window = self.window as usize;
+        let mut sent = 0;
+
+        for outstanding in self.queue.iter_mut().take(window) {
+            if outstanding.deadline.is_none() {
+                info!("Send packet: {:?}", outstanding.packet);
+                self.channel
+                    .send(outstanding.packet.clone())
+                    .unwrap_or_else(|_| {
+                        error!("SRSender::flush: Receiver dropped");
+                    });
+                outstanding.deadline = Some(deadline);
+                sent += 1;
+            }
+        }
+
+        self.stats.lock().unwrap().sent += sent;
+    }
+
+    fn retransmit(&mut self) {
+        let now = Instant::now();
+        let mut timed_out = vec![];
+
+        for outstanding in self.queue.iter_mut() {
+            match outstanding.deadline {
+                Some(deadline) if !outstanding.acked && deadline <= now => {
+                    outstanding.deadline = Some(now + self.timeout);
+                    timed_out.push(outstanding.packet.clone());
+                }
+                _ => (),
+            }
+        }
+
+        // the channel may wake up slightly before the earliest deadline
+        if timed_out.is_empty() {
+            return;
+        }
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.timeouts += 1;
+        stats.sent += timed_out.len();
+        stats.retransmitted += timed_out.len();
+        drop(stats);
+
+        for packet in &timed_out {
+            info!("Retransmitting packet {}", packet.sn());
+            self.send(packet);
+        }
+    }
+
+    fn handle(&mut self) {
+        use Message::*;
+        use RecvTimeoutError::*;
+
+        loop {
+            match self.event_recv.recv_timeout(self.until_deadline()) {
+                Ok(Data(data)) => {
+                    info!("Received data from upper layer: {:?}", data);
+                    let next_sn = self.sn.wrapping_add(self.queue.len() as u32);
+                    self.queue.push_back(Outstanding {
+                        packet: crate::protocol::Packet::new_packet(next_sn, &data),
+                        acked: false,
+                        deadline: None,
+                    });
+                }
+                Ok(Packet(ref packet)) if !packet.corrupted() => {
+                    info!("Sender received packet: {:?}", packet);
+                    self.ack(packet.sn());
+                }
+                Ok(Terminate) | Err(Disconnected) => {
+                    info!("Channel disconnected / terminate signal received, tear down sender");
+                    break;
+                }
+                Err(Timeout) => {
+                    info!("Sender timed out, retransmitting expired packets");
+                    self.retransmit();
+                }
+                _ => (),
+            }
+            self.flush();
+        }
+    }
+}
+
+/// Buffers packets received out of order within a window, delivers them in order to the upper
+/// layer.
+pub(crate) struct ReceiveWindow {
+    // sequential number of the next packet expected in order
+    pub(crate) sn: u32,
+    window: u32,
+    buffer: VecDeque<Option<Vec<u8>>>,
+    upper: Sender<Vec<u8>>,
+}
+
+impl ReceiveWindow {
+    pub(crate) fn new(window: u32, upper: Sender<Vec<u8>>) -> Self {
+        assert!(window <= MAX_WINDOW_SIZE);
+
+        Self {
+            sn: INIT_SEQUENTIAL_NUMBER,
+            window,
+            buffer: (0..window).map(|_| None).collect(),
+            upper,
+        }
+    }
+
+    /// Whether the packet falls into the window, the window then slides over packets received in
+    /// order.
+    pub(crate) fn receive(&mut self, packet: &Packet) -> bool {
+        let offset = packet.sn().wrapping_sub(self.sn);
+        if offset >= self.window {
+            return false;
+        }
+
+        self.buffer[offset as usize] = Some(packet.data().to_vec());
+        while let Some(Some(_)) = self.buffer.front() {
+            let data = self.buffer.pop_front().unwrap().unwrap();
+            self.buffer.push_back(None);
+            self.sn = self.sn.wrapping_add(1);
+            self.upper.send(data).unwrap_or_else(|_| {
+                error!("ReceiveWindow::receive: receiver dropped");
+            });
+        }
+
+        true
+    }
+
+    /// Whether the packet was delivered already, within one window before the current one.
+    pub(crate) fn delivered(&self, packet: &Packet) -> bool {
+        let behind = self.sn.wrapping_sub(packet.sn());
+        0 < behind && behind <= self.window
+    }
+}
+
+/// Selective Repeat receiver, buffers packets out of order and acknowledges each of them.
+pub struct SRReceiver {
+    event_recv: Receiver<Message>,
+    event_send: Sender<Message>,
+    channel: Sender<Packet>,
+    window: ReceiveWindow,
+}
+
+impl SRReceiver {
+    pub fn new(window: u32) -> (Self, Receiver<Packet>, Receiver<Vec<u8>>) {
+        let (event_send, event_recv) = channel();
+        let (channel_input, channel_output) = channel();
+        let (upper_input, upper_output) = channel();
+
+        let receiver = Self {
+            event_recv,
+            event_send,
+            channel: channel_input,
+            window: ReceiveWindow::new(window, upper_input),
+        };
+
+        (receiver, channel_output, upper_output)
+    }
+
+    pub fn event_send(&self) -> Sender<Message> {
+        self.event_send.clone()
+    }
+
+    pub fn process(mut self) {
+        thread::spawn(move || self.handle());
+    }
+
+    fn handle(&mut self) {
+        for event in &self.event_recv {
+            match event {
+                Message::Packet(packet) => {
+                    info!("Receiver received packet: {:?}", packet);
+                    // the ACK of a packet already delivered may have been lost
+                    if packet.corrupted()
+                        || !(self.window.receive(&packet) || self.window.delivered(&packet))
+                    {
+                        continue;
+                    }
+
+                    info!("Receiver send ACK {}", packet.sn());
+                    self.channel
+                        .send(Packet::new_ack(packet.sn()))
+                        .unwrap_or_else(|_| {
+                            error!("SRReceiver::handle: receiver dropped");
+                        });
+                }
+                Message::Terminate => {
+                    info!("Terminate signal received, tear down receiver");
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[test]
+fn selective_retransmission_test() {
+    let (sender, output) = SRSender::new(4, Duration::from_millis(300));
+    let events = sender.event_send();
+    let stats = sender.statistics();
+
+    sender.process();
+
+    for _ in 0..4 {
+        events.send(Message::Data(vec![])).unwrap();
+    }
+    for sn in 0..4 {
+        let packet = output.recv().expect("First four original packets");
+        assert_eq!(packet.sn(), sn);
+    }
+
+    // all but the second packet acknowledged
+    for &sn in &[0, 2, 3] {
+        events.send(Message::Packet(Packet::new_ack(sn))).unwrap();
+    }
+
+    let packet = output.recv().expect("Retransmission of the second packet");
+    assert_eq!(packet.sn(), 1);
+    events.send(Message::Packet(Packet::new_ack(1))).unwrap();
+    assert_eq!(
+        output.recv_timeout(Duration::from_millis(500)),
+        Err(RecvTimeoutError::Timeout)
+    );
+
+    let stats = *stats.lock().unwrap();
+    assert_eq!((stats.sent, stats.retransmitted, stats.timeouts), (5, 1, 1));
+}
+
+#[test]
+fn receive_window_test() {
+    let (upper, data) = channel();
+    let mut window = ReceiveWindow::new(2, upper);
+
+    assert!(window.receive(&Packet::new_packet(1, &[1])));
+    assert!(!window.receive(&Packet::new_packet(2, &[2])));
+    assert!(data.try_recv().is_err());
+
+    assert!(window.receive(&Packet::new_packet(0, &[0])));
+    assert_eq!(data.try_iter().collect::<Vec<_>>(), vec![vec![0], vec![1]]);
+    assert!(window.delivered(&Packet::new_packet(0, &[0])));
+    assert!(!window.delivered(&Packet::new_packet(2, &[2])));
+}
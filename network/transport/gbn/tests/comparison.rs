@@ -0,0 +1,123 @@
+use gbn::{
+    channel::Channel,
+    protocol::{GBNReceiver, GBNSender, Message, Packet, Statistics},
+    reno::{RenoReceiver, RenoSender},
+    selective_repeat::{SRReceiver, SRSender},
+};
+use std::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const PACKETS: u32 = 100;
+const WINDOW: u32 = 8;
+const RTT: Duration = Duration::from_millis(20);
+const TIMEOUT: Duration = Duration::from_millis(100);
+/// Every protocol faces the same losses and corruptions.
+const SEED: u64 = 0;
+
+struct Endpoints {
+    data_in: Sender<Message>,
+    sender_out: Receiver<Packet>,
+    receiver_out: Receiver<Packet>,
+    receiver_events: Sender<Message>,
+    data_out: Receiver<Vec<u8>>,
+}
+
+/// Push all packets through a lossy channel with the same settings and seed for every protocol, prints the
+/// throughput.
+fn transfer(endpoints: Endpoints, stats: Arc<Mutex<Statistics>>, name: &str) -> Statistics {
+    let channel = Channel::seeded(0.1, 0.1, RTT, SEED);
+    channel
+        .clone()
+        .connect(endpoints.sender_out, endpoints.receiver_events);
+    channel.connect(endpoints.receiver_out, endpoints.data_in.clone());
+
+    let start = Instant::now();
+    for i in 0..PACKETS {
+        endpoints
+            .data_in
+            .send(Message::Data(i.to_be_bytes().to_vec()))
+            .unwrap();
+    }
+    for i in 0..PACKETS {
+        let out = endpoints.data_out.recv().unwrap();
+        assert_eq!(out, i.to_be_bytes());
+    }
+    let elapsed = start.elapsed();
+    endpoints.data_in.send(Message::Terminate).unwrap();
+
+    let stats = *stats.lock().unwrap();
+    println!(
+        "{}: {:.1} packets/s, {} sent, {} retransmitted, {} timeouts, {} fast retransmits",
+        name,
+        PACKETS as f64 / elapsed.as_secs_f64(),
+        stats.sent,
+        stats.retransmitted,
+        stats.timeouts,
+        stats.fast_retransmits
+    );
+    stats
+}
+
+fn go_back_n() -> Statistics {
+    let (sender, sender_out) = GBNSender::new(WINDOW, TIMEOUT);
+    let (receiver, receiver_out, data_out) = GBNReceiver::new();
+    let endpoints = Endpoints {
+        data_in: sender.event_send(),
+        sender_out,
+        receiver_out,
+        receiver_events: receiver.event_send(),
+        data_out,
+    };
+    let stats = sender.statistics();
+    sender.process();
+    receiver.process();
+    transfer(endpoints, stats, "Go-Back-N")
+}
+
+fn selective_repeat() -> Statistics {
+    let (sender, sender_out) = SRSender::new(WINDOW, TIMEOUT);
+    let (receiver, receiver_out, data_out) = SRReceiver::new(WINDOW);
+    let endpoints = Endpoints {
+        data_in: sender.event_send(),
+        sender_out,
+        receiver_out,
+        receiver_events: receiver.event_send(),
+        data_out,
+    };
+    let stats = sender.statistics();
+    sender.process();
+    receiver.process();
+    transfer(endpoints, stats, "Selective Repeat")
+}
+
+fn reno() -> Statistics {
+    let (sender, sender_out) = RenoSender::new(WINDOW, TIMEOUT);
+    let (receiver, receiver_out, data_out) = RenoReceiver::new(WINDOW);
+    let endpoints = Endpoints {
+        data_in: sender.event_send(),
+        sender_out,
+        receiver_out,
+        receiver_events: receiver.event_send(),
+        data_out,
+    };
+    let stats = sender.statistics();
+    sender.process();
+    receiver.process();
+    transfer(endpoints, stats, "Reno")
+}
+
+#[test]
+fn comparison() {
+    let gbn = go_back_n();
+    let sr = selective_repeat();
+    let reno = reno();
+
+    // Go-Back-N resends the whole window on every loss
+    assert!(sr.retransmitted < gbn.retransmitted);
+    assert!(reno.retransmitted < gbn.retransmitted);
+}
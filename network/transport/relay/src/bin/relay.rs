@@ -0,0 +1,61 @@
+use relay::{Config, Relay};
+use std::{
+    env,
+    net::{Ipv4Addr, SocketAddr},
+    process,
+    str::FromStr,
+    time::Duration,
+};
+
+const USAGE: &str = "Usage: relay PORT SERVER_HOST:SERVER_PORT [--loss RATE] [--corrupt RATE] \
+[--rtt MILLISECONDS] [--reorder RATE] [--duplicate RATE] [--bandwidth BYTES_PER_SECOND] [--seed SEED]";
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("invalid value for {}", flag))
+}
+
+fn parse_args() -> Result<(u16, SocketAddr, Config), String> {
+    let mut args = env::args();
+    // skip executable name
+    args.next();
+
+    let port = parse_value("PORT", args.next())?;
+    let server = parse_value("SERVER_HOST:SERVER_PORT", args.next())?;
+    let mut config = Config::default();
+
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--loss" => config.loss_rate = parse_value(&flag, args.next())?,
+            "--corrupt" => config.corrupt_rate = parse_value(&flag, args.next())?,
+            "--rtt" => config.rtt = Duration::from_millis(parse_value(&flag, args.next())?),
+            "--reorder" => config.reorder_rate = parse_value(&flag, args.next())?,
+            "--duplicate" => config.duplicate_rate = parse_value(&flag, args.next())?,
+            "--bandwidth" => config.bandwidth = Some(parse_value(&flag, args.next())?),
+            "--seed" => config.seed = Some(parse_value(&flag, args.next())?),
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+
+    Ok((port, server, config))
+}
+
+fn main() {
+    let (port, server, config) = parse_args().unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    });
+
+    let relay = Relay::bind((Ipv4Addr::LOCALHOST, port), server, config).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    });
+    println!("Relaying {} to {}", relay.local_addr(), server);
+
+    if let Err(err) = relay.join() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
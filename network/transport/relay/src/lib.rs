@@ -0,0 +1,441 @@
+//! A UDP relay between one client and one server that loses, corrupts, delays, reorders and
+//! duplicates datagrams, the socket counterpart of the in-process `Channel` of the gbn crate.
+//! Tests start it on localhost and point the client to `Relay::local_addr` instead of the server.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Large enough for any UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 0x10000;
+/// How often the relay checks for shutdown while idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Reordered datagrams are held back for at most this long if the round trip time is shorter.
+const MIN_REORDER_DELAY: Duration = Duration::from_millis(10);
+
+/// Impairments of the simulated link, applied independently in both directions.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Probability a datagram is dropped.
+    pub loss_rate: f64,
+    /// Probability one byte of a datagram is flipped.
+    pub corrupt_rate: f64,
+    /// Round trip time, every datagram is delayed by half of it.
+    pub rtt: Duration,
+    /// Probability a datagram is held back for a random extra delay up to the round trip time,
+    /// letting later datagrams overtake it.
+    pub reorder_rate: f64,
+    /// Probability a datagram is delivered twice.
+    pub duplicate_rate: f64,
+    /// Bytes per second of each direction, datagrams queue up behind each other if set.
+    pub bandwidth: Option<u64>,
+    /// Seed of the random number generator, reproduces the same impairments on the same traffic.
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    /// A perfect link.
+    fn default() -> Self {
+        Self {
+            loss_rate: 0.0,
+            corrupt_rate: 0.0,
+            rtt: Duration::from_secs(0),
+            reorder_rate: 0.0,
+            duplicate_rate: 0.0,
+            bandwidth: None,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    fn validate(&self) -> Result<(), &'static str> {
+        let rates = [
+            self.loss_rate,
+            self.corrupt_rate,
+            self.reorder_rate,
+            self.duplicate_rate,
+        ];
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err("rates must be between 0 and 1");
+        }
+        if self.bandwidth == Some(0) {
+            return Err("bandwidth must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// What happened to the datagrams passing through the relay.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Statistics {
+    pub received: usize,
+    pub delivered: usize,
+    pub lost: usize,
+    pub corrupted: usize,
+    pub reordered: usize,
+    pub duplicated: usize,
+}
+
+/// A datagram waiting for its delivery time, ordered by the time then by arrival.
+type Scheduled = Reverse<(Instant, u64, SocketAddr, Vec<u8>)>;
+
+struct Link {
+    config: Config,
+    socket: UdpSocket,
+    server: SocketAddr,
+    /// The last address other than the server that sent a datagram.
+    client: Option<SocketAddr>,
+    rng: StdRng,
+    queue: BinaryHeap<Scheduled>,
+    /// Arrival counter breaking ties in the queue, keeps datagrams due at the same time in order.
+    arrivals: u64,
+    /// When the link towards each destination finishes its last transmission.
+    busy_until: HashMap<SocketAddr, Instant>,
+    stats: Arc<Mutex<Statistics>>,
+}
+
+impl Link {
+    fn until_next_delivery(&self) -> Duration {
+        self.queue
+            .peek()
+            .map_or(POLL_INTERVAL, |Reverse((deliver_at, ..))| {
+                deliver_at.saturating_duration_since(Instant::now())
+            })
+            .clamp(Duration::from_millis(1), POLL_INTERVAL)
+    }
+
+    fn on_datagram(&mut self, mut datagram: Vec<u8>, from: SocketAddr) {
+        let to = if from == self.server {
+            match self.client {
+                Some(client) => client,
+                // no one to reply to
+                None => return,
+            }
+        } else {
+            self.client = Some(from);
+            self.server
+        };
+
+        let stats = self.stats.clone();
+        let mut stats = stats.lock().unwrap();
+        stats.received += 1;
+
+        if self.rng.gen_bool(self.config.loss_rate) {
+            stats.lost += 1;
+            return;
+        }
+        if !datagram.is_empty() && self.rng.gen_bool(self.config.corrupt_rate) {
+            let idx = self.rng.gen_range(0..datagram.len());
+            datagram[idx] = !datagram[idx];
+            stats.corrupted += 1;
+        }
+
+        let now = Instant::now();
+        let sent_at = match self.config.bandwidth {
+            Some(bandwidth) => {
+                let transmission =
+                    Duration::from_secs_f64(datagram.len() as f64 / bandwidth as f64);
+                let busy_until = self.busy_until.entry(to).or_insert(now);
+                *busy_until = (*busy_until).max(now) + transmission;
+                *busy_until
+            }
+            None => now,
+        };
+        let mut deliver_at = sent_at + self.config.rtt / 2;
+        if self.rng.gen_bool(self.config.reorder_rate) {
+            let max_delay = self.config.rtt.max(MIN_REORDER_DELAY);
+            deliver_at += max_delay.mul_f64(self.rng.gen());
+            stats.reordered += 1;
+        }
+
+        if self.rng.gen_bool(self.config.duplicate_rate) {
+            self.schedule(deliver_at, to, datagram.clone());
+            stats.duplicated += 1;
+        }
+        self.schedule(deliver_at, to, datagram);
+    }
+
+    fn schedule(&mut self, deliver_at: Instant, to: SocketAddr, datagram: Vec<u8>) {
+        self.queue
+            .push(Reverse((deliver_at, self.arrivals, to, datagram)));
+        self.arrivals += 1;
+    }
+
+    fn deliver(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while let Some(Reverse((deliver_at, ..))) = self.queue.peek() {
+            if *deliver_at > now {
+                break;
+            }
+            let Reverse((_, _, to, datagram)) = self.queue.pop().unwrap();
+            // counted first, the receiver may look at the statistics as soon as it is sent
+            self.stats.lock().unwrap().delivered += 1;
+            self.socket.send_to(&datagram, to)?;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, shutdown: &AtomicBool) -> io::Result<()> {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+
+        while !shutdown.load(Ordering::Relaxed) {
+            self.socket
+                .set_read_timeout(Some(self.until_next_delivery()))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((amt, from)) => self.on_datagram(buf[..amt].to_vec(), from),
+                Err(err) => match err.kind() {
+                    // on some platforms an ICMP port unreachable from an earlier send surfaces
+                    // as a refused connection on the next receive
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused => {}
+                    _ => return Err(err),
+                },
+            }
+            self.deliver()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A relay running in a background thread, stopped when dropped.
+pub struct Relay {
+    local_addr: SocketAddr,
+    stats: Arc<Mutex<Statistics>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Relay {
+    /// Relay datagrams between `server` and whoever sends to `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A, server: SocketAddr, config: Config) -> io::Result<Self> {
+        config
+            .validate()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+        let socket = UdpSocket::bind(addr)?;
+        let local_addr = socket.local_addr()?;
+        let stats = Arc::new(Mutex::new(Statistics::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut link = Link {
+            config,
+            socket,
+            server,
+            client: None,
+            rng,
+            queue: BinaryHeap::new(),
+            arrivals: 0,
+            busy_until: HashMap::new(),
+            stats: stats.clone(),
+        };
+
+        let flag = shutdown.clone();
+        let handle = thread::spawn(move || link.run(&flag));
+
+        Ok(Self {
+            local_addr,
+            stats,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Relay datagrams to `server` from an arbitrary port on localhost.
+    pub fn spawn(server: SocketAddr, config: Config) -> io::Result<Self> {
+        Self::bind((Ipv4Addr::LOCALHOST, 0), server, config)
+    }
+
+    /// The address clients should send to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Block until the relay stops on an error, never returns otherwise.
+    pub fn join(mut self) -> io::Result<()> {
+        self.handle
+            .take()
+            .unwrap()
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("relay panicked")))
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_server() -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 0x100];
+            while let Ok((amt, peer)) = socket.recv_from(&mut buf) {
+                socket.send_to(&buf[..amt], peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn client(relay: &Relay) -> UdpSocket {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.connect(relay.local_addr()).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        socket
+    }
+
+    #[test]
+    fn perfect_link() {
+        let relay = Relay::spawn(echo_server(), Config::default()).unwrap();
+        let socket = client(&relay);
+        let mut buf = [0u8; 0x100];
+
+        for i in 0..10u8 {
+            socket.send(&[i]).unwrap();
+            assert_eq!(socket.recv(&mut buf).unwrap(), 1);
+            assert_eq!(buf[0], i);
+        }
+
+        let stats = relay.statistics();
+        assert_eq!((stats.received, stats.delivered, stats.lost), (20, 20, 0));
+    }
+
+    #[test]
+    fn delay() {
+        let config = Config {
+            rtt: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let relay = Relay::spawn(echo_server(), config).unwrap();
+        let socket = client(&relay);
+        let mut buf = [0u8; 0x100];
+
+        let start = Instant::now();
+        socket.send(b"ping").unwrap();
+        socket.recv(&mut buf).unwrap();
+        // half the round trip time each way
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn loss_and_duplication() {
+        let config = Config {
+            loss_rate: 0.3,
+            duplicate_rate: 0.3,
+            seed: Some(0),
+            ..Config::default()
+        };
+        let relay = Relay::spawn(echo_server(), config).unwrap();
+        let socket = client(&relay);
+        socket
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+
+        for i in 0..100u8 {
+            socket.send(&[i]).unwrap();
+        }
+        let mut buf = [0u8; 0x100];
+        let mut replies = 0;
+        while socket.recv(&mut buf).is_ok() {
+            replies += 1;
+        }
+
+        let stats = relay.statistics();
+        assert!(stats.lost > 0 && stats.duplicated > 0);
+        assert_eq!(stats.delivered, replies + stats.received - 100);
+        assert_eq!(
+            stats.delivered,
+            stats.received - stats.lost + stats.duplicated
+        );
+    }
+
+    #[test]
+    fn reordering() {
+        let config = Config {
+            reorder_rate: 0.5,
+            seed: Some(0),
+            ..Config::default()
+        };
+        let relay = Relay::spawn(echo_server(), config).unwrap();
+        let socket = client(&relay);
+
+        for i in 0..50u8 {
+            socket.send(&[i]).unwrap();
+        }
+        let mut buf = [0u8; 0x100];
+        let mut replies = vec![];
+        while replies.len() < 50 {
+            socket.recv(&mut buf).unwrap();
+            replies.push(buf[0]);
+        }
+
+        assert!(replies.windows(2).any(|pair| pair[0] > pair[1]));
+        replies.sort_unstable();
+        assert_eq!(replies, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bandwidth() {
+        let config = Config {
+            bandwidth: Some(10_000),
+            ..Config::default()
+        };
+        let relay = Relay::spawn(echo_server(), config).unwrap();
+        let socket = client(&relay);
+
+        let start = Instant::now();
+        for _ in 0..10 {
+            socket.send(&[0u8; 100]).unwrap();
+        }
+        let mut buf = [0u8; 0x100];
+        for _ in 0..10 {
+            socket.recv(&mut buf).unwrap();
+        }
+        // 1000 bytes at 10kB/s
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn invalid_configurations() {
+        let server = (Ipv4Addr::LOCALHOST, 9).into();
+        let config = Config {
+            loss_rate: 1.5,
+            ..Config::default()
+        };
+        assert!(Relay::spawn(server, config).is_err());
+        let config = Config {
+            bandwidth: Some(0),
+            ..Config::default()
+        };
+        assert!(Relay::spawn(server, config).is_err());
+    }
+}
@@ -2,14 +2,31 @@ use std::{
     collections::VecDeque,
     io::{self, ErrorKind},
     net::{Ipv4Addr, ToSocketAddrs, UdpSocket},
-    sync::{Arc, Mutex},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
     thread,
     time::{Duration, Instant},
 };
 
-use crate::{AckPacket, DataPacket};
+use crate::{DataPacket, Packet, RetransmissionTimer};
 
-const CLIENT_TIME_OUT: Duration = Duration::from_secs(5);
+/// Connection setup, data transfer and teardown give up after this many retransmissions in a
+/// row.
+const MAX_RETRIES: u32 = 8;
+/// The background thread checks for time outs at least this often.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// `send` blocks while this many packets are waiting for the window to open.
+const SEND_BUFFER: usize = 256;
+const INITIAL_SSTHRESH: f64 = 32.0;
+/// Duplicated acks taken as a sign of a lost packet.
+const DUPLICATED_ACKS: u32 = 3;
+
+#[derive(PartialEq)]
+enum Phase {
+    Established,
+    /// FIN sent this many times.
+    Closing(u32),
+    Closed,
+}
 
 struct RudpState {
     /// Unique identifer of a packet.
@@ -18,25 +35,57 @@ struct RudpState {
     fragment_identifer: u32,
     /// Sequential number up to but not including `acknowledged` is known as acknowledged by the server.
     acknowledged: u32,
-    /// Packet with their deadline naturally sorted by their sequential numbers.
-    packet_queue: VecDeque<(DataPacket, Instant)>,
+    /// Packets waiting for the window to open.
+    unsent: VecDeque<DataPacket>,
+    /// Packets sent but not acknowledged, naturally sorted by their sequential numbers.
+    in_flight: VecDeque<DataPacket>,
+    /// The one packet timed for a RTT sample and when it was sent. Packets acknowledged late
+    /// because of an earlier loss would inflate the estimate, the timing is abandoned on every
+    /// retransmission.
+    timing: Option<(u32, Instant)>,
+    duplicated_acks: u32,
+    /// Time outs in a row since the window last moved.
+    retries: u32,
+    /// Congestion window in packets, grows by one packet per round trip in congestion avoidance
+    /// and halves on a loss.
+    cwnd: f64,
+    ssthresh: f64,
+    /// Packets the server is willing to buffer past `acknowledged`.
+    peer_window: u32,
+    timer: RetransmissionTimer,
+    deadline: Option<Instant>,
+    phase: Phase,
+    /// Error hit by the background thread, reported by the next call.
+    error: Option<io::Error>,
+    shutdown: bool,
 }
 
 impl RudpState {
-    fn new() -> Self {
+    fn new(peer_window: u32, timer: RetransmissionTimer) -> Self {
         Self {
             sequential_number: 0,
             fragment_identifer: 1,
             acknowledged: 0,
-            packet_queue: VecDeque::new(),
+            unsent: VecDeque::new(),
+            in_flight: VecDeque::new(),
+            timing: None,
+            duplicated_acks: 0,
+            retries: 0,
+            cwnd: 1.0,
+            ssthresh: INITIAL_SSTHRESH,
+            peer_window,
+            timer,
+            deadline: None,
+            phase: Phase::Established,
+            error: None,
+            shutdown: false,
         }
     }
 
-    fn on_send(&mut self, input: &[u8]) -> io::Result<u32> {
-        let deadline = Instant::now() + CLIENT_TIME_OUT;
+    fn on_send(&mut self, input: &[u8]) -> u32 {
         let pushed = if input.len() <= DataPacket::MAX_SAFE_SIZE {
             let packet = DataPacket::new(input, self.sequential_number);
-            self.packet_queue.push_front((packet, deadline));
+            self.unsent.push_back(packet);
             1
         } else {
             // fragment the oversized input
@@ -44,7 +93,7 @@ impl RudpState {
             for packet in
                 DataPacket::fragment(input, self.sequential_number, self.fragment_identifer)
             {
-                self.packet_queue.push_front((packet, deadline));
+                self.unsent.push_back(packet);
                 pushed += 1;
             }
             self.fragment_identifer += 1;
@@ -52,52 +101,380 @@ impl RudpState {
         };
 
         self.sequential_number += pushed;
-        Ok(pushed)
+        pushed
     }
 
-    fn on_ack(&mut self, ack: u32) {
-        let mut dropped = 0;
-        for (packet, _) in self.packet_queue.iter() {
-            if packet.sequential_number < ack {
-                dropped += 1;
-            } else {
+    fn on_ack(&mut self, ack: u32, window: u32) -> Vec<Packet> {
+        self.peer_window = window;
+        if ack < self.acknowledged {
+            return vec![];
+        }
+        if ack == self.acknowledged {
+            return self.on_duplicated_ack();
+        }
+
+        if let Some((sn, sent_at)) = self.timing {
+            if sn < ack {
+                self.timer.sample(sent_at.elapsed());
+                self.timing = None;
+            }
+        }
+
+        while let Some(front) = self.in_flight.front() {
+            if front.sequential_number >= ack {
                 break;
             }
+            self.in_flight.pop_front();
+
+            if self.cwnd < self.ssthresh {
+                // slow start
+                self.cwnd += 1.0;
+            } else {
+                // additive increase
+                self.cwnd += 1.0 / self.cwnd;
+            }
+        }
+
+        self.acknowledged = ack;
+        self.duplicated_acks = 0;
+        self.retries = 0;
+        self.restart_timer();
+        vec![]
+    }
+
+    /// The server received a packet out of order, the oldest packet is likely lost.
+    fn on_duplicated_ack(&mut self) -> Vec<Packet> {
+        if self.in_flight.is_empty() {
+            return vec![];
+        }
+
+        self.duplicated_acks += 1;
+        if self.duplicated_acks != DUPLICATED_ACKS {
+            return vec![];
         }
 
-        self.packet_queue.drain(..dropped);
-        self.acknowledged = self.acknowledged.max(ack);
+        self.decrease();
+        self.restart_timer();
+        vec![Packet::Data(self.in_flight[0].clone())]
+    }
+
+    /// Multiplicative decrease on a loss.
+    fn decrease(&mut self) {
+        self.ssthresh = (self.cwnd / 2.0).max(1.0);
+        self.cwnd = self.ssthresh;
+        self.timing = None;
+    }
+
+    fn restart_timer(&mut self) {
+        self.deadline = if self.in_flight.is_empty() {
+            None
+        } else {
+            Some(Instant::now() + self.timer.rto())
+        };
+    }
+
+    fn window(&self) -> usize {
+        (self.cwnd as usize).max(1).min(self.peer_window as usize)
+    }
+
+    /// Packets to transmit now that the window may have moved.
+    fn on_transmit(&mut self) -> Vec<Packet> {
+        let mut packets = vec![];
+        while self.in_flight.len() < self.window() {
+            let packet = match self.unsent.pop_front() {
+                Some(packet) => packet,
+                None => break,
+            };
+            if self.timing.is_none() {
+                self.timing = Some((packet.sequential_number, Instant::now()));
+            }
+            packets.push(Packet::Data(packet.clone()));
+            self.in_flight.push_back(packet);
+        }
+
+        if self.deadline.is_none() && (!self.in_flight.is_empty() || !self.unsent.is_empty()) {
+            // with the peer window closed, the timer probes it
+            self.deadline = Some(Instant::now() + self.timer.rto());
+        }
+        if let Phase::Closing(0) = self.phase {
+            if self.flushed() {
+                packets.push(self.on_fin());
+            }
+        }
+
+        packets
+    }
+
+    fn on_fin(&mut self) -> Packet {
+        if let Phase::Closing(sent) = self.phase {
+            self.phase = Phase::Closing(sent + 1);
+        }
+        self.deadline = Some(Instant::now() + self.timer.rto());
+        Packet::Fin {
+            sequential_number: self.sequential_number,
+        }
+    }
+
+    fn on_time_out(&mut self) -> Vec<Packet> {
+        self.timer.backoff();
+        self.deadline = Some(Instant::now() + self.timer.rto());
+
+        if !self.flushed() {
+            self.retries += 1;
+            if self.retries > MAX_RETRIES {
+                // the server is gone, nothing acknowledged for too long
+                self.phase = Phase::Closed;
+                self.deadline = None;
+                self.error = Some(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "no acknowledgement from the server",
+                ));
+                return vec![];
+            }
+        }
+
+        if let Some(oldest) = self.in_flight.front() {
+            let packet = Packet::Data(oldest.clone());
+            self.decrease();
+            vec![packet]
+        } else if let Some(probe) = self.unsent.pop_front() {
+            // the peer window is closed, send one packet anyway to learn when it opens
+            let packet = Packet::Data(probe.clone());
+            self.in_flight.push_back(probe);
+            vec![packet]
+        } else {
+            match self.phase {
+                Phase::Closing(sent) if sent > MAX_RETRIES => {
+                    // every packet is acknowledged, only the FIN exchange failed
+                    self.phase = Phase::Closed;
+                    self.deadline = None;
+                    self.error = Some(io::Error::new(
+                        ErrorKind::TimedOut,
+                        "no response to FIN from the server",
+                    ));
+                    vec![]
+                }
+                Phase::Closing(_) => vec![self.on_fin()],
+                _ => {
+                    self.deadline = None;
+                    vec![]
+                }
+            }
+        }
+    }
+
+    fn flushed(&self) -> bool {
+        self.unsent.is_empty() && self.in_flight.is_empty()
     }
 }
 
 struct RawRudpClient {
     state: Mutex<RudpState>,
+    changed: Condvar,
     udp_socket: UdpSocket,
 }
 
 impl RawRudpClient {
+    /// Three way handshake: SYN, SYN-ACK with the initial window, then data.
     pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
         let udp_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
         udp_socket.connect(addr)?;
 
-        Ok(Self {
-            state: Mutex::new(RudpState::new()),
-            udp_socket,
-        })
+        let mut timer = RetransmissionTimer::new();
+        let mut buf = [0u8; Packet::MAX_SIZE];
+
+        for attempt in 0..=MAX_RETRIES {
+            let amt = Packet::Syn.write_to(&mut buf)?;
+            udp_socket.send(&buf[..amt])?;
+            let sent_at = Instant::now();
+            let deadline = sent_at + timer.rto();
+
+            while let Some(remain) = deadline.checked_duration_since(Instant::now()) {
+                udp_socket.set_read_timeout(Some(remain.max(Duration::from_millis(1))))?;
+                match udp_socket.recv(&mut buf) {
+                    Ok(amt) => {
+                        if let Ok(Packet::SynAck { window }) = Packet::read_from(&buf[..amt]) {
+                            if attempt == 0 {
+                                timer.sample(sent_at.elapsed());
+                            }
+                            return Ok(Self {
+                                state: Mutex::new(RudpState::new(window, timer)),
+                                changed: Condvar::new(),
+                                udp_socket,
+                            });
+                        }
+                    }
+                    Err(e) if is_transient(&e) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            timer.backoff();
+        }
+
+        Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "no response from the server",
+        ))
     }
 
-    fn send(&self, input: &[u8]) -> io::Result<()> {
+    fn lock(&self) -> io::Result<MutexGuard<'_, RudpState>> {
         let mut state = self.state.lock().unwrap();
-        let pushed = state.on_send(input)?;
+        match state.error.take() {
+            Some(e) => Err(e),
+            None => Ok(state),
+        }
+    }
 
-        let mut buf = [0u8; DataPacket::HEADER_SIZE + DataPacket::MAX_SAFE_SIZE];
-        for (packet, _) in state.packet_queue.range(..pushed as usize).rev() {
+    fn transmit(&self, packets: &[Packet]) -> io::Result<()> {
+        let mut buf = [0u8; Packet::MAX_SIZE];
+        for packet in packets {
             let amt = packet.write_to(&mut buf)?;
-            self.udp_socket.send(&buf[..amt])?;
+            match self.udp_socket.send(&buf[..amt]) {
+                Err(e) if !is_transient(&e) => return Err(e),
+                _ => (),
+            }
         }
-
         Ok(())
     }
+
+    fn send(&self, input: &[u8]) -> io::Result<()> {
+        let mut state = self.lock()?;
+        while state.unsent.len() >= SEND_BUFFER
+            && state.error.is_none()
+            && state.phase != Phase::Closed
+        {
+            state = self.changed.wait(state).unwrap();
+        }
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        if state.phase != Phase::Established {
+            return Err(io::Error::new(ErrorKind::NotConnected, "connection closed"));
+        }
+
+        state.on_send(input);
+        let packets = state.on_transmit();
+        self.transmit(&packets)
+    }
+
+    /// Wait until `done` holds or the background thread failed.
+    fn wait_until<F>(&self, mut done: F) -> io::Result<()>
+    where
+        F: FnMut(&RudpState) -> bool,
+    {
+        let mut state = self.lock()?;
+        while !done(&state) && state.error.is_none() && state.phase != Phase::Closed {
+            state = self.changed.wait(state).unwrap();
+        }
+        match state.error.take() {
+            Some(e) => Err(e),
+            // the failure was already reported by an earlier call
+            None if !done(&state) => {
+                Err(io::Error::new(ErrorKind::NotConnected, "connection closed"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn close(&self) -> io::Result<()> {
+        {
+            let mut state = self.lock()?;
+            if state.phase == Phase::Established {
+                state.phase = Phase::Closing(0);
+                let packets = state.on_transmit();
+                self.transmit(&packets)?;
+            }
+        }
+
+        self.wait_until(|state| state.phase == Phase::Closed)
+    }
+
+    fn on_packet(&self, packet: Packet) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut packets = match packet {
+            Packet::Ack(ack) => state.on_ack(ack.sequential_number, ack.window),
+            Packet::FinAck if state.flushed() => {
+                state.phase = Phase::Closed;
+                state.deadline = None;
+                vec![]
+            }
+            // a lost SYN-ACK retransmitted, or garbage
+            _ => return Ok(()),
+        };
+
+        packets.extend(state.on_transmit());
+        drop(state);
+        self.changed.notify_all();
+        self.transmit(&packets)
+    }
+
+    /// Time out of the next deadline, None once the client shuts down.
+    fn until_deadline(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        if state.shutdown || state.phase == Phase::Closed {
+            return None;
+        }
+
+        let now = Instant::now();
+        if state.deadline.is_some_and(|deadline| deadline <= now) {
+            let packets = state.on_time_out();
+            drop(state);
+            self.changed.notify_all();
+            if let Err(e) = self.transmit(&packets) {
+                self.fail(e);
+                return None;
+            }
+            return self.until_deadline();
+        }
+
+        let remain = state
+            .deadline
+            .map_or(POLL_INTERVAL, |deadline| deadline - now)
+            .min(POLL_INTERVAL);
+        Some(remain.max(Duration::from_millis(1)))
+    }
+
+    fn fail(&self, e: io::Error) {
+        self.state.lock().unwrap().error = Some(e);
+        self.changed.notify_all();
+    }
+}
+
+/// Receive acks from the server and resend the packets timed out.
+fn rudp_background(client: &RawRudpClient) {
+    let mut buf = [0u8; Packet::MAX_SIZE];
+
+    while let Some(time_out) = client.until_deadline() {
+        let result = client
+            .udp_socket
+            .set_read_timeout(Some(time_out))
+            .and_then(|_| client.udp_socket.recv(&mut buf));
+
+        let result = match result {
+            Ok(amt) => match Packet::read_from(&buf[..amt]) {
+                Ok(packet) => client.on_packet(packet),
+                // ignore malformed packets
+                Err(_) => Ok(()),
+            },
+            Err(e) if is_transient(&e) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            client.fail(e);
+            break;
+        }
+    }
+}
+
+/// Errors the protocol recovers from by retransmission: time outs, and ICMP port unreachable
+/// reported on a connected socket when the server is not yet or no longer listening.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+    )
 }
 
 pub struct RudpClient {
@@ -109,50 +486,32 @@ impl RudpClient {
         let inner = Arc::new(RawRudpClient::connect(addr)?);
         {
             let client = Arc::clone(&inner);
-            thread::spawn(move || loop {
-                thread::sleep(Duration::from_secs(1));
-                rudp_background(&client).unwrap();
-            });
+            thread::spawn(move || rudp_background(&client));
         }
         Ok(Self { inner })
     }
 
+    /// Queue a message for reliable delivery, blocks while the send buffer is full.
     pub fn send(&self, buf: &[u8]) -> io::Result<()> {
         self.inner.send(buf)
     }
-}
 
-/// Periodically receive acks from the server and resend the timeout packets. Not an ideal solution
-/// but should work.
-fn rudp_background(client: &RawRudpClient) -> io::Result<()> {
-    let mut buf = [0u8; DataPacket::HEADER_SIZE + DataPacket::MAX_SAFE_SIZE];
-    let mut state = client.state.lock().unwrap();
-    client.udp_socket.set_nonblocking(true)?;
-
-    loop {
-        match client.udp_socket.recv(&mut buf) {
-            Ok(amt) => {
-                let ack = AckPacket::read_from(&buf[..amt])?;
-                state.on_ack(ack.sequential_number);
-            }
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                break;
-            }
-            Err(e) => return Err(e),
-        }
+    /// Wait until every message sent is acknowledged by the server, fails with `TimedOut` if the
+    /// server stops acknowledging.
+    pub fn flush(&self) -> io::Result<()> {
+        self.inner.wait_until(RudpState::flushed)
     }
 
-    client.udp_socket.set_nonblocking(false)?;
-    let now = Instant::now();
-
-    for (packet, deadline) in state.packet_queue.iter() {
-        if *deadline <= now {
-            packet.write_to(&mut buf)?;
-            client.udp_socket.send(&buf)?;
-        } else {
-            break;
-        }
+    /// Deliver every message sent then close the connection with a FIN exchange, fails with
+    /// `TimedOut` if the server never answers the FIN.
+    pub fn close(self) -> io::Result<()> {
+        self.inner.close()
     }
+}
 
-    Ok(())
+impl Drop for RudpClient {
+    fn drop(&mut self) {
+        self.inner.state.lock().unwrap().shutdown = true;
+        self.inner.changed.notify_all();
+    }
 }
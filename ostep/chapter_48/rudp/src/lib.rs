@@ -1,12 +1,13 @@
 use std::{
-    io::{self, Cursor, Read, Write},
+    io::{self, Cursor, ErrorKind, Read, Write},
     mem,
+    time::Duration,
 };
 
 pub mod client;
 pub mod server;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct DataPacket {
     sequential_number: u32,
     fragment_identifer: u32,
@@ -19,7 +20,7 @@ pub(crate) struct DataPacket {
 const MAX_SAFE_DATAGRAM_SIZE: usize = 508;
 
 impl DataPacket {
-    const HEADER_SIZE: usize = mem::size_of::<u32>() * 4;
+    const HEADER_SIZE: usize = mem::size_of::<u8>() + mem::size_of::<u32>() * 4;
     const MAX_SAFE_SIZE: usize = MAX_SAFE_DATAGRAM_SIZE - Self::HEADER_SIZE;
 
     fn new(input: &[u8], sn: u32) -> Self {
@@ -57,6 +58,7 @@ impl DataPacket {
 
     fn write_to(&self, buf: &mut [u8]) -> io::Result<usize> {
         let mut cursor = Cursor::new(buf);
+        cursor.write_all(&[Kind::Data as u8])?;
         cursor.write_all(&self.sequential_number.to_be_bytes())?;
         cursor.write_all(&self.fragment_identifer.to_be_bytes())?;
         cursor.write_all(&self.fragment_total.to_be_bytes())?;
@@ -65,15 +67,15 @@ impl DataPacket {
         Ok(self.len())
     }
 
-    fn read_from(buf: &[u8]) -> io::Result<Self> {
-        let mut cursor = Cursor::new(buf);
-        let sequential_number = read_be_u32(&mut cursor)?;
-        let fragment_identifer = read_be_u32(&mut cursor)?;
-        let fragment_total = read_be_u32(&mut cursor)?;
-        let fragment_index = read_be_u32(&mut cursor)?;
+    /// Read the packet following the kind byte.
+    fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let sequential_number = read_be_u32(&mut reader)?;
+        let fragment_identifer = read_be_u32(&mut reader)?;
+        let fragment_total = read_be_u32(&mut reader)?;
+        let fragment_index = read_be_u32(&mut reader)?;
 
         let mut datagram = Vec::new();
-        cursor.read_to_end(&mut datagram)?;
+        reader.read_to_end(&mut datagram)?;
 
         Ok(Self {
             sequential_number,
@@ -85,29 +87,113 @@ impl DataPacket {
     }
 }
 
+/// Cumulative acknowledgement, together with the number of packets the receiver is willing to
+/// buffer past it.
 pub struct AckPacket {
     sequential_number: u32,
+    window: u32,
 }
 
 impl AckPacket {
-    pub const SIZE: usize = mem::size_of::<u32>();
+    pub const SIZE: usize = mem::size_of::<u8>() + mem::size_of::<u32>() * 2;
 
-    fn new(sn: u32) -> Self {
+    fn new(sn: u32, window: u32) -> Self {
         AckPacket {
             sequential_number: sn,
+            window,
         }
     }
 
-    fn read_from(buf: &[u8]) -> io::Result<Self> {
-        let cursor = Cursor::new(buf);
-        let sn = read_be_u32(cursor)?;
-        Ok(Self::new(sn))
+    fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let sn = read_be_u32(&mut reader)?;
+        let window = read_be_u32(&mut reader)?;
+        Ok(Self::new(sn, window))
     }
 
     fn write_to(&self, buf: &mut [u8]) -> io::Result<usize> {
         let mut cursor = Cursor::new(buf);
+        cursor.write_all(&[Kind::Ack as u8])?;
         cursor.write_all(&self.sequential_number.to_be_bytes())?;
-        Ok(mem::size_of::<u32>())
+        cursor.write_all(&self.window.to_be_bytes())?;
+        Ok(Self::SIZE)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Syn = 0,
+    SynAck = 1,
+    Data = 2,
+    Ack = 3,
+    Fin = 4,
+    FinAck = 5,
+}
+
+/// Every packet starts with a byte of its kind.
+pub(crate) enum Packet {
+    /// Opens a connection.
+    Syn,
+    /// Accepts a connection, advertising the initial window.
+    SynAck {
+        window: u32,
+    },
+    Data(DataPacket),
+    Ack(AckPacket),
+    /// Closes a connection after every packet before `sequential_number`.
+    Fin {
+        sequential_number: u32,
+    },
+    FinAck,
+}
+
+impl Packet {
+    /// Large enough to hold any packet.
+    const MAX_SIZE: usize = MAX_SAFE_DATAGRAM_SIZE;
+
+    fn write_to(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut cursor = Cursor::new(&mut *buf);
+        match self {
+            Packet::Syn => cursor.write_all(&[Kind::Syn as u8])?,
+            Packet::SynAck { window } => {
+                cursor.write_all(&[Kind::SynAck as u8])?;
+                cursor.write_all(&window.to_be_bytes())?;
+            }
+            Packet::Data(packet) => return packet.write_to(buf),
+            Packet::Ack(packet) => return packet.write_to(buf),
+            Packet::Fin { sequential_number } => {
+                cursor.write_all(&[Kind::Fin as u8])?;
+                cursor.write_all(&sequential_number.to_be_bytes())?;
+            }
+            Packet::FinAck => cursor.write_all(&[Kind::FinAck as u8])?,
+        }
+        Ok(cursor.position() as usize)
+    }
+
+    fn read_from(buf: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor::new(buf);
+        let mut kind = [0u8];
+        cursor.read_exact(&mut kind)?;
+
+        let packet = match kind[0] {
+            k if k == Kind::Syn as u8 => Packet::Syn,
+            k if k == Kind::SynAck as u8 => Packet::SynAck {
+                window: read_be_u32(&mut cursor)?,
+            },
+            k if k == Kind::Data as u8 => Packet::Data(DataPacket::read_from(cursor)?),
+            k if k == Kind::Ack as u8 => Packet::Ack(AckPacket::read_from(cursor)?),
+            k if k == Kind::Fin as u8 => Packet::Fin {
+                sequential_number: read_be_u32(&mut cursor)?,
+            },
+            k if k == Kind::FinAck as u8 => Packet::FinAck,
+            k => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown packet kind {}", k),
+                ))
+            }
+        };
+
+        Ok(packet)
     }
 }
 
@@ -116,3 +202,50 @@ fn read_be_u32<R: Read>(mut reader: R) -> io::Result<u32> {
     reader.read_exact(&mut buf)?;
     Ok(u32::from_be_bytes(buf))
 }
+
+const INITIAL_TIME_OUT: Duration = Duration::from_secs(1);
+const MIN_TIME_OUT: Duration = Duration::from_millis(50);
+const MAX_TIME_OUT: Duration = Duration::from_secs(60);
+
+/// Retransmission time out computed by Jacobson's algorithm. By Karn's algorithm only packets never
+/// retransmitted are sampled, and the time out is doubled on every retransmission until the next
+/// sample.
+pub(crate) struct RetransmissionTimer {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl RetransmissionTimer {
+    fn new() -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::from_secs(0),
+            rto: INITIAL_TIME_OUT,
+        }
+    }
+
+    fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                self.rttvar = self.rttvar * 3 / 4 + srtt.abs_diff(rtt) / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+
+        let rto = self.srtt.unwrap() + self.rttvar * 4;
+        self.rto = rto.clamp(MIN_TIME_OUT, MAX_TIME_OUT);
+    }
+
+    fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_TIME_OUT);
+    }
+}
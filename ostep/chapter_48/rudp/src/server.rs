@@ -1,15 +1,27 @@
 use std::{
-    cmp::{self, Ordering},
-    io,
+    cmp,
+    collections::{BTreeMap, VecDeque},
+    io::{self, ErrorKind},
     net::{SocketAddr, ToSocketAddrs, UdpSocket},
 };
 
-use crate::{AckPacket, DataPacket, MAX_SAFE_DATAGRAM_SIZE};
+use crate::{AckPacket, DataPacket, Packet};
+
+/// Packets the server buffers, either out of order or not yet read by `recv`.
+const RECV_BUFFER: usize = 64;
 
 pub struct RudpServer {
     /// The next sequential number the server is expecting.
     acknowledged: u32,
     udp_socket: UdpSocket,
+    /// The client of the connection, the server serves one client at a time.
+    peer: Option<SocketAddr>,
+    out_of_order: BTreeMap<u32, DataPacket>,
+    ready: VecDeque<DataPacket>,
+    /// The client closed the connection, a new one may be opened by any client.
+    closed: bool,
+    /// The close is not yet reported by `recv`.
+    eof: bool,
 }
 
 impl RudpServer {
@@ -18,49 +30,134 @@ impl RudpServer {
         Ok(Self {
             acknowledged: 0,
             udp_socket,
+            peer: None,
+            out_of_order: BTreeMap::new(),
+            ready: VecDeque::new(),
+            closed: false,
+            eof: false,
         })
     }
 
-    fn ack(&mut self, sn: u32, peer: SocketAddr) -> io::Result<()> {
-        let mut buf = [0u8; AckPacket::SIZE];
-        AckPacket::new(sn).write_to(&mut buf)?;
-        self.udp_socket.send_to(&buf, peer)?;
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.udp_socket.local_addr()
+    }
+
+    /// Packets the server is willing to buffer past the acknowledged ones, packets out of order
+    /// fall within the window.
+    fn window(&self) -> u32 {
+        RECV_BUFFER.saturating_sub(self.ready.len()) as u32
+    }
+
+    fn reply(&self, packet: Packet, peer: SocketAddr) -> io::Result<()> {
+        let mut buf = [0u8; Packet::MAX_SIZE];
+        let amt = packet.write_to(&mut buf)?;
+        self.udp_socket.send_to(&buf[..amt], peer)?;
 
         Ok(())
     }
 
-    fn recv_packet(&mut self) -> io::Result<DataPacket> {
-        let mut packet_buf = [0u8; MAX_SAFE_DATAGRAM_SIZE];
+    fn ack(&self, peer: SocketAddr) -> io::Result<()> {
+        let ack = AckPacket::new(self.acknowledged, self.window());
+        self.reply(Packet::Ack(ack), peer)
+    }
+
+    fn on_data(&mut self, packet: DataPacket) {
+        let sn = packet.sequential_number;
+        if sn < self.acknowledged || sn - self.acknowledged >= self.window() {
+            // duplicated, or beyond the advertised window
+            return;
+        }
+
+        self.out_of_order.insert(sn, packet);
+        while let Some(packet) = self.out_of_order.remove(&self.acknowledged) {
+            self.ready.push_back(packet);
+            self.acknowledged += 1;
+        }
+    }
+
+    fn on_syn(&mut self, peer: SocketAddr) -> io::Result<()> {
+        if self.peer.is_none() || self.closed {
+            // a new connection, `recv` has delivered everything of the previous one
+            self.acknowledged = 0;
+            self.out_of_order.clear();
+            self.peer = Some(peer);
+            self.closed = false;
+        } else if self.peer != Some(peer) {
+            // busy, the client retries its SYN
+            return Ok(());
+        }
+
+        // resent if the SYN-ACK was lost
+        self.reply(
+            Packet::SynAck {
+                window: self.window(),
+            },
+            peer,
+        )
+    }
+
+    /// The next packet in order, None once when the client closed the connection.
+    fn recv_packet(&mut self) -> io::Result<Option<DataPacket>> {
+        let mut packet_buf = [0u8; Packet::MAX_SIZE];
 
         loop {
+            if let Some(packet) = self.ready.pop_front() {
+                return Ok(Some(packet));
+            }
+            if self.eof {
+                self.eof = false;
+                return Ok(None);
+            }
+
             let (amt, peer) = self.udp_socket.recv_from(&mut packet_buf)?;
-            let packet = DataPacket::read_from(&packet_buf[..amt])?;
+            let packet = match Packet::read_from(&packet_buf[..amt]) {
+                Ok(packet) => packet,
+                // ignore malformed packets
+                Err(_) => continue,
+            };
+
+            if let Packet::Syn = packet {
+                self.on_syn(peer)?;
+                continue;
+            }
+            if self.peer != Some(peer) {
+                continue;
+            }
 
-            match packet.sequential_number.cmp(&self.acknowledged) {
-                Ordering::Equal => {
-                    self.acknowledged += 1;
-                    self.ack(self.acknowledged, peer)?;
-                    return Ok(packet);
+            match packet {
+                Packet::Data(packet) if !self.closed => {
+                    self.on_data(packet);
+                    self.ack(peer)?;
                 }
-                Ordering::Less => {
-                    // resend ack for old packet
-                    self.ack(self.acknowledged, peer)?;
+                Packet::Fin { sequential_number } if sequential_number == self.acknowledged => {
+                    // resent if the FIN-ACK was lost
+                    if !self.closed {
+                        self.closed = true;
+                        self.eof = true;
+                    }
+                    self.reply(Packet::FinAck, peer)?;
                 }
-                Ordering::Greater => {
-                    // out-of-order packets are ignored
+                _ => {
+                    // data after the close, or a FIN overtaking data
                 }
             }
         }
     }
 
-    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut packet = self.recv_packet()?;
+    /// Receive a message, truncated to the length of `buf`. Returns None once when the client
+    /// closed the connection, the next call waits for a new connection. Empty messages are
+    /// received as `Some(0)`.
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        let mut packet = match self.recv_packet()? {
+            Some(packet) => packet,
+            None => return Ok(None),
+        };
 
         if packet.fragment_identifer == 0 {
             // packet is not fragmented
-            let amt = cmp::max(packet.datagram.len(), buf.len());
-            (&mut buf[..amt]).copy_from_slice(&packet.datagram[..amt]);
-            Ok(amt)
+            let amt = cmp::min(packet.datagram.len(), buf.len());
+            buf[..amt].copy_from_slice(&packet.datagram[..amt]);
+            Ok(Some(amt))
         } else {
             // packet is fragmented
             let mut datagram = vec![0u8; packet.fragment_total as usize];
@@ -74,13 +171,15 @@ impl RudpServer {
                 if remain == 0 {
                     break;
                 } else {
-                    packet = self.recv_packet()?;
+                    packet = self.recv_packet()?.ok_or_else(|| {
+                        io::Error::new(ErrorKind::UnexpectedEof, "closed in a fragmented message")
+                    })?;
                 }
             }
 
-            let amt = cmp::max(buf.len(), datagram.len());
-            (&mut buf[..amt]).copy_from_slice(&datagram[..amt]);
-            Ok(amt)
+            let amt = cmp::min(buf.len(), datagram.len());
+            buf[..amt].copy_from_slice(&datagram[..amt]);
+            Ok(Some(amt))
         }
     }
 }
@@ -1,4 +1,10 @@
-use std::{net::Ipv4Addr, sync::Arc, thread};
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 use rand::{distributions::Standard, Rng, SeedableRng};
 use rudp::{client::RudpClient, server::RudpServer};
@@ -29,3 +35,145 @@ fn fragmented() {
 
     handle.join().unwrap();
 }
+
+#[test]
+fn flow_control_and_close() {
+    const MESSAGES: u32 = 1000;
+
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4];
+        for i in 0..MESSAGES {
+            if i % 100 == 0 {
+                // a slow reader, the client must stop at the advertised window
+                thread::sleep(Duration::from_millis(50));
+            }
+            assert_eq!(server.recv(&mut buf).unwrap(), Some(4));
+            assert_eq!(u32::from_be_bytes(buf), i);
+        }
+        // the connection is closed after the last message
+        assert_eq!(server.recv(&mut buf).unwrap(), None);
+    });
+
+    let client = RudpClient::connect(addr).unwrap();
+    for i in 0..MESSAGES {
+        client.send(&i.to_be_bytes()).unwrap();
+    }
+    client.close().unwrap();
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn consecutive_clients() {
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        for name in &[b"first", b"again"] {
+            assert_eq!(server.recv(&mut buf).unwrap(), Some(5));
+            assert_eq!(&buf[..5], *name);
+            assert_eq!(server.recv(&mut buf).unwrap(), None);
+        }
+    });
+
+    for name in &[b"first", b"again"] {
+        let client = RudpClient::connect(addr).unwrap();
+        client.send(*name).unwrap();
+        client.close().unwrap();
+    }
+
+    handle.join().unwrap();
+}
+
+/// Forwards datagrams between one client and the server, dropping the first FIN-ACK.
+fn drop_first_fin_ack(server: SocketAddr) -> SocketAddr {
+    const FIN_ACK: u8 = 5;
+
+    let proxy = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = proxy.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0u8; 1024];
+        let mut client = None;
+        let mut dropped = false;
+
+        while let Ok((amt, from)) = proxy.recv_from(&mut buf) {
+            if from != server {
+                client = Some(from);
+                proxy.send_to(&buf[..amt], server).unwrap();
+            } else if buf[0] == FIN_ACK && !dropped {
+                dropped = true;
+            } else if let Some(client) = client {
+                proxy.send_to(&buf[..amt], client).unwrap();
+            }
+        }
+    });
+
+    addr
+}
+
+#[test]
+fn lost_fin_ack() {
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let proxy = drop_first_fin_ack(server.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(4));
+        assert_eq!(server.recv(&mut buf).unwrap(), None);
+        // serves the FIN resent by the client, then the next connection
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(4));
+        assert_eq!(server.recv(&mut buf).unwrap(), None);
+    });
+
+    let client = RudpClient::connect(proxy).unwrap();
+    client.send(b"lost").unwrap();
+    client.close().unwrap();
+
+    let client = RudpClient::connect(proxy).unwrap();
+    client.send(b"next").unwrap();
+    client.close().unwrap();
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn server_gone() {
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(5));
+        // the server is dropped mid-stream
+    });
+
+    let client = RudpClient::connect(addr).unwrap();
+    client.send(b"first").unwrap();
+    client.flush().unwrap();
+    handle.join().unwrap();
+
+    client.send(b"never").unwrap();
+    assert_eq!(client.flush().unwrap_err().kind(), ErrorKind::TimedOut);
+    assert_eq!(client.flush().unwrap_err().kind(), ErrorKind::NotConnected);
+}
+
+#[test]
+fn empty_message_and_close() {
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(0));
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(4));
+        assert_eq!(server.recv(&mut buf).unwrap(), Some(0));
+        assert_eq!(server.recv(&mut buf).unwrap(), None);
+    });
+
+    let client = RudpClient::connect(addr).unwrap();
+    client.send(b"").unwrap();
+    client.send(b"data").unwrap();
+    client.send(b"").unwrap();
+    client.close().unwrap();
+
+    handle.join().unwrap();
+}
@@ -0,0 +1,46 @@
+use std::{net::Ipv4Addr, sync::mpsc, thread, time::Duration};
+
+use relay::{Config, Relay};
+use rudp::{client::RudpClient, server::RudpServer};
+
+#[test]
+fn unreliable_network() {
+    const MESSAGES: u32 = 100;
+    // fragmented into three packets each
+    const MESSAGE_LEN: usize = 1200;
+
+    let mut server = RudpServer::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let config = Config {
+        loss_rate: 0.05,
+        rtt: Duration::from_millis(10),
+        reorder_rate: 0.05,
+        duplicate_rate: 0.05,
+        seed: Some(48),
+        // rudp has no checksum, corrupted packets are not detected
+        ..Config::default()
+    };
+    let relay = Relay::spawn(server.local_addr().unwrap(), config).unwrap();
+
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; MESSAGE_LEN];
+        for i in 0..MESSAGES {
+            assert_eq!(server.recv(&mut buf).unwrap(), Some(MESSAGE_LEN));
+            assert!(buf.iter().all(|&b| b == i as u8));
+        }
+        assert_eq!(server.recv(&mut buf).unwrap(), None);
+        done.send(()).unwrap();
+        // keep answering the FIN in case the relay lost the FIN-ACK
+        while server.recv(&mut buf).is_ok() {}
+    });
+
+    let client = RudpClient::connect(relay.local_addr()).unwrap();
+    for i in 0..MESSAGES {
+        client.send(&[i as u8; MESSAGE_LEN]).unwrap();
+    }
+    client.close().unwrap();
+    finished.recv().expect("the server failed");
+
+    let stats = relay.statistics();
+    assert!(stats.lost > 0 && stats.reordered > 0 && stats.duplicated > 0);
+}